@@ -1,10 +1,39 @@
-use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+
+use chrono::NaiveDate;
+use clap::{ArgGroup, Parser, Subcommand};
+use cli_template_core::unified::mock::MockFailure;
+use cli_template_core::unified::{UnifiedPriority, UnifiedState};
+
+/// Kept in step with `ErrorCategory::exit_code`.
+const EXIT_CODES: &str = "\
+Exit codes:
+  0  success
+  1  internal error
+  2  usage: invalid arguments, query or issue reference
+  3  config: missing or invalid setting, e.g. no default repository
+  4  auth: no token, or the provider rejected it
+  5  permission: the token lacks access
+  6  not found
+  7  rate limited
+  8  network: the provider could not be reached
+  9  provider: unexpected answer from the provider";
 
 #[derive(Parser, Debug)]
-#[command(name = "kirei", about = "Unified CLI for issue trackers")]
+#[command(
+    name = "kirei",
+    about = "Unified CLI for issue trackers",
+    after_long_help = EXIT_CODES
+)]
 pub struct Cli {
     #[command(subcommand)]
     pub command: Command,
+    /// Log HTTP requests to stderr; repeat for headers (-vv) and bodies (-vvv)
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    pub verbose: u8,
+    /// Append every HTTP request to this file as JSON lines
+    #[arg(long, global = true)]
+    pub trace_file: Option<PathBuf>,
 }
 
 #[derive(Subcommand, Debug)]
@@ -13,6 +42,16 @@ pub enum Command {
     Ls(ListArgs),
     /// Create a new issue
     New(CreateArgs),
+    /// Show a single issue
+    Show(ShowArgs),
+    /// Edit the title or body of an issue
+    Edit(EditArgs),
+    /// Close an issue
+    Close(IssueArgs),
+    /// Reopen a closed issue
+    Reopen(IssueArgs),
+    /// Show which features each provider supports
+    Providers,
     /// GitHub commands
     #[command(subcommand)]
     Github(GitHubCommands),
@@ -25,6 +64,9 @@ pub enum Command {
     /// Jira commands
     #[command(subcommand)]
     Jira(JiraCommands),
+    /// Encrypted credential storage
+    #[command(subcommand)]
+    Vault(VaultCommands),
     /// Configuration
     #[command(subcommand)]
     Config(ConfigCommands),
@@ -32,9 +74,29 @@ pub enum Command {
 
 #[derive(Parser, Debug)]
 pub struct ListArgs {
-    /// Provider to list from (github, linear, trello, jira)
+    /// Filter, e.g. 'state:open label:bug assignee:@me updated:>7d "crash"'
+    pub query: Option<String>,
+    /// Provider to list from (github, linear, trello, jira, mock)
     #[arg(short, long)]
     pub provider: Option<String>,
+    /// Only show issues in these states (backlog, todo, in_progress, in_review, done, canceled)
+    #[arg(short, long, value_delimiter = ',')]
+    pub state: Vec<UnifiedState>,
+    /// Group issues by state
+    #[arg(short, long)]
+    pub group: bool,
+    /// Maximum number of issues to list
+    #[arg(short = 'n', long, default_value_t = 30, conflicts_with = "all")]
+    pub limit: usize,
+    /// List every matching issue, fetching as many pages as needed
+    #[arg(short, long)]
+    pub all: bool,
+    /// Seconds to wait for each provider when listing all of them
+    #[arg(long)]
+    pub timeout: Option<u64>,
+    /// Show the last cached listing instead of contacting the providers
+    #[arg(long)]
+    pub offline: bool,
     /// Show raw JSON output
     #[arg(short, long)]
     pub raw: bool,
@@ -50,6 +112,44 @@ pub struct CreateArgs {
     /// Provider to create on
     #[arg(short, long)]
     pub provider: Option<String>,
+    /// Priority (urgent, high, medium, low)
+    #[arg(long)]
+    pub priority: Option<UnifiedPriority>,
+    /// Due date (YYYY-MM-DD)
+    #[arg(long)]
+    pub due: Option<NaiveDate>,
+}
+
+#[derive(Parser, Debug)]
+pub struct IssueArgs {
+    /// Issue reference: gh:owner/repo#12, owner/repo#12, ENG-123, jira:PROJ-7,
+    /// trello:<shortLink> or the issue URL
+    pub id: String,
+    /// Provider the issue lives on, when the reference does not say
+    #[arg(short, long)]
+    pub provider: Option<String>,
+}
+
+#[derive(Parser, Debug)]
+pub struct ShowArgs {
+    #[command(flatten)]
+    pub issue: IssueArgs,
+    /// Show raw JSON output
+    #[arg(short, long)]
+    pub raw: bool,
+}
+
+#[derive(Parser, Debug)]
+#[command(group(ArgGroup::new("changes").required(true).multiple(true).args(["title", "body"])))]
+pub struct EditArgs {
+    #[command(flatten)]
+    pub issue: IssueArgs,
+    /// New title
+    #[arg(short, long)]
+    pub title: Option<String>,
+    /// New description
+    #[arg(short, long)]
+    pub body: Option<String>,
 }
 
 #[derive(Subcommand, Debug)]
@@ -94,7 +194,7 @@ pub struct GitHubNewArgs {
 
 #[derive(Parser, Debug)]
 pub struct GitHubAuthArgs {
-    /// Authentication method (token, oauth)
+    /// Authentication method (token, oauth, device)
     #[arg(short, long)]
     pub method: Option<String>,
     /// Token or Client ID
@@ -102,6 +202,9 @@ pub struct GitHubAuthArgs {
     /// Client Secret (for OAuth)
     #[arg(short, long)]
     pub secret: Option<String>,
+    /// Port for the OAuth redirect; must match the app's callback URL
+    #[arg(long)]
+    pub port: Option<u16>,
 }
 
 #[derive(Subcommand, Debug)]
@@ -163,8 +266,20 @@ pub struct LinearNewArgs {
 
 #[derive(Parser, Debug)]
 pub struct LinearAuthArgs {
+    /// Authentication method (token, oauth)
+    #[arg(short, long)]
+    pub method: Option<String>,
     /// API token
     pub token: Option<String>,
+    /// OAuth app client ID
+    #[arg(long)]
+    pub client_id: Option<String>,
+    /// OAuth app client secret
+    #[arg(long)]
+    pub client_secret: Option<String>,
+    /// Port for the OAuth redirect; must match the app's callback URL
+    #[arg(long)]
+    pub port: Option<u16>,
 }
 
 #[derive(Subcommand, Debug)]
@@ -296,12 +411,24 @@ pub struct JiraAuthArgs {
     /// Server URL (e.g., https://company.atlassian.net)
     #[arg(long)]
     pub server: Option<String>,
-    /// Email
+    /// Account email (basic auth)
     #[arg(long)]
     pub email: Option<String>,
-    /// API token
+    /// API token (basic) or personal access token (pat)
     #[arg(long)]
     pub token: Option<String>,
+    /// Authentication method: basic (Cloud email + API token), pat (Server/Data Center personal access token), oauth (Cloud OAuth 2.0 app)
+    #[arg(short, long)]
+    pub method: Option<String>,
+    /// OAuth app client ID
+    #[arg(long)]
+    pub client_id: Option<String>,
+    /// OAuth app client secret
+    #[arg(long)]
+    pub client_secret: Option<String>,
+    /// Port for the OAuth redirect; must match the app's callback URL
+    #[arg(long)]
+    pub port: Option<u16>,
 }
 
 #[derive(Subcommand, Debug)]
@@ -315,6 +442,9 @@ pub enum JiraProjectCommands {
 #[derive(Parser, Debug)]
 pub struct JiraProjectSetArgs {
     pub project: String,
+    /// Issue type of new issues, for projects without `Task`
+    #[arg(long)]
+    pub issue_type: Option<String>,
 }
 
 #[derive(Parser, Debug)]
@@ -324,6 +454,18 @@ pub struct JiraProjectsArgs {
     pub raw: bool,
 }
 
+#[derive(Subcommand, Debug)]
+pub enum VaultCommands {
+    /// Show where the vault is and whether it is unlocked
+    Status,
+    /// Unlock the vault for this session
+    Unlock,
+    /// Forget the session key; the next use asks for the passphrase
+    Lock,
+    /// Move plaintext credentials from config.json into the vault
+    Migrate,
+}
+
 #[derive(Subcommand, Debug)]
 pub enum ConfigCommands {
     /// Show current configuration
@@ -332,9 +474,84 @@ pub enum ConfigCommands {
     Path,
     /// Set default provider
     Provider(ConfigProviderArgs),
+    /// Map a provider state name to a unified state
+    State(ConfigStateArgs),
+    /// Point a provider at another API host, e.g. GitHub Enterprise Server
+    BaseUrl(ConfigBaseUrlArgs),
+    /// Set the proxy and CA bundle used to reach every provider
+    Http(ConfigHttpArgs),
+    /// Accept any TLS certificate from a provider (test servers only)
+    Insecure(ConfigInsecureArgs),
+    /// Configure the mock provider's store, latency and injected errors
+    Mock(ConfigMockArgs),
 }
 
 #[derive(Parser, Debug)]
 pub struct ConfigProviderArgs {
     pub provider: String,
 }
+
+#[derive(Parser, Debug)]
+pub struct ConfigBaseUrlArgs {
+    /// Provider to configure
+    pub provider: String,
+    /// API base URL (e.g. https://github.example.com); omit to restore the default
+    pub url: Option<String>,
+}
+
+#[derive(Parser, Debug)]
+pub struct ConfigHttpArgs {
+    /// Proxy URL (e.g. http://proxy.example.com:3128); overrides HTTPS_PROXY
+    #[arg(long)]
+    pub proxy: Option<String>,
+    /// Comma-separated hosts to reach without the proxy; overrides NO_PROXY
+    #[arg(long)]
+    pub no_proxy: Option<String>,
+    /// PEM file with extra root certificates to trust
+    #[arg(long)]
+    pub ca_bundle: Option<PathBuf>,
+    /// Clear all HTTP settings before applying the others
+    #[arg(long)]
+    pub reset: bool,
+}
+
+#[derive(Parser, Debug)]
+pub struct ConfigInsecureArgs {
+    /// Provider to configure
+    pub provider: String,
+    /// Whether to skip certificate verification
+    #[arg(action = clap::ArgAction::Set, default_value_t = true)]
+    pub enabled: bool,
+}
+
+#[derive(Parser, Debug)]
+pub struct ConfigMockArgs {
+    /// JSON file to keep the mock issues in
+    #[arg(long)]
+    pub store: Option<PathBuf>,
+    /// Delay before every answer, in milliseconds
+    #[arg(long)]
+    pub latency_ms: Option<u64>,
+    /// Error to fail calls with (unauthorized, forbidden, not_found, rate_limited, validation, timeout, server_error)
+    #[arg(long)]
+    pub failure: Option<MockFailure>,
+    /// Fraction of calls that fail, between 0 and 1
+    #[arg(long)]
+    pub failure_rate: Option<f64>,
+    /// Largest page a listing returns
+    #[arg(long)]
+    pub page_size: Option<usize>,
+    /// Clear all mock settings before applying the others
+    #[arg(long)]
+    pub reset: bool,
+}
+
+#[derive(Parser, Debug)]
+pub struct ConfigStateArgs {
+    /// Provider the state belongs to
+    pub provider: String,
+    /// State name as the provider reports it (e.g. a Trello list or Jira status)
+    pub name: String,
+    /// Unified state to map it to; omit to remove the override
+    pub state: Option<UnifiedState>,
+}
@@ -1,18 +1,28 @@
-use std::env;
-use std::net::TcpListener;
+use std::io::IsTerminal;
+use std::process::ExitCode;
+use std::time::Duration;
 
 use anyhow::Result;
-use cliclack::{input, intro, outro, select};
+use chrono::{DateTime, Utc};
+use cliclack::{input, intro, outro, password, select};
+use futures::TryStreamExt;
+use futures::future::join_all;
 
 use crate::args::*;
-use cli_template_core::config::{Config, ConfigStore};
-use kirei_provider_github::{
-    GitHubClient, GitHubIssue,
-    oauth::{start_callback_server, wait_for_callback},
+use cli_template_core::config::{Config, ConfigStore, JiraAuthMode};
+use cli_template_core::error::{Diagnostic, ErrorCategory};
+use cli_template_core::oauth::{AtlassianSite, CallbackServer, OAuthApp, OAuthClient, OAuthToken};
+use cli_template_core::unified::{
+    Capability, DEFAULT_LIST_TIMEOUT, FanOut, IssueCache, IssueRef, IssueStream, ProviderId,
+    ProviderRegistry, UnifiedCreateParams, UnifiedError, UnifiedIssue, UnifiedListQuery,
+    UnifiedState, UnifiedUpdateParams, http_client, resolve_jira_auth, resolve_token,
 };
+use cli_template_core::vault::{self, PassphrasePrompt};
+use kirei_http::trace;
+use kirei_provider_github::{GitHubClient, GitHubDeviceFlow, GitHubHost, GitHubIssue};
+use kirei_provider_jira::{DEFAULT_ISSUE_TYPE, JiraAuth, JiraClient, JiraIssue, JiraUser};
 use kirei_provider_linear::{LinearClient, LinearIssue};
 use kirei_provider_trello::{TrelloCard, TrelloClient};
-use kirei_provider_jira::{JiraClient, JiraIssue};
 
 fn intro_message(section: &str) -> Result<()> {
     intro(format!("kirei {}", section))?;
@@ -24,93 +34,125 @@ fn outro_message(message: &str) -> Result<()> {
     Ok(())
 }
 
+/// Prints what went wrong and how to fix it, and picks the exit code.
+pub fn report(err: &anyhow::Error) -> ExitCode {
+    let diagnostic = Diagnostic::from_error(err);
+    eprintln!("error: {}", diagnostic.message);
+    if let Some(fix) = &diagnostic.fix {
+        eprintln!("  fix: {}", fix);
+    }
+    ExitCode::from(diagnostic.exit_code())
+}
+
 pub async fn run(cli: Cli) -> Result<()> {
-    let store = ConfigStore::new()?;
+    let store = ConfigStore::new()?.with_passphrase_prompt(prompt_passphrase);
+    trace::init(cli.verbose, cli.trace_file.as_deref())?;
 
     match cli.command {
         Command::Ls(args) => ls_command(args, &store).await,
         Command::New(args) => new_command(args, &store).await,
+        Command::Show(args) => show_command(args, &store).await,
+        Command::Edit(args) => edit_command(args, &store).await,
+        Command::Close(args) => close_command(args, &store).await,
+        Command::Reopen(args) => reopen_command(args, &store).await,
+        Command::Providers => providers_command(&store),
         Command::Github(cmd) => github_command(cmd, &store).await,
         Command::Linear(cmd) => linear_command(cmd, &store).await,
         Command::Trello(cmd) => trello_command(cmd, &store).await,
         Command::Jira(cmd) => jira_command(cmd, &store).await,
+        Command::Vault(cmd) => vault_command(cmd, &store),
         Command::Config(cmd) => config_command(cmd, &store),
     }
 }
 
 async fn ls_command(args: ListArgs, store: &ConfigStore) -> Result<()> {
-    let config = store.load_or_default()?;
+    let registry = ProviderRegistry::with_builtin(store.load_or_default()?)
+        .with_cache(IssueCache::new(store.cache_dir()))
+        .with_offline(args.offline);
+    let query = UnifiedListQuery {
+        search: args.query.as_deref().unwrap_or_default().parse()?,
+        limit: (!args.all).then_some(args.limit),
+        ..Default::default()
+    };
 
-    if let Some(provider) = args.provider {
-        match provider.as_str() {
-            "github" => {
-                let token = resolve_github_token(&config)?;
-                let client = GitHubClient::new(token, config.github.default_repo.clone());
-                let issues = client.list_issues(None, None).await?;
-                display_github_issues(&issues, args.raw)?;
-            }
-            "linear" => {
-                let token = resolve_linear_token(&config)?;
-                let client = LinearClient::new(token, config.linear.default_workspace.clone());
-                let issues = client.list_issues(None).await?;
-                display_linear_issues(&issues, args.raw)?;
-            }
-            "trello" => {
-                let token = resolve_trello_token(&config)?;
-                let api_key = config.trello.api_key.clone().unwrap_or_default();
-                let client = TrelloClient::new(token, api_key, config.trello.default_board.clone());
-                let cards = client.list_cards(None).await?;
-                display_trello_cards(&cards, args.raw)?;
-            }
-            "jira" => {
-                let token = resolve_jira_token(&config)?;
-                let server_url = config.jira.server_url.clone().unwrap_or_default();
-                let client = JiraClient::new(token, server_url, config.jira.default_project.clone());
-                let issues = client.list_issues(None).await?;
-                display_jira_issues(&issues, args.raw)?;
-            }
-            _ => return Err(anyhow::anyhow!("Unknown provider: {}", provider)),
-        }
+    // Each state goes to the providers as its own query, so they filter on
+    // the server and the limit applies to what is left.
+    let queries: Vec<_> = if args.state.is_empty() {
+        vec![query.clone()]
     } else {
-        intro_message("all providers")?;
+        args.state
+            .iter()
+            .map(|state| query.in_state(*state))
+            .collect()
+    };
 
-        // GitHub
-        if let Ok(token) = resolve_github_token(&config) {
-            let client = GitHubClient::new(token, config.github.default_repo.clone());
-            if let Ok(issues) = client.list_issues(None, None).await {
-                println!("\n\x1b[1mGitHub Issues:\x1b[0m");
-                display_github_issues(&issues, false)?;
+    if let Some(provider) = args.provider {
+        let provider = parse_provider(&provider)?;
+        // Live listings print as pages arrive; offline ones and several
+        // states merged together are put together first.
+        if let [query] = &queries[..]
+            && !args.offline
+        {
+            let issues = registry.list_stream(provider, query);
+            if args.group {
+                display_listing(
+                    &issues.try_collect::<Vec<_>>().await?,
+                    true,
+                    args.raw,
+                    false,
+                )?;
+            } else {
+                print_stream(issues, args.raw).await?;
             }
+            return Ok(());
         }
 
-        // Linear
-        if let Ok(token) = resolve_linear_token(&config) {
-            let client = LinearClient::new(token, config.linear.default_workspace.clone());
-            if let Ok(issues) = client.list_issues(None).await {
-                println!("\n\x1b[1mLinear Issues:\x1b[0m");
-                display_linear_issues(&issues, false)?;
+        let mut issues = Vec::new();
+        let mut fetched_at = Utc::now();
+        for query in &queries {
+            let snapshot = registry.list_cached(provider, query).await?;
+            issues.extend(snapshot.issues);
+            fetched_at = fetched_at.min(snapshot.fetched_at);
+        }
+        if queries.len() > 1 {
+            // Newest first, as a single listing comes.
+            issues.sort_by_key(|issue| std::cmp::Reverse(issue.created_at));
+            if let Some(limit) = query.limit {
+                issues.truncate(limit);
             }
         }
+        display_listing(&issues, args.group, args.raw, false)?;
+        if args.offline {
+            eprintln!("\n{} as of {}", provider, describe_age(fetched_at));
+        }
+    } else {
+        intro_message("all providers")?;
 
-        // Trello
-        if let (Ok(token), Ok(api_key)) = (
-            resolve_trello_token(&config),
-            config.trello.api_key.clone().ok_or_else(|| anyhow::anyhow!("no api key"))
-        ) {
-            let client = TrelloClient::new(token, api_key, config.trello.default_board.clone());
-            if let Ok(cards) = client.list_cards(None).await {
-                println!("\n\x1b[1mTrello Cards:\x1b[0m");
-                display_trello_cards(&cards, false)?;
+        let timeout = args
+            .timeout
+            .or(registry.config().list_timeout_secs)
+            .map_or(DEFAULT_LIST_TIMEOUT, Duration::from_secs);
+        let listings = queries
+            .iter()
+            .map(|query| registry.list_all(query, timeout));
+        let fan_out = FanOut::merge(join_all(listings).await, query.limit);
+        display_listing(&fan_out.issues, args.group, args.raw, true)?;
+
+        // Stderr keeps `--raw` output parseable.
+        if args.offline {
+            eprintln!();
+            for (provider, fetched_at) in &fan_out.fetched {
+                eprintln!("{} as of {}", provider, describe_age(*fetched_at));
             }
         }
-
-        // Jira
-        if let Ok(token) = resolve_jira_token(&config) {
-            let server_url = config.jira.server_url.clone().unwrap_or_default();
-            let client = JiraClient::new(token, server_url, config.jira.default_project.clone());
-            if let Ok(issues) = client.list_issues(None).await {
-                println!("\n\x1b[1mJira Issues:\x1b[0m");
-                display_jira_issues(&issues, false)?;
+        if !fan_out.failures.is_empty() {
+            eprintln!("\n{} provider(s) failed:", fan_out.failures.len());
+            for (provider, err) in &fan_out.failures {
+                let diagnostic = Diagnostic::from(err);
+                eprintln!("  {}: {}", provider, diagnostic.message);
+                if let Some(fix) = &diagnostic.fix {
+                    eprintln!("    fix: {}", fix);
+                }
             }
         }
     }
@@ -119,83 +161,204 @@ async fn ls_command(args: ListArgs, store: &ConfigStore) -> Result<()> {
 }
 
 async fn new_command(args: CreateArgs, store: &ConfigStore) -> Result<()> {
-    let config = store.load_or_default()?;
+    let registry = ProviderRegistry::with_builtin(store.load_or_default()?);
+
+    let provider = resolve_provider(&registry, args.provider)?;
+    let params = UnifiedCreateParams {
+        title: args.title,
+        body: args.body,
+        priority: args.priority,
+        due_date: args.due,
+        ..Default::default()
+    };
+    registry
+        .capabilities(provider)
+        .check_create(provider, &params)?;
+
+    let client = registry.build(provider)?;
+    let issue = client.create(params).await?;
+    println!(
+        "Created {} issue {}: {}",
+        provider, issue.identifier, issue.title
+    );
+    if let Some(url) = issue.url {
+        println!("URL: {}", url);
+    }
 
-    let provider = args.provider.unwrap_or_else(|| config.default_provider.clone());
+    Ok(())
+}
 
-    match provider.as_str() {
-        "github" => {
-            let token = resolve_github_token(&config)?;
-            let client = GitHubClient::new(token, config.github.default_repo.clone());
-            let issue = client.create_issue(None, &args.title, args.body.as_deref()).await?;
-            println!("Created GitHub issue #{}: {}", issue.number, issue.title);
-            if let Some(url) = issue.html_url {
-                println!("URL: {}", url);
-            }
-        }
-        "linear" => {
-            let token = resolve_linear_token(&config)?;
-            let client = LinearClient::new(token, config.linear.default_workspace.clone());
-            let issue = client.create_issue(None, &args.title, args.body.as_deref()).await?;
-            println!("Created Linear issue {}: {}", issue.id, issue.title);
-            if let Some(url) = issue.url {
-                println!("URL: {}", url);
-            }
-        }
-        "trello" => {
-            let token = resolve_trello_token(&config)?;
-            let api_key = config.trello.api_key.clone().unwrap_or_default();
-            let client = TrelloClient::new(token, api_key, config.trello.default_board.clone());
-            let card = client.create_card(None, &args.title, args.body.as_deref()).await?;
-            println!("Created Trello card: {}", card.name);
-            if let Some(url) = card.url {
-                println!("URL: {}", url);
-            }
-        }
-        "jira" => {
-            let token = resolve_jira_token(&config)?;
-            let server_url = config.jira.server_url.clone().unwrap_or_default();
-            let client = JiraClient::new(token, server_url, config.jira.default_project.clone());
-            let issue = client.create_issue(None, &args.title, args.body.as_deref()).await?;
-            println!("Created Jira issue {}: {}", issue.key, issue.summary);
-            if let Some(url) = issue.url {
-                println!("URL: {}", url);
-            }
-        }
-        _ => return Err(anyhow::anyhow!("Unknown provider: {}", provider)),
+async fn show_command(args: ShowArgs, store: &ConfigStore) -> Result<()> {
+    let registry = ProviderRegistry::with_builtin(store.load_or_default()?);
+    let reference = resolve_issue(&registry, &args.issue)?;
+    let client = registry.build(reference.provider)?;
+
+    let issue = client.get(&reference.id).await?;
+    if args.raw {
+        println!("{}", serde_json::to_string_pretty(&issue.raw_payload)?);
+    } else {
+        display_issue_detail(&issue);
     }
+    Ok(())
+}
 
+async fn edit_command(args: EditArgs, store: &ConfigStore) -> Result<()> {
+    let registry = ProviderRegistry::with_builtin(store.load_or_default()?);
+    let reference = resolve_issue(&registry, &args.issue)?;
+    let client = registry.build(reference.provider)?;
+
+    let issue = client
+        .update(
+            &reference.id,
+            UnifiedUpdateParams {
+                title: args.title,
+                body: args.body,
+            },
+        )
+        .await?;
+    println!(
+        "Updated {} issue {}: {}",
+        reference.provider, issue.identifier, issue.title
+    );
+    Ok(())
+}
+
+async fn close_command(args: IssueArgs, store: &ConfigStore) -> Result<()> {
+    let registry = ProviderRegistry::with_builtin(store.load_or_default()?);
+    let reference = resolve_issue(&registry, &args)?;
+    let client = registry.build(reference.provider)?;
+
+    let issue = client.close(&reference.id).await?;
+    println!(
+        "Closed {} issue {} [{}]",
+        reference.provider, issue.identifier, issue.state
+    );
+    Ok(())
+}
+
+async fn reopen_command(args: IssueArgs, store: &ConfigStore) -> Result<()> {
+    let registry = ProviderRegistry::with_builtin(store.load_or_default()?);
+    let reference = resolve_issue(&registry, &args)?;
+    let client = registry.build(reference.provider)?;
+
+    let issue = client.reopen(&reference.id).await?;
+    println!(
+        "Reopened {} issue {} [{}]",
+        reference.provider, issue.identifier, issue.state
+    );
+    Ok(())
+}
+
+fn providers_command(store: &ConfigStore) -> Result<()> {
+    let registry = ProviderRegistry::with_builtin(store.load_or_default()?);
+
+    print!("{:<10}", "");
+    for capability in Capability::ALL {
+        print!(" {:^14}", capability.display_name());
+    }
+    println!(" pagination");
+
+    for provider in registry.providers() {
+        let capabilities = registry.capabilities(provider);
+        print!("{:<10}", provider.display_name());
+        for capability in Capability::ALL {
+            let mark = if capabilities.supports(capability) {
+                "yes"
+            } else {
+                "-"
+            };
+            print!(" {:^14}", mark);
+        }
+        println!(" {}", capabilities.pagination);
+    }
     Ok(())
 }
 
+/// The issue named on the command line. With `--provider` any id that provider
+/// accepts will do; otherwise the reference syntax decides.
+fn resolve_issue(registry: &ProviderRegistry, args: &IssueArgs) -> Result<IssueRef> {
+    let Some(provider) = &args.provider else {
+        return Ok(registry.resolve_reference(&args.id)?);
+    };
+    let provider = parse_provider(provider)?;
+    match IssueRef::parse(&args.id) {
+        Ok(reference) if reference.provider == provider => Ok(reference),
+        _ => Ok(IssueRef::new(provider, args.id.trim())),
+    }
+}
+
+fn parse_provider(name: &str) -> Result<ProviderId> {
+    name.parse().map_err(|err: String| {
+        Diagnostic::new(ErrorCategory::Usage, err)
+            .with_fix("kirei providers")
+            .into()
+    })
+}
+
+/// The provider named on the command line, or the configured default.
+fn resolve_provider(registry: &ProviderRegistry, provider: Option<String>) -> Result<ProviderId> {
+    match provider {
+        Some(provider) => parse_provider(&provider),
+        None => Ok(registry.default_provider()?),
+    }
+}
+
 async fn github_command(cmd: GitHubCommands, store: &ConfigStore) -> Result<()> {
     let config = store.load_or_default()?;
 
     match cmd {
         GitHubCommands::Ls(args) => {
             intro_message("github ls")?;
-            let token = resolve_github_token(&config)?;
-            let client = GitHubClient::new(token, config.github.default_repo.clone());
-            let issues = client.list_issues(None, Some(&args.state)).await?;
+            let token = resolve_token(&config, ProviderId::Github)?;
+            let client = GitHubClient::new(
+                token,
+                config.github.default_repo.clone(),
+                config.github.base_url.as_deref(),
+            )
+            .with_http(http_client(&config, ProviderId::Github)?);
+            let issues = client
+                .list_issues(None, Some(&args.state))
+                .await
+                .map_err(UnifiedError::from)?;
             display_github_issues(&issues, args.raw)?;
             outro_message("Done")?;
         }
         GitHubCommands::New(args) => {
             intro_message("github new")?;
-            let token = resolve_github_token(&config)?;
-            let client = GitHubClient::new(token, config.github.default_repo.clone());
-            let issue = client.create_issue(None, &args.title, args.body.as_deref()).await?;
+            let token = resolve_token(&config, ProviderId::Github)?;
+            let client = GitHubClient::new(
+                token,
+                config.github.default_repo.clone(),
+                config.github.base_url.as_deref(),
+            )
+            .with_http(http_client(&config, ProviderId::Github)?);
+            let issue = client
+                .create_issue(None, &args.title, args.body.as_deref())
+                .await
+                .map_err(UnifiedError::from)?;
             println!("Created GitHub issue #{}: {}", issue.number, issue.title);
         }
         GitHubCommands::Auth(args) => github_auth(args, store).await?,
         GitHubCommands::Repo(cmd) => github_repo_command(cmd, store)?,
         GitHubCommands::Repos(_args) => {
             intro_message("github repos")?;
-            let token = resolve_github_token(&config)?;
-            let client = GitHubClient::new(token, config.github.default_repo.clone());
-            let repos = client.list_repositories().await?;
+            let token = resolve_token(&config, ProviderId::Github)?;
+            let client = GitHubClient::new(
+                token,
+                config.github.default_repo.clone(),
+                config.github.base_url.as_deref(),
+            )
+            .with_http(http_client(&config, ProviderId::Github)?);
+            let repos = client
+                .list_repositories()
+                .await
+                .map_err(UnifiedError::from)?;
             for repo in repos {
-                println!("{} - {}", repo.full_name, repo.description.unwrap_or_default());
+                println!(
+                    "{} - {}",
+                    repo.full_name,
+                    repo.description.unwrap_or_default()
+                );
             }
         }
     }
@@ -212,17 +375,27 @@ async fn github_auth(args: GitHubAuthArgs, store: &ConfigStore) -> Result<()> {
         select("Choose authentication method:")
             .item("token", "Personal Access Token", "Enter a GitHub PAT")
             .item("oauth", "OAuth", "Authenticate via browser")
+            .item(
+                "device",
+                "Device code",
+                "Enter a code on any device; works over SSH",
+            )
             .interact()?
             .to_string()
     };
 
     match method.as_str() {
         "token" => {
-            let token = args.value
+            let token = args
+                .value
                 .or_else(|| {
                     input("GitHub personal access token:")
                         .validate(|v: &String| {
-                            if v.trim().is_empty() { Err("Token cannot be empty") } else { Ok(()) }
+                            if v.trim().is_empty() {
+                                Err("Token cannot be empty")
+                            } else {
+                                Ok(())
+                            }
                         })
                         .interact()
                         .ok()
@@ -236,6 +409,31 @@ async fn github_auth(args: GitHubAuthArgs, store: &ConfigStore) -> Result<()> {
             }
         }
         "oauth" => {
+            let saved_secret = reveal(&config, config.github.client_secret.as_deref())?;
+            let (client_id, client_secret) = oauth_app_credentials(
+                "GitHub OAuth App",
+                args.value
+                    .clone()
+                    .or_else(|| config.github.client_id.clone()),
+                args.secret.clone().or(saved_secret),
+            )?;
+            config.github.client_id = Some(client_id.clone());
+            config.github.client_secret = Some(client_secret.clone());
+
+            let token = oauth_sign_in(
+                &config,
+                ProviderId::Github,
+                client_id,
+                client_secret,
+                args.port,
+            )
+            .await?;
+
+            config.github.token = Some(token.access_token);
+            store.save(&config)?;
+            println!("Authentication successful!");
+        }
+        "device" => {
             let client_id = if let Some(id) = args.value.clone() {
                 id
             } else if let Some(id) = config.github.client_id.clone() {
@@ -243,47 +441,37 @@ async fn github_auth(args: GitHubAuthArgs, store: &ConfigStore) -> Result<()> {
             } else {
                 input("GitHub OAuth App Client ID (will be saved):")
                     .validate(|v: &String| {
-                        if v.trim().is_empty() { Err("Client ID cannot be empty") } else { Ok(()) }
+                        if v.trim().is_empty() {
+                            Err("Client ID cannot be empty")
+                        } else {
+                            Ok(())
+                        }
                     })
                     .interact()?
             };
-
-            let client_secret = if let Some(secret) = args.secret.clone() {
-                secret
-            } else if let Some(secret) = config.github.client_secret.clone() {
-                secret
-            } else {
-                input("GitHub OAuth App Client Secret (will be saved):")
-                    .validate(|v: &String| {
-                        if v.trim().is_empty() { Err("Client Secret cannot be empty") } else { Ok(()) }
-                    })
-                    .interact()?
-            };
-
             config.github.client_id = Some(client_id.clone());
-            config.github.client_secret = Some(client_secret.clone());
-
-            let port = get_available_port()?;
-            let oauth = kirei_provider_github::oauth::GitHubOAuth::new(client_id, client_secret);
-            let auth_url = oauth.get_authorization_url(port);
-
-            println!("\n\x1b[1mVisit this link to authenticate:\x1b[0m");
-            println!("{}\n", auth_url);
-
-            let (code_rx, close_rx) = start_callback_server(port)?;
-            println!("Waiting for authorization... (press Ctrl+C to cancel)");
-            
-            if !wait_for_callback(close_rx, 300) {
-                return Err(anyhow::anyhow!("Authorization timed out"));
-            }
-
-            let code = code_rx.recv().map_err(|_| anyhow::anyhow!("Failed to receive code"))?;
 
-            let token = kirei_provider_github::exchange_code_for_token(
-                config.github.client_id.as_ref().unwrap(),
-                config.github.client_secret.as_ref().unwrap(),
-                &code,
-            ).await?;
+            let host = config
+                .github
+                .base_url
+                .as_deref()
+                .map(GitHubHost::from_base_url)
+                .unwrap_or_default();
+            let flow = GitHubDeviceFlow::new(client_id, host)
+                .with_http(http_client(&config, ProviderId::Github)?);
+            let code = flow.request_code().await?;
+
+            println!(
+                "\n\x1b[1mOpen {} and enter the code:\x1b[0m",
+                code.verification_uri
+            );
+            println!("\n    {}\n", code.user_code);
+            println!(
+                "Waiting for approval... (expires in {} minutes, press Ctrl+C to cancel)",
+                code.expires_in / 60
+            );
+
+            let token = flow.poll_for_token(&code).await?;
 
             config.github.token = Some(token);
             store.save(&config)?;
@@ -322,26 +510,44 @@ async fn linear_command(cmd: LinearCommands, store: &ConfigStore) -> Result<()>
     match cmd {
         LinearCommands::Ls(args) => {
             intro_message("linear ls")?;
-            let token = resolve_linear_token(&config)?;
-            let client = LinearClient::new(token, config.linear.default_workspace.clone());
-            let issues = client.list_issues(None).await?;
+            let token = resolve_token(&config, ProviderId::Linear)?;
+            let client = LinearClient::new(
+                token,
+                config.linear.default_workspace.clone(),
+                config.linear.base_url.as_deref(),
+            )
+            .with_http(http_client(&config, ProviderId::Linear)?);
+            let issues = client.list_issues(None).await.map_err(UnifiedError::from)?;
             display_linear_issues(&issues, args.raw)?;
             outro_message("Done")?;
         }
         LinearCommands::New(args) => {
             intro_message("linear new")?;
-            let token = resolve_linear_token(&config)?;
-            let client = LinearClient::new(token, config.linear.default_workspace.clone());
-            let issue = client.create_issue(None, &args.title, args.body.as_deref()).await?;
+            let token = resolve_token(&config, ProviderId::Linear)?;
+            let client = LinearClient::new(
+                token,
+                config.linear.default_workspace.clone(),
+                config.linear.base_url.as_deref(),
+            )
+            .with_http(http_client(&config, ProviderId::Linear)?);
+            let issue = client
+                .create_issue(None, &args.title, args.body.as_deref())
+                .await
+                .map_err(UnifiedError::from)?;
             println!("Created Linear issue {}: {}", issue.id, issue.title);
         }
-        LinearCommands::Auth(args) => linear_auth(args, store)?,
+        LinearCommands::Auth(args) => linear_auth(args, store).await?,
         LinearCommands::Workspace(cmd) => linear_workspace_command(cmd, store)?,
         LinearCommands::Workspaces(_args) => {
             intro_message("linear workspaces")?;
-            let token = resolve_linear_token(&config)?;
-            let client = LinearClient::new(token, config.linear.default_workspace.clone());
-            let workspaces = client.list_workspaces().await?;
+            let token = resolve_token(&config, ProviderId::Linear)?;
+            let client = LinearClient::new(
+                token,
+                config.linear.default_workspace.clone(),
+                config.linear.base_url.as_deref(),
+            )
+            .with_http(http_client(&config, ProviderId::Linear)?);
+            let workspaces = client.list_workspaces().await.map_err(UnifiedError::from)?;
             for ws in workspaces {
                 println!("{} ({})", ws.name, ws.slug);
             }
@@ -350,25 +556,74 @@ async fn linear_command(cmd: LinearCommands, store: &ConfigStore) -> Result<()>
     Ok(())
 }
 
-fn linear_auth(args: LinearAuthArgs, store: &ConfigStore) -> Result<()> {
+async fn linear_auth(args: LinearAuthArgs, store: &ConfigStore) -> Result<()> {
     intro_message("linear auth")?;
     let mut config = store.load_or_default()?;
 
-    let token = args.token
-        .or_else(|| {
-            input("Linear API token:")
-                .validate(|v: &String| {
-                    if v.trim().is_empty() { Err("Token cannot be empty") } else { Ok(()) }
+    let method: String = if let Some(m) = args.method {
+        m
+    } else if args.token.is_some() {
+        "token".to_string()
+    } else {
+        select("Choose authentication method:")
+            .item("token", "API key", "Enter a Linear personal API key")
+            .item(
+                "oauth",
+                "OAuth",
+                "Authenticate via browser with an OAuth app",
+            )
+            .interact()?
+            .to_string()
+    };
+
+    match method.as_str() {
+        "token" => {
+            let token = args
+                .token
+                .or_else(|| {
+                    input("Linear API token:")
+                        .validate(|v: &String| {
+                            if v.trim().is_empty() {
+                                Err("Token cannot be empty")
+                            } else {
+                                Ok(())
+                            }
+                        })
+                        .interact()
+                        .ok()
                 })
-                .interact()
-                .ok()
-        })
-        .map(|s| s.trim().to_string());
+                .map(|s| s.trim().to_string());
 
-    if let Some(token) = token {
-        config.linear.token = Some(token);
-        store.save(&config)?;
-        println!("Token saved successfully.");
+            if let Some(token) = token {
+                config.linear.token = Some(token);
+                store.save(&config)?;
+                println!("Token saved successfully.");
+            }
+        }
+        "oauth" => {
+            let saved_secret = reveal(&config, config.linear.client_secret.as_deref())?;
+            let (client_id, client_secret) = oauth_app_credentials(
+                "Linear OAuth application",
+                args.client_id.or_else(|| config.linear.client_id.clone()),
+                args.client_secret.or(saved_secret),
+            )?;
+            config.linear.client_id = Some(client_id.clone());
+            config.linear.client_secret = Some(client_secret.clone());
+
+            let token = oauth_sign_in(
+                &config,
+                ProviderId::Linear,
+                client_id,
+                client_secret,
+                args.port,
+            )
+            .await?;
+
+            config.linear.token = Some(token.access_token);
+            store.save(&config)?;
+            println!("Authentication successful!");
+        }
+        _ => return Err(anyhow::anyhow!("Unknown auth method: {}", method)),
     }
 
     outro_message("Done")?;
@@ -401,29 +656,50 @@ async fn trello_command(cmd: TrelloCommands, store: &ConfigStore) -> Result<()>
     match cmd {
         TrelloCommands::Ls(args) => {
             intro_message("trello ls")?;
-            let token = resolve_trello_token(&config)?;
+            let token = resolve_token(&config, ProviderId::Trello)?;
             let api_key = config.trello.api_key.clone().unwrap_or_default();
-            let client = TrelloClient::new(token, api_key, config.trello.default_board.clone());
-            let cards = client.list_cards(None).await?;
+            let client = TrelloClient::new(
+                token,
+                api_key,
+                config.trello.default_board.clone(),
+                config.trello.base_url.as_deref(),
+            )
+            .with_http(http_client(&config, ProviderId::Trello)?);
+            let cards = client.list_cards(None).await.map_err(UnifiedError::from)?;
             display_trello_cards(&cards, args.raw)?;
             outro_message("Done")?;
         }
         TrelloCommands::New(args) => {
             intro_message("trello new")?;
-            let token = resolve_trello_token(&config)?;
+            let token = resolve_token(&config, ProviderId::Trello)?;
             let api_key = config.trello.api_key.clone().unwrap_or_default();
-            let client = TrelloClient::new(token, api_key, config.trello.default_board.clone());
-            let card = client.create_card(None, &args.name, args.description.as_deref()).await?;
+            let client = TrelloClient::new(
+                token,
+                api_key,
+                config.trello.default_board.clone(),
+                config.trello.base_url.as_deref(),
+            )
+            .with_http(http_client(&config, ProviderId::Trello)?);
+            let card = client
+                .create_card(None, &args.name, args.description.as_deref())
+                .await
+                .map_err(UnifiedError::from)?;
             println!("Created Trello card: {}", card.name);
         }
         TrelloCommands::Auth(args) => trello_auth(args, store)?,
         TrelloCommands::Board(cmd) => trello_board_command(cmd, store)?,
         TrelloCommands::Boards(_args) => {
             intro_message("trello boards")?;
-            let token = resolve_trello_token(&config)?;
+            let token = resolve_token(&config, ProviderId::Trello)?;
             let api_key = config.trello.api_key.clone().unwrap_or_default();
-            let client = TrelloClient::new(token, api_key, config.trello.default_board.clone());
-            let boards = client.list_boards().await?;
+            let client = TrelloClient::new(
+                token,
+                api_key,
+                config.trello.default_board.clone(),
+                config.trello.base_url.as_deref(),
+            )
+            .with_http(http_client(&config, ProviderId::Trello)?);
+            let boards = client.list_boards().await.map_err(UnifiedError::from)?;
             for board in boards {
                 println!("{} - {}", board.name, board.url);
             }
@@ -436,24 +712,34 @@ fn trello_auth(args: TrelloAuthArgs, store: &ConfigStore) -> Result<()> {
     intro_message("trello auth")?;
     let mut config = store.load_or_default()?;
 
-    let api_key = args.api_key
+    let api_key = args
+        .api_key
         .or_else(|| config.trello.api_key.clone())
         .or_else(|| {
             input("Trello API key:")
                 .validate(|v: &String| {
-                    if v.trim().is_empty() { Err("API key cannot be empty") } else { Ok(()) }
+                    if v.trim().is_empty() {
+                        Err("API key cannot be empty")
+                    } else {
+                        Ok(())
+                    }
                 })
                 .interact()
                 .ok()
         })
         .map(|s| s.trim().to_string());
 
-    let token = args.token
+    let token = args
+        .token
         .or_else(|| config.trello.token.clone())
         .or_else(|| {
             input("Trello token:")
                 .validate(|v: &String| {
-                    if v.trim().is_empty() { Err("Token cannot be empty") } else { Ok(()) }
+                    if v.trim().is_empty() {
+                        Err("Token cannot be empty")
+                    } else {
+                        Ok(())
+                    }
                 })
                 .interact()
                 .ok()
@@ -499,29 +785,26 @@ async fn jira_command(cmd: JiraCommands, store: &ConfigStore) -> Result<()> {
     match cmd {
         JiraCommands::Ls(args) => {
             intro_message("jira ls")?;
-            let token = resolve_jira_token(&config)?;
-            let server_url = config.jira.server_url.clone().unwrap_or_default();
-            let client = JiraClient::new(token, server_url, config.jira.default_project.clone());
-            let issues = client.list_issues(None).await?;
+            let client = jira_api(&config)?;
+            let issues = client.list_issues(None).await.map_err(UnifiedError::from)?;
             display_jira_issues(&issues, args.raw)?;
             outro_message("Done")?;
         }
         JiraCommands::New(args) => {
             intro_message("jira new")?;
-            let token = resolve_jira_token(&config)?;
-            let server_url = config.jira.server_url.clone().unwrap_or_default();
-            let client = JiraClient::new(token, server_url, config.jira.default_project.clone());
-            let issue = client.create_issue(None, &args.summary, args.description.as_deref()).await?;
+            let client = jira_api(&config)?;
+            let issue = client
+                .create_issue(None, &args.summary, args.description.as_deref())
+                .await
+                .map_err(UnifiedError::from)?;
             println!("Created Jira issue {}: {}", issue.key, issue.summary);
         }
-        JiraCommands::Auth(args) => jira_auth(args, store)?,
+        JiraCommands::Auth(args) => jira_auth(args, store).await?,
         JiraCommands::Project(cmd) => jira_project_command(cmd, store)?,
         JiraCommands::Projects(_args) => {
             intro_message("jira projects")?;
-            let token = resolve_jira_token(&config)?;
-            let server_url = config.jira.server_url.clone().unwrap_or_default();
-            let client = JiraClient::new(token, server_url, config.jira.default_project.clone());
-            let projects = client.list_projects().await?;
+            let client = jira_api(&config)?;
+            let projects = client.list_projects().await.map_err(UnifiedError::from)?;
             for project in projects {
                 println!("{} - {}", project.key, project.name);
             }
@@ -530,68 +813,299 @@ async fn jira_command(cmd: JiraCommands, store: &ConfigStore) -> Result<()> {
     Ok(())
 }
 
-fn jira_auth(args: JiraAuthArgs, store: &ConfigStore) -> Result<()> {
+/// The Jira API client for the `jira` commands, set up as `config` says.
+fn jira_api(config: &Config) -> Result<JiraClient> {
+    let server_url = config.jira.server_url.clone().unwrap_or_default();
+    let mut client = JiraClient::new(
+        resolve_jira_auth(config)?,
+        server_url,
+        config.jira.default_project.clone(),
+    )
+    .with_http(http_client(config, ProviderId::Jira)?);
+    if let Some(site_url) = &config.jira.site_url {
+        client = client.with_site_url(site_url);
+    }
+    if let Some(issue_type) = &config.jira.issue_type {
+        client = client.with_issue_type(issue_type);
+    }
+    Ok(client)
+}
+
+async fn jira_auth(args: JiraAuthArgs, store: &ConfigStore) -> Result<()> {
     intro_message("jira auth")?;
     let mut config = store.load_or_default()?;
 
-    let server = args.server
-        .or_else(|| config.jira.server_url.clone())
-        .or_else(|| {
-            input("Jira server URL (e.g., https://company.atlassian.net):")
-                .validate(|v: &String| {
-                    if v.trim().is_empty() { Err("Server URL cannot be empty") } else { Ok(()) }
-                })
-                .interact()
-                .ok()
-        })
-        .map(|s| s.trim().to_string());
+    let method: String = if let Some(m) = args.method.clone() {
+        m
+    } else if args.email.is_some() {
+        "basic".to_string()
+    } else {
+        select("Choose authentication method:")
+            .initial_value(config.jira.auth_mode().as_str())
+            .item(
+                "basic",
+                "Jira Cloud API token",
+                "Your account email and an API token",
+            )
+            .item("pat", "Personal access token", "Jira Server or Data Center")
+            .item(
+                "oauth",
+                "OAuth",
+                "Jira Cloud via browser with an Atlassian OAuth 2.0 (3LO) app",
+            )
+            .interact()?
+            .to_string()
+    };
+    let mode = match method.as_str() {
+        "basic" => JiraAuthMode::Basic,
+        "pat" => JiraAuthMode::Pat,
+        "oauth" => JiraAuthMode::OAuth,
+        _ => return Err(anyhow::anyhow!("Unknown auth method: {}", method)),
+    };
 
-    let email = args.email
-        .or_else(|| config.jira.email.clone())
-        .or_else(|| {
-            input("Jira email:")
-                .validate(|v: &String| {
-                    if v.trim().is_empty() { Err("Email cannot be empty") } else { Ok(()) }
-                })
-                .interact()
-                .ok()
-        })
-        .map(|s| s.trim().to_string());
+    match mode {
+        JiraAuthMode::Basic | JiraAuthMode::Pat => {
+            // After OAuth, `server_url` is the API gateway; the site is what we want.
+            let saved_server = config
+                .jira
+                .site_url
+                .clone()
+                .or(config.jira.server_url.clone());
+            let server = match args.server.or(saved_server) {
+                Some(server) => server,
+                None => input("Jira server URL (e.g., https://company.atlassian.net):")
+                    .validate(|v: &String| {
+                        if v.trim().is_empty() {
+                            Err("Server URL cannot be empty")
+                        } else {
+                            Ok(())
+                        }
+                    })
+                    .interact()?,
+            };
 
-    let token = args.token
-        .or_else(|| config.jira.token.clone())
-        .or_else(|| {
-            input("Jira API token:")
+            let email = if mode == JiraAuthMode::Basic {
+                let email = match args.email.or_else(|| config.jira.email.clone()) {
+                    Some(email) => email,
+                    None => input("Jira account email:")
+                        .validate(|v: &String| {
+                            if v.trim().is_empty() {
+                                Err("Email cannot be empty")
+                            } else {
+                                Ok(())
+                            }
+                        })
+                        .interact()?,
+                };
+                Some(email.trim().to_string())
+            } else {
+                None
+            };
+
+            // A saved token is only reused for the mode it was saved for.
+            let saved_token = if config.jira.auth_mode() == mode {
+                reveal(&config, config.jira.token.as_deref())?
+            } else {
+                None
+            };
+            let token = match args.token.or(saved_token) {
+                Some(token) => token,
+                None => input(match mode {
+                    JiraAuthMode::Basic => "Jira API token:",
+                    _ => "Jira personal access token:",
+                })
                 .validate(|v: &String| {
-                    if v.trim().is_empty() { Err("Token cannot be empty") } else { Ok(()) }
+                    if v.trim().is_empty() {
+                        Err("Token cannot be empty")
+                    } else {
+                        Ok(())
+                    }
                 })
-                .interact()
-                .ok()
-        })
-        .map(|s| s.trim().to_string());
+                .interact()?,
+            };
+            let server = server.trim().trim_end_matches('/').to_string();
+            let token = token.trim().to_string();
+
+            let auth = match &email {
+                Some(email) => JiraAuth::Basic {
+                    email: email.clone(),
+                    token: token.clone(),
+                },
+                None => JiraAuth::PersonalAccessToken(token.clone()),
+            };
+            let user = jira_myself(&config, auth, &server).await?;
 
-    if let Some(s) = server {
-        config.jira.server_url = Some(s);
-    }
-    if let Some(e) = email {
-        config.jira.email = Some(e);
-    }
-    if let Some(t) = token {
-        config.jira.token = Some(t);
+            config.jira.auth = Some(mode);
+            config.jira.server_url = Some(server);
+            config.jira.site_url = None;
+            if email.is_some() {
+                config.jira.email = email;
+            }
+            config.jira.token = Some(token);
+            store.save(&config)?;
+            println!("Signed in as {}.", user.display_name);
+        }
+        JiraAuthMode::OAuth => {
+            let saved_secret = reveal(&config, config.jira.client_secret.as_deref())?;
+            let (client_id, client_secret) = oauth_app_credentials(
+                "Atlassian OAuth 2.0 app",
+                args.client_id
+                    .clone()
+                    .or_else(|| config.jira.client_id.clone()),
+                args.client_secret.clone().or(saved_secret),
+            )?;
+            config.jira.client_id = Some(client_id.clone());
+            config.jira.client_secret = Some(client_secret.clone());
+
+            let token = oauth_sign_in(
+                &config,
+                ProviderId::Jira,
+                client_id,
+                client_secret,
+                args.port,
+            )
+            .await?;
+
+            // 3LO tokens reach the site through Atlassian's API gateway.
+            let http = http_client(&config, ProviderId::Jira)?;
+            let sites = AtlassianSite::list(&http, &token.access_token).await?;
+            let wanted = args
+                .server
+                .as_deref()
+                .or(config.jira.site_url.as_deref())
+                .or(config.jira.server_url.as_deref());
+            let site = choose_atlassian_site(sites, wanted)?;
+            let user = jira_myself(
+                &config,
+                JiraAuth::OAuth(token.access_token.clone()),
+                &site.api_url(),
+            )
+            .await?;
+
+            config.jira.auth = Some(mode);
+            config.jira.server_url = Some(site.api_url());
+            config.jira.site_url = Some(site.url.trim_end_matches('/').to_string());
+            config.jira.token = Some(token.access_token);
+            store.save(&config)?;
+            println!("Signed in to {} as {}.", site.name, user.display_name);
+        }
     }
 
-    store.save(&config)?;
-    println!("Credentials saved successfully.");
     outro_message("Done")?;
     Ok(())
 }
 
+/// Checks Jira credentials against `/myself` before they are saved.
+async fn jira_myself(config: &Config, auth: JiraAuth, server_url: &str) -> Result<JiraUser> {
+    let client = JiraClient::new(auth, server_url.to_string(), None)
+        .with_http(http_client(config, ProviderId::Jira)?);
+    Ok(client.myself().await.map_err(UnifiedError::from)?)
+}
+
+/// The site matching `wanted`, the only one, or the one the user picks.
+fn choose_atlassian_site(sites: Vec<AtlassianSite>, wanted: Option<&str>) -> Result<AtlassianSite> {
+    let wanted = wanted.map(|url| url.trim_end_matches('/'));
+    if let Some(index) = sites
+        .iter()
+        .position(|site| Some(site.url.trim_end_matches('/')) == wanted)
+    {
+        return Ok(sites.into_iter().nth(index).expect("index is in range"));
+    }
+    match sites.len() {
+        0 => Err(anyhow::anyhow!(
+            "The app was not granted access to any Jira site"
+        )),
+        1 => Ok(sites.into_iter().next().expect("one site")),
+        _ => {
+            let mut prompt = select("Choose the Jira site:");
+            for (index, site) in sites.iter().enumerate() {
+                prompt = prompt.item(index, &site.name, &site.url);
+            }
+            let index = prompt.interact()?;
+            Ok(sites.into_iter().nth(index).expect("index is in range"))
+        }
+    }
+}
+
+/// The OAuth app's client ID and secret, prompting for whichever is missing.
+fn oauth_app_credentials(
+    app: &str,
+    client_id: Option<String>,
+    client_secret: Option<String>,
+) -> Result<(String, String)> {
+    let client_id = match client_id {
+        Some(id) => id,
+        None => input(format!("{} Client ID (will be saved):", app))
+            .validate(|v: &String| {
+                if v.trim().is_empty() {
+                    Err("Client ID cannot be empty")
+                } else {
+                    Ok(())
+                }
+            })
+            .interact()?,
+    };
+    let client_secret = match client_secret {
+        Some(secret) => secret,
+        None => input(format!("{} Client Secret (will be saved):", app))
+            .validate(|v: &String| {
+                if v.trim().is_empty() {
+                    Err("Client Secret cannot be empty")
+                } else {
+                    Ok(())
+                }
+            })
+            .interact()?,
+    };
+    Ok((client_id, client_secret))
+}
+
+/// Runs the authorization code flow for `provider` in the browser and
+/// returns the token. `port` overrides the one the provider's app expects.
+async fn oauth_sign_in(
+    config: &Config,
+    provider: ProviderId,
+    client_id: String,
+    client_secret: String,
+    port: Option<u16>,
+) -> Result<OAuthToken> {
+    let app = OAuthApp::for_provider(provider, config)?;
+    let server = CallbackServer::bind(port.unwrap_or(app.callback_port()))?;
+    let client = OAuthClient::new(app, client_id)
+        .with_client_secret(Some(client_secret))
+        .with_http(http_client(config, provider)?);
+    let request = client.authorization_request(&server.redirect_uri());
+
+    if client.app().callback_port() != 0 {
+        println!("\nThe app's callback URL must be {}", request.redirect_uri);
+    }
+    println!("\n\x1b[1mVisit this link to authenticate:\x1b[0m");
+    println!("{}\n", request.url);
+    println!("Waiting for authorization... (press Ctrl+C to cancel)");
+
+    let code = server
+        .wait(&request.state, Duration::from_secs(300))
+        .await?;
+    Ok(client.exchange_code(&code, &request).await?)
+}
+
+/// A stored secret, read from the vault if it is a reference.
+fn reveal(config: &Config, value: Option<&str>) -> Result<Option<String>> {
+    Ok(value
+        .map(|value| config.reveal(value))
+        .transpose()?
+        .flatten())
+}
+
 fn jira_project_command(cmd: JiraProjectCommands, store: &ConfigStore) -> Result<()> {
     let mut config = store.load_or_default()?;
 
     match cmd {
         JiraProjectCommands::Set(args) => {
             config.jira.default_project = Some(args.project);
+            if args.issue_type.is_some() {
+                config.jira.issue_type = args.issue_type;
+            }
             store.save(&config)?;
             println!("Default project set.");
         }
@@ -601,6 +1115,101 @@ fn jira_project_command(cmd: JiraProjectCommands, store: &ConfigStore) -> Result
             } else {
                 println!("No default project set.");
             }
+            let issue_type = config.jira.issue_type.as_deref();
+            println!("Issue type: {}", issue_type.unwrap_or(DEFAULT_ISSUE_TYPE));
+        }
+    }
+    Ok(())
+}
+
+/// Asks for the vault passphrase on the terminal; `None` when there is none.
+fn prompt_passphrase(purpose: PassphrasePrompt) -> Option<String> {
+    if !std::io::stdin().is_terminal() {
+        return None;
+    }
+    let not_empty = |value: &String| {
+        if value.is_empty() {
+            Err("Passphrase cannot be empty")
+        } else {
+            Ok(())
+        }
+    };
+    match purpose {
+        PassphrasePrompt::Unlock => password("Vault passphrase:")
+            .validate(not_empty)
+            .interact()
+            .ok(),
+        PassphrasePrompt::Create => {
+            let passphrase = password("New passphrase to encrypt your credentials with:")
+                .validate(not_empty)
+                .interact()
+                .ok()?;
+            let repeated = password("Repeat the passphrase:").interact().ok()?;
+            (repeated == passphrase).then_some(passphrase)
+        }
+    }
+}
+
+fn vault_command(cmd: VaultCommands, store: &ConfigStore) -> Result<()> {
+    let keyring = store.keyring();
+
+    match cmd {
+        VaultCommands::Status => {
+            println!("Vault: {}", keyring.path().display());
+            if !keyring.exists() {
+                println!("Not created yet; saving a credential creates it.");
+            } else if keyring.is_unlocked() {
+                println!("Unlocked for this session.");
+            } else {
+                println!("Locked.");
+            }
+            let plaintext = store.load_or_default()?.plaintext_secrets();
+            if !plaintext.is_empty() {
+                println!(
+                    "Still in plaintext: {}. Run: kirei vault migrate",
+                    plaintext.join(", ")
+                );
+            }
+        }
+        VaultCommands::Unlock => {
+            if !keyring.exists() {
+                println!("No vault yet; saving a credential creates it.");
+                return Ok(());
+            }
+            let passphrase = std::env::var(vault::PASSPHRASE_ENV)
+                .ok()
+                .filter(|passphrase| !passphrase.is_empty())
+                .or_else(|| prompt_passphrase(PassphrasePrompt::Unlock))
+                .ok_or(vault::VaultError::Locked)?;
+            keyring.unlock(&passphrase)?;
+            if keyring.has_sessions() {
+                println!(
+                    "Vault unlocked for {} hours.",
+                    vault::SESSION_TTL.num_hours()
+                );
+            } else {
+                println!(
+                    "Passphrase accepted, but without $XDG_RUNTIME_DIR the unlock is not kept. \
+                     Set {} to avoid the prompt.",
+                    vault::PASSPHRASE_ENV
+                );
+            }
+        }
+        VaultCommands::Lock => {
+            keyring.forget()?;
+            println!("Vault locked.");
+        }
+        VaultCommands::Migrate => {
+            let moved = store.migrate_secrets()?;
+            if moved.is_empty() {
+                println!("No plaintext credentials in {}.", store.path().display());
+            } else {
+                println!(
+                    "Moved {} into {}.",
+                    moved.join(", "),
+                    keyring.path().display()
+                );
+            }
         }
     }
     Ok(())
@@ -610,7 +1219,7 @@ fn config_command(cmd: ConfigCommands, store: &ConfigStore) -> Result<()> {
     match cmd {
         ConfigCommands::Show => {
             let config = store.load_or_default()?;
-            println!("{}", serde_json::to_string_pretty(&config)?);
+            println!("{}", serde_json::to_string_pretty(&config.masked())?);
         }
         ConfigCommands::Path => {
             println!("{}", store.path().display());
@@ -621,48 +1230,207 @@ fn config_command(cmd: ConfigCommands, store: &ConfigStore) -> Result<()> {
             store.save(&config)?;
             println!("Default provider set.");
         }
+        ConfigCommands::State(args) => {
+            let provider = parse_provider(&args.provider)?;
+            let mut config = store.load_or_default()?;
+            let overrides = config.state_map_mut(provider);
+            // Keys match case-insensitively, so replace any spelling of the name.
+            overrides.retain(|name, _| !name.eq_ignore_ascii_case(&args.name));
+            match args.state {
+                Some(state) => {
+                    overrides.insert(args.name.clone(), state);
+                    println!("{} state '{}' now maps to {}.", provider, args.name, state);
+                }
+                None => println!("Removed {} state override for '{}'.", provider, args.name),
+            }
+            store.save(&config)?;
+        }
+        ConfigCommands::BaseUrl(args) => {
+            let provider = parse_provider(&args.provider)?;
+            let mut config = store.load_or_default()?;
+            match &args.url {
+                Some(url) => println!("{} now uses {}.", provider, url),
+                None => println!("{} uses its default API host again.", provider),
+            }
+            config.set_base_url(provider, args.url);
+            store.save(&config)?;
+        }
+        ConfigCommands::Http(args) => {
+            let mut config = store.load_or_default()?;
+            let http = &mut config.http;
+            if args.reset {
+                *http = Default::default();
+            }
+            if let Some(proxy) = args.proxy {
+                http.proxy = Some(proxy);
+            }
+            if let Some(no_proxy) = args.no_proxy {
+                http.no_proxy = Some(no_proxy);
+            }
+            if let Some(ca_bundle) = args.ca_bundle {
+                http.ca_bundle = Some(ca_bundle);
+            }
+            println!("{}", serde_json::to_string_pretty(&config.http)?);
+            store.save(&config)?;
+        }
+        ConfigCommands::Mock(args) => {
+            let mut config = store.load_or_default()?;
+            let mock = &mut config.mock;
+            if args.reset {
+                *mock = Default::default();
+            }
+            if let Some(path) = args.store {
+                mock.store = Some(path);
+            }
+            if let Some(latency) = args.latency_ms {
+                mock.latency_ms = Some(latency);
+            }
+            if let Some(failure) = args.failure {
+                mock.failure = Some(failure);
+            }
+            if let Some(rate) = args.failure_rate {
+                mock.failure_rate = Some(rate);
+            }
+            if let Some(page_size) = args.page_size {
+                mock.page_size = Some(page_size);
+            }
+            println!("{}", serde_json::to_string_pretty(&config.mock)?);
+            store.save(&config)?;
+        }
+        ConfigCommands::Insecure(args) => {
+            let provider = parse_provider(&args.provider)?;
+            let mut config = store.load_or_default()?;
+            config.set_insecure_skip_verify(provider, args.enabled);
+            if args.enabled {
+                println!("{} certificates will not be verified.", provider);
+            } else {
+                println!("{} certificates are verified again.", provider);
+            }
+            store.save(&config)?;
+        }
     }
     Ok(())
 }
 
-fn resolve_github_token(config: &Config) -> Result<String> {
-    if let Ok(env_token) = env::var("KIREI_GITHUB_TOKEN") {
-        if !env_token.trim().is_empty() {
-            return Ok(env_token);
+/// `merged` listings mix providers, so each issue is tagged with its provider.
+fn display_listing(issues: &[UnifiedIssue], group: bool, raw: bool, merged: bool) -> Result<()> {
+    if !group || raw || issues.is_empty() {
+        return display_issues(issues, raw, merged);
+    }
+    for state in UnifiedState::ALL {
+        let in_state: Vec<_> = issues
+            .iter()
+            .filter(|issue| issue.unified_state == state)
+            .cloned()
+            .collect();
+        if !in_state.is_empty() {
+            println!("\n{} ({})", state, in_state.len());
+            display_issues(&in_state, false, merged)?;
         }
     }
-    config.github.token.clone()
-        .ok_or_else(|| anyhow::anyhow!("GitHub token not configured. Run: kirei github auth"))
+    Ok(())
 }
 
-fn resolve_linear_token(config: &Config) -> Result<String> {
-    if let Ok(env_token) = env::var("KIREI_LINEAR_TOKEN") {
-        if !env_token.trim().is_empty() {
-            return Ok(env_token);
+/// Prints issues as they arrive instead of waiting for every page.
+async fn print_stream(mut issues: IssueStream<'_>, raw: bool) -> Result<()> {
+    let mut count = 0;
+    if raw {
+        println!("[");
+    }
+    while let Some(issue) = issues.try_next().await? {
+        if raw {
+            if count > 0 {
+                println!(",");
+            }
+            print!("{}", serde_json::to_string_pretty(&issue.raw_payload)?);
+        } else {
+            print_issue(&issue, false);
         }
+        count += 1;
     }
-    config.linear.token.clone()
-        .ok_or_else(|| anyhow::anyhow!("Linear token not configured. Run: kirei linear auth"))
+    if raw {
+        println!("\n]");
+    } else if count == 0 {
+        println!("No issues found.");
+    }
+    Ok(())
 }
 
-fn resolve_trello_token(config: &Config) -> Result<String> {
-    if let Ok(env_token) = env::var("KIREI_TRELLO_TOKEN") {
-        if !env_token.trim().is_empty() {
-            return Ok(env_token);
-        }
+/// How long ago a snapshot was taken, e.g. `12 minutes ago`.
+fn describe_age(at: DateTime<Utc>) -> String {
+    let age = Utc::now() - at;
+    let (count, unit) = if age.num_days() > 0 {
+        (age.num_days(), "day")
+    } else if age.num_hours() > 0 {
+        (age.num_hours(), "hour")
+    } else if age.num_minutes() > 0 {
+        (age.num_minutes(), "minute")
+    } else {
+        return "less than a minute ago".into();
+    };
+    format!(
+        "{} {}{} ago",
+        count,
+        unit,
+        if count == 1 { "" } else { "s" }
+    )
+}
+
+fn print_issue(issue: &UnifiedIssue, merged: bool) {
+    if merged {
+        print!("{} ", issue.provider.slug());
+    }
+    println!("{} [{}] {}", issue.identifier, issue.state, issue.title);
+    if let Some(url) = &issue.url {
+        println!("  {}", url);
     }
-    config.trello.token.clone()
-        .ok_or_else(|| anyhow::anyhow!("Trello token not configured. Run: kirei trello auth"))
 }
 
-fn resolve_jira_token(config: &Config) -> Result<String> {
-    if let Ok(env_token) = env::var("KIREI_JIRA_TOKEN") {
-        if !env_token.trim().is_empty() {
-            return Ok(env_token);
+fn display_issues(issues: &[UnifiedIssue], raw: bool, merged: bool) -> Result<()> {
+    if raw {
+        let payloads: Vec<_> = issues.iter().map(|issue| &issue.raw_payload).collect();
+        println!("{}", serde_json::to_string_pretty(&payloads)?);
+    } else if issues.is_empty() {
+        println!("No issues found.");
+    } else {
+        for issue in issues {
+            print_issue(issue, merged);
         }
     }
-    config.jira.token.clone()
-        .ok_or_else(|| anyhow::anyhow!("Jira token not configured. Run: kirei jira auth"))
+    Ok(())
+}
+
+fn display_issue_detail(issue: &UnifiedIssue) {
+    println!("\x1b[1m{} {}\x1b[0m", issue.identifier, issue.title);
+    println!("  Provider:  {}", issue.provider);
+    println!("  State:     {} ({})", issue.state, issue.unified_state);
+    if !issue.assignees.is_empty() {
+        println!("  Assignees: {}", issue.assignees.join(", "));
+    }
+    if !issue.labels.is_empty() {
+        println!("  Labels:    {}", issue.labels.join(", "));
+    }
+    if let Some(priority) = issue.priority {
+        println!("  Priority:  {}", priority);
+    }
+    if let Some(due_date) = issue.due_date {
+        println!("  Due:       {}", due_date);
+    }
+    if let Some(created_at) = issue.created_at {
+        println!("  Created:   {}", created_at.format("%Y-%m-%d %H:%M UTC"));
+    }
+    if let Some(updated_at) = issue.updated_at {
+        println!("  Updated:   {}", updated_at.format("%Y-%m-%d %H:%M UTC"));
+    }
+    if let Some(comment_count) = issue.comment_count {
+        println!("  Comments:  {}", comment_count);
+    }
+    if let Some(url) = &issue.url {
+        println!("  URL:       {}", url);
+    }
+    if let Some(description) = &issue.description {
+        println!("\n{}", description);
+    }
 }
 
 fn display_github_issues(issues: &[GitHubIssue], _raw: bool) -> Result<()> {
@@ -684,7 +1452,7 @@ fn display_linear_issues(issues: &[LinearIssue], _raw: bool) -> Result<()> {
         println!("No issues found.");
     } else {
         for issue in issues {
-            println!("{} [{}] {}", issue.id, issue.state, issue.title);
+            println!("{} [{}] {}", issue.identifier, issue.state, issue.title);
             if let Some(url) = &issue.url {
                 println!("  {}", url);
             }
@@ -720,9 +1488,3 @@ fn display_jira_issues(issues: &[JiraIssue], _raw: bool) -> Result<()> {
     }
     Ok(())
 }
-
-fn get_available_port() -> Result<u16> {
-    let listener = TcpListener::bind("127.0.0.1:0")?;
-    let port = listener.local_addr()?.port();
-    Ok(port)
-}
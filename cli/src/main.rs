@@ -1,11 +1,15 @@
-use anyhow::Result;
+use std::process::ExitCode;
+
 use clap::Parser;
 use tokio::main;
 
 use cli_template::{args::Cli, commands};
 
 #[main]
-async fn main() -> Result<()> {
+async fn main() -> ExitCode {
     let cli = Cli::parse();
-    commands::run(cli).await
+    match commands::run(cli).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => commands::report(&err),
+    }
 }
@@ -0,0 +1,51 @@
+//! Helpers shared by the integration tests.
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use cli_template_core::unified::ProviderId;
+
+/// A home directory holding `config` as the kirei config, removed on drop.
+pub struct Home(PathBuf);
+
+impl Home {
+    /// Tests run in parallel, so each gets a directory of its own.
+    pub fn new(config: &str) -> Self {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "kirei-test-{}-{}",
+            std::process::id(),
+            COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(path.join(".kirei")).unwrap();
+        fs::write(path.join(".kirei").join("config.json"), config).unwrap();
+        Self(path)
+    }
+
+    /// `kirei <args>` in this home, without any of the caller's tokens,
+    /// cassettes or vault session.
+    pub fn kirei(&self, args: &[&str]) -> Command {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_cli-template"));
+        command
+            .args(args)
+            .env("HOME", &self.0)
+            .env_remove("KIREI_RECORD")
+            .env_remove("KIREI_REPLAY")
+            .env_remove("KIREI_MOCK_STORE")
+            .env_remove("KIREI_VAULT_PASSPHRASE")
+            .env_remove("XDG_RUNTIME_DIR");
+        for provider in ProviderId::ALL {
+            command.env_remove(provider.env_var());
+        }
+        command
+    }
+}
+
+impl Drop for Home {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
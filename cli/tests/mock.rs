@@ -0,0 +1,152 @@
+//! Drives the `kirei` binary against the mock provider: a full issue round
+//! trip, and the failures it can be told to inject.
+
+mod common;
+
+use std::process::Output;
+
+use common::Home;
+
+/// The mock provider as the default, with nothing else configured.
+const CONFIG: &str = r#"{ "default_provider": "mock" }"#;
+
+fn run(home: &Home, args: &[&str]) -> Output {
+    home.kirei(args).output().unwrap()
+}
+
+/// Runs a command that must succeed and returns its output.
+fn succeed(home: &Home, args: &[&str]) -> String {
+    let output = run(home, args);
+    assert!(
+        output.status.success(),
+        "kirei {}: {}",
+        args.join(" "),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+#[test]
+fn issues_round_trip_through_a_mock_store() {
+    let home = Home::new(CONFIG);
+    // Each command is a new process, so changes need the store to last.
+    let store = std::env::temp_dir().join(format!("kirei-mock-{}.json", std::process::id()));
+    let _ = std::fs::remove_file(&store);
+    succeed(
+        &home,
+        &["config", "mock", "--store", store.to_str().unwrap()],
+    );
+
+    let created = succeed(
+        &home,
+        &["new", "Exercise the mock", "--body", "From a test"],
+    );
+    assert!(
+        created.contains("Created Mock issue MOCK-43"),
+        "{}",
+        created
+    );
+
+    let shown = succeed(&home, &["show", "MOCK-43"]);
+    assert!(shown.contains("Exercise the mock"), "{}", shown);
+    assert!(shown.contains("From a test"), "{}", shown);
+
+    let closed = succeed(&home, &["close", "MOCK-43"]);
+    assert!(closed.contains("[Done]"), "{}", closed);
+    let listed = succeed(&home, &["ls", "--provider", "mock", "-n", "50"]);
+    assert!(!listed.contains("MOCK-43"), "{}", listed);
+
+    succeed(&home, &["reopen", "MOCK-43"]);
+    let listed = succeed(&home, &["ls", "--provider", "mock", "-n", "50"]);
+    assert!(listed.contains("MOCK-43"), "{}", listed);
+
+    let _ = std::fs::remove_file(&store);
+}
+
+#[test]
+fn injected_failures_exit_with_their_category() {
+    let home = Home::new(CONFIG);
+    let cases = [
+        ("unauthorized", 4),
+        ("forbidden", 5),
+        ("not_found", 6),
+        ("rate_limited", 7),
+        ("server_error", 9),
+    ];
+    for (failure, code) in cases {
+        succeed(&home, &["config", "mock", "--reset", "--failure", failure]);
+        let output = run(&home, &["show", "MOCK-1"]);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert_eq!(output.status.code(), Some(code), "{}: {}", failure, stderr);
+        assert!(
+            stderr.contains("injected by the mock provider"),
+            "{}: {}",
+            failure,
+            stderr
+        );
+    }
+
+    succeed(&home, &["config", "mock", "--reset"]);
+    succeed(&home, &["show", "MOCK-1"]);
+}
+
+#[test]
+fn auth_failures_suggest_resetting_the_mock() {
+    let home = Home::new(CONFIG);
+    succeed(&home, &["config", "mock", "--failure", "unauthorized"]);
+
+    // A failed provider leaves the others' issues to show, so `ls` still
+    // succeeds and reports the failure on stderr.
+    let output = run(&home, &["ls"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert!(stderr.contains("1 provider(s) failed"), "{}", stderr);
+    assert!(
+        stderr.contains("fix: kirei config mock --reset"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn a_failure_rate_of_zero_never_fails() {
+    let home = Home::new(CONFIG);
+    succeed(
+        &home,
+        &[
+            "config",
+            "mock",
+            "--failure",
+            "server_error",
+            "--failure-rate",
+            "0",
+        ],
+    );
+    succeed(&home, &["show", "MOCK-1"]);
+}
+
+#[test]
+fn states_are_filtered_before_the_limit() {
+    let home = Home::new(CONFIG);
+    // The newest issues are open, so filtering the first five would leave none.
+    for args in [
+        &[
+            "ls",
+            "--provider",
+            "mock",
+            "--state",
+            "done,canceled",
+            "-n",
+            "5",
+        ][..],
+        &["ls", "--state", "done,canceled", "-n", "5"][..],
+    ] {
+        let listed = succeed(&home, args);
+        let closed = listed
+            .lines()
+            .filter(|line| line.contains("[Done]") || line.contains("[Canceled]"))
+            .count();
+        assert_eq!(closed, 5, "{}", listed);
+        assert!(!listed.contains("[Todo]"), "{}", listed);
+    }
+}
@@ -0,0 +1,111 @@
+//! Replays the cassettes in `tests/cassettes`, one directory per provider,
+//! through the `kirei` binary. `KIREI_REPLAY` answers every request from
+//! them, so nothing here touches the network.
+
+mod common;
+
+use std::path::Path;
+use std::process::Output;
+
+use common::Home;
+
+/// The settings the cassettes were recorded with.
+const CONFIG: &str = r#"{
+  "github": { "default_repo": "kirei-dev/kirei" },
+  "linear": { "default_workspace": "8f3b6c1e-2a4d-4e7b-9c1a-5d2e7f9a0b3c" },
+  "trello": { "default_board": "5f9a1c2b3d4e5f6a7b8c9d0e", "api_key": "trello-api-key" },
+  "jira": {
+    "server_url": "https://kirei.atlassian.net",
+    "default_project": "KIR",
+    "auth": "basic",
+    "email": "dev@kirei.example"
+  }
+}"#;
+
+/// Runs `kirei <args>` against the cassettes of `provider`, with a token
+/// that was never recorded: cassettes match without credentials.
+fn kirei(provider: &str, args: &[&str]) -> Output {
+    let cassettes = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("cassettes")
+        .join(provider);
+    Home::new(CONFIG)
+        .kirei(args)
+        .env("KIREI_REPLAY", cassettes)
+        .env(
+            format!("KIREI_{}_TOKEN", provider.to_uppercase()),
+            "replayed-token",
+        )
+        .output()
+        .unwrap()
+}
+
+fn assert_lists(provider: &str, expected: &[&str]) {
+    let output = kirei(provider, &["ls", "--provider", provider, "-n", "2"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    for line in expected {
+        assert!(
+            stdout.contains(line),
+            "'{}' missing from:\n{}",
+            line,
+            stdout
+        );
+    }
+}
+
+#[test]
+fn github_listing_replays() {
+    assert_lists(
+        "github",
+        &[
+            "#128 [open] Listing hangs when one provider times out",
+            "#127 [open] Support GitHub Enterprise Server",
+        ],
+    );
+}
+
+#[test]
+fn linear_listing_replays() {
+    assert_lists(
+        "linear",
+        &[
+            "KIR-42 [In Progress] Cache Linear workflow states per team",
+            "KIR-41 [Todo] Show due dates in the grouped listing",
+        ],
+    );
+}
+
+#[test]
+fn trello_listing_replays() {
+    assert_lists(
+        "trello",
+        &[
+            "Xk3pQ9aZ [To Do] Write the release notes for 0.3",
+            "Lm7tR2wB [In Review] Pick a name for the mock provider",
+        ],
+    );
+}
+
+#[test]
+fn jira_listing_replays() {
+    assert_lists(
+        "jira",
+        &[
+            "KIR-87 [In Review] Jira transitions fail on team-managed projects",
+            "KIR-86 [To Do] Document the JQL the query syntax turns into",
+        ],
+    );
+}
+
+#[test]
+fn unrecorded_requests_fail_without_the_network() {
+    let output = kirei("github", &["show", "github:999"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(9), "{}", stderr);
+    assert!(stderr.contains("no recorded response"), "{}", stderr);
+}
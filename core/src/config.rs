@@ -1,62 +1,378 @@
+use crate::error::CoreError;
+use crate::unified::ProviderId;
+use crate::unified::mock::MockFailure;
+use crate::unified::state::StateOverrides;
+use crate::vault::{self, Keyring, PromptFn, VaultError};
+use kirei_http::HttpOptions;
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 const CONFIG_DIR_NAME: &str = ".kirei";
 const CONFIG_FILE_NAME: &str = "config.json";
+const CACHE_DIR_NAME: &str = "cache";
+const VAULT_FILE_NAME: &str = "vault.json";
+/// Shown by `config show` in place of a secret still stored in plaintext.
+const MASK: &str = "********";
 
 #[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct GitHubConfig {
     pub default_repo: Option<String>,
+    /// GitHub Enterprise Server URL, e.g. `https://github.example.com`; unset
+    /// for github.com.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_url: Option<String>,
     pub client_id: Option<String>,
     pub client_secret: Option<String>,
     pub token: Option<String>,
+    /// Accepts any TLS certificate from this provider. Only meant for test servers.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub insecure_skip_verify: bool,
+    /// Overrides for mapping this provider's states onto `UnifiedState`.
+    #[serde(default, skip_serializing_if = "StateOverrides::is_empty")]
+    pub state_map: StateOverrides,
 }
 
 #[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct LinearConfig {
     pub default_workspace: Option<String>,
+    /// API root to use instead of `https://api.linear.app`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_url: Option<String>,
+    /// OAuth app to sign in with, for `auth --method oauth`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_secret: Option<String>,
     pub token: Option<String>,
+    /// Accepts any TLS certificate from this provider. Only meant for test servers.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub insecure_skip_verify: bool,
+    /// Overrides for mapping this provider's states onto `UnifiedState`.
+    #[serde(default, skip_serializing_if = "StateOverrides::is_empty")]
+    pub state_map: StateOverrides,
 }
 
 #[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct TrelloConfig {
     pub default_board: Option<String>,
+    /// API root to use instead of `https://api.trello.com/1`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_url: Option<String>,
     pub api_key: Option<String>,
     pub token: Option<String>,
+    /// Accepts any TLS certificate from this provider. Only meant for test servers.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub insecure_skip_verify: bool,
+    /// Overrides for mapping this provider's states onto `UnifiedState`.
+    #[serde(default, skip_serializing_if = "StateOverrides::is_empty")]
+    pub state_map: StateOverrides,
+}
+
+/// How to sign in to Jira.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JiraAuthMode {
+    /// Jira Cloud: the account email and an API token.
+    Basic,
+    /// Jira Server / Data Center: a personal access token.
+    Pat,
+    /// Jira Cloud: an OAuth 2.0 (3LO) access token.
+    OAuth,
+}
+
+impl JiraAuthMode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            JiraAuthMode::Basic => "basic",
+            JiraAuthMode::Pat => "pat",
+            JiraAuthMode::OAuth => "oauth",
+        }
+    }
 }
 
 #[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct JiraConfig {
     pub server_url: Option<String>,
     pub default_project: Option<String>,
+    /// How `token` is used; see [`JiraConfig::auth_mode`] when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth: Option<JiraAuthMode>,
+    /// The account email for `basic` auth.
     pub email: Option<String>,
+    /// The site people browse, e.g. `https://company.atlassian.net`, when
+    /// `server_url` is Atlassian's API gateway, as it is with OAuth.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub site_url: Option<String>,
+    /// Issue type of new issues; `Task` when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub issue_type: Option<String>,
+    /// Atlassian OAuth 2.0 (3LO) app to sign in with, for `auth --method oauth`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_secret: Option<String>,
     pub token: Option<String>,
+    /// Accepts any TLS certificate from this provider. Only meant for test servers.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub insecure_skip_verify: bool,
+    /// Overrides for mapping this provider's states onto `UnifiedState`.
+    #[serde(default, skip_serializing_if = "StateOverrides::is_empty")]
+    pub state_map: StateOverrides,
+}
+
+impl JiraConfig {
+    /// The configured auth mode. Configs from before there was one used
+    /// Basic auth when they had an email and a bearer token otherwise.
+    pub fn auth_mode(&self) -> JiraAuthMode {
+        self.auth.unwrap_or(match &self.email {
+            Some(email) if !email.trim().is_empty() => JiraAuthMode::Basic,
+            _ => JiraAuthMode::Pat,
+        })
+    }
+}
+
+/// The mock provider: where it keeps its issues and how it misbehaves.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct MockConfig {
+    /// JSON file the issues are kept in; unset keeps them in memory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub store: Option<PathBuf>,
+    /// Delay before every answer.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<u64>,
+    /// Error to fail calls with.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failure: Option<MockFailure>,
+    /// Fraction of calls that fail, between 0 and 1; all of them when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failure_rate: Option<f64>,
+    /// Largest page a listing returns.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page_size: Option<usize>,
+    /// Overrides for mapping this provider's states onto `UnifiedState`.
+    #[serde(default, skip_serializing_if = "StateOverrides::is_empty")]
+    pub state_map: StateOverrides,
+}
+
+/// Network settings shared by every provider. Unset values fall back to the
+/// standard `HTTPS_PROXY` and `NO_PROXY` variables.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct HttpConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<String>,
+    /// Comma-separated hosts to reach without the proxy.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub no_proxy: Option<String>,
+    /// PEM file with extra root certificates, e.g. an internal CA.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ca_bundle: Option<PathBuf>,
 }
 
 #[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct Config {
     #[serde(default)]
     pub default_provider: String,
+    /// Seconds each provider gets to answer when `kirei ls` queries them all.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub list_timeout_secs: Option<u64>,
+    #[serde(default)]
+    pub http: HttpConfig,
+    #[serde(default)]
     pub github: GitHubConfig,
+    #[serde(default)]
     pub linear: LinearConfig,
+    #[serde(default)]
     pub trello: TrelloConfig,
+    #[serde(default)]
     pub jira: JiraConfig,
+    #[serde(default)]
+    pub mock: MockConfig,
+    /// Where `vault:` references are looked up; set by [`ConfigStore`].
+    #[serde(skip)]
+    keyring: Option<Arc<Keyring>>,
+}
+
+impl Config {
+    /// The fields holding credentials, by the name of their vault entry.
+    fn secrets(&self) -> [(&'static str, Option<&str>); 7] {
+        [
+            ("github.token", self.github.token.as_deref()),
+            ("github.client_secret", self.github.client_secret.as_deref()),
+            ("linear.token", self.linear.token.as_deref()),
+            ("linear.client_secret", self.linear.client_secret.as_deref()),
+            ("trello.token", self.trello.token.as_deref()),
+            ("jira.token", self.jira.token.as_deref()),
+            ("jira.client_secret", self.jira.client_secret.as_deref()),
+        ]
+    }
+
+    fn secrets_mut(&mut self) -> [(&'static str, &mut Option<String>); 7] {
+        [
+            ("github.token", &mut self.github.token),
+            ("github.client_secret", &mut self.github.client_secret),
+            ("linear.token", &mut self.linear.token),
+            ("linear.client_secret", &mut self.linear.client_secret),
+            ("trello.token", &mut self.trello.token),
+            ("jira.token", &mut self.jira.token),
+            ("jira.client_secret", &mut self.jira.client_secret),
+        ]
+    }
+
+    /// Names of the credentials still stored in plaintext.
+    pub fn plaintext_secrets(&self) -> Vec<&'static str> {
+        self.secrets()
+            .into_iter()
+            .filter(|(_, value)| is_plaintext(*value))
+            .map(|(name, _)| name)
+            .collect()
+    }
+
+    /// The secret a config value stands for: the value itself, or the vault
+    /// entry it references. `None` if the vault has no such entry.
+    pub fn reveal(&self, value: &str) -> Result<Option<String>, VaultError> {
+        match vault::referenced_name(value) {
+            Some(name) => self
+                .keyring
+                .as_ref()
+                .ok_or(VaultError::Unavailable)?
+                .get(name),
+            None => Ok(Some(value.to_string())),
+        }
+    }
+
+    /// Opens the vault now, asking for the passphrase if needed, so later
+    /// [`reveal`](Self::reveal) calls do not. Nothing happens without a vault.
+    pub fn unlock_vault(&self) -> Result<(), VaultError> {
+        match &self.keyring {
+            Some(keyring) if keyring.exists() => keyring.with_vault(|_| ()),
+            _ => Ok(()),
+        }
+    }
+
+    /// A copy safe to print: plaintext secrets are masked, vault references kept.
+    pub fn masked(&self) -> Config {
+        let mut config = self.clone();
+        for (_, value) in config.secrets_mut() {
+            if is_plaintext(value.as_deref()) {
+                *value = Some(MASK.to_string());
+            }
+        }
+        config
+    }
+
+    pub fn state_map(&self, provider: ProviderId) -> &StateOverrides {
+        match provider {
+            ProviderId::Github => &self.github.state_map,
+            ProviderId::Linear => &self.linear.state_map,
+            ProviderId::Trello => &self.trello.state_map,
+            ProviderId::Jira => &self.jira.state_map,
+            ProviderId::Mock => &self.mock.state_map,
+        }
+    }
+
+    /// Where the provider's API lives, when it is not the public default.
+    /// For Jira that is the server URL, which has no default.
+    pub fn base_url(&self, provider: ProviderId) -> Option<&str> {
+        match provider {
+            ProviderId::Github => self.github.base_url.as_deref(),
+            ProviderId::Linear => self.linear.base_url.as_deref(),
+            ProviderId::Trello => self.trello.base_url.as_deref(),
+            ProviderId::Jira => self.jira.server_url.as_deref(),
+            ProviderId::Mock => None,
+        }
+    }
+
+    pub fn set_base_url(&mut self, provider: ProviderId, url: Option<String>) {
+        let slot = match provider {
+            ProviderId::Github => &mut self.github.base_url,
+            ProviderId::Linear => &mut self.linear.base_url,
+            ProviderId::Trello => &mut self.trello.base_url,
+            ProviderId::Jira => &mut self.jira.server_url,
+            // Nothing to point elsewhere.
+            ProviderId::Mock => return,
+        };
+        *slot = url;
+    }
+
+    /// How requests to `provider` reach the network.
+    pub fn http_options(&self, provider: ProviderId) -> HttpOptions {
+        let insecure_skip_verify = match provider {
+            ProviderId::Github => self.github.insecure_skip_verify,
+            ProviderId::Linear => self.linear.insecure_skip_verify,
+            ProviderId::Trello => self.trello.insecure_skip_verify,
+            ProviderId::Jira => self.jira.insecure_skip_verify,
+            ProviderId::Mock => false,
+        };
+        HttpOptions {
+            proxy: self.http.proxy.clone(),
+            no_proxy: self.http.no_proxy.clone(),
+            ca_bundle: self.http.ca_bundle.clone(),
+            insecure_skip_verify,
+        }
+    }
+
+    pub fn set_insecure_skip_verify(&mut self, provider: ProviderId, insecure: bool) {
+        let slot = match provider {
+            ProviderId::Github => &mut self.github.insecure_skip_verify,
+            ProviderId::Linear => &mut self.linear.insecure_skip_verify,
+            ProviderId::Trello => &mut self.trello.insecure_skip_verify,
+            ProviderId::Jira => &mut self.jira.insecure_skip_verify,
+            ProviderId::Mock => return,
+        };
+        *slot = insecure;
+    }
+
+    pub fn state_map_mut(&mut self, provider: ProviderId) -> &mut StateOverrides {
+        match provider {
+            ProviderId::Github => &mut self.github.state_map,
+            ProviderId::Linear => &mut self.linear.state_map,
+            ProviderId::Trello => &mut self.trello.state_map,
+            ProviderId::Jira => &mut self.jira.state_map,
+            ProviderId::Mock => &mut self.mock.state_map,
+        }
+    }
+}
+
+fn is_plaintext(value: Option<&str>) -> bool {
+    value.is_some_and(|value| !value.is_empty() && vault::referenced_name(value).is_none())
 }
 
 pub struct ConfigStore {
     dir: PathBuf,
     path: PathBuf,
+    keyring: Arc<Keyring>,
 }
 
 impl ConfigStore {
-    pub fn new() -> Result<Self, anyhow::Error> {
-        let base_dirs = directories::BaseDirs::new()
-            .ok_or_else(|| anyhow::anyhow!("Could not find home directory"))?;
-        let home = base_dirs.home_dir();
-        let dir = home.join(CONFIG_DIR_NAME);
+    pub fn new() -> Result<Self, CoreError> {
+        let base_dirs = directories::BaseDirs::new().ok_or(CoreError::NoHomeDir)?;
+        Ok(Self::in_dir(base_dirs.home_dir().join(CONFIG_DIR_NAME)))
+    }
+
+    /// A store kept in `dir` rather than the home directory.
+    pub(crate) fn in_dir(dir: PathBuf) -> Self {
         let path = dir.join(CONFIG_FILE_NAME);
-        Ok(Self { dir, path })
+        let keyring = Arc::new(Self::keyring_in(&dir));
+        Self { dir, path, keyring }
+    }
+
+    /// Asks for the vault passphrase through `prompt` when neither the
+    /// environment nor an unlocked session provides it.
+    pub fn with_passphrase_prompt(mut self, prompt: PromptFn) -> Self {
+        self.keyring = Arc::new(Self::keyring_in(&self.dir).with_prompt(prompt));
+        self
+    }
+
+    /// The session key lives in `$XDG_RUNTIME_DIR`, which is private to the
+    /// user and cleared on logout. Where there is none, as on macOS, the key
+    /// is not kept at all rather than left on disk.
+    fn keyring_in(dir: &Path) -> Keyring {
+        let session_dir = std::env::var_os("XDG_RUNTIME_DIR")
+            .map(PathBuf::from)
+            .filter(|runtime| runtime.is_dir());
+        Keyring::new(dir.join(VAULT_FILE_NAME), session_dir.as_deref())
     }
 
     pub fn dir(&self) -> &Path {
@@ -67,26 +383,160 @@ impl ConfigStore {
         &self.path
     }
 
-    pub fn load_or_default(&self) -> Result<Config, anyhow::Error> {
+    /// The encrypted vault holding the credentials.
+    pub fn keyring(&self) -> &Keyring {
+        &self.keyring
+    }
+
+    /// Where listing snapshots are kept.
+    pub fn cache_dir(&self) -> PathBuf {
+        self.dir.join(CACHE_DIR_NAME)
+    }
+
+    pub fn load_or_default(&self) -> Result<Config, CoreError> {
         if !self.path.exists() {
-            return Ok(Config::default());
+            return Ok(self.empty());
         }
 
-        let bytes = fs::read(&self.path)?;
-        match serde_json::from_slice::<Config>(&bytes) {
-            Ok(config) => Ok(config),
-            Err(_) => {
-                // Config file exists but has old/invalid format, return default
-                Ok(Config::default())
-            }
+        let bytes = fs::read(&self.path).map_err(|source| CoreError::ReadConfig {
+            path: self.path.clone(),
+            source,
+        })?;
+        let mut config =
+            serde_json::from_slice::<Config>(&bytes).map_err(|source| CoreError::ParseConfig {
+                path: self.path.clone(),
+                source,
+            })?;
+        config.keyring = Some(Arc::clone(&self.keyring));
+        Ok(config)
+    }
+
+    fn empty(&self) -> Config {
+        Config {
+            keyring: Some(Arc::clone(&self.keyring)),
+            ..Config::default()
         }
     }
 
-    pub fn save(&self, config: &Config) -> Result<PathBuf, anyhow::Error> {
-        fs::create_dir_all(&self.dir)?;
+    /// Writes `config`, first moving any plaintext credential into the vault
+    /// and storing a reference to it instead.
+    pub fn save(&self, config: &Config) -> Result<PathBuf, CoreError> {
+        let mut config = config.clone();
+        self.seal(&mut config, false)?;
+        self.write(&config)
+    }
+
+    /// Moves every plaintext credential in the stored config into the vault,
+    /// returning their names.
+    pub fn migrate_secrets(&self) -> Result<Vec<&'static str>, CoreError> {
+        let mut config = self.load_or_default()?;
+        let moved = config.plaintext_secrets();
+        if !moved.is_empty() {
+            self.seal(&mut config, true)?;
+            self.write(&config)?;
+        }
+        Ok(moved)
+    }
+
+    fn write(&self, config: &Config) -> Result<PathBuf, CoreError> {
+        fs::create_dir_all(&self.dir).map_err(|source| CoreError::CreateConfigDir {
+            path: self.dir.clone(),
+            source,
+        })?;
 
-        let json = serde_json::to_vec_pretty(config)?;
-        fs::write(&self.path, json)?;
+        let json = serde_json::to_vec_pretty(config)
+            .map_err(|source| CoreError::SerializeConfig { source })?;
+        fs::write(&self.path, json).map_err(|source| CoreError::WriteConfig {
+            path: self.path.clone(),
+            source,
+        })?;
         Ok(self.path.clone())
     }
+
+    /// Without `strict`, a locked vault leaves secrets that are already on
+    /// disk in plaintext as they are; new ones are refused either way.
+    fn seal(&self, config: &mut Config, strict: bool) -> Result<(), CoreError> {
+        let plaintext = config.plaintext_secrets();
+        if plaintext.is_empty() {
+            return Ok(());
+        }
+        let sealed = self.keyring.with_vault(|vault| {
+            for (name, value) in config.secrets_mut() {
+                if let Some(secret) = value.take_if(|value| is_plaintext(Some(value))) {
+                    vault.insert(name, secret);
+                    *value = Some(vault::reference(name));
+                }
+            }
+            vault.save()
+        });
+        match sealed {
+            Ok(saved) => Ok(saved?),
+            Err(VaultError::Locked | VaultError::NoPassphrase)
+                if !strict && self.unchanged_on_disk(config, &plaintext) =>
+            {
+                Ok(())
+            }
+            Err(source) => Err(CoreError::SaveCredentials { source }),
+        }
+    }
+
+    fn unchanged_on_disk(&self, config: &Config, names: &[&'static str]) -> bool {
+        let Ok(stored) = self.load_or_default() else {
+            return false;
+        };
+        config
+            .secrets()
+            .into_iter()
+            .zip(stored.secrets())
+            .filter(|((name, _), _)| names.contains(name))
+            .all(|((_, value), (_, on_disk))| value == on_disk)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    #[test]
+    fn a_missing_file_is_the_default() {
+        let dir = TempDir::new("config-missing");
+        let config = ConfigStore::in_dir(dir.path().to_path_buf())
+            .load_or_default()
+            .unwrap();
+        assert!(config.default_provider.is_empty());
+        assert!(config.github.token.is_none());
+    }
+
+    #[test]
+    fn missing_sections_are_filled_in() {
+        let dir = TempDir::new("config-partial");
+        let store = ConfigStore::in_dir(dir.path().to_path_buf());
+        fs::write(
+            store.path(),
+            r#"{ "default_provider": "github", "github": { "default_repo": "octo/kirei" } }"#,
+        )
+        .unwrap();
+
+        let config = store.load_or_default().unwrap();
+        assert_eq!(config.default_provider, "github");
+        assert_eq!(config.github.default_repo.as_deref(), Some("octo/kirei"));
+        assert!(config.jira.server_url.is_none());
+    }
+
+    #[test]
+    fn an_unreadable_file_is_an_error() {
+        let dir = TempDir::new("config-invalid");
+        let store = ConfigStore::in_dir(dir.path().to_path_buf());
+        for contents in [
+            r#"{ "default_provider": "#,
+            r#"{ "github": { "token": 12 } }"#,
+        ] {
+            fs::write(store.path(), contents).unwrap();
+            match store.load_or_default() {
+                Err(CoreError::ParseConfig { path, .. }) => assert_eq!(path, store.path()),
+                other => panic!("expected a parse error, got {:?}", other.map(|_| ())),
+            }
+        }
+    }
 }
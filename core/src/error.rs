@@ -1,5 +1,12 @@
+use std::fmt;
 use std::path::PathBuf;
 
+use kirei_provider_github::DeviceFlowError;
+
+use crate::oauth::{CallbackError, OAuthError};
+use crate::unified::{ProviderId, QueryError, ReferenceError, UnifiedError};
+use crate::vault::{PASSPHRASE_ENV, VaultError};
+
 #[derive(Debug, thiserror::Error)]
 pub enum CoreError {
     #[error("Could not determine the user home directory")]
@@ -38,4 +45,407 @@ pub enum CoreError {
         #[source]
         source: serde_json::Error,
     },
+
+    #[error("Credentials were not saved")]
+    SaveCredentials {
+        #[source]
+        source: VaultError,
+    },
+
+    #[error(transparent)]
+    Vault(#[from] VaultError),
+}
+
+/// The kind of failure that ended a command. Each has its own exit code so
+/// scripts can tell, say, a missing login from a rate limit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ErrorCategory {
+    /// Anything not covered below.
+    Internal,
+    /// The command line asked for something invalid.
+    Usage,
+    /// A setting is missing or wrong.
+    Config,
+    /// No credentials, or the provider rejected them.
+    Auth,
+    /// The credentials work but lack access.
+    Permission,
+    NotFound,
+    RateLimited,
+    /// The provider could not be reached in time.
+    Network,
+    /// The provider answered with something unexpected.
+    Provider,
+}
+
+impl ErrorCategory {
+    /// Process exit code. `2` matches what clap uses for bad arguments.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            ErrorCategory::Internal => 1,
+            ErrorCategory::Usage => 2,
+            ErrorCategory::Config => 3,
+            ErrorCategory::Auth => 4,
+            ErrorCategory::Permission => 5,
+            ErrorCategory::NotFound => 6,
+            ErrorCategory::RateLimited => 7,
+            ErrorCategory::Network => 8,
+            ErrorCategory::Provider => 9,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCategory::Internal => "internal",
+            ErrorCategory::Usage => "usage",
+            ErrorCategory::Config => "config",
+            ErrorCategory::Auth => "auth",
+            ErrorCategory::Permission => "permission",
+            ErrorCategory::NotFound => "not_found",
+            ErrorCategory::RateLimited => "rate_limited",
+            ErrorCategory::Network => "network",
+            ErrorCategory::Provider => "provider",
+        }
+    }
+}
+
+impl fmt::Display for ErrorCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A failure as the user sees it: what went wrong, and the command that
+/// most likely fixes it.
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    pub category: ErrorCategory,
+    pub message: String,
+    pub fix: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn new(category: ErrorCategory, message: impl Into<String>) -> Self {
+        Self {
+            category,
+            message: message.into(),
+            fix: None,
+        }
+    }
+
+    pub fn with_fix(mut self, fix: impl Into<String>) -> Self {
+        self.fix = Some(fix.into());
+        self
+    }
+
+    pub fn exit_code(&self) -> u8 {
+        self.category.exit_code()
+    }
+
+    /// Diagnoses the first error in the chain that kirei knows, or reports
+    /// the whole chain as an internal error.
+    pub fn from_error(err: &anyhow::Error) -> Self {
+        for cause in err.chain() {
+            if let Some(diagnostic) = cause.downcast_ref::<Diagnostic>() {
+                return diagnostic.clone();
+            }
+            if let Some(err) = cause.downcast_ref::<UnifiedError>() {
+                return err.into();
+            }
+            if let Some(err) = cause.downcast_ref::<CoreError>() {
+                return err.into();
+            }
+            if let Some(err) = cause.downcast_ref::<VaultError>() {
+                return err.into();
+            }
+            if let Some(err) = cause.downcast_ref::<OAuthError>() {
+                return err.into();
+            }
+            if let Some(err) = cause.downcast_ref::<CallbackError>() {
+                return err.into();
+            }
+            if let Some(err) = cause.downcast_ref::<DeviceFlowError>() {
+                return err.into();
+            }
+            if let Some(err) = cause.downcast_ref::<QueryError>() {
+                return Diagnostic::new(ErrorCategory::Usage, format!("invalid query: {}", err));
+            }
+            if let Some(err) = cause.downcast_ref::<ReferenceError>() {
+                return Diagnostic::new(ErrorCategory::Usage, err.to_string());
+            }
+            if let Some(err) = cause.downcast_ref::<reqwest::Error>() {
+                return network(err);
+            }
+        }
+        Diagnostic::new(ErrorCategory::Internal, format!("{:#}", err))
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for Diagnostic {}
+
+impl From<&UnifiedError> for Diagnostic {
+    fn from(err: &UnifiedError) -> Self {
+        use ErrorCategory::*;
+
+        let message = err.to_string();
+        match err {
+            UnifiedError::MissingToken(provider) => Diagnostic::new(Auth, format!(
+                "{} (or set {})",
+                message,
+                provider.env_var()
+            ))
+            .with_fix(auth_command(*provider)),
+            UnifiedError::MissingSetting { provider, .. } => {
+                Diagnostic::new(Config, message).with_fix(auth_command(*provider))
+            }
+            UnifiedError::MissingDefault { provider, .. } => {
+                let diagnostic = Diagnostic::new(Config, message);
+                match set_default_command(*provider) {
+                    Some(fix) => diagnostic.with_fix(fix),
+                    None => diagnostic,
+                }
+            }
+            UnifiedError::NoDefaultProvider => Diagnostic::new(Config, message)
+                .with_fix("kirei config provider <github|linear|trello|jira|mock>"),
+            UnifiedError::Unauthorized { provider, .. } if *provider == ProviderId::Jira => {
+                Diagnostic::new(
+                    Auth,
+                    format!(
+                        "{} (Jira Cloud API tokens only work with the email of the account that created them; Server and Data Center need a personal access token)",
+                        message
+                    ),
+                )
+                .with_fix("kirei jira auth --method <basic|pat|oauth>")
+            }
+            UnifiedError::Unauthorized { provider, .. } => {
+                Diagnostic::new(Auth, message).with_fix(auth_command(*provider))
+            }
+            UnifiedError::Forbidden { provider, .. } => Diagnostic::new(
+                Permission,
+                format!("{} (check the token's scopes)", message),
+            )
+            .with_fix(auth_command(*provider)),
+            UnifiedError::NotFound { provider, .. } if *provider == ProviderId::Github => {
+                // GitHub answers 404 for private repositories the token cannot see.
+                Diagnostic::new(
+                    NotFound,
+                    format!(
+                        "{} (the repository may not exist or the token may lack access to it)",
+                        message
+                    ),
+                )
+                .with_fix("kirei github repo set <owner/name>")
+            }
+            UnifiedError::NotFound { provider, .. } => {
+                let diagnostic = Diagnostic::new(NotFound, message);
+                match list_scopes_command(*provider) {
+                    Some(fix) => diagnostic.with_fix(fix),
+                    None => diagnostic,
+                }
+            }
+            UnifiedError::RateLimited { .. } => Diagnostic::new(RateLimited, message),
+            UnifiedError::Validation { .. }
+            | UnifiedError::InvalidId(..)
+            | UnifiedError::Query(_)
+            | UnifiedError::Reference(_)
+            | UnifiedError::UnsupportedQuery { .. } => Diagnostic::new(Usage, message),
+            UnifiedError::Unsupported { .. } => {
+                Diagnostic::new(Usage, message).with_fix("kirei providers")
+            }
+            UnifiedError::NotCached(provider) => Diagnostic::new(NotFound, message)
+                .with_fix(format!("kirei ls --provider {}", provider.slug())),
+            UnifiedError::Vault(err) => err.into(),
+            UnifiedError::Http(err) => network(err),
+            UnifiedError::Timeout(_) => Diagnostic::new(Network, message),
+            UnifiedError::UnexpectedResponse(_) => Diagnostic::new(Provider, message),
+            UnifiedError::Configuration(_) => Diagnostic::new(Config, message),
+            UnifiedError::NotImplemented(_) | UnifiedError::NotRegistered(_) => {
+                Diagnostic::new(Internal, message)
+            }
+        }
+    }
+}
+
+impl From<&CoreError> for Diagnostic {
+    fn from(err: &CoreError) -> Self {
+        let message = match std::error::Error::source(err) {
+            Some(source) => format!("{}: {}", err, source),
+            None => err.to_string(),
+        };
+        match err {
+            CoreError::Vault(err) => err.into(),
+            CoreError::SaveCredentials { source } => {
+                let diagnostic = Diagnostic::from(source);
+                Diagnostic {
+                    message: format!("{}: {}", err, diagnostic.message),
+                    ..diagnostic
+                }
+            }
+            CoreError::ParseConfig { .. } => {
+                Diagnostic::new(ErrorCategory::Config, message).with_fix("kirei config path")
+            }
+            _ => Diagnostic::new(ErrorCategory::Config, message),
+        }
+    }
+}
+
+impl From<&VaultError> for Diagnostic {
+    fn from(err: &VaultError) -> Self {
+        let message = match std::error::Error::source(err) {
+            Some(source) => format!("{}: {}", err, source),
+            None => err.to_string(),
+        };
+        match err {
+            VaultError::Locked => Diagnostic::new(
+                ErrorCategory::Auth,
+                format!(
+                    "{} (set {} where there is no terminal to ask)",
+                    message, PASSPHRASE_ENV
+                ),
+            )
+            .with_fix("kirei vault unlock"),
+            VaultError::NoPassphrase => Diagnostic::new(
+                ErrorCategory::Auth,
+                format!(
+                    "{} (set {} to the passphrase to encrypt it with)",
+                    message, PASSPHRASE_ENV
+                ),
+            )
+            .with_fix(format!("export {}=<passphrase>", PASSPHRASE_ENV)),
+            VaultError::WrongPassphrase => {
+                Diagnostic::new(ErrorCategory::Auth, message).with_fix("kirei vault unlock")
+            }
+            _ => Diagnostic::new(ErrorCategory::Config, message),
+        }
+    }
+}
+
+impl From<&OAuthError> for Diagnostic {
+    fn from(err: &OAuthError) -> Self {
+        let message = err.to_string();
+        match err {
+            OAuthError::Unsupported(provider) => {
+                Diagnostic::new(ErrorCategory::Usage, message).with_fix(auth_command(*provider))
+            }
+            OAuthError::Rejected { .. } => Diagnostic::new(ErrorCategory::Auth, message),
+            OAuthError::UnexpectedResponse(_) => Diagnostic::new(ErrorCategory::Provider, message),
+            OAuthError::Http(err) => network(err),
+        }
+    }
+}
+
+impl From<&DeviceFlowError> for Diagnostic {
+    fn from(err: &DeviceFlowError) -> Self {
+        let message = err.to_string();
+        match err {
+            DeviceFlowError::Expired | DeviceFlowError::Denied => {
+                Diagnostic::new(ErrorCategory::Auth, message)
+                    .with_fix("kirei github auth --method device")
+            }
+            DeviceFlowError::Rejected { .. } => Diagnostic::new(ErrorCategory::Auth, message),
+            DeviceFlowError::Disabled => Diagnostic::new(ErrorCategory::Config, message),
+            DeviceFlowError::UnexpectedResponse(_) => {
+                Diagnostic::new(ErrorCategory::Provider, message)
+            }
+            DeviceFlowError::Http(err) => network(err),
+        }
+    }
+}
+
+impl From<&CallbackError> for Diagnostic {
+    fn from(err: &CallbackError) -> Self {
+        let category = match err {
+            CallbackError::Bind { .. } => ErrorCategory::Network,
+            _ => ErrorCategory::Auth,
+        };
+        Diagnostic::new(category, err.to_string())
+    }
+}
+
+fn network(err: &reqwest::Error) -> Diagnostic {
+    if !err.is_connect() {
+        return Diagnostic::new(ErrorCategory::Network, err.to_string());
+    }
+    // The useful part, e.g. "connection refused", sits at the bottom.
+    let mut source: &dyn std::error::Error = err;
+    while let Some(next) = source.source() {
+        source = next;
+    }
+    Diagnostic::new(
+        ErrorCategory::Network,
+        format!("could not connect: {}", source),
+    )
+    .with_fix("kirei config http --proxy <url>")
+}
+
+fn auth_command(provider: ProviderId) -> String {
+    match provider {
+        // The mock provider takes no credentials; its auth errors are injected.
+        ProviderId::Mock => "kirei config mock --reset".to_string(),
+        _ => format!("kirei {} auth", provider.slug()),
+    }
+}
+
+fn set_default_command(provider: ProviderId) -> Option<&'static str> {
+    match provider {
+        ProviderId::Github => Some("kirei github repo set <owner/name>"),
+        ProviderId::Linear => Some("kirei linear workspace set <workspace>"),
+        ProviderId::Trello => Some("kirei trello board set <board>"),
+        ProviderId::Jira => Some("kirei jira project set <project-key>"),
+        ProviderId::Mock => None,
+    }
+}
+
+fn list_scopes_command(provider: ProviderId) -> Option<&'static str> {
+    match provider {
+        ProviderId::Github => Some("kirei github repos"),
+        ProviderId::Linear => Some("kirei linear workspaces"),
+        ProviderId::Trello => Some("kirei trello boards"),
+        ProviderId::Jira => Some("kirei jira projects"),
+        ProviderId::Mock => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diagnose(err: impl std::error::Error + Send + Sync + 'static) -> Diagnostic {
+        Diagnostic::from_error(&anyhow::Error::new(err).context("GitHub sign-in failed"))
+    }
+
+    #[test]
+    fn device_flow_failures_are_categorized() {
+        assert_eq!(diagnose(DeviceFlowError::Expired).exit_code(), 4);
+        assert_eq!(diagnose(DeviceFlowError::Denied).exit_code(), 4);
+        assert_eq!(
+            diagnose(DeviceFlowError::Rejected {
+                error: "incorrect_client_credentials".into(),
+                description: String::new(),
+            })
+            .exit_code(),
+            4
+        );
+        assert_eq!(diagnose(DeviceFlowError::Disabled).exit_code(), 3);
+        assert_eq!(
+            diagnose(DeviceFlowError::UnexpectedResponse("{}".into())).exit_code(),
+            9
+        );
+    }
+
+    #[test]
+    fn vault_refusals_name_the_passphrase_variable() {
+        let diagnostic = diagnose(CoreError::SaveCredentials {
+            source: VaultError::NoPassphrase,
+        });
+        assert_eq!(diagnostic.category, ErrorCategory::Auth);
+        assert!(diagnostic.message.starts_with("Credentials were not saved"));
+        assert!(diagnostic.message.contains(PASSPHRASE_ENV));
+    }
 }
@@ -1,4 +1,14 @@
 pub mod config;
 pub mod error;
+pub mod oauth;
+pub mod unified;
+pub mod vault;
+
+#[cfg(test)]
+mod test_support;
+
+/// The HTTP client every provider sends its requests through.
+pub use kirei_http as http;
 
 pub use config::{Config, ConfigStore, GitHubConfig, JiraConfig, LinearConfig, TrelloConfig};
+pub use unified::{ProviderClient, ProviderId, ProviderRegistry};
@@ -0,0 +1,285 @@
+//! The local server an OAuth redirect lands on.
+//!
+//! Only `/callback` counts, and only with the `state` the flow was started
+//! with: a favicon fetch, a stray tab or a forged redirect gets an error page
+//! and the server keeps waiting. A redirect carrying `error` ends the flow
+//! with that error instead of a code.
+
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use thiserror::Error;
+use tokio::sync::oneshot;
+use url::Url;
+
+pub const CALLBACK_PATH: &str = "/callback";
+
+#[derive(Error, Debug)]
+pub enum CallbackError {
+    #[error("failed to start the callback server on port {port}: {message}")]
+    Bind { port: u16, message: String },
+    #[error("authorization timed out after {}s", .0.as_secs())]
+    Timeout(Duration),
+    #[error("authorization cancelled")]
+    Cancelled,
+    #[error("authorization failed: {error}{}", description_suffix(.description))]
+    Denied {
+        error: String,
+        description: Option<String>,
+    },
+}
+
+pub(super) fn description_suffix(description: &Option<String>) -> String {
+    description
+        .as_deref()
+        .map(|description| format!(" ({})", description))
+        .unwrap_or_default()
+}
+
+type Outcome = Result<String, CallbackError>;
+
+/// Listens on 127.0.0.1 for the redirect of one authorization. Stops when
+/// dropped.
+pub struct CallbackServer {
+    server: Arc<tiny_http::Server>,
+    port: u16,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl CallbackServer {
+    /// Binds `port`, or any free port for `0`.
+    pub fn bind(port: u16) -> Result<Self, CallbackError> {
+        let bind_error = |message: String| CallbackError::Bind { port, message };
+        let server = tiny_http::Server::http(("127.0.0.1", port))
+            .map(Arc::new)
+            .map_err(|err| bind_error(err.to_string()))?;
+        let port = server
+            .server_addr()
+            .to_ip()
+            .map(|addr| addr.port())
+            .ok_or_else(|| bind_error("not a TCP address".into()))?;
+        Ok(Self {
+            server,
+            port,
+            thread: None,
+        })
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// The `redirect_uri` to start the authorization with. OAuth apps are
+    /// registered with `localhost`, which browsers also try on 127.0.0.1.
+    pub fn redirect_uri(&self) -> String {
+        format!("http://localhost:{}{}", self.port, CALLBACK_PATH)
+    }
+
+    /// Waits for the code of the authorization started with `state`, giving
+    /// up after `timeout` or on Ctrl-C.
+    pub async fn wait(mut self, state: &str, timeout: Duration) -> Result<String, CallbackError> {
+        let (sender, outcome) = oneshot::channel();
+        let server = Arc::clone(&self.server);
+        let state = state.to_string();
+        self.thread = Some(std::thread::spawn(move || {
+            for request in server.incoming_requests() {
+                let (status, page, outcome) = answer(request.url(), &state);
+                let _ = request.respond(html(status, &page));
+                if let Some(outcome) = outcome {
+                    let _ = sender.send(outcome);
+                    break;
+                }
+            }
+        }));
+
+        tokio::select! {
+            outcome = outcome => outcome.unwrap_or(Err(CallbackError::Cancelled)),
+            _ = tokio::time::sleep(timeout) => Err(CallbackError::Timeout(timeout)),
+            _ = tokio::signal::ctrl_c() => Err(CallbackError::Cancelled),
+        }
+    }
+}
+
+impl Drop for CallbackServer {
+    fn drop(&mut self) {
+        self.server.unblock();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// The response to a request, and how it ends the flow, if it does.
+fn answer(url: &str, state: &str) -> (u16, String, Option<Outcome>) {
+    let Ok(url) = Url::parse(&format!("http://127.0.0.1{}", url)) else {
+        return (
+            400,
+            page("Bad request", "The request could not be read."),
+            None,
+        );
+    };
+    if url.path() != CALLBACK_PATH {
+        return (
+            404,
+            page(
+                "Not found",
+                "This server only accepts the sign-in redirect.",
+            ),
+            None,
+        );
+    }
+
+    let param = |name: &str| {
+        url.query_pairs()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.into_owned())
+    };
+    if param("state").as_deref() != Some(state) {
+        return (
+            400,
+            page(
+                "Sign-in not recognized",
+                "This redirect does not belong to the sign-in started from the terminal, so it was ignored.",
+            ),
+            None,
+        );
+    }
+
+    if let Some(error) = param("error") {
+        let description = param("error_description");
+        let detail = description.clone().unwrap_or_else(|| error.clone());
+        return (
+            400,
+            page(
+                "Sign-in failed",
+                &format!("The provider reported: {}", detail),
+            ),
+            Some(Err(CallbackError::Denied { error, description })),
+        );
+    }
+    match param("code") {
+        Some(code) if !code.is_empty() => (
+            200,
+            page(
+                "Signed in",
+                "You can close this window and return to the terminal.",
+            ),
+            Some(Ok(code)),
+        ),
+        _ => (
+            400,
+            page(
+                "Sign-in failed",
+                "The redirect carried no authorization code.",
+            ),
+            None,
+        ),
+    }
+}
+
+fn page(title: &str, message: &str) -> String {
+    format!(
+        "<!doctype html><html><head><meta charset=\"utf-8\"><title>kirei: {title}</title></head>\
+         <body style=\"font-family: sans-serif; margin: 4em auto; max-width: 36em\">\
+         <h1>{title}</h1><p>{message}</p></body></html>",
+        title = escape(title),
+        message = escape(message),
+    )
+}
+
+fn html(status: u16, body: &str) -> tiny_http::Response<std::io::Cursor<Vec<u8>>> {
+    tiny_http::Response::from_string(body)
+        .with_status_code(status)
+        .with_header(
+            tiny_http::Header::from_bytes(&b"Content-Type"[..], &b"text/html; charset=utf-8"[..])
+                .expect("static header is valid"),
+        )
+}
+
+/// Provider error descriptions end up in the page, so they are escaped.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STATE: &str = "s3cr3t";
+
+    #[test]
+    fn the_code_ends_the_flow() {
+        let (status, _, outcome) = answer("/callback?code=abc&state=s3cr3t", STATE);
+        assert_eq!(status, 200);
+        assert!(matches!(outcome, Some(Ok(code)) if code == "abc"));
+    }
+
+    #[test]
+    fn a_wrong_or_missing_state_is_ignored() {
+        for url in ["/callback?code=abc&state=forged", "/callback?code=abc"] {
+            let (status, page, outcome) = answer(url, STATE);
+            assert_eq!(status, 400, "{}", url);
+            assert!(page.contains("Sign-in not recognized"), "{}", url);
+            assert!(outcome.is_none(), "{}", url);
+        }
+    }
+
+    #[test]
+    fn other_paths_get_a_404_and_the_server_keeps_waiting() {
+        let (status, _, outcome) = answer("/favicon.ico", STATE);
+        assert_eq!(status, 404);
+        assert!(outcome.is_none());
+    }
+
+    #[tokio::test]
+    async fn a_favicon_fetch_does_not_end_the_flow() {
+        let server = CallbackServer::bind(0).unwrap();
+        let base = format!("http://127.0.0.1:{}", server.port());
+        let flow = tokio::spawn(async move { server.wait(STATE, Duration::from_secs(5)).await });
+
+        let http = reqwest::Client::new();
+        let favicon = http
+            .get(format!("{}/favicon.ico", base))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(favicon.status(), 404);
+        let callback = http
+            .get(format!("{}/callback?code=abc&state={}", base, STATE))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(callback.status(), 200);
+
+        assert_eq!(flow.await.unwrap().unwrap(), "abc");
+    }
+
+    #[test]
+    fn an_error_redirect_ends_the_flow_denied() {
+        let url = "/callback?error=access_denied&error_description=User+said+%3Cno%3E&state=s3cr3t";
+        let (status, page, outcome) = answer(url, STATE);
+        assert_eq!(status, 400);
+        assert!(page.contains("User said &lt;no&gt;"), "{}", page);
+        match outcome {
+            Some(Err(CallbackError::Denied { error, description })) => {
+                assert_eq!(error, "access_denied");
+                assert_eq!(description.as_deref(), Some("User said <no>"));
+            }
+            other => panic!("expected a denial, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn an_empty_code_is_rejected() {
+        for url in ["/callback?code=&state=s3cr3t", "/callback?state=s3cr3t"] {
+            let (status, page, outcome) = answer(url, STATE);
+            assert_eq!(status, 400, "{}", url);
+            assert!(page.contains("no authorization code"), "{}", url);
+            assert!(outcome.is_none(), "{}", url);
+        }
+    }
+}
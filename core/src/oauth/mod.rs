@@ -0,0 +1,398 @@
+//! Signing in with the OAuth 2.0 authorization code flow.
+//!
+//! An [`OAuthApp`] describes one provider's authorization server: endpoints,
+//! scopes and the quirks of its token endpoint. [`OAuthClient`] runs the flow
+//! for an app registered there, and [`CallbackServer`] catches the redirect
+//! back to this machine. GitHub, Linear and Atlassian (Jira Cloud 3LO) have
+//! presets; Trello only offers OAuth 1.0a and keeps using tokens.
+
+pub mod callback;
+
+pub use callback::{CallbackError, CallbackServer};
+
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use kirei_http::{HttpClient, RequestBuilderExt};
+use kirei_provider_github::GitHubHost;
+use rand::RngCore;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use url::Url;
+
+use crate::config::Config;
+use crate::unified::ProviderId;
+
+const LINEAR_AUTHORIZE_URL: &str = "https://linear.app/oauth/authorize";
+const LINEAR_API: &str = "https://api.linear.app";
+const ATLASSIAN_AUTHORIZE_URL: &str = "https://auth.atlassian.com/authorize";
+const ATLASSIAN_TOKEN_URL: &str = "https://auth.atlassian.com/oauth/token";
+const ATLASSIAN_RESOURCES_URL: &str = "https://api.atlassian.com/oauth/token/accessible-resources";
+const ATLASSIAN_JIRA_API: &str = "https://api.atlassian.com/ex/jira";
+
+/// Callback port for apps that only accept the exact registered callback
+/// URL, i.e. `http://localhost:8976/callback`.
+pub const DEFAULT_CALLBACK_PORT: u16 = 8976;
+
+#[derive(Debug, thiserror::Error)]
+pub enum OAuthError {
+    #[error("{0} does not offer OAuth sign-in")]
+    Unsupported(ProviderId),
+    #[error("the authorization server refused the code: {error}{}", callback::description_suffix(.description))]
+    Rejected {
+        error: String,
+        description: Option<String>,
+    },
+    #[error("token response is malformed: {0}")]
+    UnexpectedResponse(String),
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+}
+
+/// How the token endpoint wants its parameters.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TokenBody {
+    /// `application/x-www-form-urlencoded`, as RFC 6749 specifies.
+    #[default]
+    Form,
+    Json,
+}
+
+/// Reads the token out of a token endpoint response, which has been turned
+/// into a JSON object whether it came as JSON or as a form. `None` if there
+/// is no token in it.
+pub type TokenParser = fn(&Value) -> Option<OAuthToken>;
+
+#[derive(Clone, Debug)]
+pub struct OAuthToken {
+    pub access_token: String,
+    pub token_type: Option<String>,
+    pub refresh_token: Option<String>,
+    /// Seconds until `access_token` expires.
+    pub expires_in: Option<u64>,
+    /// The scopes granted, which may be fewer than the ones asked for.
+    pub scope: Option<String>,
+}
+
+impl OAuthToken {
+    /// The standard RFC 6749 response fields.
+    pub fn parse(body: &Value) -> Option<Self> {
+        let field = |name: &str| body[name].as_str().map(str::to_string);
+        Some(Self {
+            access_token: field("access_token").filter(|token| !token.is_empty())?,
+            token_type: field("token_type"),
+            refresh_token: field("refresh_token"),
+            // A number in JSON, a string in a form.
+            expires_in: body["expires_in"]
+                .as_u64()
+                .or_else(|| body["expires_in"].as_str()?.parse().ok()),
+            scope: field("scope"),
+        })
+    }
+}
+
+/// A provider's authorization server and how to talk to it.
+#[derive(Clone, Debug)]
+pub struct OAuthApp {
+    authorize_url: String,
+    token_url: String,
+    scopes: Vec<String>,
+    scope_separator: &'static str,
+    params: Vec<(String, String)>,
+    token_body: TokenBody,
+    token_parser: TokenParser,
+    pkce: bool,
+    callback_port: u16,
+}
+
+impl OAuthApp {
+    /// An app with no scopes that sends a form to `token_url`, uses PKCE and
+    /// takes the redirect on any free port.
+    pub fn new(authorize_url: impl Into<String>, token_url: impl Into<String>) -> Self {
+        Self {
+            authorize_url: authorize_url.into(),
+            token_url: token_url.into(),
+            scopes: Vec::new(),
+            scope_separator: " ",
+            params: Vec::new(),
+            token_body: TokenBody::Form,
+            token_parser: OAuthToken::parse,
+            pkce: true,
+            callback_port: 0,
+        }
+    }
+
+    pub fn with_scopes(mut self, scopes: &[&str]) -> Self {
+        self.scopes = scopes.iter().map(|scope| scope.to_string()).collect();
+        self
+    }
+
+    /// Joins the scopes in the authorization URL; a space unless the provider
+    /// wants something else.
+    pub fn with_scope_separator(mut self, separator: &'static str) -> Self {
+        self.scope_separator = separator;
+        self
+    }
+
+    /// Adds a query parameter to the authorization URL.
+    pub fn with_param(mut self, name: &str, value: &str) -> Self {
+        self.params.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn with_token_body(mut self, body: TokenBody) -> Self {
+        self.token_body = body;
+        self
+    }
+
+    pub fn with_token_parser(mut self, parser: TokenParser) -> Self {
+        self.token_parser = parser;
+        self
+    }
+
+    /// Whether to protect the code with PKCE (S256).
+    pub fn with_pkce(mut self, pkce: bool) -> Self {
+        self.pkce = pkce;
+        self
+    }
+
+    /// The port the app's callback URL is registered with; `0` for any.
+    pub fn with_callback_port(mut self, port: u16) -> Self {
+        self.callback_port = port;
+        self
+    }
+
+    pub fn callback_port(&self) -> u16 {
+        self.callback_port
+    }
+
+    /// github.com or a GitHub Enterprise Server. GitHub accepts a loopback
+    /// redirect on any port.
+    pub fn github(host: &GitHubHost) -> Self {
+        Self::new(host.authorize_url(), host.token_url()).with_scopes(&["repo"])
+    }
+
+    /// `base_url` replaces `https://api.linear.app` for the token endpoint.
+    pub fn linear(base_url: Option<&str>) -> Self {
+        let base_url = base_url.unwrap_or(LINEAR_API).trim_end_matches('/');
+        Self::new(LINEAR_AUTHORIZE_URL, format!("{}/oauth/token", base_url))
+            .with_scopes(&["read", "write"])
+            .with_scope_separator(",")
+            .with_param("prompt", "consent")
+            .with_callback_port(DEFAULT_CALLBACK_PORT)
+    }
+
+    /// Atlassian's 3LO apps, for Jira Cloud. Their tokens work against
+    /// [`AtlassianSite::api_url`], not the site itself.
+    pub fn atlassian() -> Self {
+        Self::new(ATLASSIAN_AUTHORIZE_URL, ATLASSIAN_TOKEN_URL)
+            .with_scopes(&["read:jira-work", "write:jira-work", "read:jira-user"])
+            .with_param("audience", "api.atlassian.com")
+            .with_param("prompt", "consent")
+            .with_token_body(TokenBody::Json)
+            .with_pkce(false)
+            .with_callback_port(DEFAULT_CALLBACK_PORT)
+    }
+
+    /// The preset for `provider`, pointed at the servers `config` names.
+    pub fn for_provider(provider: ProviderId, config: &Config) -> Result<Self, OAuthError> {
+        match provider {
+            ProviderId::Github => {
+                let host = config
+                    .github
+                    .base_url
+                    .as_deref()
+                    .map(GitHubHost::from_base_url)
+                    .unwrap_or_default();
+                Ok(Self::github(&host))
+            }
+            ProviderId::Linear => Ok(Self::linear(config.linear.base_url.as_deref())),
+            ProviderId::Jira => Ok(Self::atlassian()),
+            ProviderId::Trello | ProviderId::Mock => Err(OAuthError::Unsupported(provider)),
+        }
+    }
+}
+
+/// One authorization in progress: the URL to send the user to, and what the
+/// redirect and the token exchange are checked against.
+#[derive(Clone, Debug)]
+pub struct AuthorizationRequest {
+    pub url: String,
+    /// Echoed back by the redirect; anything else is not our redirect.
+    pub state: String,
+    pub redirect_uri: String,
+    /// PKCE secret whose hash went out with the authorization.
+    code_verifier: Option<String>,
+}
+
+/// Runs the flow for the app registered as `client_id`.
+pub struct OAuthClient {
+    http: HttpClient,
+    app: OAuthApp,
+    client_id: String,
+    client_secret: Option<String>,
+}
+
+impl OAuthClient {
+    pub fn new(app: OAuthApp, client_id: String) -> Self {
+        Self {
+            http: HttpClient::shared(),
+            app,
+            client_id,
+            client_secret: None,
+        }
+    }
+
+    /// Confidential apps authenticate the exchange with their secret; public
+    /// ones rely on PKCE alone.
+    pub fn with_client_secret(mut self, client_secret: Option<String>) -> Self {
+        self.client_secret = client_secret;
+        self
+    }
+
+    /// Exchanges the code through `http` instead of the shared client.
+    pub fn with_http(mut self, http: HttpClient) -> Self {
+        self.http = http;
+        self
+    }
+
+    pub fn app(&self) -> &OAuthApp {
+        &self.app
+    }
+
+    /// Starts an authorization that redirects to `redirect_uri`.
+    pub fn authorization_request(&self, redirect_uri: &str) -> AuthorizationRequest {
+        let state = random_token();
+        let code_verifier = self.app.pkce.then(random_token);
+
+        let mut url = Url::parse(&self.app.authorize_url).expect("authorize URLs are valid");
+        {
+            let mut query = url.query_pairs_mut();
+            query
+                .append_pair("response_type", "code")
+                .append_pair("client_id", &self.client_id)
+                .append_pair("redirect_uri", redirect_uri)
+                .append_pair("state", &state);
+            if !self.app.scopes.is_empty() {
+                query.append_pair("scope", &self.app.scopes.join(self.app.scope_separator));
+            }
+            for (name, value) in &self.app.params {
+                query.append_pair(name, value);
+            }
+            if let Some(verifier) = &code_verifier {
+                query
+                    .append_pair("code_challenge", &pkce_challenge(verifier))
+                    .append_pair("code_challenge_method", "S256");
+            }
+        }
+
+        AuthorizationRequest {
+            url: url.to_string(),
+            state,
+            redirect_uri: redirect_uri.to_string(),
+            code_verifier,
+        }
+    }
+
+    pub async fn exchange_code(
+        &self,
+        code: &str,
+        request: &AuthorizationRequest,
+    ) -> Result<OAuthToken, OAuthError> {
+        let mut params = vec![
+            ("grant_type", "authorization_code"),
+            ("client_id", self.client_id.as_str()),
+            ("code", code),
+            ("redirect_uri", request.redirect_uri.as_str()),
+        ];
+        if let Some(secret) = &self.client_secret {
+            params.push(("client_secret", secret));
+        }
+        if let Some(verifier) = &request.code_verifier {
+            params.push(("code_verifier", verifier));
+        }
+
+        let builder = self
+            .http
+            .post(&self.app.token_url)
+            .header("Accept", "application/json");
+        let builder = match self.app.token_body {
+            TokenBody::Form => builder.form(&params),
+            TokenBody::Json => builder.json(
+                &params
+                    .into_iter()
+                    .map(|(name, value)| (name.to_string(), Value::from(value)))
+                    .collect::<Map<_, _>>(),
+            ),
+        };
+        let response = builder.send_with(&self.http).await?;
+
+        let status = response.status();
+        let body = parse_body(&response.text().await?);
+        // GitHub reports errors with a 200.
+        if let Some(error) = body["error"].as_str() {
+            return Err(OAuthError::Rejected {
+                error: error.to_string(),
+                description: body["error_description"].as_str().map(str::to_string),
+            });
+        }
+        if !status.is_success() {
+            return Err(OAuthError::UnexpectedResponse(format!("HTTP {}", status)));
+        }
+        (self.app.token_parser)(&body)
+            .ok_or_else(|| OAuthError::UnexpectedResponse("no access token in it".into()))
+    }
+}
+
+/// A JSON object, or a form turned into one.
+fn parse_body(text: &str) -> Value {
+    match serde_json::from_str::<Value>(text) {
+        Ok(body) if body.is_object() => body,
+        _ => Value::Object(
+            url::form_urlencoded::parse(text.as_bytes())
+                .map(|(name, value)| (name.into_owned(), Value::String(value.into_owned())))
+                .collect(),
+        ),
+    }
+}
+
+/// 32 random bytes, URL-safe: a `state` or a PKCE code verifier.
+fn random_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+fn pkce_challenge(verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()))
+}
+
+/// A Jira Cloud site a 3LO token was granted access to.
+#[derive(Clone, Debug, Deserialize)]
+pub struct AtlassianSite {
+    /// The cloud ID.
+    pub id: String,
+    /// The site, e.g. `https://company.atlassian.net`.
+    pub url: String,
+    pub name: String,
+}
+
+impl AtlassianSite {
+    /// Where the site's REST API is reached with a 3LO token.
+    pub fn api_url(&self) -> String {
+        format!("{}/{}", ATLASSIAN_JIRA_API, self.id)
+    }
+
+    /// The sites `token` can reach.
+    pub async fn list(http: &HttpClient, token: &str) -> Result<Vec<Self>, OAuthError> {
+        let response = http
+            .get(ATLASSIAN_RESOURCES_URL)
+            .bearer_auth(token)
+            .header("Accept", "application/json")
+            .send_with(http)
+            .await?
+            .error_for_status()?;
+        let body: Value = response.json().await?;
+        serde_json::from_value(body).map_err(|err| OAuthError::UnexpectedResponse(err.to_string()))
+    }
+}
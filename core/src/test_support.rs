@@ -0,0 +1,30 @@
+//! Helpers shared by the unit tests.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use rand::RngCore;
+
+/// A fresh directory under the system temp dir, removed on drop.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        let mut suffix = [0u8; 8];
+        rand::thread_rng().fill_bytes(&mut suffix);
+        let dir =
+            std::env::temp_dir().join(format!("kirei-{}-{}", name, u64::from_le_bytes(suffix)));
+        fs::create_dir_all(&dir).unwrap();
+        Self(dir)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
@@ -0,0 +1,432 @@
+//! Snapshots of issue listings kept on disk, so a listing can be brought up to
+//! date by fetching only what changed, and shown with `--offline` when the
+//! providers cannot be reached.
+//!
+//! A snapshot is stored per provider, target (repository, workspace, board or
+//! project) and query, whatever the limit: it is cut to the limit when read,
+//! and refreshed at the longest limit it was asked for. How it is refreshed is up to the provider: GitHub
+//! revalidates each page with its ETag, Linear and Jira ask for the issues
+//! updated since the snapshot was taken, and Trello lists everything again.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+use chrono::{DateTime, Duration, Utc};
+use kirei_http::fnv1a;
+use serde::{Deserialize, Serialize};
+
+use crate::unified::query::{Clause, DateBound, Query, StateFilter};
+use crate::unified::{ProviderClient, ProviderId, UnifiedError, UnifiedIssue, UnifiedListQuery};
+
+/// Margin for clocks that disagree and for APIs that compare at minute
+/// precision when asking for issues updated since the last sync.
+const SYNC_OVERLAP: Duration = Duration::minutes(5);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub fetched_at: DateTime<Utc>,
+    pub issues: Vec<UnifiedIssue>,
+    /// The limit the issues were listed with; `None` when they are every
+    /// matching issue.
+    #[serde(default)]
+    pub limit: Option<usize>,
+    #[serde(default)]
+    pub sync: SyncState,
+}
+
+/// What a provider needs to refresh a snapshot without listing everything.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SyncState {
+    /// Refreshed by listing again.
+    #[default]
+    None,
+    /// The pages the issues came from, to revalidate with `If-None-Match`.
+    Pages { pages: Vec<CachedPage> },
+    /// The snapshot holds every matching issue as of `since`.
+    UpdatedSince { since: DateTime<Utc> },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedPage {
+    pub url: String,
+    pub etag: Option<String>,
+    /// Ids of the issues on the page, in order.
+    pub ids: Vec<String>,
+    pub next: Option<String>,
+}
+
+impl Snapshot {
+    pub fn new(issues: Vec<UnifiedIssue>, sync: SyncState) -> Self {
+        Self {
+            fetched_at: Utc::now(),
+            issues,
+            limit: None,
+            sync,
+        }
+    }
+
+    pub fn age(&self) -> Duration {
+        Utc::now() - self.fetched_at
+    }
+
+    /// The first `limit` issues, or all of them for `None`.
+    pub fn limited(mut self, limit: Option<usize>) -> Self {
+        if let Some(limit) = limit {
+            self.issues.truncate(limit);
+        }
+        self.limit = limit;
+        self
+    }
+
+    /// Whether the snapshot holds the first `limit` matching issues, so a
+    /// refresh can start from it.
+    pub fn covers(&self, limit: Option<usize>) -> bool {
+        match (self.limit, limit) {
+            (None, _) => true,
+            // Fewer issues than the limit allowed are all there are.
+            (Some(listed), _) if self.issues.len() < listed => true,
+            (Some(listed), Some(limit)) => listed >= limit,
+            (Some(_), None) => false,
+        }
+    }
+}
+
+/// A directory of snapshots, one JSON file each.
+#[derive(Debug, Clone)]
+pub struct IssueCache {
+    dir: PathBuf,
+}
+
+impl IssueCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// A snapshot that cannot be read is treated as missing.
+    pub fn load(&self, key: &CacheKey) -> Option<Snapshot> {
+        let bytes = fs::read(self.path(key)).ok()?;
+        serde_json::from_slice(&bytes).ok()
+    }
+
+    pub fn save(&self, key: &CacheKey, snapshot: &Snapshot) -> Result<PathBuf, anyhow::Error> {
+        fs::create_dir_all(&self.dir)?;
+        let path = self.path(key);
+        fs::write(&path, serde_json::to_vec(snapshot)?)?;
+        Ok(path)
+    }
+
+    fn path(&self, key: &CacheKey) -> PathBuf {
+        self.dir
+            .join(format!("{}-{:016x}.json", key.provider.slug(), key.hash))
+    }
+}
+
+/// Identifies the listing a snapshot belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheKey {
+    provider: ProviderId,
+    hash: u64,
+}
+
+impl CacheKey {
+    /// `target` names where the issues live, including the server, so that
+    /// changing the default repository or project starts a new snapshot.
+    pub fn new(provider: ProviderId, target: &str, query: &UnifiedListQuery) -> Self {
+        let key = format!(
+            "{}\n{:?}\n{:?}\n{:?}\n{:?}\n{}",
+            target,
+            query.workspace,
+            query.repo,
+            query.board,
+            query.project,
+            query_key(&query.search)
+        );
+        Self {
+            provider,
+            hash: fnv1a(key.as_bytes()),
+        }
+    }
+}
+
+/// The query with date bounds reduced to the day. `updated:>7d` is resolved
+/// to a timestamp when parsed, and keying on that would never hit; a bound
+/// that moves forward only narrows the issues, which a refresh handles.
+fn query_key(query: &Query) -> String {
+    let day = |bound: &DateBound| match bound {
+        DateBound::After(at) => format!(">{}", at.format("%Y-%m-%d")),
+        DateBound::Before(at) => format!("<{}", at.format("%Y-%m-%d")),
+    };
+    query
+        .clauses
+        .iter()
+        .map(|clause| match clause {
+            Clause::Updated(bound) => format!("updated:{}", day(bound)),
+            Clause::Created(bound) => format!("created:{}", day(bound)),
+            clause => format!("{:?}", clause),
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Refreshes `cached` for providers that filter on the update time: only the
+/// issues updated since the last sync are fetched, whatever their state, and
+/// merged in. A full listing is done instead when there is no cursor yet, when
+/// the query has clauses only the provider can evaluate, or when issues
+/// leaving the listing would leave it short of `query.limit`.
+pub(crate) async fn refresh_since<C>(
+    client: &C,
+    query: &UnifiedListQuery,
+    cached: Option<Snapshot>,
+) -> Result<Snapshot, UnifiedError>
+where
+    C: ProviderClient + ?Sized,
+{
+    let started = Utc::now();
+    let local = query.search.is_local();
+    let cursor = cached
+        .filter(|_| local)
+        .and_then(|snapshot| match snapshot.sync {
+            SyncState::UpdatedSince { since } => Some((since, snapshot.issues)),
+            _ => None,
+        });
+    let Some((since, cached_issues)) = cursor else {
+        return list_since(client, query, started).await;
+    };
+
+    let changes = UnifiedListQuery {
+        search: Query {
+            clauses: vec![
+                Clause::State(StateFilter::All),
+                Clause::Updated(DateBound::After(since - SYNC_OVERLAP)),
+            ],
+        },
+        limit: None,
+        ..query.clone()
+    };
+    let changed = client.list(changes).await?;
+
+    let was_full = query
+        .limit
+        .is_some_and(|limit| cached_issues.len() >= limit);
+    // New issues go first, as they would in a listing by creation date;
+    // changed ones keep their place.
+    let mut positions: HashMap<String, usize> = cached_issues
+        .iter()
+        .enumerate()
+        .map(|(position, issue)| (issue.id.clone(), position))
+        .collect();
+    let mut issues = cached_issues;
+    let mut added = Vec::new();
+    for issue in changed {
+        match positions.remove(&issue.id) {
+            Some(position) => issues[position] = issue,
+            None => added.push(issue),
+        }
+    }
+    added.append(&mut issues);
+    let mut issues = added;
+    issues.retain(|issue| query.search.matches(issue));
+
+    if let Some(limit) = query.limit {
+        // Issues beyond the limit were never fetched, so they cannot move up.
+        if was_full && issues.len() < limit {
+            return list_since(client, query, started).await;
+        }
+    }
+    // The order every provider lists in. The sort is stable, so issues
+    // without a creation date stay where they were.
+    issues.sort_by_key(|issue| std::cmp::Reverse(issue.created_at));
+    Ok(Snapshot::new(issues, SyncState::UpdatedSince { since: started }).limited(query.limit))
+}
+
+async fn list_since<C>(
+    client: &C,
+    query: &UnifiedListQuery,
+    started: DateTime<Utc>,
+) -> Result<Snapshot, UnifiedError>
+where
+    C: ProviderClient + ?Sized,
+{
+    let issues = client.list(query.clone()).await?;
+    Ok(Snapshot::new(issues, updated_since(query, started)))
+}
+
+/// The [`SyncState`] of a whole listing started at `started`, for providers
+/// refreshed through [`refresh_since`].
+pub(crate) fn updated_since(query: &UnifiedListQuery, started: DateTime<Utc>) -> SyncState {
+    if query.search.is_local() {
+        SyncState::UpdatedSince { since: started }
+    } else {
+        SyncState::None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::unified::mock::MockClient;
+    use crate::unified::{UnifiedCreateParams, UnifiedUpdateParams};
+
+    /// Marks a cached issue so the test can tell whether it was fetched again.
+    const STALE: &str = "only in the snapshot";
+
+    fn limited(limit: usize) -> UnifiedListQuery {
+        UnifiedListQuery {
+            limit: Some(limit),
+            ..Default::default()
+        }
+    }
+
+    async fn snapshot(client: &MockClient, query: &UnifiedListQuery) -> Snapshot {
+        let mut snapshot = refresh_since(client, query, None).await.unwrap();
+        assert!(matches!(snapshot.sync, SyncState::UpdatedSince { .. }));
+        snapshot.issues.last_mut().unwrap().title = STALE.into();
+        snapshot
+    }
+
+    fn ids(snapshot: &Snapshot) -> Vec<&str> {
+        snapshot
+            .issues
+            .iter()
+            .map(|issue| issue.id.as_str())
+            .collect()
+    }
+
+    fn title<'a>(snapshot: &'a Snapshot, id: &str) -> Option<&'a str> {
+        snapshot
+            .issues
+            .iter()
+            .find(|issue| issue.id == id)
+            .map(|issue| issue.title.as_str())
+    }
+
+    #[tokio::test]
+    async fn changed_closed_and_new_issues_are_merged() {
+        let client = MockClient::new();
+        let query = UnifiedListQuery::default();
+        let cached = snapshot(&client, &query).await;
+        let (changed, closed) = (cached.issues[3].id.clone(), cached.issues[5].id.clone());
+
+        client
+            .update(
+                &changed,
+                UnifiedUpdateParams {
+                    title: Some("Renamed".into()),
+                    body: None,
+                },
+            )
+            .await
+            .unwrap();
+        client.close(&closed).await.unwrap();
+        let created = client
+            .create(UnifiedCreateParams {
+                title: "Brand new".into(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let refreshed = refresh_since(&client, &query, Some(cached.clone()))
+            .await
+            .unwrap();
+        assert_eq!(refreshed.issues.len(), cached.issues.len());
+        assert_eq!(refreshed.issues[0].id, created.id);
+        assert_eq!(title(&refreshed, &changed), Some("Renamed"));
+        assert!(title(&refreshed, &closed).is_none());
+        // Issues that did not change are not fetched again.
+        assert_eq!(refreshed.issues.last().unwrap().title, STALE);
+    }
+
+    #[tokio::test]
+    async fn merged_listings_are_cut_to_the_limit() {
+        let client = MockClient::new();
+        let query = limited(5);
+        let cached = snapshot(&client, &query).await;
+        let created = client
+            .create(UnifiedCreateParams {
+                title: "Brand new".into(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let refreshed = refresh_since(&client, &query, Some(cached.clone()))
+            .await
+            .unwrap();
+        assert_eq!(ids(&refreshed)[0], created.id);
+        assert_eq!(ids(&refreshed)[1..], ids(&cached)[..4]);
+        assert_eq!(refreshed.limit, Some(5));
+    }
+
+    #[tokio::test]
+    async fn listings_left_short_of_the_limit_are_listed_again() {
+        let client = MockClient::new();
+        let query = limited(5);
+        let cached = snapshot(&client, &query).await;
+        let closed = cached.issues[0].id.clone();
+        client.close(&closed).await.unwrap();
+
+        let refreshed = refresh_since(&client, &query, Some(cached.clone()))
+            .await
+            .unwrap();
+        assert_eq!(refreshed.issues.len(), 5);
+        assert!(title(&refreshed, &closed).is_none());
+        // The issue after the snapshot moved up, so everything was fetched again.
+        assert_eq!(ids(&refreshed)[..4], ids(&cached)[1..]);
+        assert!(refreshed.issues.iter().all(|issue| issue.title != STALE));
+    }
+
+    #[test]
+    fn keys_ignore_the_limit_and_the_time_of_day() {
+        let key = |target: &str, search: &str, limit: Option<usize>| {
+            let query = UnifiedListQuery {
+                search: Query::parse_at(search, "2024-05-10T12:00:00Z".parse().unwrap()).unwrap(),
+                limit,
+                ..Default::default()
+            };
+            CacheKey::new(ProviderId::Github, target, &query)
+        };
+        let base = key("https://api.github.com kirei/kirei", "label:bug", Some(30));
+
+        assert_eq!(
+            base,
+            key("https://api.github.com kirei/kirei", "label:bug", None)
+        );
+        assert_ne!(
+            base,
+            key("https://api.github.com kirei/other", "label:bug", Some(30))
+        );
+        assert_ne!(
+            base,
+            key("https://api.github.com kirei/kirei", "label:docs", Some(30))
+        );
+
+        let parse = |search: &str, now: &str| UnifiedListQuery {
+            search: Query::parse_at(search, now.parse().unwrap()).unwrap(),
+            ..Default::default()
+        };
+        let morning = parse("updated:>7d", "2024-05-10T08:00:00Z");
+        let evening = parse("updated:>7d", "2024-05-10T20:00:00Z");
+        let next_day = parse("updated:>7d", "2024-05-11T08:00:00Z");
+        assert_eq!(
+            CacheKey::new(ProviderId::Jira, "", &morning),
+            CacheKey::new(ProviderId::Jira, "", &evening)
+        );
+        assert_ne!(
+            CacheKey::new(ProviderId::Jira, "", &morning),
+            CacheKey::new(ProviderId::Jira, "", &next_day)
+        );
+    }
+
+    #[test]
+    fn keys_name_the_same_file_on_every_build() {
+        let cache = IssueCache::new("/cache");
+        let key = CacheKey::new(ProviderId::Linear, "team", &UnifiedListQuery::default());
+        assert_eq!(
+            cache.path(&key),
+            PathBuf::from("/cache/linear-6ca29d115fcf63d2.json")
+        );
+    }
+}
@@ -0,0 +1,132 @@
+//! What each provider can express, so callers can refuse a request up front
+//! instead of sending one the API will reject or silently ignore.
+
+use crate::unified::query::{Clause, Query};
+use crate::unified::{ProviderId, UnifiedCreateParams, UnifiedError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Capability {
+    /// Short sequential references such as `#12` or `ENG-123`.
+    IssueNumbers,
+    Labels,
+    Priority,
+    Assignees,
+    Comments,
+    /// Workflow states beyond open and closed.
+    Transitions,
+    DueDates,
+}
+
+impl Capability {
+    pub const ALL: [Capability; 7] = [
+        Capability::IssueNumbers,
+        Capability::Labels,
+        Capability::Priority,
+        Capability::Assignees,
+        Capability::Comments,
+        Capability::Transitions,
+        Capability::DueDates,
+    ];
+
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            Capability::IssueNumbers => "issue numbers",
+            Capability::Labels => "labels",
+            Capability::Priority => "priority",
+            Capability::Assignees => "assignees",
+            Capability::Comments => "comments",
+            Capability::Transitions => "transitions",
+            Capability::DueDates => "due dates",
+        }
+    }
+}
+
+impl std::fmt::Display for Capability {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.display_name())
+    }
+}
+
+/// How a provider hands out the next page of a listing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pagination {
+    /// A `Link: <...>; rel="next"` response header.
+    LinkHeader,
+    /// An opaque cursor from the previous response.
+    Cursor,
+    /// A numeric offset into the result set.
+    Offset,
+    /// The id of the last item seen.
+    Keyset,
+}
+
+impl Pagination {
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            Pagination::LinkHeader => "link header",
+            Pagination::Cursor => "cursor",
+            Pagination::Offset => "offset",
+            Pagination::Keyset => "keyset",
+        }
+    }
+}
+
+impl std::fmt::Display for Pagination {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.display_name())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+    pub features: &'static [Capability],
+    pub pagination: Pagination,
+}
+
+impl Capabilities {
+    pub fn supports(&self, capability: Capability) -> bool {
+        self.features.contains(&capability)
+    }
+
+    pub fn require(
+        &self,
+        provider: ProviderId,
+        capability: Capability,
+    ) -> Result<(), UnifiedError> {
+        if self.supports(capability) {
+            Ok(())
+        } else {
+            Err(UnifiedError::Unsupported {
+                provider,
+                capability,
+            })
+        }
+    }
+
+    /// Rejects create parameters the provider has no field for.
+    pub fn check_create(
+        &self,
+        provider: ProviderId,
+        params: &UnifiedCreateParams,
+    ) -> Result<(), UnifiedError> {
+        if params.priority.is_some() {
+            self.require(provider, Capability::Priority)?;
+        }
+        if params.due_date.is_some() {
+            self.require(provider, Capability::DueDates)?;
+        }
+        Ok(())
+    }
+
+    /// Rejects query clauses that filter on a field the provider lacks.
+    pub fn check_query(&self, provider: ProviderId, query: &Query) -> Result<(), UnifiedError> {
+        for clause in query.filters() {
+            match clause {
+                Clause::Label(_) => self.require(provider, Capability::Labels)?,
+                Clause::Assignee(_) => self.require(provider, Capability::Assignees)?,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
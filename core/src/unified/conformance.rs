@@ -0,0 +1,765 @@
+//! A conformance suite every [`ProviderClient`] is held to, so providers
+//! behave alike: the same round trip from create to close, pagination that
+//! neither repeats nor loses issues, error statuses mapped to the same
+//! [`UnifiedError`] variants, and issues normalized the same way.
+//!
+//! The suite runs against whatever backend the client is pointed at. A
+//! [`StubServer`] answers on a local port with scripted responses, for a
+//! provider's base URL; the mock provider needs none. A [`FakeBackend`] on
+//! the stub keeps the issues it is sent, so the whole suite can run without
+//! a real account: it stores and pages through them, and each provider's
+//! [`FakeApi`] speaks that provider's wire format.
+//!
+//! Only built for tests, where each provider runs it against a fake backend,
+//! or for other crates with the `conformance` feature.
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use serde_json::Value;
+
+use crate::unified::{
+    ProviderClient, ProviderId, UnifiedCreateParams, UnifiedError, UnifiedIssue, UnifiedListQuery,
+    UnifiedUpdateParams,
+};
+
+/// Pages walked before pagination is taken to be looping.
+const MAX_PAGES: usize = 10;
+
+/// A check the provider failed.
+#[derive(Debug, Clone)]
+pub struct Violation {
+    pub check: &'static str,
+    pub message: String,
+}
+
+impl Violation {
+    fn new(check: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            check,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.check, self.message)
+    }
+}
+
+impl std::error::Error for Violation {}
+
+/// Outcome of [`Conformance::run`].
+#[derive(Debug, Default)]
+pub struct Report {
+    pub passed: Vec<&'static str>,
+    pub violations: Vec<Violation>,
+}
+
+impl Report {
+    pub fn is_ok(&self) -> bool {
+        self.violations.is_empty()
+    }
+
+    fn record(&mut self, check: &'static str, result: Result<(), Violation>) {
+        match result {
+            Ok(()) => self.passed.push(check),
+            Err(violation) => self.violations.push(violation),
+        }
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for check in &self.passed {
+            writeln!(f, "ok    {}", check)?;
+        }
+        for violation in &self.violations {
+            writeln!(f, "FAIL  {}", violation)?;
+        }
+        Ok(())
+    }
+}
+
+/// Builds a client for a base URL, for [`error_mapping`].
+type Build<'a> = Box<dyn Fn(&str) -> Box<dyn ProviderClient> + 'a>;
+
+/// A provider's error response for a status and message, e.g. [`FakeApi::error`].
+pub type ErrorResponse = fn(u16, &str) -> StubResponse;
+
+/// The checks that need a working backend, run against one client.
+pub struct Conformance<'a> {
+    client: &'a dyn ProviderClient,
+    page_size: usize,
+    errors: Option<(Build<'a>, String, ErrorResponse)>,
+}
+
+impl<'a> Conformance<'a> {
+    pub fn new(client: &'a dyn ProviderClient) -> Self {
+        Self {
+            client,
+            page_size: 5,
+            errors: None,
+        }
+    }
+
+    /// Issues per page in [`pagination`](Self::pagination). Small, so that a
+    /// backend with a handful of issues still has several pages.
+    pub fn with_page_size(mut self, page_size: usize) -> Self {
+        self.page_size = page_size.max(1);
+        self
+    }
+
+    /// Also checks [`error_mapping`] in [`run`](Self::run), with clients
+    /// built by `build`, asked for issue `id` and answered with `error`.
+    /// Without it the provider's error statuses go unchecked, since `run`
+    /// cannot point the client it was given at a stub.
+    pub fn with_error_mapping<F>(mut self, build: F, id: &str, error: ErrorResponse) -> Self
+    where
+        F: Fn(&str) -> Box<dyn ProviderClient> + 'a,
+    {
+        self.errors = Some((Box::new(build), id.to_string(), error));
+        self
+    }
+
+    /// Runs every check, carrying on past failures.
+    pub async fn run(&self) -> Report {
+        let mut report = Report::default();
+        report.record("round trip", self.round_trip().await);
+        report.record("pagination", self.pagination().await);
+        report.record("normalization", self.normalization().await);
+        if let Some((build, id, error)) = &self.errors {
+            report.record("error mapping", error_mapping(build, id, *error).await);
+        }
+        report
+    }
+
+    /// Creates an issue, finds it in a listing, reads, renames, closes and
+    /// reopens it, then deletes it again if the provider allows.
+    pub async fn round_trip(&self) -> Result<(), Violation> {
+        const CHECK: &str = "round trip";
+        let client = self.client;
+        let fail =
+            |step: &str, err: UnifiedError| Violation::new(CHECK, format!("{}: {}", step, err));
+
+        let title = format!("kirei conformance {}", chrono::Utc::now().timestamp());
+        let body = "Created by the kirei conformance suite.";
+        let created = client
+            .create(UnifiedCreateParams {
+                title: title.clone(),
+                body: Some(body.into()),
+                ..Default::default()
+            })
+            .await
+            .map_err(|err| fail("create", err))?;
+        check_issue(client.provider(), &created)
+            .map_err(|message| Violation::new(CHECK, format!("create: {}", message)))?;
+        expect(created.title == title, CHECK, || {
+            format!(
+                "create returned title '{}', sent '{}'",
+                created.title, title
+            )
+        })?;
+        expect(created.description.as_deref() == Some(body), CHECK, || {
+            format!("create returned description {:?}", created.description)
+        })?;
+        expect(!created.unified_state.is_closed(), CHECK, || {
+            format!("new issue is already {}", created.unified_state)
+        })?;
+
+        let listed = client
+            .list(UnifiedListQuery {
+                limit: Some(50),
+                ..Default::default()
+            })
+            .await
+            .map_err(|err| fail("list", err))?;
+        expect(
+            listed.iter().any(|issue| issue.id == created.id),
+            CHECK,
+            || format!("new issue {} is missing from the listing", created.id),
+        )?;
+
+        let fetched = client
+            .get(&created.id)
+            .await
+            .map_err(|err| fail("get", err))?;
+        expect(
+            fetched.id == created.id && fetched.title == title,
+            CHECK,
+            || {
+                format!(
+                    "get {} returned {} '{}'",
+                    created.id, fetched.id, fetched.title
+                )
+            },
+        )?;
+
+        let renamed = format!("{} (renamed)", title);
+        let updated = client
+            .update(
+                &created.id,
+                UnifiedUpdateParams {
+                    title: Some(renamed.clone()),
+                    body: None,
+                },
+            )
+            .await
+            .map_err(|err| fail("update", err))?;
+        expect(updated.title == renamed, CHECK, || {
+            format!("update returned title '{}'", updated.title)
+        })?;
+        let fetched = client
+            .get(&created.id)
+            .await
+            .map_err(|err| fail("get", err))?;
+        expect(fetched.title == renamed, CHECK, || {
+            format!("get after update returned title '{}'", fetched.title)
+        })?;
+
+        let closed = client
+            .close(&created.id)
+            .await
+            .map_err(|err| fail("close", err))?;
+        expect(closed.unified_state.is_closed(), CHECK, || {
+            format!("close left the issue {}", closed.unified_state)
+        })?;
+        let reopened = client
+            .reopen(&created.id)
+            .await
+            .map_err(|err| fail("reopen", err))?;
+        expect(!reopened.unified_state.is_closed(), CHECK, || {
+            format!("reopen left the issue {}", reopened.unified_state)
+        })?;
+
+        match client.delete(&created.id).await {
+            Ok(()) | Err(UnifiedError::Unsupported { .. } | UnifiedError::NotImplemented(_)) => {
+                Ok(())
+            }
+            Err(err) => Err(fail("delete", err)),
+        }
+    }
+
+    /// Walks the listing page by page: no page is larger than asked for, no
+    /// issue appears twice, and the cursors end. A limited listing then
+    /// returns exactly the first issues of the walk.
+    pub async fn pagination(&self) -> Result<(), Violation> {
+        const CHECK: &str = "pagination";
+        let query = UnifiedListQuery {
+            limit: Some(self.page_size),
+            ..Default::default()
+        };
+
+        let mut ids: Vec<String> = Vec::new();
+        let mut cursors = Vec::new();
+        let mut cursor = None;
+        for _ in 0..MAX_PAGES {
+            let page = self
+                .client
+                .list_page(&query, cursor.clone())
+                .await
+                .map_err(|err| Violation::new(CHECK, err.to_string()))?;
+            expect(page.issues.len() <= self.page_size, CHECK, || {
+                format!(
+                    "asked for {} issues, got {}",
+                    self.page_size,
+                    page.issues.len()
+                )
+            })?;
+            for issue in page.issues {
+                expect(!ids.contains(&issue.id), CHECK, || {
+                    format!("{} appears on more than one page", issue.id)
+                })?;
+                ids.push(issue.id);
+            }
+            match page.next {
+                Some(next) => {
+                    expect(!cursors.contains(&next), CHECK, || {
+                        format!("cursor '{}' was handed out twice", next)
+                    })?;
+                    cursors.push(next.clone());
+                    cursor = Some(next);
+                }
+                None => {
+                    cursor = None;
+                    break;
+                }
+            }
+        }
+
+        let limit = self.page_size + 1;
+        let limited = self
+            .client
+            .list(UnifiedListQuery {
+                limit: Some(limit),
+                ..Default::default()
+            })
+            .await
+            .map_err(|err| Violation::new(CHECK, err.to_string()))?;
+        // Without a last page the walk is a prefix of the listing, not all of it.
+        let expected = if cursor.is_none() {
+            ids.len().min(limit)
+        } else {
+            limit
+        };
+        expect(limited.len() == expected, CHECK, || {
+            format!(
+                "a listing limited to {} returned {} issues",
+                limit,
+                limited.len()
+            )
+        })?;
+        let prefix: Vec<&String> = ids.iter().take(limited.len()).collect();
+        let listed: Vec<&String> = limited.iter().map(|issue| &issue.id).collect();
+        expect(prefix == listed, CHECK, || {
+            "a limited listing disagrees with the page walk on order".to_string()
+        })
+    }
+
+    /// Every listed issue passes [`check_issue`].
+    pub async fn normalization(&self) -> Result<(), Violation> {
+        const CHECK: &str = "normalization";
+        let issues = self
+            .client
+            .list(UnifiedListQuery {
+                limit: Some(50),
+                ..Default::default()
+            })
+            .await
+            .map_err(|err| Violation::new(CHECK, err.to_string()))?;
+        for issue in &issues {
+            check_issue(self.client.provider(), issue)
+                .map_err(|message| Violation::new(CHECK, message))?;
+        }
+        Ok(())
+    }
+}
+
+/// The fields every provider must fill in the same way.
+pub fn check_issue(provider: ProviderId, issue: &UnifiedIssue) -> Result<(), String> {
+    let id = &issue.id;
+    if id.trim().is_empty() {
+        return Err(format!("issue '{}' has an empty id", issue.title));
+    }
+    if issue.provider != provider {
+        return Err(format!("{} claims to come from {}", id, issue.provider));
+    }
+    if issue.identifier.trim().is_empty() {
+        return Err(format!("{} has an empty identifier", id));
+    }
+    if issue.title.trim().is_empty() {
+        return Err(format!("{} has an empty title", id));
+    }
+    if issue.state.trim().is_empty() {
+        return Err(format!("{} has an empty state name", id));
+    }
+    if issue
+        .labels
+        .iter()
+        .chain(&issue.assignees)
+        .any(|name| name.trim().is_empty())
+    {
+        return Err(format!("{} has an empty label or assignee", id));
+    }
+    if let (Some(created), Some(updated)) = (issue.created_at, issue.updated_at)
+        && updated < created
+    {
+        return Err(format!("{} was updated before it was created", id));
+    }
+    if issue.raw_payload.is_null() {
+        return Err(format!("{} has no raw payload", id));
+    }
+    Ok(())
+}
+
+/// Error statuses and the variant each must map to.
+const ERROR_CASES: &[(u16, &str)] = &[
+    (401, "Unauthorized"),
+    (403, "Forbidden"),
+    (404, "NotFound"),
+    (422, "Validation"),
+    (429, "RateLimited"),
+];
+
+/// Points clients built by `build` at a stub answering every request with
+/// an error status, in the body `error` makes, and checks that `get(id)`
+/// reports the matching [`UnifiedError`] with the provider's message.
+pub async fn error_mapping<F>(build: F, id: &str, error: ErrorResponse) -> Result<(), Violation>
+where
+    F: Fn(&str) -> Box<dyn ProviderClient>,
+{
+    const CHECK: &str = "error mapping";
+    let stub = StubServer::start().map_err(|err| Violation::new(CHECK, err.to_string()))?;
+    for &(status, expected) in ERROR_CASES {
+        let message = format!("stub error {}", status);
+        let mut response = error(status, &message);
+        if status == 429 {
+            // Longer than any retry policy waits, so the limit is reported.
+            response = response.with_header("Retry-After", "3600");
+        }
+        stub.fallback(response);
+
+        let client = build(stub.url());
+        let err = match client.get(id).await {
+            Ok(issue) => {
+                return Err(Violation::new(
+                    CHECK,
+                    format!(
+                        "{} answered {} with issue {}",
+                        client.provider(),
+                        status,
+                        issue.id
+                    ),
+                ));
+            }
+            Err(err) => err,
+        };
+        let matched = match &err {
+            UnifiedError::Unauthorized { .. } => expected == "Unauthorized",
+            UnifiedError::Forbidden { .. } => expected == "Forbidden",
+            UnifiedError::NotFound { .. } => expected == "NotFound",
+            UnifiedError::Validation { .. } => expected == "Validation",
+            UnifiedError::RateLimited { .. } => expected == "RateLimited",
+            _ => false,
+        };
+        expect(matched, CHECK, || {
+            format!(
+                "{} mapped {} to {:?}, expected {}",
+                client.provider(),
+                status,
+                err,
+                expected
+            )
+        })?;
+        expect(err.to_string().contains(&message), CHECK, || {
+            format!(
+                "{} dropped the message of a {}: {}",
+                client.provider(),
+                status,
+                err
+            )
+        })?;
+    }
+    Ok(())
+}
+
+fn expect(
+    condition: bool,
+    check: &'static str,
+    message: impl FnOnce() -> String,
+) -> Result<(), Violation> {
+    if condition {
+        Ok(())
+    } else {
+        Err(Violation::new(check, message()))
+    }
+}
+
+/// A scripted answer from a [`StubServer`].
+#[derive(Debug, Clone)]
+pub struct StubResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl StubResponse {
+    /// A JSON body; `null` sends none.
+    pub fn json(status: u16, body: Value) -> Self {
+        let body = match body {
+            Value::Null => String::new(),
+            body => body.to_string(),
+        };
+        Self::with_body(status, "application/json", body)
+    }
+
+    pub fn text(status: u16, body: &str) -> Self {
+        Self::with_body(status, "text/plain; charset=utf-8", body.to_string())
+    }
+
+    fn with_body(status: u16, content_type: &str, body: String) -> Self {
+        Self {
+            status,
+            headers: vec![("Content-Type".into(), content_type.into())],
+            body,
+        }
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+}
+
+/// A request the stub received.
+#[derive(Debug, Clone)]
+pub struct StubRequest {
+    pub method: String,
+    /// Path and query.
+    pub url: String,
+    pub body: String,
+}
+
+impl StubRequest {
+    pub fn path(&self) -> &str {
+        self.url.split('?').next().unwrap_or_default()
+    }
+
+    /// The first value of query parameter `name`, decoded.
+    pub fn query(&self, name: &str) -> Option<String> {
+        let (_, query) = self.url.split_once('?')?;
+        url::form_urlencoded::parse(query.as_bytes())
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.into_owned())
+    }
+
+    /// The body as JSON; `null` if it is not.
+    pub fn json(&self) -> Value {
+        serde_json::from_str(&self.body).unwrap_or_default()
+    }
+}
+
+/// Computes the answer to a request, e.g. from a fake backend's state.
+type Handler = Arc<dyn Fn(&StubRequest) -> StubResponse + Send + Sync>;
+
+#[derive(Default)]
+struct StubState {
+    /// Method, path prefix and handler; the latest matching route wins.
+    routes: Vec<(String, String, Handler)>,
+    fallback: Option<StubResponse>,
+    requests: Vec<StubRequest>,
+}
+
+/// An HTTP server on a local port that answers with scripted responses and
+/// remembers what it was asked. Stops when dropped.
+pub struct StubServer {
+    server: Arc<tiny_http::Server>,
+    url: String,
+    state: Arc<Mutex<StubState>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl StubServer {
+    pub fn start() -> std::io::Result<Self> {
+        let server = tiny_http::Server::http("127.0.0.1:0")
+            .map(Arc::new)
+            .map_err(std::io::Error::other)?;
+        let port = server
+            .server_addr()
+            .to_ip()
+            .map(|addr| addr.port())
+            .ok_or_else(|| std::io::Error::other("stub server has no TCP address"))?;
+        let state = Arc::new(Mutex::new(StubState::default()));
+
+        let thread = {
+            let server = Arc::clone(&server);
+            let state = Arc::clone(&state);
+            std::thread::spawn(move || {
+                for mut request in server.incoming_requests() {
+                    let mut body = String::new();
+                    let _ = request.as_reader().read_to_string(&mut body);
+                    let received = StubRequest {
+                        method: request.method().as_str().to_uppercase(),
+                        url: request.url().to_string(),
+                        body,
+                    };
+                    let (handler, fallback) = {
+                        let mut state = state.lock().unwrap_or_else(|err| err.into_inner());
+                        state.requests.push(received.clone());
+                        let handler = state
+                            .routes
+                            .iter()
+                            .rev()
+                            .find(|(method, prefix, _)| {
+                                *method == received.method
+                                    && received.path().starts_with(prefix.as_str())
+                            })
+                            .map(|(_, _, handler)| Arc::clone(handler));
+                        (handler, state.fallback.clone())
+                    };
+                    // Handlers run unlocked, so they may take their time.
+                    let response = match (handler, fallback) {
+                        (Some(handler), _) => handler(&received),
+                        (None, Some(fallback)) => fallback,
+                        (None, None) => StubResponse::text(
+                            404,
+                            &format!("no stub for {} {}", received.method, received.url),
+                        ),
+                    };
+                    let _ = request.respond(to_tiny(response));
+                }
+            })
+        };
+
+        Ok(Self {
+            server,
+            url: format!("http://127.0.0.1:{}", port),
+            state,
+            thread: Some(thread),
+        })
+    }
+
+    /// Base URL to configure the provider with.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Answers `method` requests whose path starts with `path`.
+    pub fn route(&self, method: &str, path: &str, response: StubResponse) {
+        self.handle(method, path, move |_| response.clone());
+    }
+
+    /// Answers `method` requests whose path starts with `path` with what
+    /// `handler` makes of them, e.g. a fake backend that keeps the issues it
+    /// was sent.
+    pub fn handle<H>(&self, method: &str, path: &str, handler: H)
+    where
+        H: Fn(&StubRequest) -> StubResponse + Send + Sync + 'static,
+    {
+        self.lock()
+            .routes
+            .push((method.to_uppercase(), path.to_string(), Arc::new(handler)));
+    }
+
+    /// Answers requests no route matches; a `404` by default.
+    pub fn fallback(&self, response: StubResponse) {
+        self.lock().fallback = Some(response);
+    }
+
+    /// Requests received so far, oldest first.
+    pub fn requests(&self) -> Vec<StubRequest> {
+        self.lock().requests.clone()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, StubState> {
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl Drop for StubServer {
+    fn drop(&mut self) {
+        self.server.unblock();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn to_tiny(response: StubResponse) -> tiny_http::Response<std::io::Cursor<Vec<u8>>> {
+    let mut tiny = tiny_http::Response::from_data(response.body.into_bytes())
+        .with_status_code(response.status);
+    for (name, value) in response.headers {
+        if let Ok(header) = tiny_http::Header::from_bytes(name.as_bytes(), value.as_bytes()) {
+            tiny.add_header(header);
+        }
+    }
+    tiny
+}
+
+/// The provider's half of a [`FakeBackend`]: what its issues and errors
+/// look like on the wire, and the endpoints that serve them.
+pub trait FakeApi: Send + Sized + 'static {
+    /// Method and path prefix of every endpoint the client calls.
+    fn routes(&self) -> Vec<(&'static str, String)>;
+
+    /// Issue `number` as the provider returns it, created at `created` with
+    /// `title` and `body` as the client sent them.
+    fn issue(&self, number: i64, created: DateTime<Utc>, title: &str, body: Value) -> Value;
+
+    /// Whether the default listing, of open issues, shows `issue`.
+    fn is_open(issue: &Value) -> bool;
+
+    /// An error response the way the provider sends it.
+    fn error(status: u16, message: &str) -> StubResponse;
+
+    /// Answers `request` from the issues `backend` keeps.
+    fn answer(backend: &mut FakeBackend<Self>, request: &StubRequest) -> StubResponse;
+}
+
+/// A provider backend kept in memory: issues are numbered in order, each
+/// created an hour after the one before, and listed newest first.
+pub struct FakeBackend<A> {
+    pub api: A,
+    issues: Vec<Value>,
+    next_number: i64,
+}
+
+impl<A: FakeApi> FakeBackend<A> {
+    /// Seeds `seeded` issues and answers the routes of `api` on `stub`.
+    pub fn serve(stub: &StubServer, api: A, seeded: usize) {
+        let routes = api.routes();
+        let mut backend = Self {
+            api,
+            issues: Vec::new(),
+            next_number: 1,
+        };
+        for n in 1..=seeded {
+            backend.create(&format!("Seeded issue {}", n), Value::Null);
+        }
+
+        let backend = Arc::new(Mutex::new(backend));
+        for (method, path) in routes {
+            let backend = Arc::clone(&backend);
+            stub.handle(method, &path, move |request| {
+                let mut backend = backend.lock().unwrap_or_else(|err| err.into_inner());
+                A::answer(&mut backend, request)
+            });
+        }
+    }
+
+    /// Stores a new issue and returns it.
+    pub fn create(&mut self, title: &str, body: Value) -> Value {
+        let number = self.next_number;
+        self.next_number += 1;
+        let created = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap() + Duration::hours(number);
+        let issue = self.api.issue(number, created, title, body);
+        self.issues.push(issue.clone());
+        issue
+    }
+
+    /// Open issues, newest first.
+    pub fn open(&self) -> Vec<&Value> {
+        self.issues
+            .iter()
+            .rev()
+            .filter(|issue| A::is_open(issue))
+            .collect()
+    }
+
+    /// `size` open issues from offset `start`, and whether more follow.
+    pub fn page(&self, start: usize, size: usize) -> (Vec<Value>, bool) {
+        let open = self.open();
+        let issues = open.iter().skip(start).take(size).copied().cloned();
+        (issues.collect(), start + size < open.len())
+    }
+
+    /// `size` open issues after the one whose `key` is `after`, or from the
+    /// first without one, and whether more follow.
+    pub fn page_after(&self, key: &str, after: Option<&str>, size: usize) -> (Vec<Value>, bool) {
+        let open = self.open();
+        let start = after.map_or(0, |after| {
+            open.iter()
+                .position(|issue| issue[key] == after)
+                .map_or(open.len(), |position| position + 1)
+        });
+        self.page(start, size)
+    }
+
+    pub fn find(&self, matches: impl Fn(&Value) -> bool) -> Option<&Value> {
+        self.issues.iter().find(|issue| matches(issue))
+    }
+
+    pub fn find_mut(&mut self, matches: impl Fn(&Value) -> bool) -> Option<&mut Value> {
+        self.issues.iter_mut().find(|issue| matches(issue))
+    }
+
+    /// Removes the issues `matches` picks; `false` if there were none.
+    pub fn remove(&mut self, matches: impl Fn(&Value) -> bool) -> bool {
+        let count = self.issues.len();
+        self.issues.retain(|issue| !matches(issue));
+        self.issues.len() < count
+    }
+}
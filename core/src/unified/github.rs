@@ -1,81 +1,224 @@
+use std::collections::HashMap;
+
+use crate::unified::cache::CachedPage;
+use crate::unified::query::{Assignee, Clause, DateBound, Query, StateFilter};
 use crate::unified::{
-    ProviderClient, ProviderId, UnifiedCreateParams, UnifiedError, UnifiedIssue, UnifiedListQuery,
+    Capabilities, Capability, IssuePage, Pagination, ProviderClient, ProviderId, Snapshot,
+    StateOverrides, SyncState, UnifiedCreateParams, UnifiedError, UnifiedIssue, UnifiedListQuery,
+    UnifiedState, UnifiedUpdateParams,
+};
+use chrono::{DateTime, Utc};
+use kirei_http::HttpClient;
+use kirei_provider_github::{
+    self as provider, GitHubError, GitHubIssue, GitHubIssuePage, GitHubIssueUpdate,
+};
+
+/// GitHub has no priorities or per-issue due dates; milestones are the closest.
+pub const CAPABILITIES: Capabilities = Capabilities {
+    features: &[
+        Capability::IssueNumbers,
+        Capability::Labels,
+        Capability::Assignees,
+        Capability::Comments,
+    ],
+    pagination: Pagination::LinkHeader,
 };
-use reqwest::Client;
-use serde_json::{Value, json};
 
+/// [`ProviderClient`] on top of the `kirei-provider-github` client.
 pub struct GitHubClient {
-    http: Client,
-    token: String,
-    default_repo: Option<String>,
+    api: provider::GitHubClient,
+    states: StateOverrides,
 }
 
 impl GitHubClient {
-    pub fn new(token: String, default_repo: Option<String>) -> Self {
+    pub fn new(token: String, default_repo: Option<String>, base_url: Option<&str>) -> Self {
         Self {
-            http: Client::new(),
-            token,
-            default_repo,
+            api: provider::GitHubClient::new(token, default_repo, base_url),
+            states: StateOverrides::new(),
         }
     }
 
-    fn resolve_repo(
+    /// Sends requests through `http`; see [`http_client`](crate::unified::registry::http_client).
+    pub fn with_http(mut self, http: HttpClient) -> Self {
+        self.api = self.api.with_http(http);
+        self
+    }
+
+    pub fn with_state_map(mut self, states: StateOverrides) -> Self {
+        self.states = states;
+        self
+    }
+
+    /// Splits `12`, `#12` or `owner/repo#12` into an optional repository and
+    /// the issue number.
+    fn parse_id(id: &str) -> Result<(Option<String>, u64), UnifiedError> {
+        let (repo, number) = match id.rsplit_once('#') {
+            Some((repo, number)) if !repo.is_empty() => (Some(repo.to_string()), number),
+            Some((_, number)) => (None, number),
+            None => (None, id),
+        };
+        let number = number
+            .parse()
+            .map_err(|_| UnifiedError::InvalidId(ProviderId::Github, id.to_string()))?;
+        Ok((repo, number))
+    }
+
+    /// The issue list for plain listings, issue search for anything filtered.
+    fn first_page_url(&self, query: &UnifiedListQuery) -> Result<String, UnifiedError> {
+        let per_page = query.page_size(100);
+        let url = if query.search.is_empty() {
+            self.api
+                .list_issues_url(query.repo.clone(), Some("open"), per_page)?
+        } else {
+            let qualifiers = search_qualifiers(&query.search)?.join(" ");
+            self.api
+                .search_issues_url(query.repo.clone(), &qualifiers, per_page)?
+        };
+        Ok(url)
+    }
+
+    fn to_unified_page(&self, page: GitHubIssuePage) -> Vec<UnifiedIssue> {
+        page.issues
+            .into_iter()
+            .map(|issue| self.to_unified(issue))
+            .collect()
+    }
+
+    /// Records where a freshly fetched page came from and appends its issues.
+    fn cache_page(
         &self,
-        override_repo: Option<&String>,
-    ) -> Result<(String, String), UnifiedError> {
-        let repo = override_repo
-            .cloned()
-            .or_else(|| self.default_repo.clone())
-            .ok_or_else(|| UnifiedError::Configuration("repository is required".into()))?;
-        let mut segments = repo.splitn(2, '/');
-        let owner = segments
-            .next()
-            .filter(|s| !s.is_empty())
-            .ok_or_else(|| UnifiedError::Configuration("repository owner missing".into()))?;
-        let repo = segments
-            .next()
-            .filter(|s| !s.is_empty())
-            .ok_or_else(|| UnifiedError::Configuration("repository name missing".into()))?;
-        Ok((owner.to_string(), repo.to_string()))
-    }
-
-    fn map_issue(&self, value: Value) -> UnifiedIssue {
-        let number = value
-            .get("number")
-            .and_then(Value::as_i64)
-            .map(|n| n.to_string())
-            .unwrap_or_else(|| {
-                value
-                    .get("id")
-                    .and_then(Value::as_str)
-                    .unwrap_or_default()
-                    .to_string()
-            });
-        let title = value
-            .get("title")
-            .and_then(Value::as_str)
-            .unwrap_or("untitled")
-            .to_string();
-        let state = value
-            .get("state")
-            .and_then(Value::as_str)
-            .unwrap_or("unknown")
-            .to_string();
-
-        let url = value
-            .get("html_url")
-            .and_then(Value::as_str)
-            .map(str::to_string);
-
-        UnifiedIssue {
-            id: number,
-            title,
-            state,
+        url: String,
+        page: GitHubIssuePage,
+        issues: &mut Vec<UnifiedIssue>,
+    ) -> CachedPage {
+        let cached = CachedPage {
             url,
-            provider: ProviderId::Github,
-            raw_payload: value,
+            etag: page.etag.clone(),
+            ids: page
+                .issues
+                .iter()
+                .map(|issue| issue.number.to_string())
+                .collect(),
+            next: page.next.clone(),
+        };
+        issues.extend(self.to_unified_page(page));
+        cached
+    }
+
+    async fn update_issue(
+        &self,
+        id: &str,
+        update: GitHubIssueUpdate,
+    ) -> Result<UnifiedIssue, UnifiedError> {
+        let (repo, number) = Self::parse_id(id)?;
+        let issue = self.api.update_issue(repo, number, &update).await?;
+        Ok(self.to_unified(issue))
+    }
+
+    fn to_unified(&self, issue: GitHubIssue) -> UnifiedIssue {
+        let default_state = match (issue.state.as_str(), issue.state_reason.as_deref()) {
+            ("closed", Some("not_planned")) => UnifiedState::Canceled,
+            ("closed", _) => UnifiedState::Done,
+            _ => UnifiedState::Todo,
+        };
+
+        let mut unified = UnifiedIssue::new(
+            ProviderId::Github,
+            issue.number.to_string(),
+            issue.title,
+            issue.state,
+        );
+        unified.unified_state = UnifiedState::resolve(&self.states, &unified.state, default_state);
+        unified.identifier = format!("#{}", issue.number);
+        unified.description = issue.body.filter(|body| !body.is_empty());
+        unified.assignees = issue.assignees;
+        unified.labels = issue.labels;
+        unified.created_at = issue.created_at;
+        unified.updated_at = issue.updated_at;
+        unified.due_date = issue.due_date;
+        unified.comment_count = Some(issue.comments);
+        unified.url = issue.html_url;
+        unified.raw_payload = issue.raw;
+        unified
+    }
+}
+
+impl From<GitHubError> for UnifiedError {
+    fn from(err: GitHubError) -> Self {
+        match err {
+            GitHubError::Http(err) => UnifiedError::Http(err),
+            GitHubError::MissingCredentials => UnifiedError::MissingToken(ProviderId::Github),
+            GitHubError::RepositoryRequired => UnifiedError::MissingDefault {
+                provider: ProviderId::Github,
+                scope: "repository",
+            },
+            GitHubError::Unauthorized(message) => UnifiedError::Unauthorized {
+                provider: ProviderId::Github,
+                message,
+            },
+            GitHubError::Forbidden(message) => UnifiedError::Forbidden {
+                provider: ProviderId::Github,
+                message,
+            },
+            GitHubError::NotFound(message) => UnifiedError::NotFound {
+                provider: ProviderId::Github,
+                message,
+            },
+            GitHubError::RateLimited { reset, message } => UnifiedError::RateLimited {
+                provider: ProviderId::Github,
+                reset,
+                message,
+            },
+            GitHubError::Validation { field, message } => UnifiedError::Validation {
+                provider: ProviderId::Github,
+                field,
+                message,
+            },
+            GitHubError::UnexpectedResponse(body) => UnifiedError::UnexpectedResponse(body),
+            other => UnifiedError::Configuration(other.to_string()),
+        }
+    }
+}
+
+/// Translates a query into issue search qualifiers, e.g. `is:open label:"bug"`.
+fn search_qualifiers(query: &Query) -> Result<Vec<String>, UnifiedError> {
+    let mut qualifiers = vec!["is:issue".to_string()];
+    match query.state() {
+        StateFilter::Open | StateFilter::Is(UnifiedState::Todo) => {
+            qualifiers.push("is:open".into())
+        }
+        StateFilter::Closed => qualifiers.push("is:closed".into()),
+        StateFilter::All => {}
+        StateFilter::Is(UnifiedState::Done) => qualifiers.push("is:closed reason:completed".into()),
+        StateFilter::Is(UnifiedState::Canceled) => {
+            qualifiers.push("is:closed reason:\"not planned\"".into())
         }
+        state @ StateFilter::Is(_) => {
+            return Err(Clause::State(state).unsupported(ProviderId::Github));
+        }
+    }
+
+    let timestamp = |bound: &DateBound| match bound {
+        DateBound::After(at) => format!(">={}", at.format("%Y-%m-%dT%H:%M:%SZ")),
+        DateBound::Before(at) => format!("<{}", at.format("%Y-%m-%dT%H:%M:%SZ")),
+    };
+    for clause in query.filters() {
+        qualifiers.push(match clause {
+            Clause::Label(label) => format!("label:{}", quote(label)),
+            Clause::Assignee(Assignee::Me) => "assignee:@me".into(),
+            Clause::Assignee(Assignee::User(user)) => format!("assignee:{}", user),
+            Clause::Updated(bound) => format!("updated:{}", timestamp(bound)),
+            Clause::Created(bound) => format!("created:{}", timestamp(bound)),
+            Clause::Text(text) => quote(text),
+            Clause::State(_) => continue,
+        });
     }
+    Ok(qualifiers)
+}
+
+/// Search syntax has no escapes, so embedded quotes are dropped.
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('"', ""))
 }
 
 #[async_trait::async_trait]
@@ -84,46 +227,319 @@ impl ProviderClient for GitHubClient {
         ProviderId::Github
     }
 
-    async fn list(&self, query: UnifiedListQuery) -> Result<Vec<UnifiedIssue>, UnifiedError> {
-        let (owner, repo) = self.resolve_repo(query.repo.as_ref())?;
-        let url = format!(
-            "https://api.github.com/repos/{}/{}/issues?state=open&per_page=20",
-            owner, repo
-        );
+    fn capabilities(&self) -> Capabilities {
+        CAPABILITIES
+    }
 
-        let response = self
-            .http
-            .get(&url)
-            .bearer_auth(&self.token)
-            .header("User-Agent", "kirei-cli")
-            .send()
-            .await?;
+    async fn list_page(
+        &self,
+        query: &UnifiedListQuery,
+        cursor: Option<String>,
+    ) -> Result<IssuePage, UnifiedError> {
+        let url = match cursor {
+            Some(next) => next,
+            None => self.first_page_url(query)?,
+        };
+        let page = self.api.issue_page(&url).await?;
+        let next = page.next.clone();
+        Ok(IssuePage {
+            issues: self.to_unified_page(page),
+            next,
+        })
+    }
 
-        let issues: Vec<Value> = response.json().await?;
-        Ok(issues
-            .into_iter()
-            .map(|issue| self.map_issue(issue))
-            .collect())
+    /// Walks the listing again, sending each page's ETag. Pages GitHub
+    /// reports unchanged are rebuilt from the snapshot, and those `304`s do
+    /// not count against the rate limit.
+    async fn refresh(
+        &self,
+        query: &UnifiedListQuery,
+        cached: Option<Snapshot>,
+    ) -> Result<Snapshot, UnifiedError> {
+        let (cached_pages, mut cached_issues) = match cached {
+            Some(Snapshot {
+                issues,
+                sync: SyncState::Pages { pages },
+                ..
+            }) => (
+                pages,
+                issues
+                    .into_iter()
+                    .map(|issue| (issue.id.clone(), issue))
+                    .collect(),
+            ),
+            _ => (Vec::new(), HashMap::new()),
+        };
+
+        let mut pages = Vec::new();
+        let mut issues = Vec::new();
+        let mut url = Some(self.first_page_url(query)?);
+        while let Some(current) = url {
+            let revalidate = cached_pages
+                .get(pages.len())
+                .filter(|page| page.url == current)
+                .and_then(|page| Some((page, page.etag.as_deref()?)));
+            let page = match revalidate {
+                Some((cached, etag)) => {
+                    match self.api.issue_page_if_changed(&current, etag).await? {
+                        Some(fresh) => self.cache_page(current, fresh, &mut issues),
+                        // Unchanged: the same issues as last time.
+                        None => {
+                            issues.extend(
+                                cached.ids.iter().filter_map(|id| cached_issues.remove(id)),
+                            );
+                            cached.clone()
+                        }
+                    }
+                }
+                None => {
+                    let fresh = self.api.issue_page(&current).await?;
+                    self.cache_page(current, fresh, &mut issues)
+                }
+            };
+            url = page.next.clone();
+            pages.push(page);
+
+            if query.limit.is_some_and(|limit| issues.len() >= limit) {
+                break;
+            }
+        }
+
+        Ok(Snapshot::new(issues, SyncState::Pages { pages }))
     }
 
-    async fn create(&self, params: UnifiedCreateParams) -> Result<UnifiedIssue, UnifiedError> {
-        let (owner, repo) = self.resolve_repo(params.repo.as_ref())?;
-        let url = format!("https://api.github.com/repos/{}/{}/issues", owner, repo);
-        let body = json!({
-            "title": params.title,
-            "body": params.body,
-        });
+    /// A streamed listing keeps no ETags, so the next refresh walks the pages
+    /// once to collect them; those after it revalidate.
+    fn sync_state(&self, _: &UnifiedListQuery, _: DateTime<Utc>) -> SyncState {
+        SyncState::Pages { pages: Vec::new() }
+    }
 
-        let response = self
-            .http
-            .post(&url)
-            .bearer_auth(&self.token)
-            .header("User-Agent", "kirei-cli")
-            .json(&body)
-            .send()
+    async fn create(&self, params: UnifiedCreateParams) -> Result<UnifiedIssue, UnifiedError> {
+        CAPABILITIES.check_create(ProviderId::Github, &params)?;
+        let issue = self
+            .api
+            .create_issue(params.repo, &params.title, params.body.as_deref())
             .await?;
+        Ok(self.to_unified(issue))
+    }
+
+    async fn get(&self, id: &str) -> Result<UnifiedIssue, UnifiedError> {
+        let (repo, number) = Self::parse_id(id)?;
+        let issue = self.api.get_issue(repo, number).await?;
+        Ok(self.to_unified(issue))
+    }
+
+    async fn update(
+        &self,
+        id: &str,
+        params: UnifiedUpdateParams,
+    ) -> Result<UnifiedIssue, UnifiedError> {
+        let update = GitHubIssueUpdate {
+            title: params.title,
+            body: params.body,
+            ..Default::default()
+        };
+        self.update_issue(id, update).await
+    }
+
+    async fn close(&self, id: &str) -> Result<UnifiedIssue, UnifiedError> {
+        let update = GitHubIssueUpdate {
+            state: Some("closed".into()),
+            ..Default::default()
+        };
+        self.update_issue(id, update).await
+    }
+
+    async fn reopen(&self, id: &str) -> Result<UnifiedIssue, UnifiedError> {
+        let update = GitHubIssueUpdate {
+            state: Some("open".into()),
+            ..Default::default()
+        };
+        self.update_issue(id, update).await
+    }
+
+    async fn delete(&self, id: &str) -> Result<(), UnifiedError> {
+        let (repo, number) = Self::parse_id(id)?;
+        let issue = self.api.get_issue(repo, number).await?;
+        self.api.delete_issue(&issue.node_id).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use serde_json::{Value, json};
+
+    use super::*;
+    use crate::unified::conformance::{
+        Conformance, FakeApi, FakeBackend, StubRequest, StubResponse, StubServer,
+    };
+
+    const ISSUES: &str = "/api/v3/repos/kirei/conformance/issues";
+
+    /// A repository behind the REST and GraphQL endpoints the client uses.
+    struct FakeRepo {
+        base_url: String,
+    }
+
+    impl FakeApi for FakeRepo {
+        fn routes(&self) -> Vec<(&'static str, String)> {
+            vec![
+                ("GET", ISSUES.into()),
+                ("POST", ISSUES.into()),
+                ("PATCH", ISSUES.into()),
+                ("POST", "/api/graphql".into()),
+            ]
+        }
+
+        fn issue(&self, number: i64, created: DateTime<Utc>, title: &str, body: Value) -> Value {
+            json!({
+                "id": 1000 + number,
+                "node_id": format!("I_{}", number),
+                "number": number,
+                "title": title,
+                "body": body,
+                "state": "open",
+                "state_reason": null,
+                "html_url": format!("https://github.com/kirei/conformance/issues/{}", number),
+                "assignees": [],
+                "labels": [],
+                "comments": 0,
+                "created_at": created.to_rfc3339(),
+                "updated_at": created.to_rfc3339(),
+            })
+        }
+
+        fn is_open(issue: &Value) -> bool {
+            issue["state"] == "open"
+        }
+
+        /// `{"message", "documentation_url"}`, with the offending field in
+        /// `errors` on a `422`.
+        fn error(status: u16, message: &str) -> StubResponse {
+            let mut body = json!({
+                "message": message,
+                "documentation_url": "https://docs.github.com/rest",
+            });
+            if status == 422 {
+                body["errors"] = json!([
+                    { "resource": "Issue", "field": "title", "code": "missing_field" },
+                ]);
+            }
+            StubResponse::json(status, body)
+        }
+
+        fn answer(repo: &mut FakeBackend<Self>, request: &StubRequest) -> StubResponse {
+            if request.path() == "/api/graphql" {
+                return delete(repo, request);
+            }
+            let rest = request.path().trim_start_matches(ISSUES);
+            let fields = request.json();
+            match (request.method.as_str(), rest.strip_prefix('/')) {
+                ("GET", None) => list(repo, request),
+                ("POST", None) => StubResponse::json(
+                    201,
+                    repo.create(
+                        fields["title"].as_str().unwrap_or_default(),
+                        fields["body"].clone(),
+                    ),
+                ),
+                ("GET", Some(number)) => match repo.find(numbered(number)) {
+                    Some(issue) => StubResponse::json(200, issue.clone()),
+                    None => not_found(),
+                },
+                ("PATCH", Some(number)) => {
+                    let Some(issue) = repo.find_mut(numbered(number)) else {
+                        return not_found();
+                    };
+                    for key in ["title", "body", "state"] {
+                        if let Some(value) = fields.get(key) {
+                            issue[key] = value.clone();
+                        }
+                    }
+                    issue["state_reason"] = match issue["state"].as_str() {
+                        Some("closed") => json!("completed"),
+                        _ => Value::Null,
+                    };
+                    issue["updated_at"] = json!(Utc::now().to_rfc3339());
+                    StubResponse::json(200, issue.clone())
+                }
+                _ => not_found(),
+            }
+        }
+    }
+
+    /// Open issues, newest first, paged through `Link` headers.
+    fn list(repo: &FakeBackend<FakeRepo>, request: &StubRequest) -> StubResponse {
+        let per_page: usize = request
+            .query("per_page")
+            .and_then(|n| n.parse().ok())
+            .unwrap_or(30);
+        let page: usize = request
+            .query("page")
+            .and_then(|n| n.parse().ok())
+            .unwrap_or(1);
+        let (issues, more) = repo.page((page - 1) * per_page, per_page);
+
+        let response = StubResponse::json(200, Value::Array(issues));
+        if more {
+            let next = format!(
+                "<{}{}?state=open&per_page={}&page={}>; rel=\"next\"",
+                repo.api.base_url,
+                ISSUES,
+                per_page,
+                page + 1
+            );
+            response.with_header("Link", &next)
+        } else {
+            response
+        }
+    }
+
+    /// The `deleteIssue` mutation, the only GraphQL the client sends.
+    fn delete(repo: &mut FakeBackend<FakeRepo>, request: &StubRequest) -> StubResponse {
+        let node_id = request.json()["variables"]["id"].clone();
+        if !repo.remove(|issue| issue["node_id"] == node_id) {
+            return StubResponse::json(
+                200,
+                json!({ "errors": [{ "type": "NOT_FOUND", "message": "Could not resolve to a node" }] }),
+            );
+        }
+        StubResponse::json(
+            200,
+            json!({ "data": { "deleteIssue": { "clientMutationId": null } } }),
+        )
+    }
+
+    fn numbered(number: &str) -> impl Fn(&Value) -> bool {
+        let number: Option<i64> = number.parse().ok();
+        move |issue| issue["number"].as_i64() == number
+    }
+
+    fn not_found() -> StubResponse {
+        FakeRepo::error(404, "Not Found")
+    }
+
+    fn github(url: &str) -> GitHubClient {
+        GitHubClient::new("token".into(), Some("kirei/conformance".into()), Some(url))
+    }
+
+    #[tokio::test]
+    async fn conforms_against_a_fake_repository() {
+        let stub = StubServer::start().unwrap();
+        let repo = FakeRepo {
+            base_url: stub.url().to_string(),
+        };
+        FakeBackend::serve(&stub, repo, 12);
+        let client = github(stub.url());
+
+        let report = Conformance::new(&client)
+            .with_error_mapping(|url| Box::new(github(url)), "7", FakeRepo::error)
+            .run()
+            .await;
 
-        let issue: Value = response.json().await?;
-        Ok(self.map_issue(issue))
+        assert!(report.is_ok(), "{}", report);
+        assert_eq!(report.passed.len(), 4);
     }
 }
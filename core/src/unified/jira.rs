@@ -1,12 +1,234 @@
+use crate::config::JiraAuthMode;
+use crate::unified::cache;
+use crate::unified::query::{Assignee, Clause, DateBound, Query, StateFilter};
 use crate::unified::{
-    ProviderClient, ProviderId, UnifiedCreateParams, UnifiedError, UnifiedIssue, UnifiedListQuery,
+    Capabilities, Capability, IssuePage, Pagination, ProviderClient, ProviderId, Snapshot,
+    StateOverrides, SyncState, UnifiedCreateParams, UnifiedError, UnifiedIssue, UnifiedListQuery,
+    UnifiedPriority, UnifiedState, UnifiedUpdateParams,
 };
+use chrono::{DateTime, Utc};
+use kirei_http::HttpClient;
+use kirei_provider_jira::{
+    self as provider, JiraAuth, JiraError, JiraIssue, JiraIssueCreate, JiraIssueUpdate,
+};
+
+/// Priority and due dates are left out: projects can drop either from their
+/// create screen, and Jira rejects fields the screen lacks.
+const FEATURES: [Capability; 5] = [
+    Capability::IssueNumbers,
+    Capability::Labels,
+    Capability::Assignees,
+    Capability::Comments,
+    Capability::Transitions,
+];
+
+/// Jira Cloud, which pages searches by token.
+pub const CAPABILITIES: Capabilities = Capabilities {
+    features: &FEATURES,
+    pagination: Pagination::Cursor,
+};
+
+/// What Jira supports when signed in with `mode`: personal access tokens are
+/// for Server and Data Center, whose v2 search pages by offset.
+pub fn capabilities(mode: JiraAuthMode) -> Capabilities {
+    match mode {
+        JiraAuthMode::Pat => Capabilities {
+            pagination: Pagination::Offset,
+            ..CAPABILITIES
+        },
+        JiraAuthMode::Basic | JiraAuthMode::OAuth => CAPABILITIES,
+    }
+}
 
-pub struct JiraClient;
+/// [`ProviderClient`] on top of the `kirei-provider-jira` client.
+pub struct JiraClient {
+    api: provider::JiraClient,
+    default_project: Option<String>,
+    states: StateOverrides,
+    capabilities: Capabilities,
+}
 
 impl JiraClient {
-    pub fn new() -> Self {
-        Self
+    pub fn new(auth: JiraAuth, server_url: String, default_project: Option<String>) -> Self {
+        let mode = match auth {
+            JiraAuth::Basic { .. } => JiraAuthMode::Basic,
+            JiraAuth::PersonalAccessToken(_) => JiraAuthMode::Pat,
+            JiraAuth::OAuth(_) => JiraAuthMode::OAuth,
+        };
+        Self {
+            api: provider::JiraClient::new(auth, server_url, default_project.clone()),
+            default_project,
+            states: StateOverrides::new(),
+            capabilities: capabilities(mode),
+        }
+    }
+
+    /// Sends requests through `http`; see [`http_client`](crate::unified::registry::http_client).
+    pub fn with_http(mut self, http: HttpClient) -> Self {
+        self.api = self.api.with_http(http);
+        self
+    }
+
+    pub fn with_state_map(mut self, states: StateOverrides) -> Self {
+        self.states = states;
+        self
+    }
+
+    /// Links issues to `site_url`, the site people browse, when the API is
+    /// reached elsewhere.
+    pub fn with_site_url(mut self, site_url: &str) -> Self {
+        self.api = self.api.with_site_url(site_url);
+        self
+    }
+
+    /// Creates issues of type `issue_type` instead of `Task`.
+    pub fn with_issue_type(mut self, issue_type: impl Into<String>) -> Self {
+        self.api = self.api.with_issue_type(issue_type);
+        self
+    }
+
+    fn resolve_project(&self, override_project: Option<&String>) -> Result<String, UnifiedError> {
+        override_project
+            .cloned()
+            .or_else(|| self.default_project.clone())
+            .ok_or(UnifiedError::MissingDefault {
+                provider: ProviderId::Jira,
+                scope: "project",
+            })
+    }
+
+    /// Translates a query into JQL scoped to `project`.
+    fn jql(&self, project: &str, query: &Query) -> Result<String, UnifiedError> {
+        let mut conditions = vec![format!("project = {}", quote(project))];
+        match query.state() {
+            StateFilter::Open => conditions.push("statusCategory != Done".into()),
+            StateFilter::Closed => conditions.push("statusCategory = Done".into()),
+            StateFilter::All => {}
+            StateFilter::Is(state) => {
+                // Statuses mapped in the config are exact; otherwise fall back to
+                // the status category where one corresponds to the state.
+                let statuses: Vec<_> = self
+                    .states
+                    .iter()
+                    .filter(|(_, mapped)| **mapped == state)
+                    .map(|(name, _)| quote(name))
+                    .collect();
+                conditions.push(match state {
+                    _ if !statuses.is_empty() => format!("status in ({})", statuses.join(", ")),
+                    UnifiedState::Todo => "statusCategory = \"To Do\"".into(),
+                    UnifiedState::InProgress => "statusCategory = \"In Progress\"".into(),
+                    UnifiedState::Done => "statusCategory = Done".into(),
+                    _ => {
+                        return Err(
+                            Clause::State(StateFilter::Is(state)).unsupported(ProviderId::Jira)
+                        );
+                    }
+                });
+            }
+        }
+
+        // JQL reads absolute dates in the user's time zone, which is unknown
+        // here, so bounds go as minutes relative to now. Rounding widens the
+        // range rather than missing an issue.
+        let now = Utc::now();
+        let timestamp = |bound: &DateBound| match bound {
+            DateBound::After(at) => {
+                format!(">= \"-{}m\"", (now - *at).num_minutes().max(0) + 1)
+            }
+            DateBound::Before(at) => format!("< \"-{}m\"", (now - *at).num_minutes().max(0)),
+        };
+        for clause in query.filters() {
+            conditions.push(match clause {
+                Clause::Label(label) => format!("labels = {}", quote(label)),
+                Clause::Assignee(Assignee::Me) => "assignee = currentUser()".into(),
+                Clause::Assignee(Assignee::User(user)) => format!("assignee = {}", quote(user)),
+                Clause::Updated(bound) => format!("updated {}", timestamp(bound)),
+                Clause::Created(bound) => format!("created {}", timestamp(bound)),
+                Clause::Text(text) => format!("text ~ {}", quote(text)),
+                Clause::State(_) => continue,
+            });
+        }
+
+        Ok(format!(
+            "{} ORDER BY created DESC",
+            conditions.join(" AND ")
+        ))
+    }
+
+    fn to_unified(&self, issue: JiraIssue) -> UnifiedIssue {
+        // Status categories are fixed (new / indeterminate / done); the status
+        // name refines them where the category is too coarse.
+        let named = UnifiedState::from_name(&issue.status);
+        let default_state = match issue.status_category.as_deref() {
+            Some("new") if named == Some(UnifiedState::Backlog) => UnifiedState::Backlog,
+            Some("new") => UnifiedState::Todo,
+            Some("indeterminate") if named == Some(UnifiedState::InReview) => {
+                UnifiedState::InReview
+            }
+            Some("indeterminate") => UnifiedState::InProgress,
+            Some("done") if named == Some(UnifiedState::Canceled) => UnifiedState::Canceled,
+            Some("done") => UnifiedState::Done,
+            _ => named.unwrap_or_default(),
+        };
+
+        let mut unified =
+            UnifiedIssue::new(ProviderId::Jira, issue.key, issue.summary, issue.status);
+        unified.unified_state = UnifiedState::resolve(&self.states, &unified.state, default_state);
+        unified.description = issue.description;
+        unified.assignees = issue.assignees;
+        unified.labels = issue.labels;
+        unified.priority = issue
+            .priority
+            .as_deref()
+            .and_then(UnifiedPriority::from_jira);
+        unified.created_at = issue.created;
+        unified.updated_at = issue.updated;
+        unified.due_date = issue.due_date;
+        unified.comment_count = Some(issue.comment_count);
+        unified.url = issue.url;
+        unified.raw_payload = issue.raw;
+        unified
+    }
+}
+
+impl From<JiraError> for UnifiedError {
+    fn from(err: JiraError) -> Self {
+        match err {
+            JiraError::Http(err) => UnifiedError::Http(err),
+            JiraError::MissingCredentials => UnifiedError::MissingToken(ProviderId::Jira),
+            JiraError::ProjectRequired => UnifiedError::MissingDefault {
+                provider: ProviderId::Jira,
+                scope: "project",
+            },
+            JiraError::ServerUrlRequired => UnifiedError::MissingSetting {
+                provider: ProviderId::Jira,
+                setting: "server URL",
+            },
+            JiraError::Unauthorized(message) => UnifiedError::Unauthorized {
+                provider: ProviderId::Jira,
+                message,
+            },
+            JiraError::Forbidden(message) => UnifiedError::Forbidden {
+                provider: ProviderId::Jira,
+                message,
+            },
+            JiraError::NotFound(message) => UnifiedError::NotFound {
+                provider: ProviderId::Jira,
+                message,
+            },
+            JiraError::RateLimited { reset, message } => UnifiedError::RateLimited {
+                provider: ProviderId::Jira,
+                reset,
+                message,
+            },
+            JiraError::Validation { field, message } => UnifiedError::Validation {
+                provider: ProviderId::Jira,
+                field,
+                message,
+            },
+            JiraError::UnexpectedResponse(body) => UnifiedError::UnexpectedResponse(body),
+            other => UnifiedError::Configuration(other.to_string()),
+        }
     }
 }
 
@@ -16,11 +238,420 @@ impl ProviderClient for JiraClient {
         ProviderId::Jira
     }
 
-    async fn list(&self, _query: UnifiedListQuery) -> Result<Vec<UnifiedIssue>, UnifiedError> {
-        Err(UnifiedError::NotImplemented(ProviderId::Jira))
+    fn capabilities(&self) -> Capabilities {
+        self.capabilities
+    }
+
+    async fn list_page(
+        &self,
+        query: &UnifiedListQuery,
+        cursor: Option<String>,
+    ) -> Result<IssuePage, UnifiedError> {
+        let project = self.resolve_project(query.project.as_ref())?;
+        let jql = self.jql(&project, &query.search)?;
+        let page = self
+            .api
+            .search_page(&jql, query.page_size(100), cursor)
+            .await?;
+
+        Ok(IssuePage {
+            issues: page
+                .issues
+                .into_iter()
+                .map(|issue| self.to_unified(issue))
+                .collect(),
+            next: page.next,
+        })
+    }
+
+    async fn refresh(
+        &self,
+        query: &UnifiedListQuery,
+        cached: Option<Snapshot>,
+    ) -> Result<Snapshot, UnifiedError> {
+        cache::refresh_since(self, query, cached).await
+    }
+
+    fn sync_state(&self, query: &UnifiedListQuery, started: DateTime<Utc>) -> SyncState {
+        cache::updated_since(query, started)
+    }
+
+    async fn create(&self, params: UnifiedCreateParams) -> Result<UnifiedIssue, UnifiedError> {
+        self.capabilities.check_create(ProviderId::Jira, &params)?;
+        let create = JiraIssueCreate {
+            summary: params.title,
+            description: params.body,
+            ..Default::default()
+        };
+        let issue = self.api.create_issue_with(params.project, &create).await?;
+        Ok(self.to_unified(issue))
+    }
+
+    async fn get(&self, id: &str) -> Result<UnifiedIssue, UnifiedError> {
+        let issue = self.api.get_issue(id).await?;
+        Ok(self.to_unified(issue))
+    }
+
+    async fn update(
+        &self,
+        id: &str,
+        params: UnifiedUpdateParams,
+    ) -> Result<UnifiedIssue, UnifiedError> {
+        let update = JiraIssueUpdate {
+            summary: params.title,
+            description: params.body,
+        };
+        let issue = self.api.update_issue(id, &update).await?;
+        Ok(self.to_unified(issue))
+    }
+
+    async fn close(&self, id: &str) -> Result<UnifiedIssue, UnifiedError> {
+        let issue = self.api.transition_to(id, "done").await?;
+        Ok(self.to_unified(issue))
+    }
+
+    async fn reopen(&self, id: &str) -> Result<UnifiedIssue, UnifiedError> {
+        let issue = self.api.transition_to(id, "new").await?;
+        Ok(self.to_unified(issue))
+    }
+
+    async fn delete(&self, id: &str) -> Result<(), UnifiedError> {
+        self.api.delete_issue(id).await?;
+        Ok(())
     }
+}
+
+/// Quotes a JQL string literal.
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{Value, json};
+
+    use super::*;
+    use crate::unified::conformance::{
+        Conformance, FakeApi, FakeBackend, StubRequest, StubResponse, StubServer,
+    };
+
+    /// A project behind one version of the REST API: v2 pages searches by
+    /// offset, v3 by token. Like a real project, it only takes the issue
+    /// types its scheme has.
+    struct FakeProject {
+        base_url: String,
+        version: u8,
+        issue_types: &'static [&'static str],
+    }
+
+    impl FakeProject {
+        fn prefix(&self) -> String {
+            format!("/rest/api/{}/", self.version)
+        }
+    }
+
+    impl FakeApi for FakeProject {
+        fn routes(&self) -> Vec<(&'static str, String)> {
+            ["GET", "POST", "PUT", "DELETE"]
+                .into_iter()
+                .map(|method| (method, self.prefix()))
+                .collect()
+        }
+
+        fn issue(&self, number: i64, created: DateTime<Utc>, title: &str, body: Value) -> Value {
+            let id = (10000 + number).to_string();
+            json!({
+                "id": id,
+                "key": format!("KIR-{}", number),
+                "self": format!("{}{}issue/{}", self.base_url, self.prefix(), id),
+                "fields": {
+                    "summary": title,
+                    "description": body,
+                    "status": status("To Do", "new"),
+                    "assignee": null,
+                    "labels": [],
+                    "priority": { "name": "Medium" },
+                    "created": timestamp(created),
+                    "updated": timestamp(created),
+                    "duedate": null,
+                    "comment": { "total": 0 },
+                },
+            })
+        }
+
+        fn is_open(issue: &Value) -> bool {
+            issue["fields"]["status"]["statusCategory"]["key"] != "done"
+        }
+
+        /// `{"errorMessages": [...], "errors": {"field": "message"}}`, with
+        /// the field errors filled in when the request was rejected.
+        fn error(status: u16, message: &str) -> StubResponse {
+            let body = match status {
+                400 | 422 => json!({ "errorMessages": [], "errors": { "summary": message } }),
+                _ => json!({ "errorMessages": [message], "errors": {} }),
+            };
+            StubResponse::json(status, body)
+        }
+
+        fn answer(project: &mut FakeBackend<Self>, request: &StubRequest) -> StubResponse {
+            let prefix = project.api.prefix();
+            let path = request.path().trim_start_matches(&prefix);
+            let segments: Vec<&str> = path.split('/').collect();
+            let body = request.json();
+            let version = project.api.version;
+            match (request.method.as_str(), segments.as_slice()) {
+                ("GET", ["search"]) if version == 2 => search(project, request),
+                ("GET", ["search", "jql"]) if version == 3 => search(project, request),
+                ("POST", ["issue"]) => {
+                    let fields = &body["fields"];
+                    let issue_type = fields["issuetype"]["name"].as_str().unwrap_or_default();
+                    if !project.api.issue_types.contains(&issue_type) {
+                        return StubResponse::json(
+                            400,
+                            json!({
+                                "errorMessages": [],
+                                "errors": { "issuetype": "Specify a valid issue type" },
+                            }),
+                        );
+                    }
+                    let title = fields["summary"].as_str().unwrap_or_default();
+                    let issue = project.create(title, fields["description"].clone());
+                    StubResponse::json(
+                        201,
+                        json!({ "id": issue["id"], "key": issue["key"], "self": issue["self"] }),
+                    )
+                }
+                ("GET", ["issue", key]) => match project.find(keyed(key)) {
+                    Some(issue) => StubResponse::json(200, issue.clone()),
+                    None => not_found(),
+                },
+                ("PUT", ["issue", key]) => edit(project, key, |issue| {
+                    for field in ["summary", "description"] {
+                        if let Some(value) = body["fields"].get(field) {
+                            issue["fields"][field] = value.clone();
+                        }
+                    }
+                }),
+                ("DELETE", ["issue", key]) if project.remove(keyed(key)) => {
+                    StubResponse::json(204, Value::Null)
+                }
+                ("GET", ["issue", key, "transitions"]) => match project.find(keyed(key)) {
+                    Some(_) => StubResponse::json(
+                        200,
+                        json!({ "transitions": [
+                            { "id": "11", "name": "To Do", "to": status("To Do", "new") },
+                            { "id": "31", "name": "Done", "to": status("Done", "done") },
+                        ] }),
+                    ),
+                    None => not_found(),
+                },
+                ("POST", ["issue", key, "transitions"]) => {
+                    let to = match body["transition"]["id"].as_str() {
+                        Some("11") => status("To Do", "new"),
+                        Some("31") => status("Done", "done"),
+                        _ => {
+                            return StubResponse::json(
+                                400,
+                                json!({
+                                    "errorMessages": ["Transition id is not valid for this issue."],
+                                    "errors": {},
+                                }),
+                            );
+                        }
+                    };
+                    edit(project, key, |issue| issue["fields"]["status"] = to)
+                }
+                _ => not_found(),
+            }
+        }
+    }
+
+    /// Unresolved issues, newest first: the default search kirei runs.
+    fn search(project: &FakeBackend<FakeProject>, request: &StubRequest) -> StubResponse {
+        let max_results: usize = request
+            .query("maxResults")
+            .and_then(|n| n.parse().ok())
+            .unwrap_or(50);
+        let cursor = match project.api.version {
+            2 => request.query("startAt"),
+            _ => request.query("nextPageToken"),
+        };
+        let start: usize = cursor.and_then(|n| n.parse().ok()).unwrap_or(0);
+        let (issues, more) = project.page(start, max_results);
+        let end = start + issues.len();
+
+        let body = match project.api.version {
+            2 => json!({
+                "startAt": start,
+                "maxResults": max_results,
+                "total": project.open().len(),
+                "issues": issues,
+            }),
+            _ if more => json!({
+                "issues": issues,
+                "nextPageToken": end.to_string(),
+                "isLast": false,
+            }),
+            _ => json!({ "issues": issues, "isLast": true }),
+        };
+        StubResponse::json(200, body)
+    }
+
+    /// Changes an issue and answers `204 No Content`, as Jira does.
+    fn edit(
+        project: &mut FakeBackend<FakeProject>,
+        key: &str,
+        change: impl FnOnce(&mut Value),
+    ) -> StubResponse {
+        let Some(issue) = project.find_mut(keyed(key)) else {
+            return not_found();
+        };
+        change(issue);
+        issue["fields"]["updated"] = json!(timestamp(Utc::now()));
+        StubResponse::json(204, Value::Null)
+    }
+
+    fn keyed(key: &str) -> impl Fn(&Value) -> bool + '_ {
+        move |issue| issue["key"] == key
+    }
+
+    fn status(name: &str, category: &str) -> Value {
+        json!({ "name": name, "statusCategory": { "key": category } })
+    }
+
+    fn timestamp(at: DateTime<Utc>) -> String {
+        at.format("%Y-%m-%dT%H:%M:%S%.3f%z").to_string()
+    }
+
+    fn not_found() -> StubResponse {
+        FakeProject::error(
+            404,
+            "Issue does not exist or you do not have permission to see it.",
+        )
+    }
+
+    fn jira(auth: &JiraAuth, issue_type: Option<&str>, url: &str) -> JiraClient {
+        let client = JiraClient::new(auth.clone(), url.to_string(), Some("KIR".into()));
+        match issue_type {
+            Some(issue_type) => client.with_issue_type(issue_type),
+            None => client,
+        }
+    }
+
+    async fn conforms(
+        auth: JiraAuth,
+        version: u8,
+        issue_types: &'static [&'static str],
+        issue_type: Option<&str>,
+    ) {
+        let stub = StubServer::start().unwrap();
+        let project = FakeProject {
+            base_url: stub.url().to_string(),
+            version,
+            issue_types,
+        };
+        FakeBackend::serve(&stub, project, 12);
+        let client = jira(&auth, issue_type, stub.url());
+
+        let report = Conformance::new(&client)
+            .with_error_mapping(
+                |url| Box::new(jira(&auth, issue_type, url)),
+                "KIR-7",
+                FakeProject::error,
+            )
+            .run()
+            .await;
+
+        assert!(report.is_ok(), "{}", report);
+        assert_eq!(report.passed.len(), 4);
+    }
+
+    #[tokio::test]
+    async fn cloud_conforms_against_a_fake_project() {
+        let auth = JiraAuth::Basic {
+            email: "kirei@example.com".into(),
+            token: "token".into(),
+        };
+        conforms(auth, 3, &["Task", "Bug"], None).await;
+    }
+
+    #[tokio::test]
+    async fn server_conforms_against_a_fake_project() {
+        let auth = JiraAuth::PersonalAccessToken("token".into());
+        conforms(auth, 2, &["Task", "Bug"], None).await;
+    }
+
+    #[tokio::test]
+    async fn projects_without_tasks_take_the_configured_issue_type() {
+        let auth = JiraAuth::PersonalAccessToken("token".into());
+        conforms(auth, 2, &["Story", "Bug"], Some("Story")).await;
+    }
+
+    #[test]
+    fn server_pages_by_offset_and_cloud_by_token() {
+        let url = "https://jira.example.com".to_string();
+        let server = JiraClient::new(
+            JiraAuth::PersonalAccessToken("token".into()),
+            url.clone(),
+            None,
+        );
+        assert_eq!(server.capabilities().pagination, Pagination::Offset);
+        let cloud = JiraClient::new(JiraAuth::OAuth("token".into()), url, None);
+        assert_eq!(cloud.capabilities().pagination, Pagination::Cursor);
+        assert!(!cloud.capabilities().supports(Capability::DueDates));
+    }
+
+    #[tokio::test]
+    async fn issues_link_to_the_site_not_the_api_gateway() {
+        let stub = StubServer::start().unwrap();
+        let project = FakeProject {
+            base_url: stub.url().to_string(),
+            version: 3,
+            issue_types: &["Task"],
+        };
+        FakeBackend::serve(&stub, project, 1);
+        let auth = JiraAuth::OAuth("token".into());
+
+        let issue = jira(&auth, None, stub.url()).get("KIR-1").await.unwrap();
+        let browse = format!("{}/browse/KIR-1", stub.url().trim_end_matches('/'));
+        assert_eq!(issue.url.as_deref(), Some(browse.as_str()));
+
+        let issue = jira(&auth, None, stub.url())
+            .with_site_url("https://kirei.atlassian.net/")
+            .get("KIR-1")
+            .await
+            .unwrap();
+        assert_eq!(
+            issue.url.as_deref(),
+            Some("https://kirei.atlassian.net/browse/KIR-1")
+        );
+    }
+
+    #[tokio::test]
+    async fn unknown_issue_types_are_rejected() {
+        let stub = StubServer::start().unwrap();
+        let project = FakeProject {
+            base_url: stub.url().to_string(),
+            version: 2,
+            issue_types: &["Story", "Bug"],
+        };
+        FakeBackend::serve(&stub, project, 0);
+        let auth = JiraAuth::PersonalAccessToken("token".into());
+        let client = jira(&auth, None, stub.url());
 
-    async fn create(&self, _params: UnifiedCreateParams) -> Result<UnifiedIssue, UnifiedError> {
-        Err(UnifiedError::NotImplemented(ProviderId::Jira))
+        let error = client
+            .create(UnifiedCreateParams {
+                title: "Needs a Task".into(),
+                ..Default::default()
+            })
+            .await
+            .unwrap_err();
+        match error {
+            UnifiedError::Validation { field, message, .. } => {
+                assert_eq!(field.as_deref(), Some("issuetype"));
+                assert_eq!(message, "Specify a valid issue type");
+            }
+            other => panic!("expected a validation error, got {:?}", other),
+        }
     }
 }
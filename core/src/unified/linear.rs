@@ -1,70 +1,186 @@
+use crate::unified::cache;
+use crate::unified::query::{Assignee, Clause, DateBound, Query, StateFilter};
 use crate::unified::{
-    ProviderClient, ProviderId, UnifiedCreateParams, UnifiedError, UnifiedIssue, UnifiedListQuery,
+    Capabilities, Capability, IssuePage, Pagination, ProviderClient, ProviderId, Snapshot,
+    StateOverrides, SyncState, UnifiedCreateParams, UnifiedError, UnifiedIssue, UnifiedListQuery,
+    UnifiedPriority, UnifiedState, UnifiedUpdateParams,
+};
+use chrono::{DateTime, Utc};
+use kirei_http::HttpClient;
+use kirei_provider_linear::{
+    self as provider, LinearError, LinearIssue, LinearIssueCreate, LinearIssueUpdate,
 };
-use reqwest::Client;
 use serde_json::{Value, json};
 
-const LINEAR_GRAPHQL: &str = "https://api.linear.app/graphql";
+/// Linear models every field kirei knows about.
+pub const CAPABILITIES: Capabilities = Capabilities {
+    features: &Capability::ALL,
+    pagination: Pagination::Cursor,
+};
 
+/// [`ProviderClient`] on top of the `kirei-provider-linear` client.
 pub struct LinearClient {
-    http: Client,
-    token: String,
-    default_workspace: Option<String>,
+    api: provider::LinearClient,
+    states: StateOverrides,
 }
 
 impl LinearClient {
-    pub fn new(token: String, default_workspace: Option<String>) -> Self {
+    pub fn new(token: String, default_workspace: Option<String>, base_url: Option<&str>) -> Self {
         Self {
-            http: Client::new(),
-            token,
-            default_workspace,
+            api: provider::LinearClient::new(token, default_workspace, base_url),
+            states: StateOverrides::new(),
         }
     }
 
-    fn workspace_variable(&self, override_workspace: Option<&String>) -> Option<String> {
-        override_workspace
-            .cloned()
-            .or_else(|| self.default_workspace.clone())
-    }
-
-    fn map_node(&self, value: &Value) -> UnifiedIssue {
-        let id = value
-            .get("id")
-            .and_then(Value::as_str)
-            .unwrap_or_default()
-            .to_string();
-        let title = value
-            .get("title")
-            .and_then(Value::as_str)
-            .unwrap_or("untitled")
-            .to_string();
-        let state = value
-            .get("state")
-            .and_then(|state| state.get("name"))
-            .and_then(Value::as_str)
-            .unwrap_or("unknown")
-            .to_string();
-        let url = value.get("url").and_then(Value::as_str).map(str::to_string);
-
-        UnifiedIssue {
-            id,
-            title,
-            state,
-            url,
-            provider: ProviderId::Linear,
-            raw_payload: value.clone(),
+    /// Sends requests through `http`; see [`http_client`](crate::unified::registry::http_client).
+    pub fn with_http(mut self, http: HttpClient) -> Self {
+        self.api = self.api.with_http(http);
+        self
+    }
+
+    pub fn with_state_map(mut self, states: StateOverrides) -> Self {
+        self.states = states;
+        self
+    }
+
+    /// Moves the issue to the first workflow state of one of `types`.
+    async fn move_to(&self, id: &str, types: &[&str]) -> Result<UnifiedIssue, UnifiedError> {
+        let state_id = self.api.workflow_state_id(id, types).await?;
+        let update = LinearIssueUpdate {
+            state_id: Some(state_id),
+            ..Default::default()
+        };
+        let issue = self.api.update_issue(id, &update).await?;
+        Ok(self.to_unified(issue))
+    }
+
+    fn to_unified(&self, issue: LinearIssue) -> UnifiedIssue {
+        // Linear's state type is authoritative; the name only separates review
+        // columns from other "started" states.
+        let default_state = match issue.state_type.as_deref() {
+            Some("triage" | "backlog") => UnifiedState::Backlog,
+            Some("started")
+                if UnifiedState::from_name(&issue.state) == Some(UnifiedState::InReview) =>
+            {
+                UnifiedState::InReview
+            }
+            Some("started") => UnifiedState::InProgress,
+            Some("completed") => UnifiedState::Done,
+            Some("canceled") => UnifiedState::Canceled,
+            _ => UnifiedState::Todo,
+        };
+
+        let mut unified = UnifiedIssue::new(ProviderId::Linear, issue.id, issue.title, issue.state);
+        unified.unified_state = UnifiedState::resolve(&self.states, &unified.state, default_state);
+        unified.identifier = issue.identifier;
+        unified.description = issue
+            .description
+            .filter(|description| !description.is_empty());
+        unified.assignees = issue.assignees;
+        unified.labels = issue.labels;
+        unified.priority = issue
+            .priority
+            .and_then(|priority| UnifiedPriority::from_linear(priority.into()));
+        unified.created_at = issue.created_at;
+        unified.updated_at = issue.updated_at;
+        unified.due_date = issue.due_date;
+        unified.comment_count = Some(issue.comment_count);
+        unified.url = issue.url;
+        unified.raw_payload = issue.raw;
+        unified
+    }
+}
+
+impl From<LinearError> for UnifiedError {
+    fn from(err: LinearError) -> Self {
+        match err {
+            LinearError::Http(err) => UnifiedError::Http(err),
+            LinearError::MissingCredentials => UnifiedError::MissingToken(ProviderId::Linear),
+            LinearError::WorkspaceRequired => UnifiedError::MissingDefault {
+                provider: ProviderId::Linear,
+                scope: "workspace",
+            },
+            LinearError::Unauthorized(message) => UnifiedError::Unauthorized {
+                provider: ProviderId::Linear,
+                message,
+            },
+            LinearError::Forbidden(message) => UnifiedError::Forbidden {
+                provider: ProviderId::Linear,
+                message,
+            },
+            LinearError::NotFound(message) => UnifiedError::NotFound {
+                provider: ProviderId::Linear,
+                message,
+            },
+            LinearError::RateLimited { reset, message } => UnifiedError::RateLimited {
+                provider: ProviderId::Linear,
+                reset,
+                message,
+            },
+            LinearError::Validation { field, message } => UnifiedError::Validation {
+                provider: ProviderId::Linear,
+                field,
+                message,
+            },
+            LinearError::UnexpectedResponse(body) => UnifiedError::UnexpectedResponse(body),
+            other => UnifiedError::Configuration(other.to_string()),
         }
     }
+}
+
+/// Translates a query into an `IssueFilter`, one `and` operand per clause.
+fn issue_filter(query: &Query) -> Value {
+    let state_types = |types: &[&str]| json!({ "state": { "type": { "in": types } } });
+    // "started" covers both progress and review columns; the name tells them apart.
+    let started = |review: &str| {
+        json!({
+            "state": {
+                "type": { "eq": "started" },
+                "name": { review: "review" },
+            }
+        })
+    };
 
-    fn extract_nodes(response: &Value) -> Result<Vec<Value>, UnifiedError> {
-        response
-            .get("data")
-            .and_then(|data| data.get("issues"))
-            .and_then(|issues| issues.get("nodes"))
-            .and_then(Value::as_array)
-            .map(|nodes| nodes.clone())
-            .ok_or_else(|| UnifiedError::UnexpectedResponse(response.to_string()))
+    let mut filters = Vec::new();
+    match query.state() {
+        StateFilter::Open => {
+            filters.push(json!({ "state": { "type": { "nin": ["completed", "canceled"] } } }))
+        }
+        StateFilter::Closed => filters.push(state_types(&["completed", "canceled"])),
+        StateFilter::All => {}
+        StateFilter::Is(UnifiedState::Backlog) => filters.push(state_types(&["backlog", "triage"])),
+        StateFilter::Is(UnifiedState::Todo) => filters.push(state_types(&["unstarted"])),
+        StateFilter::Is(UnifiedState::InProgress) => filters.push(started("notContainsIgnoreCase")),
+        StateFilter::Is(UnifiedState::InReview) => filters.push(started("containsIgnoreCase")),
+        StateFilter::Is(UnifiedState::Done) => filters.push(state_types(&["completed"])),
+        StateFilter::Is(UnifiedState::Canceled) => filters.push(state_types(&["canceled"])),
     }
+
+    let timestamp = |bound: &DateBound| match bound {
+        DateBound::After(at) => json!({ "gte": at.to_rfc3339() }),
+        DateBound::Before(at) => json!({ "lt": at.to_rfc3339() }),
+    };
+    for clause in query.filters() {
+        filters.push(match clause {
+            Clause::Label(label) => {
+                json!({ "labels": { "some": { "name": { "eqIgnoreCase": label } } } })
+            }
+            Clause::Assignee(Assignee::Me) => json!({ "assignee": { "isMe": { "eq": true } } }),
+            Clause::Assignee(Assignee::User(user)) => {
+                json!({ "assignee": { "displayName": { "eqIgnoreCase": user } } })
+            }
+            Clause::Updated(bound) => json!({ "updatedAt": timestamp(bound) }),
+            Clause::Created(bound) => json!({ "createdAt": timestamp(bound) }),
+            Clause::Text(text) => json!({
+                "or": [
+                    { "title": { "containsIgnoreCase": text } },
+                    { "description": { "containsIgnoreCase": text } },
+                ]
+            }),
+            Clause::State(_) => continue,
+        });
+    }
+    json!({ "and": filters })
 }
 
 #[async_trait::async_trait]
@@ -73,85 +189,256 @@ impl ProviderClient for LinearClient {
         ProviderId::Linear
     }
 
-    async fn list(&self, query: UnifiedListQuery) -> Result<Vec<UnifiedIssue>, UnifiedError> {
-        let payload = json!({
-            "query": r#"
-                query($workspaceId: String) {
-                    issues(first: 20, filter: { state: { type: { neq: "completed" } } }) {
-                        nodes {
-                            id
-                            title
-                            description
-                            url
-                            state {
-                                name
-                            }
-                        }
-                    }
-                }
-            "#,
-            "variables": {
-                "workspaceId": self.workspace_variable(query.workspace.as_ref())
-            }
-        });
+    fn capabilities(&self) -> Capabilities {
+        CAPABILITIES
+    }
 
-        let response = self
-            .http
-            .post(LINEAR_GRAPHQL)
-            .bearer_auth(&self.token)
-            .json(&payload)
-            .send()
+    async fn list_page(
+        &self,
+        query: &UnifiedListQuery,
+        cursor: Option<String>,
+    ) -> Result<IssuePage, UnifiedError> {
+        let page = self
+            .api
+            .list_issues_page(
+                query.workspace.clone(),
+                issue_filter(&query.search),
+                query.page_size(100),
+                cursor,
+            )
             .await?;
 
-        let body: Value = response.json().await?;
-        let nodes = Self::extract_nodes(&body)?;
-        Ok(nodes.into_iter().map(|node| self.map_node(&node)).collect())
+        Ok(IssuePage {
+            issues: page
+                .issues
+                .into_iter()
+                .map(|issue| self.to_unified(issue))
+                .collect(),
+            next: page.next,
+        })
+    }
+
+    async fn refresh(
+        &self,
+        query: &UnifiedListQuery,
+        cached: Option<Snapshot>,
+    ) -> Result<Snapshot, UnifiedError> {
+        cache::refresh_since(self, query, cached).await
+    }
+
+    fn sync_state(&self, query: &UnifiedListQuery, started: DateTime<Utc>) -> SyncState {
+        cache::updated_since(query, started)
     }
 
     async fn create(&self, params: UnifiedCreateParams) -> Result<UnifiedIssue, UnifiedError> {
-        let workspace = self.workspace_variable(params.workspace.as_ref());
-        let input = serde_json::json!({
-            "title": params.title,
-            "description": params.body,
-            "teamId": workspace,
-        });
-        let payload = json!({
-            "query": r#"
-                mutation IssueCreate($input: IssueCreateInput!) {
-                    issueCreate(input: $input) {
-                        success
-                        issue {
-                            id
-                            title
-                            description
-                            url
-                            state {
-                                name
-                            }
-                        }
-                    }
-                }
-            "#,
-            "variables": {
-                "input": input
+        CAPABILITIES.check_create(ProviderId::Linear, &params)?;
+        let create = LinearIssueCreate {
+            title: params.title,
+            description: params.body,
+            priority: params.priority.map(|priority| priority.to_linear()),
+            due_date: params.due_date,
+        };
+        let issue = self
+            .api
+            .create_issue_with(params.workspace, &create)
+            .await?;
+        Ok(self.to_unified(issue))
+    }
+
+    async fn get(&self, id: &str) -> Result<UnifiedIssue, UnifiedError> {
+        let issue = self.api.get_issue(id).await?;
+        Ok(self.to_unified(issue))
+    }
+
+    async fn update(
+        &self,
+        id: &str,
+        params: UnifiedUpdateParams,
+    ) -> Result<UnifiedIssue, UnifiedError> {
+        let update = LinearIssueUpdate {
+            title: params.title,
+            description: params.body,
+            ..Default::default()
+        };
+        let issue = self.api.update_issue(id, &update).await?;
+        Ok(self.to_unified(issue))
+    }
+
+    async fn close(&self, id: &str) -> Result<UnifiedIssue, UnifiedError> {
+        self.move_to(id, &["completed"]).await
+    }
+
+    async fn reopen(&self, id: &str) -> Result<UnifiedIssue, UnifiedError> {
+        self.move_to(id, &["unstarted", "backlog"]).await
+    }
+
+    async fn delete(&self, id: &str) -> Result<(), UnifiedError> {
+        self.api.delete_issue(id).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::unified::conformance::{
+        Conformance, FakeApi, FakeBackend, StubRequest, StubResponse, StubServer,
+    };
+
+    /// The workflow states of the fake team: id, name, type.
+    const STATES: [(&str, &str, &str); 5] = [
+        ("state-backlog", "Backlog", "backlog"),
+        ("state-todo", "Todo", "unstarted"),
+        ("state-started", "In Progress", "started"),
+        ("state-done", "Done", "completed"),
+        ("state-canceled", "Canceled", "canceled"),
+    ];
+
+    /// A team behind a GraphQL endpoint that tells the client's operations
+    /// apart by name.
+    struct FakeTeam;
+
+    impl FakeApi for FakeTeam {
+        fn routes(&self) -> Vec<(&'static str, String)> {
+            vec![("POST", "/graphql".into())]
+        }
+
+        fn issue(&self, number: i64, created: DateTime<Utc>, title: &str, body: Value) -> Value {
+            json!({
+                "id": format!("issue-{}", number),
+                "identifier": format!("KIR-{}", number),
+                "title": title,
+                "description": body,
+                "url": format!("https://linear.app/kirei/issue/KIR-{}", number),
+                "priority": 0,
+                "createdAt": created.to_rfc3339(),
+                "updatedAt": created.to_rfc3339(),
+                "dueDate": null,
+                "state": { "name": "Todo", "type": "unstarted" },
+                "assignee": null,
+                "labels": { "nodes": [] },
+                "comments": { "nodes": [] },
+            })
+        }
+
+        fn is_open(issue: &Value) -> bool {
+            !matches!(
+                issue["state"]["type"].as_str(),
+                Some("completed" | "canceled")
+            )
+        }
+
+        /// GraphQL `errors` told apart by `extensions.code`, with the text
+        /// meant for people in `userPresentableMessage`.
+        fn error(status: u16, message: &str) -> StubResponse {
+            let (code, kind, raw) = match status {
+                401 => (
+                    "AUTHENTICATION_ERROR",
+                    "authentication error",
+                    "Authentication required, not authenticated",
+                ),
+                403 => ("FORBIDDEN", "forbidden", "Forbidden"),
+                404 => ("INVALID_INPUT", "invalid input", "Entity not found: Issue"),
+                429 => ("RATELIMITED", "ratelimited", "Rate limit exceeded"),
+                _ => (
+                    "INVALID_INPUT",
+                    "invalid input",
+                    "Argument Validation Error",
+                ),
+            };
+            StubResponse::json(
+                status,
+                json!({
+                    "errors": [{
+                        "message": raw,
+                        "extensions": {
+                            "code": code,
+                            "type": kind,
+                            "userPresentableMessage": message,
+                        },
+                    }],
+                    "data": null,
+                }),
+            )
+        }
+
+        fn answer(team: &mut FakeBackend<Self>, request: &StubRequest) -> StubResponse {
+            let body = request.json();
+            let query = body["query"].as_str().unwrap_or_default();
+            let variables = &body["variables"];
+            let input = &variables["input"];
+            let id = variables["id"].as_str().unwrap_or_default();
+            let data = if query.contains("issueCreate") {
+                let title = input["title"].as_str().unwrap_or_default();
+                let issue = team.create(title, input["description"].clone());
+                json!({ "issueCreate": { "success": true, "issue": issue } })
+            } else if query.contains("issueUpdate") {
+                json!({ "issueUpdate": { "success": true, "issue": update(team, id, input) } })
+            } else if query.contains("issueDelete") {
+                json!({ "issueDelete": { "success": team.remove(|issue| issue["id"] == id) } })
+            } else if query.contains("team {") {
+                let states: Vec<Value> = STATES
+                    .iter()
+                    .enumerate()
+                    .map(|(position, (id, _, kind))| {
+                        json!({ "id": id, "type": kind, "position": position })
+                    })
+                    .collect();
+                json!({ "issue": { "team": { "states": { "nodes": states } } } })
+            } else if query.contains("issues(") {
+                let first = variables["first"].as_u64().unwrap_or(50) as usize;
+                let (nodes, more) = team.page_after("id", variables["after"].as_str(), first);
+                let end = nodes.last().map(|issue| issue["id"].clone());
+                json!({
+                    "issues": {
+                        "nodes": nodes,
+                        "pageInfo": { "hasNextPage": more, "endCursor": end },
+                    },
+                })
+            } else {
+                // Linear looks issues up by id and by identifier alike.
+                let issue = team.find(|issue| issue["id"] == id || issue["identifier"] == id);
+                json!({ "issue": issue })
+            };
+            StubResponse::json(200, json!({ "data": data }))
+        }
+    }
+
+    fn update(team: &mut FakeBackend<FakeTeam>, id: &str, input: &Value) -> Value {
+        let Some(issue) = team.find_mut(|issue| issue["id"] == id) else {
+            return Value::Null;
+        };
+        for key in ["title", "description"] {
+            if let Some(value) = input.get(key) {
+                issue[key] = value.clone();
             }
-        });
+        }
+        if let Some((_, name, kind)) = STATES
+            .iter()
+            .find(|(state, _, _)| input["stateId"] == *state)
+        {
+            issue["state"] = json!({ "name": name, "type": kind });
+        }
+        issue["updatedAt"] = json!(Utc::now().to_rfc3339());
+        issue.clone()
+    }
 
-        let response = self
-            .http
-            .post(LINEAR_GRAPHQL)
-            .bearer_auth(&self.token)
-            .json(&payload)
-            .send()
-            .await?;
+    fn linear(url: &str) -> LinearClient {
+        LinearClient::new("token".into(), Some("team".into()), Some(url))
+    }
+
+    #[tokio::test]
+    async fn conforms_against_a_fake_team() {
+        let stub = StubServer::start().unwrap();
+        FakeBackend::serve(&stub, FakeTeam, 12);
+        let client = linear(stub.url());
 
-        let body: Value = response.json().await?;
-        let issue = body
-            .get("data")
-            .and_then(|data| data.get("issueCreate"))
-            .and_then(|create| create.get("issue"))
-            .ok_or_else(|| UnifiedError::UnexpectedResponse(body.to_string()))?;
+        let report = Conformance::new(&client)
+            .with_error_mapping(|url| Box::new(linear(url)), "KIR-7", FakeTeam::error)
+            .run()
+            .await;
 
-        Ok(self.map_node(issue))
+        assert!(report.is_ok(), "{}", report);
+        assert_eq!(report.passed.len(), 4);
     }
 }
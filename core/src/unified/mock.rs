@@ -0,0 +1,735 @@
+//! A provider that keeps its issues in memory, or in a JSON file when one is
+//! configured, so every command can run without credentials: for demos, for
+//! tests of the CLI and for tools built on top of kirei.
+//!
+//! It starts out with a few dozen seeded issues spread over every state, and
+//! can be slowed down or made to fail to see how callers cope.
+
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use chrono::{DateTime, Duration as Age, NaiveDate, TimeZone, Utc};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::config::MockConfig;
+use crate::unified::query::{Assignee, Clause};
+use crate::unified::{
+    Capabilities, Capability, IssuePage, Pagination, ProviderClient, ProviderId, StateOverrides,
+    UnifiedCreateParams, UnifiedError, UnifiedIssue, UnifiedListQuery, UnifiedPriority,
+    UnifiedState, UnifiedUpdateParams,
+};
+
+/// Supports everything, so any command path can be exercised.
+pub const CAPABILITIES: Capabilities = Capabilities {
+    features: &Capability::ALL,
+    pagination: Pagination::Offset,
+};
+
+/// Store file used instead of `mock.store` from the config.
+pub const STORE_ENV: &str = "KIREI_MOCK_STORE";
+
+/// Who `assignee:@me` means.
+pub const MOCK_USER: &str = "demo";
+
+const KEY: &str = "MOCK";
+const SEED_COUNT: u64 = 42;
+const DEFAULT_PAGE_SIZE: usize = 20;
+
+/// A failure the mock provider can be told to produce.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MockFailure {
+    Unauthorized,
+    Forbidden,
+    NotFound,
+    RateLimited,
+    Validation,
+    Timeout,
+    ServerError,
+}
+
+impl std::str::FromStr for MockFailure {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        serde_json::from_value(serde_json::Value::String(s.replace('-', "_"))).map_err(|_| {
+            format!(
+                "unknown failure '{}' (expected unauthorized, forbidden, not_found, rate_limited, validation, timeout or server_error)",
+                s
+            )
+        })
+    }
+}
+
+impl MockFailure {
+    fn to_error(self) -> UnifiedError {
+        let provider = ProviderId::Mock;
+        let message = "injected by the mock provider".to_string();
+        match self {
+            MockFailure::Unauthorized => UnifiedError::Unauthorized { provider, message },
+            MockFailure::Forbidden => UnifiedError::Forbidden { provider, message },
+            MockFailure::NotFound => UnifiedError::NotFound { provider, message },
+            MockFailure::RateLimited => UnifiedError::RateLimited {
+                provider,
+                reset: Some(Utc::now() + Age::minutes(1)),
+                message,
+            },
+            MockFailure::Validation => UnifiedError::Validation {
+                provider,
+                field: None,
+                message,
+            },
+            MockFailure::Timeout => UnifiedError::Timeout(Duration::from_secs(30)),
+            MockFailure::ServerError => {
+                UnifiedError::UnexpectedResponse(format!("500 Internal Server Error: {}", message))
+            }
+        }
+    }
+}
+
+/// An issue as the mock provider stores it; also its raw payload.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct MockIssue {
+    number: u64,
+    title: String,
+    #[serde(default)]
+    description: Option<String>,
+    state: String,
+    #[serde(default)]
+    assignees: Vec<String>,
+    #[serde(default)]
+    labels: Vec<String>,
+    #[serde(default)]
+    priority: Option<UnifiedPriority>,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+    #[serde(default)]
+    due_date: Option<NaiveDate>,
+    #[serde(default)]
+    comments: u32,
+}
+
+impl MockIssue {
+    fn key(&self) -> String {
+        format!("{}-{}", KEY, self.number)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct MockData {
+    next_number: u64,
+    issues: Vec<MockIssue>,
+}
+
+impl MockData {
+    /// Deterministic, so tests can count on the same issues every time.
+    fn seeded() -> Self {
+        const TITLES: &[&str] = &[
+            "Crash when opening settings",
+            "Add dark mode",
+            "Login button misaligned on mobile",
+            "Export issues as CSV",
+            "Slow search on large projects",
+            "Document the public API",
+            "Flaky integration test in CI",
+            "Support SSO with Okta",
+            "Typo in onboarding email",
+            "Upgrade to the latest TLS library",
+            "Keyboard shortcuts for triage",
+            "Memory leak in background sync",
+            "Show avatars in the activity feed",
+            "Rate limit webhook deliveries",
+        ];
+        const LABELS: &[&str] = &["bug", "feature", "docs", "performance", "security", "ux"];
+        const PEOPLE: &[&str] = &[MOCK_USER, "alice", "bob", "carol"];
+
+        let start = Utc.with_ymd_and_hms(2025, 1, 6, 9, 0, 0).unwrap();
+        let issues = (1..=SEED_COUNT)
+            .map(|number| {
+                let n = number as usize;
+                let created_at = start + Age::hours(number as i64 * 19);
+                let state = UnifiedState::ALL[n % UnifiedState::ALL.len()];
+                MockIssue {
+                    number,
+                    title: TITLES[n % TITLES.len()].to_string(),
+                    description: (!n.is_multiple_of(3))
+                        .then(|| format!("Seeded issue {} of the mock provider.", number)),
+                    state: state.display_name().to_string(),
+                    assignees: (n % 4 != 3)
+                        .then(|| PEOPLE[n % PEOPLE.len()].to_string())
+                        .into_iter()
+                        .collect(),
+                    labels: std::iter::once(LABELS[n % LABELS.len()])
+                        .chain(
+                            n.is_multiple_of(5)
+                                .then_some(LABELS[(n + 2) % LABELS.len()]),
+                        )
+                        .map(String::from)
+                        .collect(),
+                    priority: match n % 5 {
+                        0 => None,
+                        1 => Some(UnifiedPriority::Low),
+                        2 => Some(UnifiedPriority::Medium),
+                        3 => Some(UnifiedPriority::High),
+                        _ => Some(UnifiedPriority::Urgent),
+                    },
+                    created_at,
+                    updated_at: created_at + Age::hours((n % 7) as i64 * 5),
+                    due_date: n
+                        .is_multiple_of(6)
+                        .then(|| (created_at + Age::days(14)).date_naive()),
+                    comments: (n % 4) as u32,
+                }
+            })
+            .collect();
+        Self {
+            next_number: SEED_COUNT + 1,
+            issues,
+        }
+    }
+}
+
+/// [`ProviderClient`] over an in-memory store, optionally saved to a file.
+pub struct MockClient {
+    data: Mutex<MockData>,
+    /// Where changes are saved; `None` keeps them in memory only.
+    path: Option<PathBuf>,
+    latency: Duration,
+    failure: Option<MockFailure>,
+    /// Fraction of calls that fail with `failure`.
+    failure_rate: f64,
+    page_size: usize,
+    states: StateOverrides,
+}
+
+impl Default for MockClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockClient {
+    /// An in-memory store with the seeded issues.
+    pub fn new() -> Self {
+        Self {
+            data: Mutex::new(MockData::seeded()),
+            path: None,
+            latency: Duration::ZERO,
+            failure: None,
+            failure_rate: 1.0,
+            page_size: DEFAULT_PAGE_SIZE,
+            states: StateOverrides::new(),
+        }
+    }
+
+    /// Reads the store from `path`, seeding it if the file does not exist
+    /// yet, and saves every change back.
+    pub fn with_store(mut self, path: impl Into<PathBuf>) -> Result<Self, UnifiedError> {
+        let path = path.into();
+        let data = match fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes).map_err(|err| {
+                UnifiedError::Configuration(format!(
+                    "invalid mock store {}: {}",
+                    path.display(),
+                    err
+                ))
+            })?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => MockData::seeded(),
+            Err(err) => {
+                return Err(UnifiedError::Configuration(format!(
+                    "cannot read mock store {}: {}",
+                    path.display(),
+                    err
+                )));
+            }
+        };
+        self.data = Mutex::new(data);
+        self.path = Some(path);
+        Ok(self)
+    }
+
+    /// Waits this long before answering each call.
+    pub fn with_latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    /// Fails a `rate` fraction of calls (`1.0` for all of them) with `failure`.
+    pub fn with_failure(mut self, failure: MockFailure, rate: f64) -> Self {
+        self.failure = Some(failure);
+        self.failure_rate = rate.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Largest page a listing returns, to exercise pagination.
+    pub fn with_page_size(mut self, page_size: usize) -> Self {
+        self.page_size = page_size.max(1);
+        self
+    }
+
+    pub fn with_state_map(mut self, states: StateOverrides) -> Self {
+        self.states = states;
+        self
+    }
+
+    /// Builds the client `config` describes, with `KIREI_MOCK_STORE`
+    /// taking precedence over `mock.store`.
+    pub fn from_config(config: &MockConfig) -> Result<Self, UnifiedError> {
+        let mut client = Self::new().with_state_map(config.state_map.clone());
+        let store = std::env::var_os(STORE_ENV)
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| config.store.clone());
+        if let Some(path) = store {
+            client = client.with_store(path)?;
+        }
+        if let Some(latency) = config.latency_ms {
+            client = client.with_latency(Duration::from_millis(latency));
+        }
+        if let Some(failure) = config.failure {
+            client = client.with_failure(failure, config.failure_rate.unwrap_or(1.0));
+        }
+        if let Some(page_size) = config.page_size {
+            client = client.with_page_size(page_size);
+        }
+        Ok(client)
+    }
+
+    /// The configured delay, then the configured failure if this call draws it.
+    async fn simulate(&self) -> Result<(), UnifiedError> {
+        if !self.latency.is_zero() {
+            tokio::time::sleep(self.latency).await;
+        }
+        match self.failure {
+            Some(failure) if rand::thread_rng().gen_bool(self.failure_rate) => {
+                Err(failure.to_error())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn to_unified(&self, issue: &MockIssue) -> UnifiedIssue {
+        let default_state = UnifiedState::from_name(&issue.state).unwrap_or_default();
+        let mut unified = UnifiedIssue::new(
+            ProviderId::Mock,
+            issue.key(),
+            issue.title.clone(),
+            issue.state.clone(),
+        );
+        unified.unified_state = UnifiedState::resolve(&self.states, &issue.state, default_state);
+        unified.description = issue.description.clone();
+        unified.assignees = issue.assignees.clone();
+        unified.labels = issue.labels.clone();
+        unified.priority = issue.priority;
+        unified.created_at = Some(issue.created_at);
+        unified.updated_at = Some(issue.updated_at);
+        unified.due_date = issue.due_date;
+        unified.comment_count = Some(issue.comments);
+        unified.url = Some(format!("https://mock.kirei.invalid/issues/{}", issue.key()));
+        unified.raw_payload = serde_json::to_value(issue).unwrap_or_default();
+        unified
+    }
+
+    /// Runs `change` on the issue `id`, saves the store and returns the result.
+    fn modify(
+        &self,
+        id: &str,
+        change: impl FnOnce(&mut MockIssue),
+    ) -> Result<UnifiedIssue, UnifiedError> {
+        let number = parse_number(id)?;
+        let mut data = self.lock();
+        let issue = data
+            .issues
+            .iter_mut()
+            .find(|issue| issue.number == number)
+            .ok_or_else(|| not_found(id))?;
+        change(issue);
+        issue.updated_at = Utc::now();
+        let unified = self.to_unified(issue);
+        self.save(&data)?;
+        Ok(unified)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, MockData> {
+        self.data.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn save(&self, data: &MockData) -> Result<(), UnifiedError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let write = || -> Result<(), anyhow::Error> {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            fs::write(path, serde_json::to_vec_pretty(data)?)?;
+            Ok(())
+        };
+        write().map_err(|err| {
+            UnifiedError::Configuration(format!(
+                "cannot write mock store {}: {}",
+                path.display(),
+                err
+            ))
+        })
+    }
+
+    /// Whether `issue` passes the clauses [`Query::matches`] leaves to the
+    /// provider: assignees and free text.
+    ///
+    /// [`Query::matches`]: crate::unified::Query::matches
+    fn matches_search(issue: &UnifiedIssue, clause: &Clause) -> bool {
+        match clause {
+            Clause::Assignee(Assignee::Me) => issue.assignees.iter().any(|a| a == MOCK_USER),
+            Clause::Assignee(Assignee::User(user)) => {
+                issue.assignees.iter().any(|a| a.eq_ignore_ascii_case(user))
+            }
+            Clause::Text(text) => {
+                let text = text.to_lowercase();
+                issue.title.to_lowercase().contains(&text)
+                    || issue
+                        .description
+                        .as_deref()
+                        .is_some_and(|body| body.to_lowercase().contains(&text))
+            }
+            _ => true,
+        }
+    }
+}
+
+/// Accepts `MOCK-12`, `#12` or `12`.
+fn parse_number(id: &str) -> Result<u64, UnifiedError> {
+    let id = id.trim();
+    let number = id
+        .split_once('-')
+        .filter(|(key, _)| key.eq_ignore_ascii_case(KEY))
+        .map_or(id.trim_start_matches('#'), |(_, number)| number);
+    number
+        .parse()
+        .map_err(|_| UnifiedError::InvalidId(ProviderId::Mock, id.to_string()))
+}
+
+fn not_found(id: &str) -> UnifiedError {
+    UnifiedError::NotFound {
+        provider: ProviderId::Mock,
+        message: format!("no issue {}", id),
+    }
+}
+
+#[async_trait::async_trait]
+impl ProviderClient for MockClient {
+    fn provider(&self) -> ProviderId {
+        ProviderId::Mock
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        CAPABILITIES
+    }
+
+    async fn list_page(
+        &self,
+        query: &UnifiedListQuery,
+        cursor: Option<String>,
+    ) -> Result<IssuePage, UnifiedError> {
+        self.simulate().await?;
+        let offset = match cursor {
+            Some(cursor) => cursor.parse().map_err(|_| {
+                UnifiedError::UnexpectedResponse(format!("invalid mock cursor '{}'", cursor))
+            })?,
+            None => 0,
+        };
+        let page_size = query.page_size(self.page_size);
+
+        let data = self.lock();
+        let mut issues: Vec<UnifiedIssue> = data
+            .issues
+            .iter()
+            .map(|issue| self.to_unified(issue))
+            .filter(|issue| {
+                query.search.matches(issue)
+                    && query
+                        .search
+                        .filters()
+                        .all(|clause| Self::matches_search(issue, clause))
+            })
+            .collect();
+        // Newest first, like every other provider.
+        issues.sort_by_key(|issue| std::cmp::Reverse(issue.created_at));
+
+        let end = (offset + page_size).min(issues.len());
+        let next = (end < issues.len()).then(|| end.to_string());
+        Ok(IssuePage {
+            issues: issues.drain(offset.min(end)..end).collect(),
+            next,
+        })
+    }
+
+    async fn create(&self, params: UnifiedCreateParams) -> Result<UnifiedIssue, UnifiedError> {
+        self.simulate().await?;
+        if params.title.trim().is_empty() {
+            return Err(UnifiedError::Validation {
+                provider: ProviderId::Mock,
+                field: Some("title".into()),
+                message: "title cannot be empty".into(),
+            });
+        }
+
+        let mut data = self.lock();
+        let now = Utc::now();
+        let issue = MockIssue {
+            number: data.next_number,
+            title: params.title,
+            description: params.body,
+            state: UnifiedState::Todo.display_name().to_string(),
+            assignees: Vec::new(),
+            labels: Vec::new(),
+            priority: params.priority,
+            created_at: now,
+            updated_at: now,
+            due_date: params.due_date,
+            comments: 0,
+        };
+        data.next_number += 1;
+        let unified = self.to_unified(&issue);
+        data.issues.push(issue);
+        self.save(&data)?;
+        Ok(unified)
+    }
+
+    async fn get(&self, id: &str) -> Result<UnifiedIssue, UnifiedError> {
+        self.simulate().await?;
+        let number = parse_number(id)?;
+        let data = self.lock();
+        data.issues
+            .iter()
+            .find(|issue| issue.number == number)
+            .map(|issue| self.to_unified(issue))
+            .ok_or_else(|| not_found(id))
+    }
+
+    async fn update(
+        &self,
+        id: &str,
+        params: UnifiedUpdateParams,
+    ) -> Result<UnifiedIssue, UnifiedError> {
+        self.simulate().await?;
+        self.modify(id, |issue| {
+            if let Some(title) = params.title {
+                issue.title = title;
+            }
+            if let Some(body) = params.body {
+                issue.description = Some(body);
+            }
+        })
+    }
+
+    async fn close(&self, id: &str) -> Result<UnifiedIssue, UnifiedError> {
+        self.simulate().await?;
+        self.modify(id, |issue| {
+            issue.state = UnifiedState::Done.display_name().to_string();
+        })
+    }
+
+    async fn reopen(&self, id: &str) -> Result<UnifiedIssue, UnifiedError> {
+        self.simulate().await?;
+        self.modify(id, |issue| {
+            issue.state = UnifiedState::Todo.display_name().to_string();
+        })
+    }
+
+    async fn delete(&self, id: &str) -> Result<(), UnifiedError> {
+        self.simulate().await?;
+        let number = parse_number(id)?;
+        let mut data = self.lock();
+        let before = data.issues.len();
+        data.issues.retain(|issue| issue.number != number);
+        if data.issues.len() == before {
+            return Err(not_found(id));
+        }
+        self.save(&data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::config::Config;
+    use crate::error::{Diagnostic, ErrorCategory};
+    use crate::unified::conformance::Conformance;
+    use crate::unified::registry::ProviderBuild;
+    use crate::unified::{DEFAULT_LIST_TIMEOUT, ProviderRegistry};
+
+    fn configured(config: &Config) -> ProviderBuild {
+        Ok(Box::new(MockClient::from_config(&config.mock)?))
+    }
+
+    fn healthy(_: &Config) -> ProviderBuild {
+        Ok(Box::new(MockClient::new()))
+    }
+
+    /// The mock as configured by `mock`, next to a healthy provider serving
+    /// the seeded issues.
+    fn registry(default_provider: &str, mock: MockConfig) -> ProviderRegistry {
+        let mut config = Config::default();
+        config.default_provider = default_provider.into();
+        config.mock = mock;
+        let mut registry = ProviderRegistry::new(config);
+        registry.register(ProviderId::Github, healthy);
+        registry.register(ProviderId::Mock, configured);
+        registry
+    }
+
+    fn limited(limit: usize) -> UnifiedListQuery {
+        UnifiedListQuery {
+            limit: Some(limit),
+            ..Default::default()
+        }
+    }
+
+    async fn injected(failure: MockFailure) -> UnifiedError {
+        let client = MockClient::new().with_failure(failure, 1.0);
+        client.get("MOCK-1").await.unwrap_err()
+    }
+
+    #[tokio::test]
+    async fn conforms() {
+        let client = MockClient::new();
+        let report = Conformance::new(&client).run().await;
+        assert!(report.is_ok(), "{}", report);
+    }
+
+    /// The mock has no HTTP to stub, so its injected failures stand in for
+    /// the error statuses of [`conformance::error_mapping`](crate::unified::conformance::error_mapping).
+    #[tokio::test]
+    async fn injected_failures_map_like_error_statuses() {
+        assert!(matches!(
+            injected(MockFailure::Unauthorized).await,
+            UnifiedError::Unauthorized { .. }
+        ));
+        assert!(matches!(
+            injected(MockFailure::Forbidden).await,
+            UnifiedError::Forbidden { .. }
+        ));
+        assert!(matches!(
+            injected(MockFailure::NotFound).await,
+            UnifiedError::NotFound { .. }
+        ));
+        assert!(matches!(
+            injected(MockFailure::Validation).await,
+            UnifiedError::Validation { .. }
+        ));
+        assert!(matches!(
+            injected(MockFailure::RateLimited).await,
+            UnifiedError::RateLimited { reset: Some(_), .. }
+        ));
+        assert!(matches!(
+            injected(MockFailure::Timeout).await,
+            UnifiedError::Timeout(_)
+        ));
+        let err = injected(MockFailure::ServerError).await;
+        assert!(
+            err.to_string().contains("injected by the mock provider"),
+            "{}",
+            err
+        );
+    }
+
+    #[tokio::test]
+    async fn registry_pages_through_the_configured_page_size() {
+        let registry = registry(
+            "mock",
+            MockConfig {
+                page_size: Some(7),
+                ..Default::default()
+            },
+        );
+
+        let snapshot = registry
+            .list_cached(ProviderId::Mock, &limited(20))
+            .await
+            .unwrap();
+
+        let ids: HashSet<&str> = snapshot
+            .issues
+            .iter()
+            .map(|issue| issue.id.as_str())
+            .collect();
+        assert_eq!(snapshot.issues.len(), 20);
+        assert_eq!(ids.len(), 20, "an issue was listed twice");
+    }
+
+    #[tokio::test]
+    async fn injected_failures_are_reported_per_provider() {
+        let registry = registry(
+            "mock",
+            MockConfig {
+                failure: Some(MockFailure::Forbidden),
+                ..Default::default()
+            },
+        );
+
+        let fan_out = registry.list_all(&limited(10), DEFAULT_LIST_TIMEOUT).await;
+
+        assert_eq!(fan_out.issues.len(), 10);
+        assert_eq!(fan_out.fetched.len(), 1);
+        let [(provider, err)] = fan_out.failures.as_slice() else {
+            panic!("expected one failure, got {:?}", fan_out.failures);
+        };
+        assert_eq!(*provider, ProviderId::Mock);
+        let diagnostic = Diagnostic::from(err);
+        assert_eq!(diagnostic.category, ErrorCategory::Permission);
+        assert_eq!(diagnostic.fix.as_deref(), Some("kirei config mock --reset"));
+    }
+
+    #[tokio::test]
+    async fn slow_providers_time_out_in_the_fan_out() {
+        let registry = registry(
+            "mock",
+            MockConfig {
+                latency_ms: Some(1000),
+                ..Default::default()
+            },
+        );
+        let timeout = Duration::from_millis(50);
+
+        let fan_out = registry.list_all(&limited(10), timeout).await;
+
+        assert_eq!(fan_out.issues.len(), 10);
+        assert!(
+            matches!(
+                fan_out.failures.as_slice(),
+                [(ProviderId::Mock, UnifiedError::Timeout(waited))] if *waited == timeout
+            ),
+            "{:?}",
+            fan_out.failures
+        );
+    }
+
+    #[tokio::test]
+    async fn the_mock_stays_out_of_the_fan_out_unless_it_is_the_default() {
+        let registry = registry(
+            "github",
+            MockConfig {
+                failure: Some(MockFailure::ServerError),
+                ..Default::default()
+            },
+        );
+
+        let fan_out = registry.list_all(&limited(10), DEFAULT_LIST_TIMEOUT).await;
+
+        assert!(fan_out.failures.is_empty(), "{:?}", fan_out.failures);
+        assert_eq!(
+            fan_out
+                .fetched
+                .iter()
+                .map(|(id, _)| *id)
+                .collect::<Vec<_>>(),
+            [ProviderId::Github]
+        );
+    }
+}
@@ -1,33 +1,71 @@
+pub mod cache;
+pub mod capabilities;
+#[cfg(any(test, feature = "conformance"))]
+pub mod conformance;
 pub mod github;
-pub mod github_oauth;
 pub mod jira;
 pub mod linear;
+pub mod mock;
+pub mod query;
+pub mod reference;
+pub mod registry;
+pub mod state;
 pub mod trello;
 
+pub use cache::{CacheKey, IssueCache, Snapshot, SyncState};
+pub use capabilities::{Capabilities, Capability, Pagination};
+pub use query::{Query, QueryError};
+pub use reference::{IssueRef, ReferenceError};
+pub use registry::{
+    DEFAULT_LIST_TIMEOUT, FanOut, ProviderRegistry, http_client, resolve_jira_auth, resolve_token,
+};
+pub use state::{StateOverrides, UnifiedState};
+
+use std::ops::Deref;
+
+use chrono::{DateTime, Local, NaiveDate, Utc};
+use futures::stream::{self, BoxStream, StreamExt, TryStreamExt};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
-#[derive(Clone, Debug, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[derive(Clone, Debug, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum ProviderId {
+    #[default]
     Github,
     Linear,
     Trello,
     Jira,
+    /// In-memory issues for demos and tests; see [`mock`].
+    Mock,
 }
 
-impl Default for ProviderId {
-    fn default() -> Self {
-        ProviderId::Github
+impl ProviderId {
+    pub const ALL: [ProviderId; 5] = [
+        ProviderId::Github,
+        ProviderId::Linear,
+        ProviderId::Trello,
+        ProviderId::Jira,
+        ProviderId::Mock,
+    ];
+
+    /// Lowercase name used on the command line and in `Config::default_provider`.
+    pub fn slug(&self) -> &'static str {
+        match self {
+            ProviderId::Github => "github",
+            ProviderId::Linear => "linear",
+            ProviderId::Trello => "trello",
+            ProviderId::Jira => "jira",
+            ProviderId::Mock => "mock",
+        }
     }
-}
 
-impl ProviderId {
     pub fn env_var(&self) -> &'static str {
         match self {
             ProviderId::Github => "KIREI_GITHUB_TOKEN",
             ProviderId::Linear => "KIREI_LINEAR_TOKEN",
             ProviderId::Trello => "KIREI_TRELLO_TOKEN",
             ProviderId::Jira => "KIREI_JIRA_TOKEN",
+            ProviderId::Mock => "KIREI_MOCK_TOKEN",
         }
     }
 
@@ -37,6 +75,18 @@ impl ProviderId {
             ProviderId::Linear => "Linear",
             ProviderId::Trello => "Trello",
             ProviderId::Jira => "Jira",
+            ProviderId::Mock => "Mock",
+        }
+    }
+
+    /// What the built-in client for this provider supports.
+    pub fn capabilities(&self) -> Capabilities {
+        match self {
+            ProviderId::Github => github::CAPABILITIES,
+            ProviderId::Linear => linear::CAPABILITIES,
+            ProviderId::Trello => trello::CAPABILITIES,
+            ProviderId::Jira => jira::CAPABILITIES,
+            ProviderId::Mock => mock::CAPABILITIES,
         }
     }
 }
@@ -56,70 +106,402 @@ impl std::str::FromStr for ProviderId {
             "linear" => Ok(ProviderId::Linear),
             "trello" => Ok(ProviderId::Trello),
             "jira" => Ok(ProviderId::Jira),
+            "mock" => Ok(ProviderId::Mock),
             _ => Err(format!("unknown provider '{}'", s)),
         }
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UnifiedIssue {
+    /// Id accepted by [`ProviderClient::get`] and the other lifecycle calls.
     pub id: String,
+    /// Human-facing reference such as `#12`, `ENG-123` or `PROJ-7`.
+    pub identifier: String,
     pub title: String,
+    pub description: Option<String>,
+    /// State as the provider names it, e.g. a Jira status or a Trello list.
     pub state: String,
+    /// `state` mapped onto the shared workflow model.
+    pub unified_state: UnifiedState,
+    pub assignees: Vec<String>,
+    pub labels: Vec<String>,
+    pub priority: Option<UnifiedPriority>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+    pub due_date: Option<NaiveDate>,
+    pub comment_count: Option<u32>,
     pub url: Option<String>,
     pub provider: ProviderId,
     pub raw_payload: Value,
 }
 
-#[derive(Debug, Clone)]
+/// Priority scale shared by the providers that have one (Linear, Jira).
+#[derive(Clone, Debug, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum UnifiedPriority {
+    Urgent,
+    High,
+    Medium,
+    Low,
+}
+
+impl UnifiedPriority {
+    /// Maps Linear's numeric priority; `0` means "no priority".
+    pub fn from_linear(priority: i64) -> Option<Self> {
+        match priority {
+            1 => Some(UnifiedPriority::Urgent),
+            2 => Some(UnifiedPriority::High),
+            3 => Some(UnifiedPriority::Medium),
+            4 => Some(UnifiedPriority::Low),
+            _ => None,
+        }
+    }
+
+    /// Maps Jira's default priority scheme (`Highest` .. `Lowest`).
+    pub fn from_jira(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "highest" | "blocker" | "critical" => Some(UnifiedPriority::Urgent),
+            "high" | "major" => Some(UnifiedPriority::High),
+            "medium" => Some(UnifiedPriority::Medium),
+            "low" | "lowest" | "minor" | "trivial" => Some(UnifiedPriority::Low),
+            _ => None,
+        }
+    }
+
+    pub fn to_linear(&self) -> u8 {
+        match self {
+            UnifiedPriority::Urgent => 1,
+            UnifiedPriority::High => 2,
+            UnifiedPriority::Medium => 3,
+            UnifiedPriority::Low => 4,
+        }
+    }
+
+    /// Name in Jira's default priority scheme.
+    pub fn to_jira(&self) -> &'static str {
+        match self {
+            UnifiedPriority::Urgent => "Highest",
+            UnifiedPriority::High => "High",
+            UnifiedPriority::Medium => "Medium",
+            UnifiedPriority::Low => "Low",
+        }
+    }
+
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            UnifiedPriority::Urgent => "Urgent",
+            UnifiedPriority::High => "High",
+            UnifiedPriority::Medium => "Medium",
+            UnifiedPriority::Low => "Low",
+        }
+    }
+}
+
+impl std::fmt::Display for UnifiedPriority {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.display_name())
+    }
+}
+
+impl std::str::FromStr for UnifiedPriority {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "urgent" => Ok(UnifiedPriority::Urgent),
+            "high" => Ok(UnifiedPriority::High),
+            "medium" => Ok(UnifiedPriority::Medium),
+            "low" => Ok(UnifiedPriority::Low),
+            _ => Err(format!(
+                "unknown priority '{}' (expected urgent, high, medium or low)",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
 pub struct UnifiedListQuery {
     pub workspace: Option<String>,
     pub repo: Option<String>,
-    pub search: Option<String>,
+    pub board: Option<String>,
+    pub project: Option<String>,
+    /// Filter in the [`query`] syntax; empty lists open issues.
+    pub search: Query,
+    /// Maximum number of issues to return; `None` lists everything.
+    pub limit: Option<usize>,
+}
+
+impl UnifiedListQuery {
+    /// Page size to request from an API that returns at most `max` per page.
+    pub fn page_size(&self, max: usize) -> usize {
+        self.limit.map_or(max, |limit| limit.clamp(1, max))
+    }
+
+    /// The same listing restricted to issues in `state`, replacing any state
+    /// clause in the search.
+    pub fn in_state(&self, state: UnifiedState) -> Self {
+        let mut query = self.clone();
+        query
+            .search
+            .clauses
+            .retain(|clause| !matches!(clause, query::Clause::State(_)));
+        query
+            .search
+            .clauses
+            .push(query::Clause::State(query::StateFilter::Is(state)));
+        query
+    }
+}
+
+/// One page of a listing and the cursor of the page after it.
+#[derive(Debug, Clone, Default)]
+pub struct IssuePage {
+    pub issues: Vec<UnifiedIssue>,
+    /// Opaque to callers: a URL, a GraphQL cursor, an offset or a card id.
+    pub next: Option<String>,
 }
 
-#[derive(Debug, Clone)]
+pub type IssueStream<'a> = BoxStream<'a, Result<UnifiedIssue, UnifiedError>>;
+
+#[derive(Debug, Clone, Default)]
 pub struct UnifiedCreateParams {
     pub workspace: Option<String>,
     pub repo: Option<String>,
+    pub board: Option<String>,
+    pub project: Option<String>,
     pub title: String,
     pub body: Option<String>,
+    pub priority: Option<UnifiedPriority>,
+    pub due_date: Option<NaiveDate>,
+}
+
+/// Fields to change on an existing issue; `None` leaves the field untouched.
+#[derive(Debug, Clone, Default)]
+pub struct UnifiedUpdateParams {
+    pub title: Option<String>,
+    pub body: Option<String>,
 }
 
 #[derive(Debug, thiserror::Error)]
 pub enum UnifiedError {
-    #[error("missing credentials for {0}")]
+    #[error("{0} token not configured")]
     MissingToken(ProviderId),
+    #[error("{provider} {setting} not configured")]
+    MissingSetting {
+        provider: ProviderId,
+        /// What is missing, e.g. `API key`.
+        setting: &'static str,
+    },
+    #[error("no {scope} given and no default {provider} {scope} set")]
+    MissingDefault {
+        provider: ProviderId,
+        /// What issues live in: `repository`, `workspace`, `board` or `project`.
+        scope: &'static str,
+    },
+    #[error("no default provider set")]
+    NoDefaultProvider,
     #[error("provider {0} is not implemented yet")]
     NotImplemented(ProviderId),
+    #[error("provider {0} is not registered")]
+    NotRegistered(ProviderId),
+    #[error("invalid {0} issue id '{1}'")]
+    InvalidId(ProviderId, String),
+    #[error("{provider} rejected the credentials: {message}")]
+    Unauthorized {
+        provider: ProviderId,
+        message: String,
+    },
+    #[error("{provider} denied access: {message}")]
+    Forbidden {
+        provider: ProviderId,
+        message: String,
+    },
+    #[error("not found on {provider}: {message}")]
+    NotFound {
+        provider: ProviderId,
+        message: String,
+    },
+    #[error("{provider} rate limit reached{}: {message}", reset_hint(.reset))]
+    RateLimited {
+        provider: ProviderId,
+        /// When the limit resets, if the provider said.
+        reset: Option<DateTime<Utc>>,
+        message: String,
+    },
+    #[error("{provider} rejected {}: {message}", .field.as_deref().unwrap_or("the request"))]
+    Validation {
+        provider: ProviderId,
+        /// The offending field, if the provider named one.
+        field: Option<String>,
+        message: String,
+    },
     #[error("provider response is malformed: {0}")]
     UnexpectedResponse(String),
     #[error(transparent)]
     Http(#[from] reqwest::Error),
     #[error("configuration error: {0}")]
     Configuration(String),
+    #[error("timed out after {}s", .0.as_secs())]
+    Timeout(std::time::Duration),
+    #[error("invalid query: {0}")]
+    Query(#[from] QueryError),
+    #[error(transparent)]
+    Reference(#[from] ReferenceError),
+    #[error("{provider} cannot filter by {clause}")]
+    UnsupportedQuery {
+        provider: ProviderId,
+        clause: String,
+    },
+    #[error("{provider} does not support {capability}")]
+    Unsupported {
+        provider: ProviderId,
+        capability: Capability,
+    },
+    #[error("no cached {0} issues for this listing yet; run it once without --offline")]
+    NotCached(ProviderId),
+    #[error(transparent)]
+    Vault(#[from] crate::vault::VaultError),
+}
+
+fn reset_hint(reset: &Option<DateTime<Utc>>) -> String {
+    reset
+        .map(|reset| format!(" until {}", reset.with_timezone(&Local).format("%H:%M:%S")))
+        .unwrap_or_default()
 }
 
 #[async_trait::async_trait]
 pub trait ProviderClient: Send + Sync {
     fn provider(&self) -> ProviderId;
 
-    async fn list(&self, query: UnifiedListQuery) -> Result<Vec<UnifiedIssue>, UnifiedError>;
+    fn capabilities(&self) -> Capabilities;
+
+    /// Fetches the page at `cursor`, or the first page when `cursor` is `None`.
+    async fn list_page(
+        &self,
+        query: &UnifiedListQuery,
+        cursor: Option<String>,
+    ) -> Result<IssuePage, UnifiedError>;
+
+    /// Streams issues page by page, stopping after `query.limit` issues.
+    fn list_stream(&self, query: UnifiedListQuery) -> IssueStream<'_> {
+        paged(self, query)
+    }
+
+    /// Collects [`list_stream`](Self::list_stream) into memory.
+    async fn list(&self, query: UnifiedListQuery) -> Result<Vec<UnifiedIssue>, UnifiedError> {
+        self.list_stream(query).try_collect().await
+    }
+
+    /// Lists again for the cache, starting from the previous snapshot when
+    /// there is one. The default ignores it and lists everything.
+    async fn refresh(
+        &self,
+        query: &UnifiedListQuery,
+        cached: Option<Snapshot>,
+    ) -> Result<Snapshot, UnifiedError> {
+        let _ = cached;
+        let issues = self.list(query.clone()).await?;
+        Ok(Snapshot::new(issues, SyncState::None))
+    }
+
+    /// How a snapshot of the whole listing of `query`, started at `started`
+    /// and streamed rather than [refreshed](Self::refresh), is refreshed
+    /// later. The default lists again.
+    fn sync_state(&self, query: &UnifiedListQuery, started: DateTime<Utc>) -> SyncState {
+        let _ = (query, started);
+        SyncState::None
+    }
 
     async fn create(&self, params: UnifiedCreateParams) -> Result<UnifiedIssue, UnifiedError>;
+
+    async fn get(&self, id: &str) -> Result<UnifiedIssue, UnifiedError>;
+
+    async fn update(
+        &self,
+        id: &str,
+        params: UnifiedUpdateParams,
+    ) -> Result<UnifiedIssue, UnifiedError>;
+
+    /// Moves the issue to the provider's closed/done state.
+    async fn close(&self, id: &str) -> Result<UnifiedIssue, UnifiedError>;
+
+    /// Moves a closed issue back to an open state.
+    async fn reopen(&self, id: &str) -> Result<UnifiedIssue, UnifiedError>;
+
+    /// Deletes the issue. Linear moves it to the trash, where it can still be
+    /// restored for a while.
+    async fn delete(&self, id: &str) -> Result<(), UnifiedError>;
+}
+
+/// Pages through a listing as [`ProviderClient::list_stream`] does, through
+/// any pointer to the client, so the stream can own the client it reads.
+pub(crate) fn paged<'a, C, P>(client: P, query: UnifiedListQuery) -> IssueStream<'a>
+where
+    C: ProviderClient + ?Sized,
+    P: Deref<Target = C> + Send + 'a,
+{
+    let limit = query.limit;
+    // `None` once the last page has been fetched.
+    let pages = stream::try_unfold(Some((client, None)), move |state| {
+        let query = query.clone();
+        async move {
+            let Some((client, cursor)) = state else {
+                return Ok::<_, UnifiedError>(None);
+            };
+            let page = client.list_page(&query, cursor).await?;
+            let next = page.next.map(|next| (client, Some(next)));
+            Ok(Some((page.issues, next)))
+        }
+    });
+    let issues = pages
+        .map_ok(|issues| stream::iter(issues.into_iter().map(Ok)))
+        .try_flatten();
+    match limit {
+        Some(limit) => issues.take(limit).boxed(),
+        None => issues.boxed(),
+    }
 }
 
 impl UnifiedIssue {
+    /// An issue with only the required fields set; mappers fill in the rest.
+    pub fn new(provider: ProviderId, id: String, title: String, state: String) -> Self {
+        Self {
+            identifier: id.clone(),
+            id,
+            title,
+            description: None,
+            unified_state: UnifiedState::from_name(&state).unwrap_or_default(),
+            state,
+            assignees: Vec::new(),
+            labels: Vec::new(),
+            priority: None,
+            created_at: None,
+            updated_at: None,
+            due_date: None,
+            comment_count: None,
+            url: None,
+            provider,
+            raw_payload: Value::Null,
+        }
+    }
+
     pub fn display_summary(&self) -> String {
         format!(
-            "{} [{}] {} ({})",
+            "{} {} [{}] {} ({})",
             self.provider.display_name(),
+            self.identifier,
             self.state,
             self.title,
-            self.raw_payload
-                .get("url")
-                .and_then(Value::as_str)
-                .unwrap_or("no-url")
+            self.url.as_deref().unwrap_or("no-url")
         )
     }
 }
+
+/// Parses RFC 3339 timestamps and the Jira style `2024-01-31T09:15:00.000+0000`.
+pub(crate) fn parse_timestamp(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(value)
+        .or_else(|_| DateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S%.f%z"))
+        .ok()
+        .map(|timestamp| timestamp.with_timezone(&Utc))
+}
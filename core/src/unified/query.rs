@@ -0,0 +1,370 @@
+//! Provider-neutral search syntax for listings, e.g.
+//! `state:open label:bug assignee:@me updated:>7d "crash on start"`.
+//!
+//! Clauses are separated by whitespace and all of them must match. Each
+//! provider translates the parsed [`Query`] into its own filter language and
+//! reports clauses it cannot express as [`UnifiedError::UnsupportedQuery`].
+//!
+//! [`UnifiedError::UnsupportedQuery`]: crate::unified::UnifiedError::UnsupportedQuery
+
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+
+use crate::unified::{ProviderId, UnifiedError, UnifiedIssue, UnifiedState, parse_timestamp};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Query {
+    pub clauses: Vec<Clause>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Clause {
+    State(StateFilter),
+    Label(String),
+    Assignee(Assignee),
+    Updated(DateBound),
+    Created(DateBound),
+    Text(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StateFilter {
+    Open,
+    Closed,
+    All,
+    Is(UnifiedState),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Assignee {
+    Me,
+    User(String),
+}
+
+/// `updated:>7d` is `After(now - 7 days)`; `updated:<2024-01-01` is `Before(..)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateBound {
+    After(DateTime<Utc>),
+    Before(DateTime<Utc>),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum QueryError {
+    #[error("unterminated quote in query")]
+    UnterminatedQuote,
+    #[error("unknown query field '{0}' (expected state, label, assignee, updated, created)")]
+    UnknownField(String),
+    #[error("missing value for '{0}:'")]
+    MissingValue(String),
+    #[error("invalid value '{value}' for '{field}:'")]
+    InvalidValue { field: String, value: String },
+}
+
+impl Query {
+    pub fn parse(input: &str) -> Result<Self, QueryError> {
+        Self::parse_at(input, Utc::now())
+    }
+
+    /// Parses with relative dates (`7d`) counted back from `now`.
+    pub fn parse_at(input: &str, now: DateTime<Utc>) -> Result<Self, QueryError> {
+        let clauses = tokenize(input)?
+            .into_iter()
+            .map(|(field, value)| Clause::from_token(field, value, now))
+            .collect::<Result<_, _>>()?;
+        Ok(Self { clauses })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.clauses.is_empty()
+    }
+
+    /// The requested state. Listings show open issues unless told otherwise.
+    pub fn state(&self) -> StateFilter {
+        self.clauses
+            .iter()
+            .rev()
+            .find_map(|clause| match clause {
+                Clause::State(state) => Some(*state),
+                _ => None,
+            })
+            .unwrap_or(StateFilter::Open)
+    }
+
+    /// Every clause except the state, which providers handle separately so the
+    /// open-by-default rule applies.
+    pub fn filters(&self) -> impl Iterator<Item = &Clause> {
+        self.clauses
+            .iter()
+            .filter(|clause| !matches!(clause, Clause::State(_)))
+    }
+
+    /// Whether [`matches`](Self::matches) decides the query on its own. Free
+    /// text and assignees follow each provider's search rules, and an upper
+    /// date bound admits issues without them changing, so those need the API.
+    pub fn is_local(&self) -> bool {
+        self.filters().all(|clause| {
+            matches!(
+                clause,
+                Clause::Label(_)
+                    | Clause::Updated(DateBound::After(_))
+                    | Clause::Created(DateBound::After(_))
+            )
+        })
+    }
+
+    /// Evaluates the query against an issue already fetched. Clauses that are
+    /// not [local](Self::is_local) are taken as matching.
+    pub fn matches(&self, issue: &UnifiedIssue) -> bool {
+        let state = match self.state() {
+            StateFilter::Open => !issue.unified_state.is_closed(),
+            StateFilter::Closed => issue.unified_state.is_closed(),
+            StateFilter::All => true,
+            StateFilter::Is(state) => issue.unified_state == state,
+        };
+        let since = |at: Option<DateTime<Utc>>, bound: &DateBound| match bound {
+            DateBound::After(bound) => at.is_some_and(|at| at >= *bound),
+            DateBound::Before(bound) => at.is_some_and(|at| at < *bound),
+        };
+        state
+            && self.filters().all(|clause| match clause {
+                Clause::Label(label) => issue
+                    .labels
+                    .iter()
+                    .any(|name| name.eq_ignore_ascii_case(label)),
+                Clause::Updated(bound) => since(issue.updated_at, bound),
+                Clause::Created(bound) => since(issue.created_at, bound),
+                _ => true,
+            })
+    }
+}
+
+impl std::str::FromStr for Query {
+    type Err = QueryError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+impl Clause {
+    fn from_token(
+        field: Option<String>,
+        value: String,
+        now: DateTime<Utc>,
+    ) -> Result<Self, QueryError> {
+        let Some(field) = field else {
+            return Ok(Clause::Text(value));
+        };
+        if value.is_empty() {
+            return Err(QueryError::MissingValue(field));
+        }
+        let invalid = || QueryError::InvalidValue {
+            field: field.clone(),
+            value: value.clone(),
+        };
+
+        match field.to_lowercase().as_str() {
+            "state" | "is" => match value.to_lowercase().as_str() {
+                "open" => Ok(Clause::State(StateFilter::Open)),
+                "closed" => Ok(Clause::State(StateFilter::Closed)),
+                "all" => Ok(Clause::State(StateFilter::All)),
+                _ => value
+                    .parse()
+                    .map(|state| Clause::State(StateFilter::Is(state)))
+                    .map_err(|_| invalid()),
+            },
+            "label" => Ok(Clause::Label(value)),
+            "assignee" => match value.as_str() {
+                "@me" | "me" => Ok(Clause::Assignee(Assignee::Me)),
+                _ => Ok(Clause::Assignee(Assignee::User(
+                    value.trim_start_matches('@').to_string(),
+                ))),
+            },
+            "updated" => DateBound::parse(&value, now)
+                .map(Clause::Updated)
+                .ok_or_else(invalid),
+            "created" => DateBound::parse(&value, now)
+                .map(Clause::Created)
+                .ok_or_else(invalid),
+            _ => Err(QueryError::UnknownField(field)),
+        }
+    }
+
+    pub(crate) fn unsupported(&self, provider: ProviderId) -> UnifiedError {
+        UnifiedError::UnsupportedQuery {
+            provider,
+            clause: self.to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for Clause {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Clause::State(StateFilter::Open) => write!(f, "state:open"),
+            Clause::State(StateFilter::Closed) => write!(f, "state:closed"),
+            Clause::State(StateFilter::All) => write!(f, "state:all"),
+            Clause::State(StateFilter::Is(state)) => write!(f, "state:{}", state.slug()),
+            Clause::Label(label) => write!(f, "label:{}", label),
+            Clause::Assignee(Assignee::Me) => write!(f, "assignee:@me"),
+            Clause::Assignee(Assignee::User(user)) => write!(f, "assignee:{}", user),
+            Clause::Updated(bound) => write!(f, "updated:{}", bound),
+            Clause::Created(bound) => write!(f, "created:{}", bound),
+            Clause::Text(text) => write!(f, "\"{}\"", text),
+        }
+    }
+}
+
+impl DateBound {
+    /// Accepts an optional `>`/`>=`/`<`/`<=` followed by a relative age
+    /// (`12h`, `7d`, `2w`), a date (`2024-05-01`) or an RFC 3339 timestamp.
+    /// Without an operator the bound means "since".
+    fn parse(value: &str, now: DateTime<Utc>) -> Option<Self> {
+        let (before, rest) = if let Some(rest) = value.strip_prefix('<') {
+            (true, rest)
+        } else {
+            (false, value.strip_prefix('>').unwrap_or(value))
+        };
+        let rest = rest.strip_prefix('=').unwrap_or(rest);
+
+        let at = relative_age(rest)
+            .and_then(|age| now.checked_sub_signed(age))
+            .or_else(|| {
+                NaiveDate::parse_from_str(rest, "%Y-%m-%d")
+                    .ok()
+                    .and_then(|date| date.and_hms_opt(0, 0, 0))
+                    .map(|time| time.and_utc())
+            })
+            .or_else(|| parse_timestamp(rest))?;
+
+        Some(if before {
+            DateBound::Before(at)
+        } else {
+            DateBound::After(at)
+        })
+    }
+}
+
+impl std::fmt::Display for DateBound {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DateBound::After(at) => write!(f, ">{}", at.format("%Y-%m-%dT%H:%M:%SZ")),
+            DateBound::Before(at) => write!(f, "<{}", at.format("%Y-%m-%dT%H:%M:%SZ")),
+        }
+    }
+}
+
+/// `None` for anything but `<n>h`, `<n>d` or `<n>w`, and for ages too long to
+/// represent.
+fn relative_age(value: &str) -> Option<Duration> {
+    let unit = value.chars().last()?;
+    let amount: i64 = value[..value.len() - unit.len_utf8()].parse().ok()?;
+    match unit {
+        'h' => Duration::try_hours(amount),
+        'd' => Duration::try_days(amount),
+        'w' => Duration::try_weeks(amount),
+        _ => None,
+    }
+}
+
+/// Splits the input into `(field, value)` pairs. Quotes group words, either as
+/// free text (`"crash on start"`) or as a value (`label:"help wanted"`).
+fn tokenize(input: &str) -> Result<Vec<(Option<String>, String)>, QueryError> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+
+        let mut field = None;
+        let mut value = String::new();
+        while let Some(&c) = chars.peek() {
+            if c.is_whitespace() {
+                break;
+            }
+            chars.next();
+            match c {
+                '"' => loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => value.push(c),
+                        None => return Err(QueryError::UnterminatedQuote),
+                    }
+                },
+                ':' if field.is_none() && !value.is_empty() => {
+                    field = Some(std::mem::take(&mut value));
+                }
+                c => value.push(c),
+            }
+        }
+        tokens.push((field, value));
+    }
+
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn now() -> DateTime<Utc> {
+        "2024-05-10T12:00:00Z".parse().unwrap()
+    }
+
+    fn invalid(input: &str) -> bool {
+        matches!(
+            Query::parse_at(input, now()),
+            Err(QueryError::InvalidValue { .. })
+        )
+    }
+
+    #[test]
+    fn relative_ages_count_back_from_now() {
+        let query = Query::parse_at("updated:>7d created:<2w", now()).unwrap();
+        assert_eq!(
+            query.clauses,
+            vec![
+                Clause::Updated(DateBound::After(now() - Duration::days(7))),
+                Clause::Created(DateBound::Before(now() - Duration::weeks(2))),
+            ]
+        );
+    }
+
+    #[test]
+    fn dates_and_timestamps_are_accepted() {
+        let query =
+            Query::parse_at("updated:>=2024-05-01 created:2024-05-01T08:30:00Z", now()).unwrap();
+        assert_eq!(
+            query.clauses,
+            vec![
+                Clause::Updated(DateBound::After("2024-05-01T00:00:00Z".parse().unwrap())),
+                Clause::Created(DateBound::After("2024-05-01T08:30:00Z".parse().unwrap())),
+            ]
+        );
+    }
+
+    #[test]
+    fn ages_beyond_the_duration_range_are_invalid() {
+        assert!(invalid("updated:>99999999999999d"));
+        assert!(invalid("updated:>99999999999999h"));
+        assert!(invalid("created:<9999999999999w"));
+        assert!(invalid(&format!("updated:>{}h", i64::MAX)));
+        assert!(invalid(&format!("updated:>{}d", i64::MIN)));
+    }
+
+    #[test]
+    fn ages_before_the_earliest_date_are_invalid() {
+        // Representable as a duration, but not as a point in time.
+        assert!(invalid("updated:>99999999999d"));
+        assert!(invalid("updated:<-99999999999d"));
+    }
+
+    #[test]
+    fn malformed_dates_are_invalid() {
+        assert!(invalid("updated:>7x"));
+        assert!(invalid("updated:>d"));
+        assert!(invalid("created:2024-13-01"));
+    }
+}
@@ -0,0 +1,318 @@
+//! Issue references as people paste them: `gh:owner/repo#12`, `owner/repo#12`,
+//! `ENG-123`, `jira:PROJ-7`, `trello:AbCd1234` or the issue's web URL.
+//!
+//! A reference names the provider and an id its [`ProviderClient`] accepts.
+//! Bare `KEY-123` references are read as Linear issues; Jira keys look the
+//! same, so they need the `jira:` prefix or a `/browse/` URL. So do mock
+//! issues: a bare `MOCK-12` could be a Linear team's.
+//!
+//! [`ProviderClient`]: crate::unified::ProviderClient
+
+use reqwest::Url;
+
+use crate::unified::ProviderId;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IssueRef {
+    pub provider: ProviderId,
+    /// Id in the form the provider's client expects, e.g. `owner/repo#12`.
+    pub id: String,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ReferenceError {
+    #[error(
+        "unrecognized issue reference '{0}' (expected e.g. gh:owner/repo#12, ENG-123, jira:PROJ-7, trello:<shortLink> or a URL)"
+    )]
+    Unrecognized(String),
+    #[error("'{reference}' is not a valid {provider} issue reference")]
+    Invalid {
+        provider: ProviderId,
+        reference: String,
+    },
+}
+
+impl IssueRef {
+    pub fn new(provider: ProviderId, id: impl Into<String>) -> Self {
+        Self {
+            provider,
+            id: id.into(),
+        }
+    }
+
+    pub fn parse(input: &str) -> Result<Self, ReferenceError> {
+        let input = input.trim();
+        if input.starts_with("http://") || input.starts_with("https://") {
+            return Self::from_url(input);
+        }
+
+        if let Some((prefix, id)) = input.split_once(':')
+            && let Some(provider) = prefix_provider(prefix)
+        {
+            return Self::with_provider(provider, id);
+        }
+
+        if is_repo_issue(input) {
+            Ok(Self::new(ProviderId::Github, input))
+        } else if is_issue_key(input) {
+            Ok(Self::new(ProviderId::Linear, input))
+        } else {
+            Err(ReferenceError::Unrecognized(input.to_string()))
+        }
+    }
+
+    /// Checks an id given for a known provider, e.g. after a `jira:` prefix.
+    pub fn with_provider(provider: ProviderId, id: &str) -> Result<Self, ReferenceError> {
+        let valid = match provider {
+            ProviderId::Github => {
+                is_repo_issue(id) || is_number(id.strip_prefix('#').unwrap_or(id))
+            }
+            ProviderId::Jira | ProviderId::Mock => is_issue_key(id) || is_number(id),
+            ProviderId::Linear | ProviderId::Trello => {
+                !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+            }
+        };
+        if valid {
+            Ok(Self::new(provider, id))
+        } else {
+            Err(ReferenceError::Invalid {
+                provider,
+                reference: id.to_string(),
+            })
+        }
+    }
+
+    fn from_url(input: &str) -> Result<Self, ReferenceError> {
+        let unrecognized = || ReferenceError::Unrecognized(input.to_string());
+        let url = Url::parse(input).map_err(|_| unrecognized())?;
+        let host = url.host_str().unwrap_or_default();
+        let segments: Vec<&str> = url
+            .path_segments()
+            .map(|segments| segments.filter(|s| !s.is_empty()).collect())
+            .unwrap_or_default();
+
+        let (provider, id) = match segments.as_slice() {
+            // https://trello.com/c/AbCd1234/12-card-title
+            ["c", short_link, ..] if host.ends_with("trello.com") => {
+                (ProviderId::Trello, short_link.to_string())
+            }
+            // https://linear.app/acme/issue/ENG-123/issue-title
+            [_, "issue", key, ..] if host.ends_with("linear.app") => {
+                (ProviderId::Linear, key.to_string())
+            }
+            // https://acme.atlassian.net/browse/PROJ-7, also on self-hosted servers
+            [.., "browse", key] => (ProviderId::Jira, key.to_string()),
+            // https://github.com/owner/repo/issues/12, also on GitHub Enterprise
+            [owner, repo, "issues", number, ..] => {
+                (ProviderId::Github, format!("{}/{}#{}", owner, repo, number))
+            }
+            _ => return Err(unrecognized()),
+        };
+        Self::with_provider(provider, &id)
+    }
+}
+
+impl std::str::FromStr for IssueRef {
+    type Err = ReferenceError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+/// The canonical, prefixed form, e.g. `gh:owner/repo#12` or `linear:ENG-123`.
+impl std::fmt::Display for IssueRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let prefix = match self.provider {
+            ProviderId::Github => "gh",
+            ProviderId::Linear => "linear",
+            ProviderId::Trello => "trello",
+            ProviderId::Jira => "jira",
+            ProviderId::Mock => "mock",
+        };
+        write!(f, "{}:{}", prefix, self.id)
+    }
+}
+
+fn prefix_provider(prefix: &str) -> Option<ProviderId> {
+    match prefix.to_lowercase().as_str() {
+        "gh" => Some(ProviderId::Github),
+        prefix => prefix.parse().ok(),
+    }
+}
+
+fn is_number(value: &str) -> bool {
+    !value.is_empty() && value.chars().all(|c| c.is_ascii_digit())
+}
+
+/// `owner/repo#12`.
+fn is_repo_issue(value: &str) -> bool {
+    let Some((repo, number)) = value.rsplit_once('#') else {
+        return false;
+    };
+    let name = |part: &str| {
+        !part.is_empty()
+            && part
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    };
+    is_number(number)
+        && repo
+            .split_once('/')
+            .is_some_and(|(o, r)| name(o) && name(r))
+}
+
+/// `MOCK-12`, the keys the mock provider hands out.
+pub(crate) fn is_mock_key(value: &str) -> bool {
+    is_issue_key(value)
+        && value
+            .split_once('-')
+            .is_some_and(|(key, _)| key.eq_ignore_ascii_case("mock"))
+}
+
+/// `ENG-123`: a team or project key, a dash and a number.
+pub(crate) fn is_issue_key(value: &str) -> bool {
+    let Some((key, number)) = value.rsplit_once('-') else {
+        return false;
+    };
+    key.chars().next().is_some_and(|c| c.is_ascii_alphabetic())
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && is_number(number)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parsed(input: &str) -> (ProviderId, String) {
+        let reference = IssueRef::parse(input).unwrap_or_else(|err| panic!("{}: {}", input, err));
+        (reference.provider, reference.id)
+    }
+
+    #[test]
+    fn references_name_their_provider() {
+        let cases = [
+            ("gh:owner/repo#12", ProviderId::Github, "owner/repo#12"),
+            ("GH:owner/repo#12", ProviderId::Github, "owner/repo#12"),
+            ("github:#12", ProviderId::Github, "#12"),
+            ("owner/repo#12", ProviderId::Github, "owner/repo#12"),
+            (
+                "kirei-dev/kirei.rs#7",
+                ProviderId::Github,
+                "kirei-dev/kirei.rs#7",
+            ),
+            ("ENG-123", ProviderId::Linear, "ENG-123"),
+            ("linear:ENG-123", ProviderId::Linear, "ENG-123"),
+            ("jira:PROJ-7", ProviderId::Jira, "PROJ-7"),
+            ("trello:AbCd1234", ProviderId::Trello, "AbCd1234"),
+            ("mock:MOCK-12", ProviderId::Mock, "MOCK-12"),
+            ("MOCK-12", ProviderId::Linear, "MOCK-12"),
+            ("  ENG-123\n", ProviderId::Linear, "ENG-123"),
+        ];
+        for (input, provider, id) in cases {
+            assert_eq!(parsed(input), (provider, id.to_string()), "{}", input);
+        }
+    }
+
+    #[test]
+    fn urls_name_their_provider() {
+        let cases = [
+            (
+                "https://github.com/owner/repo/issues/12",
+                ProviderId::Github,
+                "owner/repo#12",
+            ),
+            (
+                "https://github.com/owner/repo/issues/12#issuecomment-1",
+                ProviderId::Github,
+                "owner/repo#12",
+            ),
+            (
+                "https://ghe.example.com/owner/repo/issues/12",
+                ProviderId::Github,
+                "owner/repo#12",
+            ),
+            (
+                "https://linear.app/acme/issue/ENG-123/issue-title",
+                ProviderId::Linear,
+                "ENG-123",
+            ),
+            (
+                "https://trello.com/c/AbCd1234/12-card-title",
+                ProviderId::Trello,
+                "AbCd1234",
+            ),
+            (
+                "https://acme.atlassian.net/browse/PROJ-7",
+                ProviderId::Jira,
+                "PROJ-7",
+            ),
+            (
+                "https://jira.example.com/jira/browse/PROJ-7",
+                ProviderId::Jira,
+                "PROJ-7",
+            ),
+        ];
+        for (input, provider, id) in cases {
+            assert_eq!(parsed(input), (provider, id.to_string()), "{}", input);
+        }
+    }
+
+    #[test]
+    fn references_print_in_their_prefixed_form() {
+        for input in ["owner/repo#12", "https://github.com/owner/repo/issues/12"] {
+            assert_eq!(
+                IssueRef::parse(input).unwrap().to_string(),
+                "gh:owner/repo#12"
+            );
+        }
+        assert_eq!(
+            IssueRef::parse("ENG-123").unwrap().to_string(),
+            "linear:ENG-123"
+        );
+    }
+
+    #[test]
+    fn malformed_references_are_rejected() {
+        let unrecognized = [
+            "",
+            "12",
+            "ENG",
+            "ENG-",
+            "-123",
+            "123-456",
+            "owner/repo",
+            "owner#12",
+            "unknown:ENG-123",
+            "https://github.com/owner/repo",
+            "https://github.com/owner/repo/pull/12",
+            "https://example.com/",
+            "https://",
+        ];
+        for input in unrecognized {
+            assert!(
+                matches!(IssueRef::parse(input), Err(ReferenceError::Unrecognized(_))),
+                "{}",
+                input
+            );
+        }
+
+        let invalid = [
+            "gh:owner/repo",
+            "gh:#12a",
+            "jira:PROJ",
+            "jira:PROJ-x",
+            "trello:",
+            "trello:AbCd/1234",
+            "https://github.com/owner/repo/issues/twelve",
+            "https://acme.atlassian.net/browse/not-a-key",
+        ];
+        for input in invalid {
+            assert!(
+                matches!(IssueRef::parse(input), Err(ReferenceError::Invalid { .. })),
+                "{}",
+                input
+            );
+        }
+    }
+}
@@ -0,0 +1,641 @@
+use std::env;
+use std::mem;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use futures::future::{self, join_all};
+use futures::stream::{self, StreamExt, TryStreamExt};
+use kirei_http::HttpClient;
+use kirei_provider_jira::JiraAuth;
+
+use crate::config::{Config, JiraAuthMode};
+use crate::unified::github::GitHubClient;
+use crate::unified::jira::{self, JiraClient};
+use crate::unified::linear::LinearClient;
+use crate::unified::mock::MockClient;
+use crate::unified::reference::{self, IssueRef, ReferenceError};
+use crate::unified::trello::TrelloClient;
+use crate::unified::{
+    CacheKey, Capabilities, IssueCache, IssueStream, ProviderClient, ProviderId, Snapshot,
+    SyncState, UnifiedError, UnifiedIssue, UnifiedListQuery, paged,
+};
+use crate::vault;
+
+/// How long each provider gets in [`ProviderRegistry::list_all`] unless configured.
+pub const DEFAULT_LIST_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Outcome of constructing a provider client.
+pub type ProviderBuild = Result<Box<dyn ProviderClient>, UnifiedError>;
+
+/// Builds a provider client from the loaded configuration.
+pub type ProviderFactory = fn(&Config) -> ProviderBuild;
+
+/// Issues gathered from every provider, plus the providers that failed.
+#[derive(Debug, Default)]
+pub struct FanOut {
+    /// Most recently updated first; issues without a timestamp come last.
+    pub issues: Vec<UnifiedIssue>,
+    /// In registry order.
+    pub failures: Vec<(ProviderId, UnifiedError)>,
+    /// When each provider's issues were fetched, in registry order.
+    pub fetched: Vec<(ProviderId, DateTime<Utc>)>,
+}
+
+impl FanOut {
+    /// Combines listings of the same providers, e.g. one per state, keeping
+    /// the `limit` most recently updated issues, a provider's first failure
+    /// and its oldest fetch time.
+    pub fn merge(fan_outs: impl IntoIterator<Item = FanOut>, limit: Option<usize>) -> FanOut {
+        let mut merged = FanOut::default();
+        for fan_out in fan_outs {
+            merged.issues.extend(fan_out.issues);
+            for (id, err) in fan_out.failures {
+                if !merged.failures.iter().any(|(failed, _)| *failed == id) {
+                    merged.failures.push((id, err));
+                }
+            }
+            for (id, fetched_at) in fan_out.fetched {
+                match merged
+                    .fetched
+                    .iter_mut()
+                    .find(|(fetched, _)| *fetched == id)
+                {
+                    Some(entry) => entry.1 = entry.1.min(fetched_at),
+                    None => merged.fetched.push((id, fetched_at)),
+                }
+            }
+        }
+        merged.keep_newest(limit);
+        merged
+    }
+
+    fn keep_newest(&mut self, limit: Option<usize>) {
+        self.issues
+            .sort_by_key(|issue| std::cmp::Reverse(issue.updated_at));
+        if let Some(limit) = limit {
+            self.issues.truncate(limit);
+        }
+    }
+}
+
+/// Maps each [`ProviderId`] to the factory that knows how to construct its client,
+/// so commands can work against any provider through [`ProviderClient`].
+pub struct ProviderRegistry {
+    config: Config,
+    factories: Vec<(ProviderId, ProviderFactory)>,
+    cache: Option<IssueCache>,
+    offline: bool,
+}
+
+impl ProviderRegistry {
+    /// Creates an empty registry. Use [`ProviderRegistry::with_builtin`] for the
+    /// providers that ship with kirei.
+    pub fn new(config: Config) -> Self {
+        Self {
+            config,
+            factories: Vec::new(),
+            cache: None,
+            offline: false,
+        }
+    }
+
+    pub fn with_builtin(config: Config) -> Self {
+        let mut registry = Self::new(config);
+        registry.register(ProviderId::Github, build_github);
+        registry.register(ProviderId::Linear, build_linear);
+        registry.register(ProviderId::Trello, build_trello);
+        registry.register(ProviderId::Jira, build_jira);
+        registry.register(ProviderId::Mock, build_mock);
+        registry
+    }
+
+    /// Keeps listing snapshots in `cache` and refreshes them incrementally.
+    pub fn with_cache(mut self, cache: IssueCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Serves listings from the cache only, never contacting a provider.
+    pub fn with_offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    /// Registers a factory, replacing any factory previously registered for `provider`.
+    pub fn register(&mut self, provider: ProviderId, factory: ProviderFactory) {
+        match self.factories.iter_mut().find(|(id, _)| *id == provider) {
+            Some(entry) => entry.1 = factory,
+            None => self.factories.push((provider, factory)),
+        }
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Registered providers, in registration order.
+    pub fn providers(&self) -> impl Iterator<Item = ProviderId> + '_ {
+        self.factories.iter().map(|(id, _)| *id)
+    }
+
+    /// What `provider` supports as configured; Jira pages differently on
+    /// Server and Data Center than on Cloud.
+    pub fn capabilities(&self, provider: ProviderId) -> Capabilities {
+        match provider {
+            ProviderId::Jira => jira::capabilities(self.config.jira.auth_mode()),
+            _ => provider.capabilities(),
+        }
+    }
+
+    pub fn build(&self, provider: ProviderId) -> ProviderBuild {
+        let (_, factory) = self
+            .factories
+            .iter()
+            .find(|(id, _)| *id == provider)
+            .ok_or(UnifiedError::NotRegistered(provider))?;
+        factory(&self.config)
+    }
+
+    /// Builds every registered provider, keeping the error for the ones that are
+    /// not configured.
+    pub fn build_all(&self) -> Vec<(ProviderId, ProviderBuild)> {
+        self.factories
+            .iter()
+            .map(|(id, factory)| (*id, factory(&self.config)))
+            .collect()
+    }
+
+    /// Lists `provider` through the cache: the previous snapshot is refreshed
+    /// and saved again, or, offline, returned as it is.
+    pub async fn list_cached(
+        &self,
+        provider: ProviderId,
+        query: &UnifiedListQuery,
+    ) -> Result<Snapshot, UnifiedError> {
+        let snapshot = self.snapshot(provider, query).await?;
+        Ok(snapshot.limited(query.limit))
+    }
+
+    /// Streams `provider`'s listing as its pages arrive, and saves the
+    /// snapshot once the last issue has gone past. Offline, or with a
+    /// snapshot the provider refreshes by merging changes into it, the
+    /// listing is put together in memory first, as in
+    /// [`list_cached`](Self::list_cached).
+    pub fn list_stream<'a>(
+        &'a self,
+        provider: ProviderId,
+        query: &'a UnifiedListQuery,
+    ) -> IssueStream<'a> {
+        stream::once(self.open_stream(provider, query))
+            .try_flatten()
+            .boxed()
+    }
+
+    async fn open_stream<'a>(
+        &'a self,
+        provider: ProviderId,
+        query: &'a UnifiedListQuery,
+    ) -> Result<IssueStream<'a>, UnifiedError> {
+        let key = self.cache_key(provider, query);
+        let cached = self.load(&key);
+        let merges = cached.as_ref().is_some_and(|snapshot| {
+            snapshot.covers(query.limit) && !matches!(snapshot.sync, SyncState::None)
+        });
+        if self.offline || merges {
+            let snapshot = match cached {
+                Some(snapshot) if self.offline => snapshot,
+                None if self.offline => return Err(UnifiedError::NotCached(provider)),
+                cached => self.refresh(provider, query, &key, cached).await?,
+            };
+            let issues = snapshot.limited(query.limit).issues;
+            return Ok(stream::iter(issues.into_iter().map(Ok)).boxed());
+        }
+
+        let client = self.build(provider)?;
+        client.capabilities().check_query(provider, &query.search)?;
+        let started = Utc::now();
+        let sync = client.sync_state(query, started);
+        let mut listed = Vec::new();
+        let mut failed = false;
+        // The end of the stream is marked with `None`, when the snapshot is
+        // saved; a listing that failed part way is not.
+        let issues = paged(client, query.clone())
+            .map(Some)
+            .chain(stream::once(future::ready(None)))
+            .filter_map(move |item| {
+                let item = match item {
+                    Some(Ok(issue)) => {
+                        listed.push(issue.clone());
+                        Some(Ok(issue))
+                    }
+                    Some(Err(err)) => {
+                        failed = true;
+                        Some(Err(err))
+                    }
+                    None => {
+                        if !failed && let Some(cache) = &self.cache {
+                            let mut snapshot = Snapshot::new(mem::take(&mut listed), sync.clone())
+                                .limited(query.limit);
+                            snapshot.fetched_at = started;
+                            let _ = cache.save(&key, &snapshot);
+                        }
+                        None
+                    }
+                };
+                future::ready(item)
+            });
+        Ok(issues.boxed())
+    }
+
+    /// The whole snapshot behind [`list_cached`](Self::list_cached), before
+    /// `query.limit` is applied. Snapshots are shared by every limit.
+    async fn snapshot(
+        &self,
+        provider: ProviderId,
+        query: &UnifiedListQuery,
+    ) -> Result<Snapshot, UnifiedError> {
+        let key = self.cache_key(provider, query);
+        let cached = self.load(&key);
+        if self.offline {
+            return cached.ok_or(UnifiedError::NotCached(provider));
+        }
+        self.refresh(provider, query, &key, cached).await
+    }
+
+    /// Refreshes `cached`, or lists from scratch, and saves the result.
+    async fn refresh(
+        &self,
+        provider: ProviderId,
+        query: &UnifiedListQuery,
+        key: &CacheKey,
+        cached: Option<Snapshot>,
+    ) -> Result<Snapshot, UnifiedError> {
+        // A shorter snapshot cannot be refreshed into a longer listing, and a
+        // shorter listing refreshes the snapshot at its own, longer limit.
+        let cached = cached.filter(|snapshot| snapshot.covers(query.limit));
+        let limit = match &cached {
+            Some(snapshot) => snapshot.limit.zip(query.limit).map(|(a, b)| a.max(b)),
+            None => query.limit,
+        };
+        let query = &UnifiedListQuery {
+            limit,
+            ..query.clone()
+        };
+
+        let client = self.build(provider)?;
+        client.capabilities().check_query(provider, &query.search)?;
+        let snapshot = client.refresh(query, cached).await?.limited(limit);
+        if let Some(cache) = &self.cache {
+            // A snapshot that fails to save only costs the next run a full fetch.
+            let _ = cache.save(key, &snapshot);
+        }
+        Ok(snapshot)
+    }
+
+    fn load(&self, key: &CacheKey) -> Option<Snapshot> {
+        self.cache.as_ref().and_then(|cache| cache.load(key))
+    }
+
+    /// Lists every provider concurrently, giving each at most `timeout`, and
+    /// keeps the `query.limit` most recently updated issues of them all.
+    /// Providers without a token, or offline without a snapshot, are skipped
+    /// rather than reported as failures. The mock provider only takes part
+    /// when it is the default, so demo issues never mix with real ones.
+    pub async fn list_all(&self, query: &UnifiedListQuery, timeout: Duration) -> FanOut {
+        let mock_default = self.default_provider().ok() == Some(ProviderId::Mock);
+        let providers: Vec<ProviderId> = self
+            .providers()
+            .filter(|id| *id != ProviderId::Mock || mock_default)
+            .collect();
+
+        // Any passphrase prompt happens here, once, rather than inside the
+        // timed listings; if the vault stays locked, the providers whose
+        // tokens are in it report that.
+        if !self.offline
+            && providers.iter().any(|id| {
+                stored_token(&self.config, *id)
+                    .is_some_and(|token| vault::referenced_name(token).is_some())
+            })
+        {
+            let _ = self.config.unlock_vault();
+        }
+
+        let listings = providers.into_iter().map(|id| async move {
+            let listing = tokio::time::timeout(timeout, self.snapshot(id, query))
+                .await
+                .map_err(|_| UnifiedError::Timeout(timeout))
+                .and_then(|listing| listing);
+            (id, listing)
+        });
+
+        let mut fan_out = FanOut::default();
+        for (id, result) in join_all(listings).await {
+            match result {
+                Ok(snapshot) => {
+                    fan_out.fetched.push((id, snapshot.fetched_at));
+                    fan_out.issues.extend(snapshot.issues);
+                }
+                Err(UnifiedError::MissingToken(_) | UnifiedError::NotCached(_)) => {}
+                Err(err) => fan_out.failures.push((id, err)),
+            }
+        }
+        fan_out.keep_newest(query.limit);
+        fan_out
+    }
+
+    /// The snapshot for `query` on `provider`, told apart by the server and
+    /// the configured default the listing falls back to.
+    fn cache_key(&self, provider: ProviderId, query: &UnifiedListQuery) -> CacheKey {
+        let config = &self.config;
+        let default_target = match provider {
+            ProviderId::Github => config.github.default_repo.as_deref(),
+            ProviderId::Linear => config.linear.default_workspace.as_deref(),
+            ProviderId::Trello => config.trello.default_board.as_deref(),
+            ProviderId::Jira => config.jira.default_project.as_deref(),
+            ProviderId::Mock => None,
+        };
+        let target = format!(
+            "{} {}",
+            config.base_url(provider).unwrap_or_default(),
+            default_target.unwrap_or_default()
+        );
+        CacheKey::new(provider, &target, query)
+    }
+
+    /// The provider from `Config::default_provider`.
+    pub fn default_provider(&self) -> Result<ProviderId, UnifiedError> {
+        let name = self.config.default_provider.trim();
+        if name.is_empty() {
+            return Err(UnifiedError::NoDefaultProvider);
+        }
+        name.parse().map_err(UnifiedError::Configuration)
+    }
+
+    /// Resolves an issue reference, using the config where the syntax alone is
+    /// ambiguous: a bare key whose prefix is the default Jira project is a Jira
+    /// issue, `MOCK-12` is a mock issue when mock is the default provider, and
+    /// ids without any provider marker (`12`, a Trello card id) belong to the
+    /// default provider.
+    pub fn resolve_reference(&self, input: &str) -> Result<IssueRef, UnifiedError> {
+        let input = input.trim();
+        let jira_project = self.config.jira.default_project.as_deref();
+        if let Some((key, _)) = input.split_once('-')
+            && jira_project.is_some_and(|project| project.eq_ignore_ascii_case(key))
+            && reference::is_issue_key(input)
+        {
+            return Ok(IssueRef::new(ProviderId::Jira, input));
+        }
+        if self.default_provider().ok() == Some(ProviderId::Mock) && reference::is_mock_key(input) {
+            return Ok(IssueRef::new(ProviderId::Mock, input));
+        }
+
+        match IssueRef::parse(input) {
+            Err(ReferenceError::Unrecognized(_)) if !self.config.default_provider.is_empty() => {
+                Ok(IssueRef::with_provider(self.default_provider()?, input)?)
+            }
+            parsed => Ok(parsed?),
+        }
+    }
+}
+
+/// Resolves the token for `provider`, preferring its `KIREI_*_TOKEN` env var over
+/// the value stored in the config file.
+pub fn resolve_token(config: &Config, provider: ProviderId) -> Result<String, UnifiedError> {
+    if let Ok(env_token) = env::var(provider.env_var())
+        && !env_token.trim().is_empty()
+    {
+        return Ok(env_token);
+    }
+
+    let configured = configured_token(config, provider)
+        .filter(|token| !token.trim().is_empty())
+        .ok_or(UnifiedError::MissingToken(provider))?;
+    // A vault reference whose entry is gone counts as no token.
+    config
+        .reveal(configured)?
+        .filter(|token| !token.trim().is_empty())
+        .ok_or(UnifiedError::MissingToken(provider))
+}
+
+fn configured_token(config: &Config, provider: ProviderId) -> Option<&str> {
+    match provider {
+        ProviderId::Github => config.github.token.as_deref(),
+        ProviderId::Linear => config.linear.token.as_deref(),
+        ProviderId::Trello => config.trello.token.as_deref(),
+        ProviderId::Jira => config.jira.token.as_deref(),
+        ProviderId::Mock => None,
+    }
+}
+
+/// The token from the config file, when no env var overrides it.
+fn stored_token(config: &Config, provider: ProviderId) -> Option<&str> {
+    let overridden = env::var(provider.env_var()).is_ok_and(|token| !token.trim().is_empty());
+    configured_token(config, provider).filter(|_| !overridden)
+}
+
+/// The Jira credentials for the configured auth mode.
+pub fn resolve_jira_auth(config: &Config) -> Result<JiraAuth, UnifiedError> {
+    let token = resolve_token(config, ProviderId::Jira)?;
+    Ok(match config.jira.auth_mode() {
+        JiraAuthMode::Basic => JiraAuth::Basic {
+            email: config
+                .jira
+                .email
+                .clone()
+                .filter(|email| !email.trim().is_empty())
+                .ok_or(UnifiedError::MissingSetting {
+                    provider: ProviderId::Jira,
+                    setting: "email",
+                })?,
+            token,
+        },
+        JiraAuthMode::Pat => JiraAuth::PersonalAccessToken(token),
+        JiraAuthMode::OAuth => JiraAuth::OAuth(token),
+    })
+}
+
+/// The HTTP client for `provider`, going through the configured proxy and
+/// trusting the configured CA bundle.
+pub fn http_client(config: &Config, provider: ProviderId) -> Result<HttpClient, UnifiedError> {
+    HttpClient::with_options(&config.http_options(provider))
+        .map_err(|err| UnifiedError::Configuration(err.to_string()))
+}
+
+fn build_github(config: &Config) -> ProviderBuild {
+    let token = resolve_token(config, ProviderId::Github)?;
+    Ok(Box::new(
+        GitHubClient::new(
+            token,
+            config.github.default_repo.clone(),
+            config.github.base_url.as_deref(),
+        )
+        .with_http(http_client(config, ProviderId::Github)?)
+        .with_state_map(config.github.state_map.clone()),
+    ))
+}
+
+fn build_linear(config: &Config) -> ProviderBuild {
+    let token = resolve_token(config, ProviderId::Linear)?;
+    Ok(Box::new(
+        LinearClient::new(
+            token,
+            config.linear.default_workspace.clone(),
+            config.linear.base_url.as_deref(),
+        )
+        .with_http(http_client(config, ProviderId::Linear)?)
+        .with_state_map(config.linear.state_map.clone()),
+    ))
+}
+
+fn build_trello(config: &Config) -> ProviderBuild {
+    let token = resolve_token(config, ProviderId::Trello)?;
+    let api_key = config
+        .trello
+        .api_key
+        .clone()
+        .ok_or(UnifiedError::MissingSetting {
+            provider: ProviderId::Trello,
+            setting: "API key",
+        })?;
+    Ok(Box::new(
+        TrelloClient::new(
+            token,
+            api_key,
+            config.trello.default_board.clone(),
+            config.trello.base_url.as_deref(),
+        )
+        .with_http(http_client(config, ProviderId::Trello)?)
+        .with_state_map(config.trello.state_map.clone()),
+    ))
+}
+
+fn build_jira(config: &Config) -> ProviderBuild {
+    let auth = resolve_jira_auth(config)?;
+    let server_url = config
+        .jira
+        .server_url
+        .clone()
+        .ok_or(UnifiedError::MissingSetting {
+            provider: ProviderId::Jira,
+            setting: "server URL",
+        })?;
+    let mut client = JiraClient::new(auth, server_url, config.jira.default_project.clone())
+        .with_http(http_client(config, ProviderId::Jira)?)
+        .with_state_map(config.jira.state_map.clone());
+    if let Some(site_url) = &config.jira.site_url {
+        client = client.with_site_url(site_url);
+    }
+    if let Some(issue_type) = &config.jira.issue_type {
+        client = client.with_issue_type(issue_type);
+    }
+    Ok(Box::new(client))
+}
+
+fn build_mock(config: &Config) -> ProviderBuild {
+    Ok(Box::new(MockClient::from_config(&config.mock)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seeded(_: &Config) -> ProviderBuild {
+        Ok(Box::new(MockClient::new()))
+    }
+
+    /// Two providers serving the mock provider's seeded issues.
+    fn registry() -> ProviderRegistry {
+        let mut registry = ProviderRegistry::new(Config::default());
+        registry.register(ProviderId::Github, seeded);
+        registry.register(ProviderId::Linear, seeded);
+        registry
+    }
+
+    #[test]
+    fn bare_mock_keys_are_mock_issues_only_when_mock_is_the_default() {
+        let resolve = |default_provider: &str, input: &str| {
+            let mut config = Config::default();
+            config.default_provider = default_provider.to_string();
+            ProviderRegistry::with_builtin(config)
+                .resolve_reference(input)
+                .unwrap()
+                .provider
+        };
+        assert_eq!(resolve("linear", "MOCK-12"), ProviderId::Linear);
+        assert_eq!(resolve("", "MOCK-12"), ProviderId::Linear);
+        assert_eq!(resolve("mock", "MOCK-12"), ProviderId::Mock);
+        assert_eq!(resolve("mock", "ENG-12"), ProviderId::Linear);
+        assert_eq!(resolve("linear", "mock:MOCK-12"), ProviderId::Mock);
+    }
+
+    #[tokio::test]
+    async fn list_all_limits_the_merged_listing() {
+        let query = UnifiedListQuery {
+            limit: Some(5),
+            ..Default::default()
+        };
+        let fan_out = registry().list_all(&query, DEFAULT_LIST_TIMEOUT).await;
+
+        assert!(fan_out.failures.is_empty());
+        assert_eq!(fan_out.issues.len(), 5);
+        assert!(
+            fan_out
+                .issues
+                .windows(2)
+                .all(|pair| pair[0].updated_at >= pair[1].updated_at)
+        );
+
+        let everything = registry()
+            .list_all(&UnifiedListQuery::default(), DEFAULT_LIST_TIMEOUT)
+            .await;
+        let newest: Vec<_> = everything
+            .issues
+            .iter()
+            .take(5)
+            .map(|i| i.updated_at)
+            .collect();
+        let limited: Vec<_> = fan_out.issues.iter().map(|i| i.updated_at).collect();
+        assert_eq!(limited, newest);
+    }
+
+    #[tokio::test]
+    async fn streamed_listings_are_saved_for_offline_use() {
+        let dir = env::temp_dir().join(format!("kirei-stream-{}", std::process::id()));
+        let query = |limit| UnifiedListQuery {
+            limit: Some(limit),
+            ..Default::default()
+        };
+        let streamed: Vec<_> = registry()
+            .with_cache(IssueCache::new(&dir))
+            .list_stream(ProviderId::Github, &query(4))
+            .try_collect()
+            .await
+            .unwrap();
+        let offline = registry()
+            .with_cache(IssueCache::new(&dir))
+            .with_offline(true)
+            .list_cached(ProviderId::Github, &query(2))
+            .await
+            .unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+
+        assert_eq!(streamed.len(), 4);
+        let ids = |issues: &[UnifiedIssue]| -> Vec<String> {
+            issues.iter().map(|issue| issue.id.clone()).collect()
+        };
+        assert_eq!(ids(&offline.issues), ids(&streamed[..2]));
+    }
+
+    #[tokio::test]
+    async fn list_cached_limits_a_single_provider() {
+        let query = UnifiedListQuery {
+            limit: Some(3),
+            ..Default::default()
+        };
+        let snapshot = registry()
+            .list_cached(ProviderId::Github, &query)
+            .await
+            .unwrap();
+        assert_eq!(snapshot.issues.len(), 3);
+    }
+}
@@ -0,0 +1,213 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+/// Per-provider overrides from a raw state name (GitHub state, Linear state,
+/// Jira status, Trello list) to a [`UnifiedState`]. Keys match case-insensitively.
+pub type StateOverrides = BTreeMap<String, UnifiedState>;
+
+/// Workflow stage shared by every provider, so listings can filter and group
+/// the same way regardless of where an issue lives.
+#[derive(
+    Clone, Debug, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum UnifiedState {
+    Backlog,
+    #[default]
+    Todo,
+    InProgress,
+    InReview,
+    Done,
+    Canceled,
+}
+
+impl UnifiedState {
+    pub const ALL: [UnifiedState; 6] = [
+        UnifiedState::Backlog,
+        UnifiedState::Todo,
+        UnifiedState::InProgress,
+        UnifiedState::InReview,
+        UnifiedState::Done,
+        UnifiedState::Canceled,
+    ];
+
+    /// Name used in config files and queries, e.g. `in_progress`.
+    pub fn slug(&self) -> &'static str {
+        match self {
+            UnifiedState::Backlog => "backlog",
+            UnifiedState::Todo => "todo",
+            UnifiedState::InProgress => "in_progress",
+            UnifiedState::InReview => "in_review",
+            UnifiedState::Done => "done",
+            UnifiedState::Canceled => "canceled",
+        }
+    }
+
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            UnifiedState::Backlog => "Backlog",
+            UnifiedState::Todo => "Todo",
+            UnifiedState::InProgress => "In Progress",
+            UnifiedState::InReview => "In Review",
+            UnifiedState::Done => "Done",
+            UnifiedState::Canceled => "Canceled",
+        }
+    }
+
+    /// Whether the issue no longer needs work.
+    pub fn is_closed(&self) -> bool {
+        matches!(self, UnifiedState::Done | UnifiedState::Canceled)
+    }
+
+    /// Best guess from a free-form state name such as a Jira status or a Trello
+    /// list. Returns `None` when the name gives no hint.
+    ///
+    /// Hints match whole words, so "Renewals" is not "new" and "Aqua" not
+    /// "QA"; a hint of several words matches them in a row.
+    pub fn from_name(name: &str) -> Option<Self> {
+        let words = words(name);
+        let has = |hints: &[&str]| {
+            hints.iter().any(|hint| {
+                let hint = self::words(hint);
+                words.windows(hint.len()).any(|window| window == hint)
+            })
+        };
+
+        if has(&[
+            "cancel",
+            "canceled",
+            "cancelled",
+            "won't",
+            "wont",
+            "wontfix",
+            "rejected",
+            "duplicate",
+            "not planned",
+        ]) {
+            Some(UnifiedState::Canceled)
+        } else if has(&[
+            "review",
+            "reviewing",
+            "inreview",
+            "qa",
+            "testing",
+            "verify",
+            "verifying",
+            "verification",
+        ]) {
+            Some(UnifiedState::InReview)
+        } else if has(&[
+            "done",
+            "complete",
+            "completed",
+            "closed",
+            "resolved",
+            "shipped",
+            "released",
+        ]) {
+            Some(UnifiedState::Done)
+        } else if has(&[
+            "progress",
+            "inprogress",
+            "doing",
+            "started",
+            "active",
+            "wip",
+        ]) {
+            Some(UnifiedState::InProgress)
+        } else if has(&["backlog", "icebox", "idea", "ideas", "triage", "someday"]) {
+            Some(UnifiedState::Backlog)
+        } else if has(&[
+            "todo", "to do", "open", "reopened", "new", "next", "ready", "selected",
+        ]) {
+            Some(UnifiedState::Todo)
+        } else {
+            None
+        }
+    }
+
+    /// Applies the configured override for `name`, if any, on top of `default`.
+    pub fn resolve(overrides: &StateOverrides, name: &str, default: Self) -> Self {
+        overrides
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name.trim()))
+            .map(|(_, state)| *state)
+            .unwrap_or(default)
+    }
+}
+
+/// Lowercase words of a state name, split at anything but letters and digits.
+fn words(name: &str) -> Vec<String> {
+    name.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+impl std::fmt::Display for UnifiedState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.display_name())
+    }
+}
+
+impl std::str::FromStr for UnifiedState {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let normalized: String = s
+            .chars()
+            .filter(|c| c.is_ascii_alphanumeric())
+            .collect::<String>()
+            .to_lowercase();
+        match normalized.as_str() {
+            "backlog" => Ok(UnifiedState::Backlog),
+            "todo" => Ok(UnifiedState::Todo),
+            "inprogress" | "doing" | "started" => Ok(UnifiedState::InProgress),
+            "inreview" | "review" => Ok(UnifiedState::InReview),
+            "done" | "completed" => Ok(UnifiedState::Done),
+            "canceled" | "cancelled" => Ok(UnifiedState::Canceled),
+            _ => Err(format!(
+                "unknown state '{}' (expected backlog, todo, in_progress, in_review, done or canceled)",
+                s
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn guess(name: &str) -> Option<UnifiedState> {
+        UnifiedState::from_name(name)
+    }
+
+    #[test]
+    fn common_state_names() {
+        assert_eq!(guess("To Do"), Some(UnifiedState::Todo));
+        assert_eq!(guess("to-do"), Some(UnifiedState::Todo));
+        assert_eq!(guess("Selected for Development"), Some(UnifiedState::Todo));
+        assert_eq!(guess("New"), Some(UnifiedState::Todo));
+        assert_eq!(guess("Reopened"), Some(UnifiedState::Todo));
+        assert_eq!(guess("In Progress"), Some(UnifiedState::InProgress));
+        assert_eq!(guess("WIP"), Some(UnifiedState::InProgress));
+        assert_eq!(guess("Code Review"), Some(UnifiedState::InReview));
+        assert_eq!(guess("Ready for QA"), Some(UnifiedState::InReview));
+        assert_eq!(guess("Done"), Some(UnifiedState::Done));
+        assert_eq!(guess("Resolved"), Some(UnifiedState::Done));
+        assert_eq!(guess("Won't Do"), Some(UnifiedState::Canceled));
+        assert_eq!(guess("Cancelled"), Some(UnifiedState::Canceled));
+        assert_eq!(guess("Icebox"), Some(UnifiedState::Backlog));
+    }
+
+    #[test]
+    fn hints_inside_other_words_do_not_count() {
+        assert_eq!(guess("Renewals"), None);
+        assert_eq!(guess("renew"), None);
+        assert_eq!(guess("News"), None);
+        assert_eq!(guess("Aqua"), None);
+        assert_eq!(guess("Squad"), None);
+        assert_eq!(guess("Nextcloud migration"), None);
+    }
+}
@@ -1,26 +1,419 @@
+use crate::unified::query::{Assignee, Clause, DateBound, Query, StateFilter};
 use crate::unified::{
-    ProviderClient, ProviderId, UnifiedCreateParams, UnifiedError, UnifiedIssue, UnifiedListQuery,
+    Capabilities, Capability, IssuePage, Pagination, ProviderClient, ProviderId, StateOverrides,
+    UnifiedCreateParams, UnifiedError, UnifiedIssue, UnifiedListQuery, UnifiedState,
+    UnifiedUpdateParams,
 };
+use chrono::{DateTime, Utc};
+use kirei_http::HttpClient;
+use kirei_provider_trello::{self as provider, TrelloCard, TrelloError};
 
-pub struct TrelloClient;
+/// Cards have no numbers; the lists of a board stand in for workflow states.
+pub const CAPABILITIES: Capabilities = Capabilities {
+    features: &[
+        Capability::Labels,
+        Capability::Assignees,
+        Capability::Comments,
+        Capability::Transitions,
+        Capability::DueDates,
+    ],
+    pagination: Pagination::Keyset,
+};
+
+/// [`ProviderClient`] on top of the `kirei-provider-trello` client.
+pub struct TrelloClient {
+    api: provider::TrelloClient,
+    states: StateOverrides,
+}
 
 impl TrelloClient {
-    pub fn new() -> Self {
-        Self
+    pub fn new(
+        token: String,
+        api_key: String,
+        default_board: Option<String>,
+        base_url: Option<&str>,
+    ) -> Self {
+        Self {
+            api: provider::TrelloClient::new(token, api_key, default_board, base_url),
+            states: StateOverrides::new(),
+        }
+    }
+
+    /// Sends requests through `http`; see [`http_client`](crate::unified::registry::http_client).
+    pub fn with_http(mut self, http: HttpClient) -> Self {
+        self.api = self.api.with_http(http);
+        self
+    }
+
+    pub fn with_state_map(mut self, states: StateOverrides) -> Self {
+        self.states = states;
+        self
+    }
+
+    async fn update_card(
+        &self,
+        id: &str,
+        params: &[(&str, &str)],
+    ) -> Result<UnifiedIssue, UnifiedError> {
+        let card = self.api.update_card(id, params).await?;
+        Ok(self.to_unified(card))
+    }
+
+    fn to_unified(&self, card: TrelloCard) -> UnifiedIssue {
+        // Archived cards keep their list, but are closed as far as kirei is concerned.
+        let (state, default_state) = if card.closed {
+            ("Archived".to_string(), UnifiedState::Done)
+        } else {
+            let default_state = UnifiedState::from_name(&card.list_name).unwrap_or_default();
+            (card.list_name, default_state)
+        };
+
+        let mut issue = UnifiedIssue::new(ProviderId::Trello, card.id, card.name, state);
+        issue.unified_state = UnifiedState::resolve(&self.states, &issue.state, default_state);
+        if !card.short_link.is_empty() {
+            issue.identifier = card.short_link;
+        }
+        issue.description = card.description;
+        issue.assignees = card.members;
+        issue.labels = card.labels;
+        issue.created_at = card.created_at;
+        issue.updated_at = card.last_activity;
+        issue.due_date = card.due.map(|due| due.date_naive());
+        issue.comment_count = Some(card.comment_count);
+        issue.url = card.url;
+        issue.raw_payload = card.raw;
+        issue
+    }
+}
+
+impl From<TrelloError> for UnifiedError {
+    fn from(err: TrelloError) -> Self {
+        match err {
+            TrelloError::Http(err) => UnifiedError::Http(err),
+            TrelloError::MissingCredentials => UnifiedError::MissingToken(ProviderId::Trello),
+            TrelloError::BoardRequired => UnifiedError::MissingDefault {
+                provider: ProviderId::Trello,
+                scope: "board",
+            },
+            TrelloError::ApiKeyRequired => UnifiedError::MissingSetting {
+                provider: ProviderId::Trello,
+                setting: "API key",
+            },
+            TrelloError::Unauthorized(message) => UnifiedError::Unauthorized {
+                provider: ProviderId::Trello,
+                message,
+            },
+            TrelloError::Forbidden(message) => UnifiedError::Forbidden {
+                provider: ProviderId::Trello,
+                message,
+            },
+            TrelloError::NotFound(message) => UnifiedError::NotFound {
+                provider: ProviderId::Trello,
+                message,
+            },
+            TrelloError::RateLimited { reset, message } => UnifiedError::RateLimited {
+                provider: ProviderId::Trello,
+                reset,
+                message,
+            },
+            TrelloError::Validation { field, message } => UnifiedError::Validation {
+                provider: ProviderId::Trello,
+                field,
+                message,
+            },
+            TrelloError::UnexpectedResponse(body) => UnifiedError::UnexpectedResponse(body),
+            other => UnifiedError::Configuration(other.to_string()),
+        }
     }
 }
 
+/// Translates a query into Trello search operators, e.g. `is:open label:"bug" @me`.
+fn search_terms(query: &Query, now: DateTime<Utc>) -> Result<Vec<String>, UnifiedError> {
+    let mut terms = Vec::new();
+    match query.state() {
+        StateFilter::Open => terms.push("is:open".to_string()),
+        StateFilter::Closed => terms.push("is:archived".to_string()),
+        // Specific states depend on list names, so `search` filters after mapping.
+        StateFilter::All | StateFilter::Is(_) => {}
+    }
+
+    // Search only knows "in the last N days".
+    let days = |clause: &Clause, bound: &DateBound| match bound {
+        DateBound::After(at) => Ok(((now - *at).num_hours() + 23).div_euclid(24).max(1)),
+        DateBound::Before(_) => Err(clause.unsupported(ProviderId::Trello)),
+    };
+    for clause in query.filters() {
+        terms.push(match clause {
+            Clause::Label(label) => format!("label:{}", quote(label)),
+            Clause::Assignee(Assignee::Me) => "@me".into(),
+            Clause::Assignee(Assignee::User(user)) => format!("@{}", user),
+            Clause::Updated(bound) => format!("edited:{}", days(clause, bound)?),
+            Clause::Created(bound) => format!("created:{}", days(clause, bound)?),
+            Clause::Text(text) => quote(text),
+            Clause::State(_) => continue,
+        });
+    }
+    Ok(terms)
+}
+
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('"', ""))
+}
+
 #[async_trait::async_trait]
 impl ProviderClient for TrelloClient {
     fn provider(&self) -> ProviderId {
         ProviderId::Trello
     }
 
-    async fn list(&self, _query: UnifiedListQuery) -> Result<Vec<UnifiedIssue>, UnifiedError> {
-        Err(UnifiedError::NotImplemented(ProviderId::Trello))
+    fn capabilities(&self) -> Capabilities {
+        CAPABILITIES
+    }
+
+    async fn list_page(
+        &self,
+        query: &UnifiedListQuery,
+        cursor: Option<String>,
+    ) -> Result<IssuePage, UnifiedError> {
+        let limit = query.page_size(1000);
+        if query.search.is_empty() {
+            let page = self
+                .api
+                .list_cards_page(query.board.clone(), limit, cursor)
+                .await?;
+            return Ok(IssuePage {
+                issues: page
+                    .cards
+                    .into_iter()
+                    .map(|card| self.to_unified(card))
+                    .collect(),
+                next: page.next,
+            });
+        }
+
+        let terms = search_terms(&query.search, Utc::now())?.join(" ");
+        let page = self
+            .api
+            .search_cards_page(query.board.clone(), &terms, limit, cursor)
+            .await?;
+        // Specific states depend on list names, so they are filtered after mapping.
+        let wanted = match query.search.state() {
+            StateFilter::Is(state) => Some(state),
+            _ => None,
+        };
+        Ok(IssuePage {
+            issues: page
+                .cards
+                .into_iter()
+                .map(|card| self.to_unified(card))
+                .filter(|issue| wanted.is_none_or(|state| issue.unified_state == state))
+                .collect(),
+            next: page.next,
+        })
+    }
+
+    async fn create(&self, params: UnifiedCreateParams) -> Result<UnifiedIssue, UnifiedError> {
+        CAPABILITIES.check_create(ProviderId::Trello, &params)?;
+        let card = self
+            .api
+            .create_card_with(
+                params.board,
+                &params.title,
+                params.body.as_deref(),
+                params.due_date,
+            )
+            .await?;
+        Ok(self.to_unified(card))
+    }
+
+    async fn get(&self, id: &str) -> Result<UnifiedIssue, UnifiedError> {
+        let card = self.api.get_card(id).await?;
+        Ok(self.to_unified(card))
+    }
+
+    async fn update(
+        &self,
+        id: &str,
+        params: UnifiedUpdateParams,
+    ) -> Result<UnifiedIssue, UnifiedError> {
+        let mut query = Vec::new();
+        if let Some(title) = params.title.as_deref() {
+            query.push(("name", title));
+        }
+        if let Some(body) = params.body.as_deref() {
+            query.push(("desc", body));
+        }
+        self.update_card(id, &query).await
+    }
+
+    /// Trello has no open/closed state; closing a card archives it.
+    async fn close(&self, id: &str) -> Result<UnifiedIssue, UnifiedError> {
+        self.update_card(id, &[("closed", "true")]).await
+    }
+
+    async fn reopen(&self, id: &str) -> Result<UnifiedIssue, UnifiedError> {
+        self.update_card(id, &[("closed", "false")]).await
+    }
+
+    async fn delete(&self, id: &str) -> Result<(), UnifiedError> {
+        self.api.delete_card(id).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{Value, json};
+
+    use super::*;
+    use crate::unified::conformance::{
+        Conformance, FakeApi, FakeBackend, StubRequest, StubResponse, StubServer,
+    };
+
+    /// The lists of the fake board: id and name.
+    const LISTS: [(&str, &str); 2] = [("list-todo", "To Do"), ("list-done", "Done")];
+
+    /// A board that takes every field as a query parameter; bodies are never
+    /// read.
+    struct FakeBoard;
+
+    impl FakeApi for FakeBoard {
+        fn routes(&self) -> Vec<(&'static str, String)> {
+            ["GET", "POST", "PUT", "DELETE"]
+                .into_iter()
+                .map(|method| (method, "/".to_string()))
+                .collect()
+        }
+
+        /// Cards go into the first list. Their ids start with the creation
+        /// time, like Trello's.
+        fn issue(&self, number: i64, created: DateTime<Utc>, title: &str, body: Value) -> Value {
+            json!({
+                "id": format!("{:08x}{:016x}", created.timestamp(), number),
+                "shortLink": format!("kirei{}", number),
+                "name": title,
+                "desc": body.as_str().unwrap_or_default(),
+                "idList": LISTS[0].0,
+                "closed": false,
+                "url": format!("https://trello.com/c/kirei{}", number),
+                "members": [],
+                "labels": [],
+                "dateLastActivity": created.to_rfc3339(),
+                "due": null,
+                "badges": { "comments": 0 },
+            })
+        }
+
+        fn is_open(card: &Value) -> bool {
+            card["closed"] == false
+        }
+
+        /// Plain text such as `invalid token`; rate limits come as JSON.
+        fn error(status: u16, message: &str) -> StubResponse {
+            match status {
+                429 => StubResponse::json(
+                    status,
+                    json!({ "error": "API_TOKEN_LIMIT_EXCEEDED", "message": message }),
+                ),
+                _ => StubResponse::text(status, message),
+            }
+        }
+
+        fn answer(board: &mut FakeBackend<Self>, request: &StubRequest) -> StubResponse {
+            let segments: Vec<&str> = request.path().trim_start_matches('/').split('/').collect();
+            match (request.method.as_str(), segments.as_slice()) {
+                // Open cards, newest first, `limit` at a time before a card id.
+                ("GET", ["boards", "board", "cards"]) => {
+                    let limit: usize = request
+                        .query("limit")
+                        .and_then(|n| n.parse().ok())
+                        .unwrap_or(1000);
+                    let before = request.query("before");
+                    let (cards, _) = board.page_after("id", before.as_deref(), limit);
+                    StubResponse::json(200, Value::Array(cards))
+                }
+                ("GET", ["boards", "board", "lists"]) => {
+                    let lists: Vec<Value> = LISTS
+                        .iter()
+                        .map(|(id, name)| json!({ "id": id, "name": name }))
+                        .collect();
+                    StubResponse::json(200, Value::Array(lists))
+                }
+                ("POST", ["cards"]) => match request.query("name") {
+                    Some(name) => {
+                        let desc = request.query("desc").map(Value::String);
+                        StubResponse::json(200, board.create(&name, desc.unwrap_or_default()))
+                    }
+                    None => FakeBoard::error(400, "invalid value for name"),
+                },
+                ("GET", ["cards", id]) => match board.find(card(id)) {
+                    Some(found) => StubResponse::json(200, with_list(found)),
+                    None => not_found(),
+                },
+                ("PUT", ["cards", id]) => update(board, id, request),
+                ("DELETE", ["cards", id]) if board.remove(card(id)) => {
+                    StubResponse::json(200, json!({ "limits": {} }))
+                }
+                _ => not_found(),
+            }
+        }
+    }
+
+    fn update(board: &mut FakeBackend<FakeBoard>, id: &str, request: &StubRequest) -> StubResponse {
+        let Some(card) = board.find_mut(card(id)) else {
+            return not_found();
+        };
+        for field in ["name", "desc"] {
+            if let Some(value) = request.query(field) {
+                card[field] = json!(value);
+            }
+        }
+        if let Some(closed) = request.query("closed") {
+            card["closed"] = json!(closed == "true");
+        }
+        card["dateLastActivity"] = json!(Utc::now().to_rfc3339());
+        StubResponse::json(200, card.clone())
+    }
+
+    /// Cards are found by id and by short link alike.
+    fn card(id: &str) -> impl Fn(&Value) -> bool + '_ {
+        move |card| card["id"] == id || card["shortLink"] == id
     }
 
-    async fn create(&self, _params: UnifiedCreateParams) -> Result<UnifiedIssue, UnifiedError> {
-        Err(UnifiedError::NotImplemented(ProviderId::Trello))
+    /// A card with its list embedded, as `list=true` asks for.
+    fn with_list(card: &Value) -> Value {
+        let mut card = card.clone();
+        if let Some((id, name)) = LISTS.iter().find(|(id, _)| card["idList"] == *id) {
+            card["list"] = json!({ "id": id, "name": name });
+        }
+        card
+    }
+
+    fn not_found() -> StubResponse {
+        FakeBoard::error(404, "The requested resource was not found.")
+    }
+
+    fn trello(url: &str) -> TrelloClient {
+        TrelloClient::new(
+            "token".into(),
+            "key".into(),
+            Some("board".into()),
+            Some(url),
+        )
+    }
+
+    #[tokio::test]
+    async fn conforms_against_a_fake_board() {
+        let stub = StubServer::start().unwrap();
+        FakeBackend::serve(&stub, FakeBoard, 12);
+        let client = trello(stub.url());
+
+        let report = Conformance::new(&client)
+            .with_error_mapping(|url| Box::new(trello(url)), "kirei7", FakeBoard::error)
+            .run()
+            .await;
+
+        assert!(report.is_ok(), "{}", report);
+        assert_eq!(report.passed.len(), 4);
     }
 }
@@ -0,0 +1,653 @@
+//! Credentials kept out of `config.json`, in a file encrypted with a key
+//! derived from a passphrase.
+//!
+//! The config stores `vault:<name>` references in place of secrets. The
+//! vault is unlocked on first use, from `KIREI_VAULT_PASSPHRASE`, a session
+//! key left by an earlier unlock, or a passphrase prompt. Unlocking caches
+//! the derived key in a runtime directory (`$XDG_RUNTIME_DIR`) so the
+//! passphrase is asked once per session. Without one, nothing is cached and
+//! every command needs the passphrase.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use chrono::{DateTime, Duration, Utc};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+
+pub const PASSPHRASE_ENV: &str = "KIREI_VAULT_PASSPHRASE";
+/// Prefix of a config value that names a vault entry.
+pub const REFERENCE_PREFIX: &str = "vault:";
+/// How long a session key stays valid after an unlock.
+pub const SESSION_TTL: Duration = Duration::hours(8);
+
+const VERSION: u32 = 1;
+const KEY_LEN: usize = 32;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+/// Bound into the ciphertext so a vault of another format cannot pass as one.
+const AAD: &[u8] = b"kirei-vault-v1";
+
+#[derive(Debug, thiserror::Error)]
+pub enum VaultError {
+    #[error("the credential vault is locked and no passphrase is available")]
+    Locked,
+    #[error("creating the credential vault needs a passphrase and none is available")]
+    NoPassphrase,
+    #[error("wrong vault passphrase")]
+    WrongPassphrase,
+    #[error("no credential vault for this config")]
+    Unavailable,
+    #[error("vault passphrase must not be empty")]
+    EmptyPassphrase,
+    #[error("failed to access {path}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("vault file {path} is damaged")]
+    Format {
+        path: PathBuf,
+        #[source]
+        source: serde_json::Error,
+    },
+    #[error("unsupported vault: {0}")]
+    Unsupported(String),
+}
+
+/// A vault reference for `name`, e.g. `vault:github.token`.
+pub fn reference(name: &str) -> String {
+    format!("{}{}", REFERENCE_PREFIX, name)
+}
+
+/// The entry named by `value`, if it is a vault reference.
+pub fn referenced_name(value: &str) -> Option<&str> {
+    value.strip_prefix(REFERENCE_PREFIX)
+}
+
+/// Why a passphrase is asked for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PassphrasePrompt {
+    /// To open the existing vault.
+    Unlock,
+    /// To encrypt a vault that does not exist yet.
+    Create,
+}
+
+/// Asks the user for a passphrase; `None` when nobody can answer.
+pub type PromptFn = fn(PassphrasePrompt) -> Option<String>;
+
+#[derive(Clone, Serialize, Deserialize)]
+struct KdfParams {
+    algorithm: String,
+    salt: String,
+    memory_kib: u32,
+    iterations: u32,
+    parallelism: u32,
+}
+
+impl KdfParams {
+    fn generate() -> Self {
+        let mut salt = [0u8; SALT_LEN];
+        rand::thread_rng().fill_bytes(&mut salt);
+        Self {
+            algorithm: "argon2id".into(),
+            salt: BASE64.encode(salt),
+            memory_kib: Params::DEFAULT_M_COST,
+            iterations: Params::DEFAULT_T_COST,
+            parallelism: Params::DEFAULT_P_COST,
+        }
+    }
+
+    fn derive(&self, passphrase: &str) -> Result<[u8; KEY_LEN], VaultError> {
+        if self.algorithm != "argon2id" {
+            return Err(VaultError::Unsupported(format!(
+                "key derivation '{}'",
+                self.algorithm
+            )));
+        }
+        let salt = BASE64
+            .decode(&self.salt)
+            .map_err(|err| VaultError::Unsupported(format!("salt: {}", err)))?;
+        let params = Params::new(
+            self.memory_kib,
+            self.iterations,
+            self.parallelism,
+            Some(KEY_LEN),
+        )
+        .map_err(|err| VaultError::Unsupported(err.to_string()))?;
+        let mut key = [0u8; KEY_LEN];
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(passphrase.as_bytes(), &salt, &mut key)
+            .map_err(|err| VaultError::Unsupported(err.to_string()))?;
+        Ok(key)
+    }
+}
+
+/// The vault file as stored.
+#[derive(Serialize, Deserialize)]
+struct Sealed {
+    version: u32,
+    kdf: KdfParams,
+    nonce: String,
+    ciphertext: String,
+}
+
+/// An unlocked vault: secrets by name, and the key to write them back with.
+pub struct Vault {
+    path: PathBuf,
+    kdf: KdfParams,
+    key: [u8; KEY_LEN],
+    secrets: BTreeMap<String, String>,
+}
+
+impl Vault {
+    /// A new, empty vault at `path`, encrypted with `passphrase` once saved.
+    pub fn create(path: &Path, passphrase: &str) -> Result<Self, VaultError> {
+        if passphrase.is_empty() {
+            return Err(VaultError::EmptyPassphrase);
+        }
+        let kdf = KdfParams::generate();
+        let key = kdf.derive(passphrase)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            kdf,
+            key,
+            secrets: BTreeMap::new(),
+        })
+    }
+
+    pub fn unlock(path: &Path, passphrase: &str) -> Result<Self, VaultError> {
+        let sealed = read_sealed(path)?;
+        let key = sealed.kdf.derive(passphrase)?;
+        Self::open(path, sealed, key)
+    }
+
+    /// Opens the vault with a key from an earlier unlock.
+    fn unlock_with_key(path: &Path, key: [u8; KEY_LEN]) -> Result<Self, VaultError> {
+        Self::open(path, read_sealed(path)?, key)
+    }
+
+    fn open(path: &Path, sealed: Sealed, key: [u8; KEY_LEN]) -> Result<Self, VaultError> {
+        if sealed.version != VERSION {
+            return Err(VaultError::Unsupported(format!(
+                "version {}",
+                sealed.version
+            )));
+        }
+        let decode = |value: &str| {
+            BASE64
+                .decode(value)
+                .map_err(|err| VaultError::Unsupported(err.to_string()))
+        };
+        let nonce = decode(&sealed.nonce)?;
+        if nonce.len() != NONCE_LEN {
+            return Err(VaultError::Unsupported("nonce length".into()));
+        }
+        let plaintext = XChaCha20Poly1305::new(&key.into())
+            .decrypt(
+                XNonce::from_slice(&nonce),
+                Payload {
+                    msg: &decode(&sealed.ciphertext)?,
+                    aad: AAD,
+                },
+            )
+            .map_err(|_| VaultError::WrongPassphrase)?;
+        let secrets = serde_json::from_slice(&plaintext).map_err(|source| VaultError::Format {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            kdf: sealed.kdf,
+            key,
+            secrets,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.secrets.get(name).map(String::as_str)
+    }
+
+    pub fn insert(&mut self, name: &str, secret: String) {
+        self.secrets.insert(name.to_string(), secret);
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<String> {
+        self.secrets.remove(name)
+    }
+
+    /// Names of the stored secrets.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.secrets.keys().map(String::as_str)
+    }
+
+    /// Encrypts the secrets under a fresh nonce and writes the file.
+    pub fn save(&self) -> Result<(), VaultError> {
+        let mut nonce = [0u8; NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let plaintext = serde_json::to_vec(&self.secrets).map_err(|source| VaultError::Format {
+            path: self.path.clone(),
+            source,
+        })?;
+        let ciphertext = XChaCha20Poly1305::new(&self.key.into())
+            .encrypt(
+                XNonce::from_slice(&nonce),
+                Payload {
+                    msg: &plaintext,
+                    aad: AAD,
+                },
+            )
+            .map_err(|_| VaultError::Unsupported("encryption failed".into()))?;
+        let sealed = Sealed {
+            version: VERSION,
+            kdf: self.kdf.clone(),
+            nonce: BASE64.encode(nonce),
+            ciphertext: BASE64.encode(ciphertext),
+        };
+        let json = serde_json::to_vec_pretty(&sealed).map_err(|source| VaultError::Format {
+            path: self.path.clone(),
+            source,
+        })?;
+        write_private(&self.path, &json)
+    }
+}
+
+impl fmt::Debug for Vault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Vault")
+            .field("path", &self.path)
+            .field("secrets", &self.secrets.len())
+            .finish_non_exhaustive()
+    }
+}
+
+fn read_sealed(path: &Path) -> Result<Sealed, VaultError> {
+    let bytes = fs::read(path).map_err(|source| VaultError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    serde_json::from_slice(&bytes).map_err(|source| VaultError::Format {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+/// Writes `bytes` readable by the current user only. The mode is set on
+/// every write, as opening with one only applies to new files.
+fn write_private(path: &Path, bytes: &[u8]) -> Result<(), VaultError> {
+    let io = |source| VaultError::Io {
+        path: path.to_path_buf(),
+        source,
+    };
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(io)?;
+    }
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(path).map_err(io)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(fs::Permissions::from_mode(0o600))
+            .map_err(io)?;
+    }
+    file.write_all(bytes).map_err(io)
+}
+
+/// The derived key cached between commands.
+#[derive(Serialize, Deserialize)]
+struct SessionKey {
+    vault: PathBuf,
+    /// The vault's salt, so a key for a replaced vault is not tried.
+    salt: String,
+    key: String,
+    expires_at: DateTime<Utc>,
+}
+
+/// Opens the vault at most once per process and shares it between config
+/// clones.
+pub struct Keyring {
+    path: PathBuf,
+    session_path: Option<PathBuf>,
+    prompt: Option<PromptFn>,
+    vault: Mutex<Option<Vault>>,
+    /// Why the passphrase was not given or not accepted, so it is asked for
+    /// once per process rather than by every credential lookup.
+    refused: Mutex<Option<Refusal>>,
+}
+
+#[derive(Clone, Copy)]
+enum Refusal {
+    Locked,
+    NoPassphrase,
+    WrongPassphrase,
+}
+
+impl Refusal {
+    fn of(err: &VaultError) -> Option<Self> {
+        match err {
+            VaultError::Locked => Some(Refusal::Locked),
+            VaultError::NoPassphrase => Some(Refusal::NoPassphrase),
+            VaultError::WrongPassphrase => Some(Refusal::WrongPassphrase),
+            _ => None,
+        }
+    }
+
+    fn error(self) -> VaultError {
+        match self {
+            Refusal::Locked => VaultError::Locked,
+            Refusal::NoPassphrase => VaultError::NoPassphrase,
+            Refusal::WrongPassphrase => VaultError::WrongPassphrase,
+        }
+    }
+}
+
+impl Keyring {
+    /// The vault at `path`, with its session key kept in `session_dir`. The
+    /// directory must not outlive the login session; without one, no key is
+    /// kept between commands.
+    pub fn new(path: PathBuf, session_dir: Option<&Path>) -> Self {
+        Self {
+            path,
+            session_path: session_dir.map(|dir| dir.join("kirei-vault.session")),
+            prompt: None,
+            vault: Mutex::new(None),
+            refused: Mutex::new(None),
+        }
+    }
+
+    /// Asks for the passphrase through `prompt` when no other source has it.
+    pub fn with_prompt(mut self, prompt: PromptFn) -> Self {
+        self.prompt = Some(prompt);
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn exists(&self) -> bool {
+        self.path.exists()
+    }
+
+    /// Whether an unlock is remembered between commands.
+    pub fn has_sessions(&self) -> bool {
+        self.session_path.is_some()
+    }
+
+    /// Whether a session key would open the vault without a passphrase.
+    pub fn is_unlocked(&self) -> bool {
+        self.lock().is_some() || self.session_key().is_some()
+    }
+
+    /// The secret called `name`, unlocking the vault if needed.
+    pub fn get(&self, name: &str) -> Result<Option<String>, VaultError> {
+        if !self.exists() {
+            return Ok(None);
+        }
+        self.with_vault(|vault| vault.get(name).map(String::from))
+    }
+
+    /// Runs `f` on the unlocked vault, creating it first if there is none.
+    /// Once the passphrase has been refused, fails without asking again.
+    pub fn with_vault<T>(&self, f: impl FnOnce(&mut Vault) -> T) -> Result<T, VaultError> {
+        let mut slot = self.lock();
+        if slot.is_none() {
+            let mut refused = self.refused.lock().unwrap_or_else(|err| err.into_inner());
+            if let Some(refusal) = *refused {
+                return Err(refusal.error());
+            }
+            match self.open() {
+                Ok(vault) => *slot = Some(vault),
+                Err(err) => {
+                    *refused = Refusal::of(&err);
+                    return Err(err);
+                }
+            }
+        }
+        Ok(f(slot.as_mut().expect("vault opened above")))
+    }
+
+    /// Unlocks with `passphrase` and starts a session, replacing any vault
+    /// already open.
+    pub fn unlock(&self, passphrase: &str) -> Result<(), VaultError> {
+        let vault = Vault::unlock(&self.path, passphrase)?;
+        self.start_session(&vault)?;
+        *self.lock() = Some(vault);
+        *self.refused.lock().unwrap_or_else(|err| err.into_inner()) = None;
+        Ok(())
+    }
+
+    /// Ends the session; the next use asks for the passphrase again.
+    pub fn forget(&self) -> Result<(), VaultError> {
+        *self.lock() = None;
+        let Some(session_path) = &self.session_path else {
+            return Ok(());
+        };
+        match fs::remove_file(session_path) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(VaultError::Io {
+                path: session_path.clone(),
+                source: err,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    fn open(&self) -> Result<Vault, VaultError> {
+        if !self.exists() {
+            let passphrase = self.passphrase(PassphrasePrompt::Create)?;
+            let vault = Vault::create(&self.path, &passphrase)?;
+            self.start_session(&vault)?;
+            return Ok(vault);
+        }
+        if let Some(key) = self.session_key()
+            && let Ok(vault) = Vault::unlock_with_key(&self.path, key)
+        {
+            return Ok(vault);
+        }
+        let passphrase = self.passphrase(PassphrasePrompt::Unlock)?;
+        let vault = Vault::unlock(&self.path, &passphrase)?;
+        self.start_session(&vault)?;
+        Ok(vault)
+    }
+
+    fn passphrase(&self, purpose: PassphrasePrompt) -> Result<String, VaultError> {
+        if let Ok(passphrase) = std::env::var(PASSPHRASE_ENV)
+            && !passphrase.is_empty()
+        {
+            return Ok(passphrase);
+        }
+        self.prompt
+            .and_then(|prompt| prompt(purpose))
+            .ok_or(match purpose {
+                PassphrasePrompt::Unlock => VaultError::Locked,
+                PassphrasePrompt::Create => VaultError::NoPassphrase,
+            })
+    }
+
+    /// The cached key, if it is still good. An expired, damaged or stale
+    /// session file is deleted.
+    fn session_key(&self) -> Option<[u8; KEY_LEN]> {
+        let session_path = self.session_path.as_ref()?;
+        let bytes = fs::read(session_path).ok()?;
+        let salt = read_sealed(&self.path).ok()?.kdf.salt;
+        let key = serde_json::from_slice::<SessionKey>(&bytes)
+            .ok()
+            .filter(|session| {
+                session.vault == self.path
+                    && session.salt == salt
+                    && session.expires_at >= Utc::now()
+            })
+            .and_then(|session| BASE64.decode(session.key).ok()?.try_into().ok());
+        if key.is_none() {
+            let _ = fs::remove_file(session_path);
+        }
+        key
+    }
+
+    fn start_session(&self, vault: &Vault) -> Result<(), VaultError> {
+        let Some(session_path) = &self.session_path else {
+            return Ok(());
+        };
+        let session = SessionKey {
+            vault: self.path.clone(),
+            salt: vault.kdf.salt.clone(),
+            key: BASE64.encode(vault.key),
+            expires_at: Utc::now() + SESSION_TTL,
+        };
+        let json = serde_json::to_vec(&session).map_err(|source| VaultError::Format {
+            path: session_path.clone(),
+            source,
+        })?;
+        write_private(session_path, &json)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Option<Vault>> {
+        self.vault.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl fmt::Debug for Keyring {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Keyring")
+            .field("path", &self.path)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    fn sealed_vault(dir: &Path) -> PathBuf {
+        let path = dir.join("vault.json");
+        let mut vault = Vault::create(&path, "hunter2").unwrap();
+        vault.insert("github.token", "ghp_secret".into());
+        vault.save().unwrap();
+        path
+    }
+
+    #[test]
+    fn unlock_keeps_the_key_in_the_session_dir() {
+        let dir = TempDir::new("vault-session");
+        let runtime = TempDir::new("vault-runtime");
+        let path = sealed_vault(dir.path());
+
+        let keyring = Keyring::new(path.clone(), Some(runtime.path()));
+        keyring.unlock("hunter2").unwrap();
+        assert!(runtime.path().join("kirei-vault.session").exists());
+
+        let again = Keyring::new(path, Some(runtime.path()));
+        assert!(again.is_unlocked());
+        assert_eq!(
+            again.get("github.token").unwrap().as_deref(),
+            Some("ghp_secret")
+        );
+    }
+
+    #[test]
+    fn without_a_session_dir_nothing_is_written() {
+        let dir = TempDir::new("vault-no-session");
+        let path = sealed_vault(dir.path());
+
+        let keyring = Keyring::new(path.clone(), None);
+        keyring.unlock("hunter2").unwrap();
+        assert!(!keyring.has_sessions());
+        assert_eq!(
+            fs::read_dir(dir.path()).unwrap().count(),
+            1,
+            "only the vault itself"
+        );
+        assert!(!Keyring::new(path, None).is_unlocked());
+    }
+
+    #[test]
+    fn expired_sessions_are_deleted() {
+        let dir = TempDir::new("vault-expired");
+        let runtime = TempDir::new("vault-expired-runtime");
+        let path = sealed_vault(dir.path());
+        let keyring = Keyring::new(path, Some(runtime.path()));
+        keyring.unlock("hunter2").unwrap();
+
+        let session_path = runtime.path().join("kirei-vault.session");
+        let mut session: SessionKey =
+            serde_json::from_slice(&fs::read(&session_path).unwrap()).unwrap();
+        session.expires_at = Utc::now() - Duration::minutes(1);
+        fs::write(&session_path, serde_json::to_vec(&session).unwrap()).unwrap();
+
+        assert!(keyring.session_key().is_none());
+        assert!(!session_path.exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn existing_files_are_made_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = TempDir::new("vault-mode");
+        let path = dir.path().join("vault.json");
+        fs::write(&path, b"{}").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+
+        write_private(&path, b"secret").unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    #[test]
+    fn a_refused_prompt_is_not_repeated() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static PROMPTS: AtomicUsize = AtomicUsize::new(0);
+        fn wrong(_: PassphrasePrompt) -> Option<String> {
+            PROMPTS.fetch_add(1, Ordering::SeqCst);
+            Some("hunter3".into())
+        }
+
+        let dir = TempDir::new("vault-refused");
+        let path = sealed_vault(dir.path());
+        let keyring = Keyring::new(path, None).with_prompt(wrong);
+
+        for _ in 0..3 {
+            assert!(matches!(
+                keyring.get("github.token"),
+                Err(VaultError::WrongPassphrase)
+            ));
+        }
+        assert_eq!(PROMPTS.load(Ordering::SeqCst), 1);
+
+        keyring.unlock("hunter2").unwrap();
+        assert_eq!(
+            keyring.get("github.token").unwrap().as_deref(),
+            Some("ghp_secret")
+        );
+    }
+
+    #[test]
+    fn wrong_passphrase_is_rejected() {
+        let dir = TempDir::new("vault-wrong");
+        let path = sealed_vault(dir.path());
+        assert!(matches!(
+            Vault::unlock(&path, "hunter3"),
+            Err(VaultError::WrongPassphrase)
+        ));
+    }
+}
@@ -0,0 +1,364 @@
+//! Record/replay of HTTP exchanges, so provider calls can be tested and bug
+//! reports reproduced without a network.
+//!
+//! With `KIREI_RECORD=<dir>` every exchange is written to `<dir>` as one JSON
+//! file, credentials stripped. With `KIREI_REPLAY=<dir>` requests are answered
+//! from those files and no connection is ever opened. Requests match on
+//! method, URL and body after sanitizing, so a cassette recorded with one
+//! token replays with any other. Repeats of the same request are numbered, so
+//! reading an issue back after editing it gets the second recorded answer.
+//!
+//! `cli/tests/cassettes` holds a cassette per provider, which the CLI's
+//! tests replay.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::{env, fs};
+
+use reqwest::header::HeaderMap;
+use reqwest::{Request, Response, ResponseBuilderExt, StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::redact::{redact_query, request_is_form, sanitize_body};
+
+pub const RECORD_ENV: &str = "KIREI_RECORD";
+pub const REPLAY_ENV: &str = "KIREI_REPLAY";
+
+/// Headers never written to a cassette: credentials, and framing that no
+/// longer matches the decoded body.
+const DROPPED_HEADERS: &[&str] = &[
+    "authorization",
+    "proxy-authorization",
+    "cookie",
+    "set-cookie",
+    "content-encoding",
+    "content-length",
+    "transfer-encoding",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    Record,
+    Replay,
+}
+
+#[derive(Debug)]
+pub struct Cassette {
+    mode: Mode,
+    dir: PathBuf,
+    /// How often each request has been seen, to number repeats.
+    seen: Mutex<HashMap<String, usize>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Interaction {
+    request: RecordedRequest,
+    response: RecordedResponse,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RecordedRequest {
+    method: String,
+    url: String,
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Value::is_null")]
+    body: Value,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct RecordedResponse {
+    status: u16,
+    /// Where the response came from, when redirects led away from the
+    /// request URL.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+    /// JSON bodies are stored as JSON to keep cassettes readable; anything
+    /// else as a string.
+    #[serde(default)]
+    body: Value,
+}
+
+/// A request about to be recorded or replayed, and where its file lives.
+#[derive(Debug)]
+pub(crate) struct Entry {
+    request: RecordedRequest,
+    /// The request URL as sent, credentials included.
+    url: Url,
+    stem: String,
+    sequence: usize,
+}
+
+impl Cassette {
+    pub fn new(mode: Mode, dir: impl Into<PathBuf>) -> Self {
+        Self {
+            mode,
+            dir: dir.into(),
+            seen: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The cassette selected by `KIREI_REPLAY` or `KIREI_RECORD`, replay
+    /// winning if both are set.
+    pub fn from_env() -> Option<Self> {
+        let dir = |name| env::var_os(name).filter(|dir| !dir.is_empty());
+        if let Some(dir) = dir(REPLAY_ENV) {
+            Some(Self::new(Mode::Replay, dir))
+        } else {
+            dir(RECORD_ENV).map(|dir| Self::new(Mode::Record, dir))
+        }
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub(crate) fn entry(&self, request: &Request) -> Entry {
+        let mut redacted = request.url().clone();
+        redact_query(&mut redacted);
+        let body = request
+            .body()
+            .and_then(|body| body.as_bytes())
+            .map(|bytes| sanitize_body(bytes, request_is_form(request)))
+            .unwrap_or(Value::Null);
+
+        let key = format!("{} {}\n{}", request.method(), redacted, body);
+        let stem = format!(
+            "{}-{}-{:016x}",
+            request.method().as_str().to_lowercase(),
+            slug(&redacted),
+            fnv1a(key.as_bytes())
+        );
+        let sequence = {
+            let mut seen = self.seen.lock().unwrap_or_else(|err| err.into_inner());
+            let count = seen.entry(stem.clone()).or_insert(0);
+            *count += 1;
+            *count - 1
+        };
+
+        Entry {
+            request: RecordedRequest {
+                method: request.method().to_string(),
+                url: redacted.to_string(),
+                headers: header_map(request.headers()),
+                body,
+            },
+            url: request.url().clone(),
+            stem,
+            sequence,
+        }
+    }
+
+    /// Answers from the cassette. A request that was never recorded gets a
+    /// `501` naming the missing file, since there is no network to fall back on.
+    pub(crate) fn replay(&self, entry: Entry) -> Response {
+        // Past the recorded repeats, the last answer stands.
+        let interaction = (0..=entry.sequence)
+            .rev()
+            .find_map(|sequence| self.read(&self.path(&entry.stem, sequence)));
+
+        match interaction {
+            Some(interaction) => {
+                let url = interaction
+                    .response
+                    .url
+                    .as_deref()
+                    .and_then(|url| Url::parse(url).ok())
+                    .unwrap_or(entry.url);
+                build_response(interaction.response, url)
+            }
+            None => {
+                let message = format!(
+                    "no recorded response for {} {} in {}",
+                    entry.request.method,
+                    entry.request.url,
+                    self.path(&entry.stem, entry.sequence).display()
+                );
+                // Shaped so every provider's error parser finds the message.
+                let body = serde_json::json!({
+                    "message": message,
+                    "errorMessages": [message],
+                    "errors": [{ "message": message }],
+                });
+                build_response(
+                    RecordedResponse {
+                        status: StatusCode::NOT_IMPLEMENTED.as_u16(),
+                        url: None,
+                        headers: BTreeMap::new(),
+                        body,
+                    },
+                    entry.url,
+                )
+            }
+        }
+    }
+
+    /// Writes the exchange to the cassette and hands back an equivalent
+    /// response, since reading the body consumed the original.
+    pub(crate) async fn record(
+        &self,
+        entry: Entry,
+        response: Response,
+    ) -> reqwest::Result<Response> {
+        let status = response.status().as_u16();
+        let final_url = response.url().clone();
+        let headers = header_map(response.headers());
+        let bytes = response.bytes().await?;
+
+        let recorded = RecordedResponse {
+            status,
+            url: (final_url != entry.url).then(|| {
+                let mut url = final_url.clone();
+                redact_query(&mut url);
+                url.to_string()
+            }),
+            headers,
+            body: sanitize_body(&bytes, false),
+        };
+        let path = self.path(&entry.stem, entry.sequence);
+        let interaction = Interaction {
+            request: entry.request,
+            response: recorded,
+        };
+        if let Err(err) = self.write(&path, &interaction) {
+            eprintln!(
+                "warning: could not write cassette {}: {}",
+                path.display(),
+                err
+            );
+        }
+
+        // The body as received, not the sanitized copy: the caller may need
+        // the token it contains.
+        let mut response = http::Response::builder().status(status).url(final_url);
+        for (name, value) in &interaction.response.headers {
+            response = response.header(name, value);
+        }
+        Ok(response
+            .body(bytes.to_vec())
+            .expect("recorded status and headers are valid")
+            .into())
+    }
+
+    fn path(&self, stem: &str, sequence: usize) -> PathBuf {
+        self.dir.join(format!("{}-{}.json", stem, sequence))
+    }
+
+    fn read(&self, path: &Path) -> Option<Interaction> {
+        let bytes = fs::read(path).ok()?;
+        serde_json::from_slice(&bytes).ok()
+    }
+
+    fn write(&self, path: &Path, interaction: &Interaction) -> std::io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        fs::write(path, serde_json::to_vec_pretty(interaction)?)
+    }
+}
+
+fn build_response(recorded: RecordedResponse, url: Url) -> Response {
+    let body = match recorded.body {
+        Value::Null => Vec::new(),
+        Value::String(text) => text.into_bytes(),
+        json => json.to_string().into_bytes(),
+    };
+    let mut response = http::Response::builder().status(recorded.status).url(url);
+    for (name, value) in &recorded.headers {
+        response = response.header(name, value);
+    }
+    response
+        .body(body)
+        .unwrap_or_else(|_| http::Response::new(Vec::new()))
+        .into()
+}
+
+fn header_map(headers: &HeaderMap) -> BTreeMap<String, String> {
+    let mut map = BTreeMap::<String, String>::new();
+    for (name, value) in headers {
+        if DROPPED_HEADERS.contains(&name.as_str()) {
+            continue;
+        }
+        let value = String::from_utf8_lossy(value.as_bytes());
+        map.entry(name.as_str().to_string())
+            .and_modify(|existing| {
+                existing.push_str(", ");
+                existing.push_str(&value);
+            })
+            .or_insert_with(|| value.into_owned());
+    }
+    map
+}
+
+/// A readable file name part from the host and path, e.g.
+/// `api-github-com-repos-owner-repo-issues`.
+fn slug(url: &Url) -> String {
+    let raw = format!("{}{}", url.host_str().unwrap_or_default(), url.path());
+    let mut slug = String::new();
+    for c in raw.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug = slug.trim_matches('-');
+    slug[..slug.len().min(80)].to_string()
+}
+
+/// FNV-1a: stable across Rust releases, unlike `DefaultHasher`, so
+/// cassettes recorded on one machine replay on another, and files named
+/// after a hash are found again after an upgrade.
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn replayed_responses_keep_their_url() {
+        let dir = env::temp_dir().join(format!("kirei-cassette-{}", std::process::id()));
+        let cassette = Cassette::new(Mode::Replay, &dir);
+        let client = reqwest::Client::new();
+
+        let request = client
+            .get("https://api.example.com/issues?token=secret")
+            .build()
+            .unwrap();
+        let entry = cassette.entry(&request);
+        let interaction = Interaction {
+            request: entry.request.clone(),
+            response: RecordedResponse {
+                status: 200,
+                url: Some("https://cdn.example.com/issues".into()),
+                headers: BTreeMap::new(),
+                body: serde_json::json!([]),
+            },
+        };
+        cassette
+            .write(&cassette.path(&entry.stem, 0), &interaction)
+            .unwrap();
+        let redirected = cassette.replay(entry);
+        assert_eq!(redirected.url().as_str(), "https://cdn.example.com/issues");
+
+        let missing = client
+            .get("https://api.example.com/other?token=secret")
+            .build()
+            .unwrap();
+        let response = cassette.replay(cassette.entry(&missing));
+        assert_eq!(response.status(), StatusCode::NOT_IMPLEMENTED);
+        assert_eq!(response.url(), missing.url());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
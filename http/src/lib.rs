@@ -0,0 +1,394 @@
+//! HTTP client shared by every provider: one connection pool per proxy and
+//! TLS setup, common timeouts and `User-Agent`, and retries for transient
+//! failures and rate limits.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::{Client, IntoUrl, Method, Request, RequestBuilder, Response, StatusCode};
+
+pub use crate::cassette::{fnv1a, Cassette, Mode as CassetteMode};
+pub use crate::options::{HttpOptions, OptionsError};
+
+mod cassette;
+mod options;
+mod redact;
+pub mod trace;
+
+pub const USER_AGENT: &str = concat!("kirei/", env!("CARGO_PKG_VERSION"));
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+static SHARED: OnceLock<Client> = OnceLock::new();
+/// Clients built for non-default [`HttpOptions`], so providers configured
+/// alike still share a connection pool.
+static CONFIGURED: OnceLock<Mutex<HashMap<HttpOptions, Client>>> = OnceLock::new();
+static CASSETTE: OnceLock<Option<Arc<Cassette>>> = OnceLock::new();
+
+/// When and how long to wait before trying a request again.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    /// Attempts after the first one.
+    pub max_retries: u32,
+    /// Backoff before the first retry; doubles with every attempt.
+    pub base_delay: Duration,
+    /// Upper bound for the exponential backoff.
+    pub max_delay: Duration,
+    /// Longest rate-limit reset worth waiting for. Beyond it the limited
+    /// response is returned so the caller can report it.
+    pub max_wait: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(8),
+            max_wait: Duration::from_secs(60),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Exponential backoff with jitter: between half and all of the nominal delay.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let nominal = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_delay);
+        let half = nominal / 2;
+        half + half.mul_f64(rand::thread_rng().gen::<f64>())
+    }
+
+    /// The wait before retrying `response`, or `None` if it should be returned
+    /// as is. Server errors are only retried for idempotent methods, so a
+    /// create is never sent twice.
+    fn delay_for(&self, method: &Method, response: &Response, attempt: u32) -> Option<Duration> {
+        let status = response.status();
+        let headers = response.headers();
+        if is_rate_limited(status, headers) {
+            let wait = rate_limit_wait(headers).unwrap_or_else(|| self.backoff(attempt));
+            return (wait <= self.max_wait).then_some(wait);
+        }
+
+        let transient = matches!(
+            status,
+            StatusCode::INTERNAL_SERVER_ERROR
+                | StatusCode::BAD_GATEWAY
+                | StatusCode::SERVICE_UNAVAILABLE
+                | StatusCode::GATEWAY_TIMEOUT
+        );
+        (transient && is_idempotent(method)).then(|| self.backoff(attempt))
+    }
+}
+
+/// A handle to the shared client. Clones share the same connection pool.
+#[derive(Clone, Debug)]
+pub struct HttpClient {
+    client: Client,
+    retry: RetryPolicy,
+    cassette: Option<Arc<Cassette>>,
+}
+
+impl HttpClient {
+    /// The process-wide client with the default retry policy, recording or
+    /// replaying if `KIREI_RECORD` or `KIREI_REPLAY` is set.
+    pub fn shared() -> Self {
+        let client = SHARED
+            .get_or_init(|| {
+                client_builder()
+                    .build()
+                    .expect("failed to build the HTTP client")
+            })
+            .clone();
+        Self::from_client(client)
+    }
+
+    /// A client going through the proxy and trusting the certificates in
+    /// `options`; the shared client when they are the defaults.
+    pub fn with_options(options: &HttpOptions) -> Result<Self, OptionsError> {
+        if options.is_default() {
+            return Ok(Self::shared());
+        }
+        let mut configured = CONFIGURED
+            .get_or_init(Default::default)
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let client = match configured.get(options) {
+            Some(client) => client.clone(),
+            None => {
+                let client = options.apply(client_builder())?.build()?;
+                configured.insert(options.clone(), client.clone());
+                client
+            }
+        };
+        Ok(Self::from_client(client))
+    }
+
+    fn from_client(client: Client) -> Self {
+        let cassette = CASSETTE
+            .get_or_init(|| Cassette::from_env().map(Arc::new))
+            .clone();
+        Self {
+            client,
+            retry: RetryPolicy::default(),
+            cassette,
+        }
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn with_cassette(mut self, cassette: Cassette) -> Self {
+        self.cassette = Some(Arc::new(cassette));
+        self
+    }
+
+    pub fn request<U: IntoUrl>(&self, method: Method, url: U) -> RequestBuilder {
+        self.client.request(method, url)
+    }
+
+    pub fn get<U: IntoUrl>(&self, url: U) -> RequestBuilder {
+        self.request(Method::GET, url)
+    }
+
+    pub fn post<U: IntoUrl>(&self, url: U) -> RequestBuilder {
+        self.request(Method::POST, url)
+    }
+
+    /// Sends the request, retrying connection failures, timeouts, server
+    /// errors and rate limits according to the retry policy. When the retries
+    /// run out the last response is returned, whatever its status.
+    ///
+    /// With a cassette, the final response is recorded, or the request is
+    /// answered from the cassette without touching the network.
+    pub async fn send(&self, request: RequestBuilder) -> reqwest::Result<Response> {
+        let request = request.build()?;
+        let Some(cassette) = &self.cassette else {
+            return self.execute(request).await;
+        };
+
+        let entry = cassette.entry(&request);
+        match cassette.mode() {
+            CassetteMode::Replay => {
+                let trace = trace::start(&request);
+                traced(trace, Ok(cassette.replay(entry))).await
+            }
+            CassetteMode::Record => {
+                let response = self.execute(request).await?;
+                cassette.record(entry, response).await
+            }
+        }
+    }
+
+    async fn execute(&self, request: Request) -> reqwest::Result<Response> {
+        let mut attempt = 0;
+        loop {
+            // Streaming bodies cannot be replayed, so they get a single attempt.
+            let Some(current) = request.try_clone() else {
+                let trace = trace::start(&request);
+                return traced(trace, self.client.execute(request).await).await;
+            };
+            let trace = trace::start(&current);
+            let result = traced(trace, self.client.execute(current).await).await;
+
+            let delay = match &result {
+                Ok(response) => self.retry.delay_for(request.method(), response, attempt),
+                Err(err) if is_transient(err, &request) => Some(self.retry.backoff(attempt)),
+                Err(_) => None,
+            };
+            match delay {
+                Some(delay) if attempt < self.retry.max_retries => {
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                _ => return result,
+            }
+        }
+    }
+}
+
+async fn traced(
+    trace: Option<trace::Pending>,
+    result: reqwest::Result<Response>,
+) -> reqwest::Result<Response> {
+    match trace {
+        Some(trace) => trace.finish(result).await,
+        None => result,
+    }
+}
+
+fn client_builder() -> reqwest::ClientBuilder {
+    Client::builder()
+        .user_agent(USER_AGENT)
+        .connect_timeout(CONNECT_TIMEOUT)
+        .timeout(REQUEST_TIMEOUT)
+}
+
+/// Sends a `RequestBuilder` through an [`HttpClient`], keeping call sites fluent.
+pub trait RequestBuilderExt {
+    fn send_with(
+        self,
+        client: &HttpClient,
+    ) -> impl Future<Output = reqwest::Result<Response>> + Send;
+}
+
+impl RequestBuilderExt for RequestBuilder {
+    fn send_with(
+        self,
+        client: &HttpClient,
+    ) -> impl Future<Output = reqwest::Result<Response>> + Send {
+        client.send(self)
+    }
+}
+
+fn is_idempotent(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::OPTIONS | Method::PUT | Method::DELETE
+    )
+}
+
+/// Connection failures never reached the server; timeouts may have, so they
+/// are only retried when repeating the request is harmless.
+fn is_transient(err: &reqwest::Error, request: &Request) -> bool {
+    err.is_connect() || (err.is_timeout() && is_idempotent(request.method()))
+}
+
+/// `429`, or GitHub's `403` once `X-RateLimit-Remaining` hits zero.
+pub fn is_rate_limited(status: StatusCode, headers: &HeaderMap) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS
+        || (status == StatusCode::FORBIDDEN
+            && (header(headers, "x-ratelimit-remaining") == Some("0")
+                || headers.contains_key(RETRY_AFTER)))
+}
+
+/// How long the server asks us to wait, from `Retry-After` or a reset header.
+pub fn rate_limit_wait(headers: &HeaderMap) -> Option<Duration> {
+    if let Some(wait) = header(headers, RETRY_AFTER.as_str()).and_then(retry_after) {
+        return Some(wait);
+    }
+    let reset = rate_limit_reset(headers)?;
+    let now = SystemTime::now();
+    // Resets have second precision; wait for the second to actually pass.
+    Some(reset.duration_since(now).unwrap_or_default() + Duration::from_secs(1))
+}
+
+/// A `Retry-After` value: delay seconds, or an HTTP date such as
+/// `Wed, 21 Oct 2015 07:28:00 GMT`. A date in the past means no wait.
+fn retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let at = DateTime::parse_from_rfc2822(value).ok()?;
+    Some(
+        (at.with_timezone(&Utc) - Utc::now())
+            .to_std()
+            .unwrap_or_default(),
+    )
+}
+
+/// When the rate limit window resets. GitHub sends epoch seconds, Linear epoch
+/// milliseconds and Jira an ISO 8601 timestamp.
+pub fn rate_limit_reset(headers: &HeaderMap) -> Option<SystemTime> {
+    let value = header(headers, "x-ratelimit-reset")
+        .or_else(|| header(headers, "x-ratelimit-requests-reset"))?;
+    match value.parse::<u64>() {
+        Ok(millis) if millis > 100_000_000_000 => Some(UNIX_EPOCH + Duration::from_millis(millis)),
+        Ok(seconds) => Some(UNIX_EPOCH + Duration::from_secs(seconds)),
+        Err(_) => value.parse::<DateTime<Utc>>().ok().map(SystemTime::from),
+    }
+}
+
+fn header<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name)?.to_str().ok()
+}
+
+/// How an unsuccessful response should be reported, by status code. Providers
+/// pair it with the message from their own error body.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Failure {
+    Unauthorized,
+    Forbidden,
+    NotFound,
+    RateLimited {
+        reset: Option<DateTime<Utc>>,
+    },
+    /// `400` or `422`: the request itself was rejected.
+    Validation,
+    Other(StatusCode),
+}
+
+impl Failure {
+    /// `None` unless the response is a client or server error.
+    pub fn of(response: &Response) -> Option<Self> {
+        let status = response.status();
+        if !status.is_client_error() && !status.is_server_error() {
+            return None;
+        }
+        let headers = response.headers();
+        Some(if is_rate_limited(status, headers) {
+            Failure::RateLimited {
+                reset: rate_limit_reset(headers).map(DateTime::from),
+            }
+        } else {
+            match status {
+                StatusCode::UNAUTHORIZED => Failure::Unauthorized,
+                StatusCode::FORBIDDEN => Failure::Forbidden,
+                StatusCode::NOT_FOUND | StatusCode::GONE => Failure::NotFound,
+                StatusCode::BAD_REQUEST | StatusCode::UNPROCESSABLE_ENTITY => Failure::Validation,
+                status => Failure::Other(status),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    fn retry_after_header(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    #[test]
+    fn retry_after_in_seconds() {
+        assert_eq!(
+            rate_limit_wait(&retry_after_header("120")),
+            Some(Duration::from_secs(120))
+        );
+    }
+
+    #[test]
+    fn retry_after_as_an_http_date() {
+        let at = Utc::now() + chrono::Duration::seconds(90);
+        let headers = retry_after_header(&at.format("%a, %d %b %Y %H:%M:%S GMT").to_string());
+        let wait = rate_limit_wait(&headers).unwrap();
+        assert!(wait > Duration::from_secs(80) && wait <= Duration::from_secs(90));
+
+        let past = retry_after_header("Wed, 21 Oct 2015 07:28:00 GMT");
+        assert_eq!(rate_limit_wait(&past), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn unreadable_retry_after_falls_back_to_the_reset_header() {
+        let mut headers = retry_after_header("soon");
+        assert_eq!(rate_limit_wait(&headers), None);
+
+        headers.insert("x-ratelimit-reset", HeaderValue::from_static("0"));
+        assert_eq!(rate_limit_wait(&headers), Some(Duration::from_secs(1)));
+    }
+}
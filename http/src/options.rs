@@ -0,0 +1,88 @@
+//! Proxy and TLS settings for networks that do not reach the providers
+//! directly, such as a corporate proxy in front of a self-hosted Jira that
+//! presents a certificate from an internal CA.
+
+use std::path::PathBuf;
+use std::{env, fs, io};
+
+use reqwest::{Certificate, ClientBuilder, NoProxy, Proxy};
+use thiserror::Error;
+
+/// Proxy variables consulted when `no_proxy` is configured without a proxy.
+/// Without either setting reqwest reads them itself.
+const PROXY_ENV: &[&str] = &["HTTPS_PROXY", "https_proxy", "ALL_PROXY", "all_proxy"];
+
+/// How a client reaches the network. The default uses the standard
+/// `HTTPS_PROXY`/`NO_PROXY` variables and the built-in root certificates.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct HttpOptions {
+    /// Proxy for every request, e.g. `http://proxy.example.com:3128`.
+    /// Overrides `HTTPS_PROXY`.
+    pub proxy: Option<String>,
+    /// Comma-separated hosts, domains and CIDR ranges to reach directly.
+    /// Overrides `NO_PROXY`.
+    pub no_proxy: Option<String>,
+    /// PEM file of extra root certificates, trusted alongside the built-in ones.
+    pub ca_bundle: Option<PathBuf>,
+    /// Accepts any certificate. Only meant for test servers.
+    pub insecure_skip_verify: bool,
+}
+
+#[derive(Debug, Error)]
+pub enum OptionsError {
+    #[error("invalid proxy '{url}': {source}")]
+    Proxy { url: String, source: reqwest::Error },
+    #[error("cannot read CA bundle {}: {source}", .path.display())]
+    ReadCaBundle { path: PathBuf, source: io::Error },
+    #[error("invalid CA bundle {}: {source}", .path.display())]
+    CaBundle {
+        path: PathBuf,
+        source: reqwest::Error,
+    },
+    #[error(transparent)]
+    Client(#[from] reqwest::Error),
+}
+
+impl HttpOptions {
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub(crate) fn apply(&self, mut builder: ClientBuilder) -> Result<ClientBuilder, OptionsError> {
+        let proxy = self.proxy.clone().or_else(|| {
+            // `no_proxy` alone still has to go through a proxy we build, since
+            // reqwest only reads the variables when none is given.
+            self.no_proxy.as_ref()?;
+            PROXY_ENV
+                .iter()
+                .find_map(|name| env::var(name).ok().filter(|value| !value.is_empty()))
+        });
+        if let Some(url) = proxy {
+            let no_proxy = match &self.no_proxy {
+                Some(hosts) => NoProxy::from_string(hosts),
+                None => NoProxy::from_env(),
+            };
+            let proxy = Proxy::all(&url)
+                .map_err(|source| OptionsError::Proxy { url, source })?
+                .no_proxy(no_proxy);
+            builder = builder.proxy(proxy);
+        }
+
+        if let Some(path) = &self.ca_bundle {
+            let pem = fs::read(path).map_err(|source| OptionsError::ReadCaBundle {
+                path: path.clone(),
+                source,
+            })?;
+            let certificates =
+                Certificate::from_pem_bundle(&pem).map_err(|source| OptionsError::CaBundle {
+                    path: path.clone(),
+                    source,
+                })?;
+            for certificate in certificates {
+                builder = builder.add_root_certificate(certificate);
+            }
+        }
+
+        Ok(builder.danger_accept_invalid_certs(self.insecure_skip_verify))
+    }
+}
@@ -0,0 +1,114 @@
+//! Credentials stripped from anything kirei writes down: cassettes and
+//! request traces.
+
+use reqwest::header::{
+    HeaderName, HeaderValue, AUTHORIZATION, COOKIE, PROXY_AUTHORIZATION, SET_COOKIE,
+};
+use reqwest::{Request, Url};
+use serde_json::Value;
+
+pub(crate) const REDACTED: &str = "REDACTED";
+
+/// Query parameters and form fields that carry credentials. Trello passes
+/// `key` and `token` in the query string; OAuth exchanges post the rest.
+pub(crate) const SECRET_PARAMS: &[&str] = &[
+    "key",
+    "token",
+    "api_key",
+    "access_token",
+    "refresh_token",
+    "client_secret",
+    "code",
+    "code_verifier",
+    "password",
+];
+
+/// JSON fields that carry credentials, in request and response bodies.
+/// Narrower than the parameters: `key` is a Jira issue key in JSON.
+pub(crate) const SECRET_FIELDS: &[&str] = &[
+    "access_token",
+    "refresh_token",
+    "id_token",
+    "client_secret",
+    "password",
+    "token",
+];
+
+/// A header value fit to show. Credentials are replaced, keeping the
+/// authorization scheme (`Bearer REDACTED`, `Basic REDACTED`) for context.
+pub(crate) fn header_value(name: &HeaderName, value: &HeaderValue) -> String {
+    let value = String::from_utf8_lossy(value.as_bytes());
+    if *name == AUTHORIZATION || *name == PROXY_AUTHORIZATION {
+        match value.split_once(' ') {
+            Some((scheme, _)) => format!("{} {}", scheme, REDACTED),
+            None => REDACTED.to_string(),
+        }
+    } else if *name == COOKIE || *name == SET_COOKIE {
+        REDACTED.to_string()
+    } else {
+        value.into_owned()
+    }
+}
+
+pub(crate) fn request_is_form(request: &Request) -> bool {
+    request
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/x-www-form-urlencoded"))
+}
+
+pub(crate) fn redact_query(url: &mut Url) {
+    if url.query().is_none() {
+        return;
+    }
+    let pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .map(|(name, value)| {
+            let value = if SECRET_PARAMS.contains(&name.as_ref()) {
+                REDACTED.to_string()
+            } else {
+                value.into_owned()
+            };
+            (name.into_owned(), value)
+        })
+        .collect();
+    url.query_pairs_mut().clear().extend_pairs(pairs);
+}
+
+/// Parses a body for storage: JSON with secret fields redacted, form data
+/// with secret fields redacted, or the text as is.
+pub(crate) fn sanitize_body(bytes: &[u8], form: bool) -> Value {
+    if bytes.is_empty() {
+        return Value::Null;
+    }
+    if let Ok(mut json) = serde_json::from_slice::<Value>(bytes) {
+        redact_json(&mut json);
+        return json;
+    }
+    let text = String::from_utf8_lossy(bytes);
+    // GitHub's token endpoint answers in form encoding unless asked for JSON.
+    if form || text.contains("access_token=") {
+        let mut url = Url::parse("http://form.invalid/").expect("static URL");
+        url.set_query(Some(&text));
+        redact_query(&mut url);
+        return Value::String(url.query().unwrap_or_default().to_string());
+    }
+    Value::String(text.into_owned())
+}
+
+fn redact_json(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (name, value) in map.iter_mut() {
+                if SECRET_FIELDS.contains(&name.as_str()) && value.is_string() {
+                    *value = Value::String(REDACTED.to_string());
+                } else {
+                    redact_json(value);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(redact_json),
+        _ => {}
+    }
+}
@@ -0,0 +1,356 @@
+//! Request tracing for `--verbose` and `--trace-file`: every exchange with a
+//! provider, credentials redacted, on stderr and as JSON lines in a file.
+//!
+//! Levels add detail: `info` logs method, URL, status, latency and the
+//! rate-limit headers, `debug` adds all headers and `trace` the bodies.
+//! `KIREI_LOG` takes a level and per-host overrides, e.g.
+//! `KIREI_LOG=info,atlassian.net=trace`; a host matches if its name contains
+//! the given text.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use reqwest::header::HeaderMap;
+use reqwest::{Request, Response, ResponseBuilderExt, Url};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::redact::{header_value, redact_query, request_is_form, sanitize_body};
+
+pub const LOG_ENV: &str = "KIREI_LOG";
+
+static TRACER: OnceLock<Tracer> = OnceLock::new();
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    #[default]
+    Off,
+    /// One line per request.
+    Info,
+    /// Adds request and response headers.
+    Debug,
+    /// Adds request and response bodies.
+    Trace,
+}
+
+impl Level {
+    /// The level for `-v` repeated `count` times.
+    pub fn from_verbosity(count: u8) -> Self {
+        match count {
+            0 => Level::Off,
+            1 => Level::Info,
+            2 => Level::Debug,
+            _ => Level::Trace,
+        }
+    }
+}
+
+impl FromStr for Level {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "off" => Ok(Level::Off),
+            "info" => Ok(Level::Info),
+            "debug" => Ok(Level::Debug),
+            "trace" => Ok(Level::Trace),
+            other => Err(format!("unknown log level '{}'", other)),
+        }
+    }
+}
+
+/// Where exchanges are logged, and in how much detail.
+#[derive(Debug)]
+pub struct Tracer {
+    level: Level,
+    /// Host overrides from `KIREI_LOG`, first match wins.
+    hosts: Vec<(String, Level)>,
+    file: Option<Mutex<File>>,
+}
+
+impl Tracer {
+    /// `level` comes from `-v`; `KIREI_LOG` can raise it and override it per host.
+    pub fn new(level: Level, filter: Option<&str>) -> Self {
+        let mut tracer = Self {
+            level,
+            hosts: Vec::new(),
+            file: None,
+        };
+        for directive in filter.unwrap_or_default().split(',') {
+            let directive = directive.trim();
+            if directive.is_empty() {
+                continue;
+            }
+            let parsed = match directive.split_once('=') {
+                Some((host, level)) => level
+                    .parse()
+                    .map(|level| tracer.hosts.push((host.trim().to_lowercase(), level))),
+                None => directive
+                    .parse()
+                    .map(|level| tracer.level = tracer.level.max(level)),
+            };
+            if let Err(err) = parsed {
+                eprintln!(
+                    "warning: ignoring {} directive '{}': {}",
+                    LOG_ENV, directive, err
+                );
+            }
+        }
+        tracer
+    }
+
+    /// Also writes every exchange to `path` as JSON lines, at `info` level or
+    /// above, whatever is shown on stderr.
+    pub fn with_file(mut self, path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        self.file = Some(Mutex::new(file));
+        Ok(self)
+    }
+
+    /// Installs the tracer for every [`HttpClient`](crate::HttpClient). Only
+    /// the first call has an effect.
+    pub fn install(self) {
+        let _ = TRACER.set(self);
+    }
+
+    fn stderr_level(&self, url: &Url) -> Level {
+        let host = url.host_str().unwrap_or_default().to_lowercase();
+        self.hosts
+            .iter()
+            .find(|(pattern, _)| host.contains(pattern.as_str()))
+            .map_or(self.level, |(_, level)| *level)
+    }
+}
+
+/// Sets up tracing from `-v`, `KIREI_LOG` and `--trace-file`.
+pub fn init(verbosity: u8, trace_file: Option<&Path>) -> io::Result<()> {
+    let filter = env::var(LOG_ENV).ok();
+    let mut tracer = Tracer::new(Level::from_verbosity(verbosity), filter.as_deref());
+    if let Some(path) = trace_file {
+        tracer = tracer.with_file(path)?;
+    }
+    tracer.install();
+    Ok(())
+}
+
+/// A request being traced, captured before it is sent.
+pub(crate) struct Pending {
+    tracer: &'static Tracer,
+    started: Instant,
+    at: DateTime<Utc>,
+    /// Detail shown on stderr.
+    shown: Level,
+    /// Detail captured for stderr and the file together.
+    captured: Level,
+    method: String,
+    url: Url,
+    request_headers: Option<BTreeMap<String, String>>,
+    request_body: Option<Value>,
+}
+
+/// Starts tracing `request`, or `None` if nothing would be logged.
+pub(crate) fn start(request: &Request) -> Option<Pending> {
+    let tracer = TRACER.get()?;
+    let shown = tracer.stderr_level(request.url());
+    let captured = if tracer.file.is_some() {
+        shown.max(Level::Info)
+    } else {
+        shown
+    };
+    if captured == Level::Off {
+        return None;
+    }
+
+    let mut url = request.url().clone();
+    redact_query(&mut url);
+    Some(Pending {
+        tracer,
+        started: Instant::now(),
+        at: Utc::now(),
+        shown,
+        captured,
+        method: request.method().to_string(),
+        url,
+        request_headers: (captured >= Level::Debug).then(|| headers(request.headers())),
+        request_body: (captured >= Level::Trace)
+            .then(|| {
+                request
+                    .body()
+                    .and_then(|body| body.as_bytes())
+                    .map(|bytes| sanitize_body(bytes, request_is_form(request)))
+            })
+            .flatten(),
+    })
+}
+
+#[derive(Serialize)]
+struct Record<'a> {
+    time: DateTime<Utc>,
+    method: &'a str,
+    url: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    latency_ms: u128,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    rate_limit: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_headers: Option<&'a BTreeMap<String, String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_body: Option<&'a Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_headers: Option<BTreeMap<String, String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_body: Option<Value>,
+}
+
+impl Pending {
+    /// Logs the outcome and hands the response back. Logging bodies reads the
+    /// response, so an equivalent one is returned in its place.
+    pub(crate) async fn finish(
+        self,
+        result: reqwest::Result<Response>,
+    ) -> reqwest::Result<Response> {
+        let latency = self.started.elapsed();
+        let mut record = Record {
+            time: self.at,
+            method: &self.method,
+            url: self.url.as_str(),
+            status: None,
+            error: None,
+            latency_ms: latency.as_millis(),
+            rate_limit: BTreeMap::new(),
+            request_headers: self.request_headers.as_ref(),
+            request_body: self.request_body.as_ref(),
+            response_headers: None,
+            response_body: None,
+        };
+
+        let result = match result {
+            Ok(response) => {
+                record.status = Some(response.status().as_u16());
+                record.rate_limit = rate_limit_headers(response.headers());
+                if self.captured >= Level::Debug {
+                    record.response_headers = Some(headers(response.headers()));
+                }
+                if self.captured >= Level::Trace {
+                    let (body, response) = read_body(response).await?;
+                    record.response_body = body;
+                    Ok(response)
+                } else {
+                    Ok(response)
+                }
+            }
+            Err(err) => {
+                record.error = Some(err.to_string());
+                Err(err)
+            }
+        };
+
+        if self.shown > Level::Off {
+            print_record(&record, latency, self.shown);
+        }
+        if let (Some(file), Ok(line)) = (&self.tracer.file, serde_json::to_string(&record)) {
+            let mut file = file.lock().unwrap_or_else(|err| err.into_inner());
+            let _ = writeln!(file, "{}", line);
+        }
+        result
+    }
+}
+
+fn print_record(record: &Record<'_>, latency: Duration, level: Level) {
+    let outcome = match (&record.status, &record.error) {
+        (Some(status), _) => status.to_string(),
+        (None, Some(err)) => format!("error: {}", err),
+        (None, None) => String::new(),
+    };
+    let rate_limit = record
+        .rate_limit
+        .iter()
+        .map(|(name, value)| format!(" {}={}", name, value))
+        .collect::<String>();
+    let mut out = format!(
+        "[http] {} {} -> {} ({} ms){}\n",
+        record.method,
+        record.url,
+        outcome,
+        latency.as_millis(),
+        rate_limit
+    );
+    if level >= Level::Debug {
+        for (name, value) in record.request_headers.into_iter().flatten() {
+            out.push_str(&format!("  > {}: {}\n", name, value));
+        }
+        for (name, value) in record.response_headers.iter().flatten() {
+            out.push_str(&format!("  < {}: {}\n", name, value));
+        }
+    }
+    if level >= Level::Trace {
+        if let Some(body) = record.request_body {
+            out.push_str(&format!("  > {}\n", body));
+        }
+        if let Some(body) = &record.response_body {
+            out.push_str(&format!("  < {}\n", body));
+        }
+    }
+    eprint!("{}", out);
+}
+
+fn headers(headers: &HeaderMap) -> BTreeMap<String, String> {
+    let mut map = BTreeMap::<String, String>::new();
+    for (name, value) in headers {
+        let value = header_value(name, value);
+        map.entry(name.as_str().to_string())
+            .and_modify(|existing| {
+                existing.push_str(", ");
+                existing.push_str(&value);
+            })
+            .or_insert(value);
+    }
+    map
+}
+
+/// `Retry-After` and the `X-RateLimit-*` family, whatever the provider calls them.
+fn rate_limit_headers(headers: &HeaderMap) -> BTreeMap<String, String> {
+    headers
+        .iter()
+        .filter(|(name, _)| {
+            name.as_str().starts_with("x-ratelimit") || name.as_str() == "retry-after"
+        })
+        .map(|(name, value)| (name.as_str().to_string(), header_value(name, value)))
+        .collect()
+}
+
+async fn read_body(response: Response) -> reqwest::Result<(Option<Value>, Response)> {
+    let status = response.status();
+    let mut rebuilt = http::Response::builder()
+        .status(status)
+        .url(response.url().clone());
+    for (name, value) in response.headers() {
+        // The body is already decoded; its framing no longer applies.
+        if !matches!(
+            name.as_str(),
+            "content-encoding" | "content-length" | "transfer-encoding"
+        ) {
+            rebuilt = rebuilt.header(name, value);
+        }
+    }
+    let bytes = response.bytes().await?;
+    let body = match sanitize_body(&bytes, false) {
+        Value::Null => None,
+        body => Some(body),
+    };
+    let response = rebuilt
+        .body(bytes.to_vec())
+        .expect("status and headers came from a valid response")
+        .into();
+    Ok((body, response))
+}
@@ -1,15 +1,20 @@
 use anyhow::Result;
-use rand::Rng;
-use reqwest::Client;
+use chrono::{DateTime, NaiveDate, Utc};
+use reqwest::header::{HeaderMap, ETAG, IF_NONE_MATCH, LINK};
+use kirei_http::{Failure, HttpClient, RequestBuilderExt};
+use reqwest::{Method, RequestBuilder, Response, StatusCode};
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use serde_json::{json, Value};
 use thiserror::Error;
 use url::Url;
 
-pub use crate::oauth::{GitHubOAuth, start_callback_server, wait_for_callback};
+pub use crate::oauth::{DeviceCode, DeviceFlowError, GitHubDeviceFlow};
 
 pub mod oauth;
 
+const GITHUB_API: &str = "https://api.github.com";
+const GITHUB_WEB: &str = "https://github.com";
+
 #[derive(Error, Debug)]
 pub enum GitHubError {
     #[error("missing credentials")]
@@ -22,6 +27,24 @@ pub enum GitHubError {
     RepoNameMissing,
     #[error("configuration error: {0}")]
     Configuration(String),
+    #[error("unauthorized: {0}")]
+    Unauthorized(String),
+    #[error("forbidden: {0}")]
+    Forbidden(String),
+    #[error("not found: {0}")]
+    NotFound(String),
+    #[error("rate limited: {message}")]
+    RateLimited {
+        reset: Option<DateTime<Utc>>,
+        message: String,
+    },
+    #[error("invalid {}: {message}", .field.as_deref().unwrap_or("request"))]
+    Validation {
+        field: Option<String>,
+        message: String,
+    },
+    #[error("unexpected response: {0}")]
+    UnexpectedResponse(String),
     #[error(transparent)]
     Http(#[from] reqwest::Error),
 }
@@ -33,6 +56,58 @@ pub struct GitHubConfig {
     pub client_secret: Option<String>,
 }
 
+/// The endpoints of a GitHub instance: github.com or a GitHub Enterprise Server.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GitHubHost {
+    /// REST root, e.g. `https://api.github.com` or `https://ghe.example.com/api/v3`.
+    pub api_url: String,
+    pub graphql_url: String,
+    /// Web root, which also serves the OAuth endpoints.
+    pub web_url: String,
+}
+
+impl Default for GitHubHost {
+    fn default() -> Self {
+        Self {
+            api_url: GITHUB_API.to_string(),
+            graphql_url: format!("{}/graphql", GITHUB_API),
+            web_url: GITHUB_WEB.to_string(),
+        }
+    }
+}
+
+impl GitHubHost {
+    /// Reads a configured base URL. GitHub Enterprise Server can be given by
+    /// its web URL (`https://ghe.example.com`) or its REST root
+    /// (`https://ghe.example.com/api/v3`); either way the API lives under
+    /// `/api/v3` and `/api/graphql`.
+    pub fn from_base_url(base_url: &str) -> Self {
+        let base_url = base_url.trim_end_matches('/');
+        if base_url.is_empty() || base_url == GITHUB_API || base_url == GITHUB_WEB {
+            return Self::default();
+        }
+
+        let web_url = base_url.strip_suffix("/api/v3").unwrap_or(base_url);
+        Self {
+            api_url: format!("{}/api/v3", web_url),
+            graphql_url: format!("{}/api/graphql", web_url),
+            web_url: web_url.to_string(),
+        }
+    }
+
+    pub fn authorize_url(&self) -> String {
+        format!("{}/login/oauth/authorize", self.web_url)
+    }
+
+    pub fn token_url(&self) -> String {
+        format!("{}/login/oauth/access_token", self.web_url)
+    }
+
+    pub fn device_code_url(&self) -> String {
+        format!("{}/login/device/code", self.web_url)
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct GitHubRepository {
     pub owner: String,
@@ -67,11 +142,24 @@ impl GitHubRepository {
 #[derive(Clone, Debug)]
 pub struct GitHubIssue {
     pub id: String,
+    /// GraphQL node id, needed for mutations such as deleting the issue.
+    pub node_id: String,
     pub number: i64,
     pub title: String,
     pub body: Option<String>,
     pub state: String,
+    /// Why a closed issue was closed: `completed` or `not_planned`.
+    pub state_reason: Option<String>,
     pub html_url: Option<String>,
+    pub assignees: Vec<String>,
+    pub labels: Vec<String>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+    /// Due date of the issue's milestone; issues have none of their own.
+    pub due_date: Option<NaiveDate>,
+    pub comments: u32,
+    /// The issue as returned by the API.
+    pub raw: Value,
 }
 
 impl GitHubIssue {
@@ -98,26 +186,109 @@ impl GitHubIssue {
             .and_then(Value::as_str)
             .map(String::from);
 
+        let node_id = value
+            .get("node_id")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string();
+
         let state = value
             .get("state")
             .and_then(Value::as_str)
             .unwrap_or("unknown")
             .to_string();
 
+        let state_reason = value
+            .get("state_reason")
+            .and_then(Value::as_str)
+            .map(String::from);
+
         let html_url = value
             .get("html_url")
             .and_then(Value::as_str)
             .map(String::from);
 
+        let assignees = Self::names(&value, "assignees", "login");
+        let labels = Self::names(&value, "labels", "name");
+
+        let timestamp = |key: &str| {
+            value
+                .get(key)
+                .and_then(Value::as_str)
+                .and_then(|s| s.parse::<DateTime<Utc>>().ok())
+        };
+        let created_at = timestamp("created_at");
+        let updated_at = timestamp("updated_at");
+
+        let due_date = value
+            .pointer("/milestone/due_on")
+            .and_then(Value::as_str)
+            .and_then(|s| s.parse::<DateTime<Utc>>().ok())
+            .map(|due| due.date_naive());
+
+        let comments = value
+            .get("comments")
+            .and_then(Value::as_u64)
+            .unwrap_or(0) as u32;
+
         Self {
             id,
+            node_id,
             number,
             title,
             body,
             state,
+            state_reason,
             html_url,
+            assignees,
+            labels,
+            created_at,
+            updated_at,
+            due_date,
+            comments,
+            raw: value,
         }
     }
+
+    /// Human-facing reference, e.g. `#12`.
+    pub fn identifier(&self) -> String {
+        format!("#{}", self.number)
+    }
+
+    fn names(value: &Value, key: &str, field: &str) -> Vec<String> {
+        value
+            .get(key)
+            .and_then(Value::as_array)
+            .map(|items| {
+                items
+                    .iter()
+                    .filter_map(|item| item.get(field).and_then(Value::as_str))
+                    .map(String::from)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// One page of issues and the URL of the next one, from the `Link` header.
+#[derive(Clone, Debug, Default)]
+pub struct GitHubIssuePage {
+    pub issues: Vec<GitHubIssue>,
+    pub next: Option<String>,
+    /// Validator for fetching the page again with [`GitHubClient::issue_page_if_changed`].
+    pub etag: Option<String>,
+}
+
+/// Fields to change with [`GitHubClient::update_issue`]; `None` leaves a field as is.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct GitHubIssueUpdate {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+    /// `open` or `closed`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub state: Option<String>,
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -137,15 +308,19 @@ pub struct GitHubOwner {
 }
 
 pub struct GitHubClient {
-    http: Client,
+    http: HttpClient,
+    host: GitHubHost,
     token: String,
     default_repo: Option<String>,
 }
 
 impl GitHubClient {
-    pub fn new(token: String, default_repo: Option<String>) -> Self {
+    /// `base_url` points the client at GitHub Enterprise Server or a stub;
+    /// `None` means github.com. See [`GitHubHost::from_base_url`].
+    pub fn new(token: String, default_repo: Option<String>, base_url: Option<&str>) -> Self {
         Self {
-            http: Client::new(),
+            http: HttpClient::shared(),
+            host: base_url.map(GitHubHost::from_base_url).unwrap_or_default(),
             token,
             default_repo,
         }
@@ -153,12 +328,24 @@ impl GitHubClient {
 
     pub fn with_repo(token: String, repo: GitHubRepository) -> Self {
         Self {
-            http: Client::new(),
+            http: HttpClient::shared(),
+            host: GitHubHost::default(),
             token,
             default_repo: Some(repo.as_str()),
         }
     }
 
+    /// Sends requests through `http` instead of the shared client, e.g. one
+    /// configured with [`HttpClient::with_options`].
+    pub fn with_http(mut self, http: HttpClient) -> Self {
+        self.http = http;
+        self
+    }
+
+    pub fn host(&self) -> &GitHubHost {
+        &self.host
+    }
+
     pub fn config(&self) -> Option<&String> {
         self.default_repo.as_ref()
     }
@@ -171,35 +358,162 @@ impl GitHubClient {
         GitHubRepository::from_string(&repo)
     }
 
+    fn request(&self, method: Method, url: &str) -> RequestBuilder {
+        self.http
+            .request(method, url)
+            .bearer_auth(&self.token)
+    }
+
+    /// Sends the request and turns error statuses into typed errors.
+    async fn send(&self, request: RequestBuilder) -> Result<Response, GitHubError> {
+        check(request.send_with(&self.http).await?).await
+    }
+
+    fn issue_url(&self, repo: Option<String>, number: u64) -> Result<String, GitHubError> {
+        let repo = self.resolve_repo(repo.as_ref())?;
+        Ok(format!(
+            "{}/repos/{}/{}/issues/{}",
+            self.host.api_url, repo.owner, repo.name, number
+        ))
+    }
+
     pub async fn list_issues(&self, repo: Option<String>, state: Option<&str>) -> Result<Vec<GitHubIssue>, GitHubError> {
+        let page = self.list_issues_page(repo, state, 50, None).await?;
+        Ok(page.issues)
+    }
+
+    /// Lists issues (not pull requests) page by page. `cursor` is the `next`
+    /// URL of the previous page.
+    pub async fn list_issues_page(
+        &self,
+        repo: Option<String>,
+        state: Option<&str>,
+        per_page: usize,
+        cursor: Option<String>,
+    ) -> Result<GitHubIssuePage, GitHubError> {
+        let url = match cursor {
+            Some(next) => next,
+            None => self.list_issues_url(repo, state, per_page)?,
+        };
+        self.issue_page(&url).await
+    }
+
+    /// Runs an issue search scoped to the repository, e.g. with
+    /// `qualifiers = "is:issue is:open label:bug"`.
+    pub async fn search_issues_page(
+        &self,
+        repo: Option<String>,
+        qualifiers: &str,
+        per_page: usize,
+        cursor: Option<String>,
+    ) -> Result<GitHubIssuePage, GitHubError> {
+        let url = match cursor {
+            Some(next) => next,
+            None => self.search_issues_url(repo, qualifiers, per_page)?,
+        };
+        self.issue_page(&url).await
+    }
+
+    /// URL of the first page of [`list_issues_page`](Self::list_issues_page).
+    pub fn list_issues_url(
+        &self,
+        repo: Option<String>,
+        state: Option<&str>,
+        per_page: usize,
+    ) -> Result<String, GitHubError> {
         let repo = self.resolve_repo(repo.as_ref())?;
-        let state = state.unwrap_or("open");
-        
-        let url = format!(
-            "https://api.github.com/repos/{}/{}/issues?state={}&per_page=50",
-            repo.owner, repo.name, state
-        );
+        Ok(format!(
+            "{}/repos/{}/{}/issues?state={}&per_page={}",
+            self.host.api_url,
+            repo.owner,
+            repo.name,
+            state.unwrap_or("open"),
+            per_page
+        ))
+    }
 
+    /// URL of the first page of [`search_issues_page`](Self::search_issues_page).
+    pub fn search_issues_url(
+        &self,
+        repo: Option<String>,
+        qualifiers: &str,
+        per_page: usize,
+    ) -> Result<String, GitHubError> {
+        let repo = self.resolve_repo(repo.as_ref())?;
+        let q = format!("repo:{} {}", repo.as_str(), qualifiers);
+        Url::parse_with_params(
+            &format!("{}/search/issues", self.host.api_url),
+            [("q", q.as_str()), ("per_page", &per_page.to_string())],
+        )
+        .map(String::from)
+        .map_err(|err| GitHubError::Configuration(err.to_string()))
+    }
+
+    /// Fetches a page of issues from a list or search URL.
+    pub async fn issue_page(&self, url: &str) -> Result<GitHubIssuePage, GitHubError> {
+        let response = self.send(self.request(Method::GET, url)).await?;
+        Self::read_issue_page(response).await
+    }
+
+    /// Fetches a page again only if it changed since `etag` was issued.
+    /// `None` means GitHub answered `304 Not Modified`, which does not count
+    /// against the rate limit.
+    pub async fn issue_page_if_changed(
+        &self,
+        url: &str,
+        etag: &str,
+    ) -> Result<Option<GitHubIssuePage>, GitHubError> {
         let response = self
-            .http
-            .get(&url)
-            .bearer_auth(&self.token)
-            .header("User-Agent", "kirei-cli")
-            .send()
+            .send(self.request(Method::GET, url).header(IF_NONE_MATCH, etag))
             .await?;
+        if response.status() == StatusCode::NOT_MODIFIED {
+            return Ok(None);
+        }
+        Self::read_issue_page(response).await.map(Some)
+    }
 
-        let issues: Vec<Value> = response.json().await?;
-        Ok(issues
-            .into_iter()
-            .filter(|issue| issue.get("pull_request").is_none()) // Filter out PRs
-            .map(GitHubIssue::from_json)
-            .collect())
+    async fn read_issue_page(response: Response) -> Result<GitHubIssuePage, GitHubError> {
+        let next = next_link(response.headers());
+        let etag = response
+            .headers()
+            .get(ETAG)
+            .and_then(|etag| etag.to_str().ok())
+            .map(String::from);
+
+        // Search results are wrapped in `items`; the issue list is a bare array.
+        let body: Value = response.json().await?;
+        let issues = match body {
+            Value::Array(issues) => issues,
+            body => body
+                .get("items")
+                .and_then(Value::as_array)
+                .cloned()
+                .ok_or_else(|| GitHubError::UnexpectedResponse(body.to_string()))?,
+        };
+
+        Ok(GitHubIssuePage {
+            issues: issues
+                .into_iter()
+                .filter(|issue| issue.get("pull_request").is_none()) // Filter out PRs
+                .map(GitHubIssue::from_json)
+                .collect(),
+            next,
+            etag,
+        })
+    }
+
+    pub async fn get_issue(&self, repo: Option<String>, number: u64) -> Result<GitHubIssue, GitHubError> {
+        let url = self.issue_url(repo, number)?;
+        let response = self.send(self.request(Method::GET, &url)).await?;
+
+        let issue: Value = response.json().await?;
+        Ok(GitHubIssue::from_json(issue))
     }
 
     pub async fn create_issue(&self, repo: Option<String>, title: &str, body: Option<&str>) -> Result<GitHubIssue, GitHubError> {
         let repo = self.resolve_repo(repo.as_ref())?;
-        let url = format!("https://api.github.com/repos/{}/{}/issues", repo.owner, repo.name);
-        
+        let url = format!("{}/repos/{}/{}/issues", self.host.api_url, repo.owner, repo.name);
+
         let mut body_obj = serde_json::Map::new();
         body_obj.insert("title".to_string(), serde_json::Value::String(title.to_string()));
         if let Some(body) = body {
@@ -207,49 +521,142 @@ impl GitHubClient {
         }
 
         let response = self
-            .http
-            .post(&url)
-            .bearer_auth(&self.token)
-            .header("User-Agent", "kirei-cli")
-            .json(&body_obj)
-            .send()
+            .send(self.request(Method::POST, &url).json(&body_obj))
             .await?;
 
         let issue: Value = response.json().await?;
         Ok(GitHubIssue::from_json(issue))
     }
 
-    pub async fn list_repositories(&self) -> Result<Vec<GitHubRepositoryInfo>, GitHubError> {
-        let url = "https://api.github.com/user/repos?per_page=100&sort=updated";
+    /// Edits an issue; closing and reopening are a change of `state`.
+    pub async fn update_issue(
+        &self,
+        repo: Option<String>,
+        number: u64,
+        update: &GitHubIssueUpdate,
+    ) -> Result<GitHubIssue, GitHubError> {
+        let url = self.issue_url(repo, number)?;
+        let response = self
+            .send(self.request(Method::PATCH, &url).json(update))
+            .await?;
+
+        let issue: Value = response.json().await?;
+        Ok(GitHubIssue::from_json(issue))
+    }
+
+    /// Deletes an issue by its node id. The REST API cannot delete issues, and
+    /// the GraphQL mutation needs admin rights on the repository.
+    pub async fn delete_issue(&self, node_id: &str) -> Result<(), GitHubError> {
+        let payload = json!({
+            "query": "mutation($id: ID!) { deleteIssue(input: { issueId: $id }) { clientMutationId } }",
+            "variables": { "id": node_id },
+        });
 
         let response = self
-            .http
-            .get(url)
-            .bearer_auth(&self.token)
-            .header("User-Agent", "kirei-cli")
-            .send()
+            .send(self.request(Method::POST, &self.host.graphql_url).json(&payload))
             .await?;
 
+        let body: Value = response.json().await?;
+        match body.pointer("/errors/0") {
+            Some(error) => Err(graphql_error(error)),
+            None => Ok(()),
+        }
+    }
+
+    pub async fn list_repositories(&self) -> Result<Vec<GitHubRepositoryInfo>, GitHubError> {
+        let url = format!("{}/user/repos?per_page=100&sort=updated", self.host.api_url);
+
+        let response = self.send(self.request(Method::GET, &url)).await?;
+
         let repos: Vec<GitHubRepositoryInfo> = response.json().await?;
         Ok(repos)
     }
 
     pub async fn get_token_info(&self) -> Result<GitHubUser, GitHubError> {
-        let url = "https://api.github.com/user";
+        let url = format!("{}/user", self.host.api_url);
 
-        let response = self
-            .http
-            .get(url)
-            .bearer_auth(&self.token)
-            .header("User-Agent", "kirei-cli")
-            .send()
-            .await?;
+        let response = self.send(self.request(Method::GET, &url)).await?;
 
         let user: GitHubUser = response.json().await?;
         Ok(user)
     }
 }
 
+/// Passes successful responses through and reads the error body of the rest.
+/// GitHub reports `{"message": ...}`, with per-field `errors` on a `422`.
+async fn check(response: Response) -> Result<Response, GitHubError> {
+    let Some(failure) = Failure::of(&response) else {
+        return Ok(response);
+    };
+    let status = response.status();
+    let body: Value = response.json().await.unwrap_or_default();
+    let message = body
+        .get("message")
+        .and_then(Value::as_str)
+        .or(status.canonical_reason())
+        .unwrap_or_default()
+        .to_string();
+
+    Err(match failure {
+        Failure::Unauthorized => GitHubError::Unauthorized(message),
+        Failure::Forbidden => GitHubError::Forbidden(message),
+        Failure::NotFound => GitHubError::NotFound(message),
+        Failure::RateLimited { reset } => GitHubError::RateLimited { reset, message },
+        Failure::Validation => {
+            let error = body.pointer("/errors/0");
+            let field = error
+                .and_then(|error| error.get("field"))
+                .and_then(Value::as_str)
+                .map(String::from);
+            // Field errors carry a `code` such as `missing_field`, and a
+            // `message` only for custom validations.
+            let detail = error.and_then(|error| {
+                error
+                    .get("message")
+                    .or_else(|| error.get("code"))
+                    .and_then(Value::as_str)
+            });
+            let message = match detail {
+                Some(detail) => format!("{} ({})", message, detail),
+                None => message,
+            };
+            GitHubError::Validation { field, message }
+        }
+        Failure::Other(status) => GitHubError::UnexpectedResponse(format!("{}: {}", status, message)),
+    })
+}
+
+/// Maps an entry of a GraphQL `errors` array, which comes with a `200`.
+fn graphql_error(error: &Value) -> GitHubError {
+    let message = error
+        .get("message")
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .to_string();
+    match error.get("type").and_then(Value::as_str) {
+        Some("NOT_FOUND") => GitHubError::NotFound(message),
+        Some("FORBIDDEN") => GitHubError::Forbidden(message),
+        Some("RATE_LIMITED") => GitHubError::RateLimited { reset: None, message },
+        _ => GitHubError::UnexpectedResponse(message),
+    }
+}
+
+/// The `rel="next"` target of a `Link` header, if there is a next page.
+fn next_link(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(LINK)?
+        .to_str()
+        .ok()?
+        .split(',')
+        .find_map(|link| {
+            let (url, params) = link.split_once(';')?;
+            params
+                .split(';')
+                .any(|param| param.trim() == r#"rel="next""#)
+                .then(|| url.trim().trim_matches(['<', '>']).to_string())
+        })
+}
+
 #[derive(Clone, Debug, Deserialize)]
 pub struct GitHubUser {
     pub login: String,
@@ -259,69 +666,8 @@ pub struct GitHubUser {
     pub avatar_url: Option<String>,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct GitHubOAuthConfig {
     pub client_id: Option<String>,
     pub client_secret: Option<String>,
 }
-
-impl Default for GitHubOAuthConfig {
-    fn default() -> Self {
-        Self {
-            client_id: None,
-            client_secret: None,
-        }
-    }
-}
-
-pub fn get_authorization_url(client_id: &str, redirect_port: u16) -> String {
-    let state = generate_random_state();
-    let redirect_uri = format!("http://localhost:{}/callback", redirect_port);
-
-    let mut url = Url::parse("https://github.com/login/oauth/authorize").unwrap();
-    url.query_pairs_mut()
-        .append_pair("client_id", client_id)
-        .append_pair("redirect_uri", &redirect_uri)
-        .append_pair("scope", "repo")
-        .append_pair("state", &state);
-
-    format!("{}&state={}", url, state)
-}
-
-pub async fn exchange_code_for_token(client_id: &str, client_secret: &str, code: &str) -> Result<String, GitHubError> {
-    let http = Client::new();
-    
-    let params = [
-        ("client_id", client_id),
-        ("client_secret", client_secret),
-        ("code", code),
-    ];
-
-    let response = http
-        .post("https://github.com/login/oauth/access_token")
-        .form(&params)
-        .header("Accept", "application/json")
-        .send()
-        .await?;
-
-    let json: serde_json::Value = response.json().await?;
-    let access_token = json["access_token"]
-        .as_str()
-        .ok_or(GitHubError::MissingCredentials)?;
-
-    Ok(access_token.to_string())
-}
-
-fn generate_random_state() -> String {
-    let mut rng = rand::thread_rng();
-    (0..32)
-        .map(|_| {
-            let idx = rng.gen_range(0..36);
-            if idx < 10 {
-                (b'0' + idx) as char
-            } else {
-                (b'a' + idx - 10) as char
-            }
-        })
-        .collect()
-}
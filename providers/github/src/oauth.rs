@@ -1,120 +1,242 @@
-use std::sync::mpsc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+
+use kirei_http::{HttpClient, RequestBuilderExt};
+use serde::Deserialize;
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::GitHubHost;
+
+/// Why the device flow ended without a token.
+#[derive(Debug, Error)]
+pub enum DeviceFlowError {
+    #[error("the device code expired before it was approved")]
+    Expired,
+    #[error("authorization was denied")]
+    Denied,
+    #[error("device flow is not enabled for this OAuth app; enable it in the app settings")]
+    Disabled,
+    #[error("GitHub OAuth error: {error}{}", description_suffix(.description))]
+    Rejected { error: String, description: String },
+    #[error("unexpected response from GitHub: {0}")]
+    UnexpectedResponse(String),
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+}
 
-use anyhow::Result;
-use rand::Rng;
-use reqwest::Client;
-use url::Url;
+fn description_suffix(description: &str) -> String {
+    if description.is_empty() {
+        String::new()
+    } else {
+        format!(" ({})", description)
+    }
+}
+
+/// A code the user enters at `verification_uri` to approve the device.
+#[derive(Clone, Debug, Deserialize)]
+pub struct DeviceCode {
+    pub device_code: String,
+    pub user_code: String,
+    pub verification_uri: String,
+    /// Seconds until the codes expire.
+    pub expires_in: u64,
+    /// Seconds to wait between polls.
+    #[serde(default = "default_poll_interval")]
+    pub interval: u64,
+}
 
-const GITHUB_OAUTH_URL: &str = "https://github.com/login/oauth/authorize";
-const GITHUB_TOKEN_URL: &str = "https://github.com/login/oauth/access_token";
+fn default_poll_interval() -> u64 {
+    5
+}
 
-pub struct GitHubOAuth {
-    http: Client,
+/// The OAuth device flow: no client secret, callback server or local browser,
+/// so it also works over SSH and in containers. The OAuth app needs device
+/// flow enabled in its settings.
+pub struct GitHubDeviceFlow {
+    http: HttpClient,
+    host: GitHubHost,
     client_id: String,
-    client_secret: String,
 }
 
-impl GitHubOAuth {
-    pub fn new(client_id: String, client_secret: String) -> Self {
+impl GitHubDeviceFlow {
+    pub fn new(client_id: String, host: GitHubHost) -> Self {
         Self {
-            http: Client::new(),
+            http: HttpClient::shared(),
+            host,
             client_id,
-            client_secret,
         }
     }
 
-    pub fn get_authorization_url(&self, redirect_port: u16) -> String {
-        let state = generate_random_state();
-        let redirect_uri = format!("http://localhost:{}/callback", redirect_port);
-
-        let mut url = Url::parse(GITHUB_OAUTH_URL).unwrap();
-        url.query_pairs_mut()
-            .append_pair("client_id", &self.client_id)
-            .append_pair("redirect_uri", &redirect_uri)
-            .append_pair("scope", "repo")
-            .append_pair("state", &state);
+    pub fn with_http(mut self, http: HttpClient) -> Self {
+        self.http = http;
+        self
+    }
 
-        format!("{}&state={}", url, state)
+    /// Starts the flow; show the user `user_code` and `verification_uri`.
+    pub async fn request_code(&self) -> Result<DeviceCode, DeviceFlowError> {
+        let json = self
+            .post(
+                self.host.device_code_url(),
+                &[("client_id", self.client_id.as_str()), ("scope", "repo")],
+            )
+            .await?;
+        if let Some(error) = json["error"].as_str() {
+            return Err(oauth_error(error, &json));
+        }
+        serde_json::from_value(json.clone())
+            .map_err(|_| DeviceFlowError::UnexpectedResponse(json.to_string()))
     }
 
-    pub async fn exchange_code_for_token(&self, code: &str) -> Result<String> {
+    /// Polls until the user approves or denies the device, or the code expires.
+    pub async fn poll_for_token(&self, code: &DeviceCode) -> Result<String, DeviceFlowError> {
+        let deadline = Instant::now() + Duration::from_secs(code.expires_in);
+        let mut interval = Duration::from_secs(code.interval.max(1));
         let params = [
             ("client_id", self.client_id.as_str()),
-            ("client_secret", self.client_secret.as_str()),
-            ("code", code),
+            ("device_code", code.device_code.as_str()),
+            ("grant_type", "urn:ietf:params:oauth:grant-type:device_code"),
         ];
 
+        loop {
+            tokio::time::sleep(interval).await;
+            if Instant::now() >= deadline {
+                return Err(DeviceFlowError::Expired);
+            }
+
+            let json = self.post(self.host.token_url(), &params).await?;
+            if let Some(token) = json["access_token"].as_str() {
+                return Ok(token.to_string());
+            }
+            match json["error"].as_str() {
+                Some("authorization_pending") => {}
+                // GitHub says how long to wait from now on; otherwise add 5s.
+                Some("slow_down") => {
+                    interval = json["interval"]
+                        .as_u64()
+                        .map(Duration::from_secs)
+                        .unwrap_or(interval + Duration::from_secs(5));
+                }
+                Some(error) => return Err(oauth_error(error, &json)),
+                None => return Err(DeviceFlowError::UnexpectedResponse(json.to_string())),
+            }
+        }
+    }
+
+    async fn post(&self, url: String, params: &[(&str, &str)]) -> Result<Value, DeviceFlowError> {
         let response = self
             .http
-            .post(GITHUB_TOKEN_URL)
-            .form(&params)
+            .post(url)
+            .form(params)
             .header("Accept", "application/json")
-            .send()
+            .send_with(&self.http)
             .await?;
+        let status = response.status();
+        let json: Value = response.json().await.unwrap_or_default();
+        // Errors of the flow itself come with a 200 and an `error` field.
+        if !status.is_success() && json["error"].is_null() {
+            return Err(DeviceFlowError::UnexpectedResponse(format!("{} {}", status, json)));
+        }
+        Ok(json)
+    }
+}
 
-        let json: serde_json::Value = response.json().await?;
-        let access_token = json["access_token"]
-            .as_str()
-            .ok_or_else(|| anyhow::anyhow!("No access token in response"))?;
-
-        Ok(access_token.to_string())
+fn oauth_error(error: &str, json: &Value) -> DeviceFlowError {
+    match error {
+        "access_denied" => DeviceFlowError::Denied,
+        "expired_token" => DeviceFlowError::Expired,
+        "device_flow_disabled" => DeviceFlowError::Disabled,
+        _ => DeviceFlowError::Rejected {
+            error: error.to_string(),
+            description: json["error_description"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string(),
+        },
     }
 }
 
-pub fn start_callback_server(
-    port: u16,
-) -> Result<(mpsc::Receiver<String>, mpsc::Receiver<()>)> {
-    let server = tiny_http::Server::http(format!("127.0.0.1:{}", port))
-        .map_err(|e| anyhow::anyhow!("Failed to start server: {}", e))?;
-    let (code_tx, code_rx) = mpsc::channel();
-    let (close_tx, close_rx) = mpsc::channel();
-
-    std::thread::spawn(move || {
-        for request in server.incoming_requests() {
-            let url = request.url();
-            if url.starts_with("/callback?") {
-                if let Some(code) = extract_code_from_url(url) {
-                    let _ = code_tx.send(code);
-                }
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use serde_json::json;
+
+    use super::*;
+
+    /// A token endpoint that answers polls with `answers` in turn and notes
+    /// when each poll arrived.
+    fn token_endpoint(answers: Vec<Value>) -> (GitHubHost, Arc<Mutex<Vec<Instant>>>) {
+        let server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", server.server_addr());
+        let polls = Arc::new(Mutex::new(Vec::new()));
+        let seen = Arc::clone(&polls);
+        std::thread::spawn(move || {
+            for (request, answer) in server.incoming_requests().zip(answers) {
+                seen.lock().unwrap().push(Instant::now());
+                let _ = request.respond(tiny_http::Response::from_string(answer.to_string()));
             }
-            let response = tiny_http::Response::from_string(
-                r#"<html><body><h1>Authentication Complete</h1><p>You can close this window and return to the terminal.</p></body></html>"#,
-            )
-            .with_header(
-                tiny_http::Header::from_bytes(&b"Content-Type"[..], &b"text/html"[..]).unwrap(),
-            );
-            let _ = request.respond(response);
-            let _ = close_tx.send(());
+        });
+        let host = GitHubHost {
+            api_url: url.clone(),
+            graphql_url: format!("{}/graphql", url),
+            web_url: url,
+        };
+        (host, polls)
+    }
+
+    fn code(interval: u64) -> DeviceCode {
+        DeviceCode {
+            device_code: "device".into(),
+            user_code: "ABCD-1234".into(),
+            verification_uri: "https://github.com/login/device".into(),
+            expires_in: 60,
+            interval,
         }
-    });
+    }
 
-    Ok((code_rx, close_rx))
-}
+    async fn poll(answers: Vec<Value>) -> (Result<String, DeviceFlowError>, Vec<Instant>) {
+        let (host, polls) = token_endpoint(answers);
+        let flow = GitHubDeviceFlow::new("client".into(), host);
+        let token = flow.poll_for_token(&code(1)).await;
+        let polls = polls.lock().unwrap().clone();
+        (token, polls)
+    }
 
-fn extract_code_from_url(url: &str) -> Option<String> {
-    let parsed = Url::parse(&format!("http://localhost{}", url)).ok()?;
-    let code = parsed.query_pairs().find(|(k, _)| k == "code");
-    code.map(|(_, v)| v.to_string())
-}
+    #[tokio::test]
+    async fn polls_until_the_device_is_approved() {
+        let (token, polls) = poll(vec![
+            json!({ "error": "authorization_pending" }),
+            json!({ "access_token": "gho_token", "token_type": "bearer" }),
+        ])
+        .await;
+        assert_eq!(token.unwrap(), "gho_token");
+        assert_eq!(polls.len(), 2);
+    }
 
-fn generate_random_state() -> String {
-    let mut rng = rand::thread_rng();
-    (0..32)
-        .map(|_| {
-            let idx = rng.gen_range(0..36);
-            if idx < 10 {
-                (b'0' + idx) as char
-            } else {
-                (b'a' + idx - 10) as char
-            }
-        })
-        .collect()
-}
+    #[tokio::test]
+    async fn slow_down_makes_polls_wait_longer() {
+        let (token, polls) = poll(vec![
+            json!({ "error": "slow_down", "interval": 2 }),
+            json!({ "access_token": "gho_token" }),
+        ])
+        .await;
+        assert_eq!(token.unwrap(), "gho_token");
+        assert!(polls[1] - polls[0] >= Duration::from_secs(2), "{:?}", polls);
+    }
+
+    #[tokio::test]
+    async fn an_expired_code_ends_the_flow() {
+        let (token, _) = poll(vec![json!({ "error": "expired_token" })]).await;
+        assert!(matches!(token, Err(DeviceFlowError::Expired)), "{:?}", token);
+    }
 
-pub fn wait_for_callback(close_rx: mpsc::Receiver<()>, timeout_secs: u64) -> bool {
-    match close_rx.recv_timeout(Duration::from_secs(timeout_secs)) {
-        Ok(_) => true,
-        Err(_) => false,
+    #[tokio::test]
+    async fn a_denied_device_ends_the_flow() {
+        let (token, _) = poll(vec![json!({
+            "error": "access_denied",
+            "error_description": "The authorization request was denied.",
+        })])
+        .await;
+        assert!(matches!(token, Err(DeviceFlowError::Denied)), "{:?}", token);
     }
 }
@@ -1,7 +1,9 @@
 use anyhow::Result;
-use base64::Engine;
-use reqwest::Client;
+use chrono::{DateTime, NaiveDate, Utc};
+use kirei_http::{Failure, HttpClient, RequestBuilderExt};
+use reqwest::{Method, RequestBuilder, Response};
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -14,6 +16,24 @@ pub enum JiraError {
     ProjectRequired,
     #[error("configuration error: {0}")]
     Configuration(String),
+    #[error("unauthorized: {0}")]
+    Unauthorized(String),
+    #[error("forbidden: {0}")]
+    Forbidden(String),
+    #[error("not found: {0}")]
+    NotFound(String),
+    #[error("rate limited: {message}")]
+    RateLimited {
+        reset: Option<DateTime<Utc>>,
+        message: String,
+    },
+    #[error("invalid {}: {message}", .field.as_deref().unwrap_or("request"))]
+    Validation {
+        field: Option<String>,
+        message: String,
+    },
+    #[error("unexpected response: {0}")]
+    UnexpectedResponse(String),
     #[error(transparent)]
     Http(#[from] reqwest::Error),
 }
@@ -25,6 +45,10 @@ pub struct JiraConfig {
     pub email: Option<String>,
 }
 
+/// Fields requested from the search endpoint; everything `JiraIssue::from_json` reads.
+const ISSUE_FIELDS: &str =
+    "summary,description,status,assignee,labels,priority,created,updated,duedate,comment";
+
 #[derive(Clone, Debug)]
 pub struct JiraIssue {
     pub id: String,
@@ -32,11 +56,24 @@ pub struct JiraIssue {
     pub summary: String,
     pub description: Option<String>,
     pub status: String,
+    /// Key of the status category: `new`, `indeterminate` or `done`.
+    pub status_category: Option<String>,
     pub url: Option<String>,
+    pub assignees: Vec<String>,
+    pub labels: Vec<String>,
+    /// Priority name from the project's priority scheme, e.g. `High`.
+    pub priority: Option<String>,
+    pub created: Option<DateTime<Utc>>,
+    pub updated: Option<DateTime<Utc>>,
+    pub due_date: Option<NaiveDate>,
+    pub comment_count: u32,
+    /// The issue as returned by the API.
+    pub raw: Value,
 }
 
 impl JiraIssue {
-    pub fn from_json(value: &serde_json::Value) -> Self {
+    /// Reads an issue from the API; `site_url` is where people browse it.
+    pub fn from_json(value: &serde_json::Value, site_url: &str) -> Self {
         let id = value
             .get("id")
             .and_then(|v| v.as_str())
@@ -59,8 +96,8 @@ impl JiraIssue {
         let description = value
             .get("fields")
             .and_then(|f| f.get("description"))
-            .and_then(|v| v.as_str())
-            .map(String::from);
+            .map(adf_text)
+            .filter(|text| !text.is_empty());
 
         let status = value
             .get("fields")
@@ -70,23 +107,136 @@ impl JiraIssue {
             .unwrap_or("unknown")
             .to_string();
 
-        let url = value
-            .get("self")
+        // Not from `self`: with OAuth that is Atlassian's API gateway.
+        let url = (!key.is_empty()).then(|| format!("{}/browse/{}", site_url, key));
+
+        let fields = value.get("fields");
+        let field = |name: &str| fields.and_then(|f| f.get(name));
+
+        let status_category = field("status")
+            .and_then(|s| s.pointer("/statusCategory/key"))
+            .and_then(|v| v.as_str())
+            .map(String::from);
+
+        let assignees = field("assignee")
+            .and_then(|a| a.get("displayName"))
+            .and_then(|v| v.as_str())
+            .map(|name| vec![name.to_string()])
+            .unwrap_or_default();
+
+        let labels = field("labels")
+            .and_then(|v| v.as_array())
+            .map(|labels| {
+                labels
+                    .iter()
+                    .filter_map(|label| label.as_str())
+                    .map(String::from)
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let priority = field("priority")
+            .and_then(|p| p.get("name"))
             .and_then(|v| v.as_str())
-            .map(|s| s.replace("/rest/api/3/issue/", "/browse/"))
             .map(String::from);
 
+        let timestamp = |name: &str| {
+            field(name)
+                .and_then(|v| v.as_str())
+                .and_then(|s| DateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S%.f%z").ok())
+                .map(|t| t.with_timezone(&Utc))
+        };
+        let created = timestamp("created");
+        let updated = timestamp("updated");
+
+        let due_date = field("duedate")
+            .and_then(|v| v.as_str())
+            .and_then(|s| NaiveDate::parse_from_str(s, "%Y-%m-%d").ok());
+
+        let comment_count = field("comment")
+            .and_then(|c| c.get("total"))
+            .and_then(|v| v.as_u64())
+            .unwrap_or(0) as u32;
+
         Self {
             id,
             key,
             summary,
             description,
             status,
+            status_category,
             url,
+            assignees,
+            labels,
+            priority,
+            created,
+            updated,
+            due_date,
+            comment_count,
+            raw: value.clone(),
         }
     }
 }
 
+/// Flattens an Atlassian Document Format node into plain text, one line per block.
+fn adf_text(node: &serde_json::Value) -> String {
+    if let Some(text) = node.as_str() {
+        // API v2 and some Server instances still return plain strings.
+        return text.to_string();
+    }
+    if let Some(text) = node.get("text").and_then(|v| v.as_str()) {
+        return text.to_string();
+    }
+    let Some(children) = node.get("content").and_then(|v| v.as_array()) else {
+        return String::new();
+    };
+    let inline = children
+        .iter()
+        .all(|child| child.get("type").and_then(|v| v.as_str()) == Some("text"));
+    let separator = if inline { "" } else { "\n" };
+    children
+        .iter()
+        .map(adf_text)
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+/// Wraps plain text in the Atlassian Document Format used by API v3.
+fn adf_document(text: &str) -> Value {
+    json!({
+        "type": "doc",
+        "version": 1,
+        "content": [{
+            "type": "paragraph",
+            "content": [{ "type": "text", "text": text }]
+        }]
+    })
+}
+
+/// One page of search results and the cursor of the next one.
+#[derive(Clone, Debug, Default)]
+pub struct JiraIssuePage {
+    pub issues: Vec<JiraIssue>,
+    pub next: Option<String>,
+}
+
+/// Fields of a new issue, of the client's issue type.
+#[derive(Clone, Debug, Default)]
+pub struct JiraIssueCreate {
+    pub summary: String,
+    pub description: Option<String>,
+    /// Priority name, e.g. `High`.
+    pub priority: Option<String>,
+    pub due_date: Option<NaiveDate>,
+}
+
+/// Fields to edit; `None` leaves a field as is.
+#[derive(Clone, Debug, Default)]
+pub struct JiraIssueUpdate {
+    pub summary: Option<String>,
+    pub description: Option<String>,
+}
+
 #[derive(Clone, Debug, Deserialize)]
 pub struct JiraProject {
     pub id: String,
@@ -94,38 +244,123 @@ pub struct JiraProject {
     pub name: String,
 }
 
+/// How requests authenticate. Server and Data Center only serve REST API v2;
+/// Cloud is used through v3.
+#[derive(Clone, Debug)]
+pub enum JiraAuth {
+    /// Jira Cloud: the account email and an API token, sent as Basic auth.
+    Basic { email: String, token: String },
+    /// Jira Server / Data Center: a personal access token, sent as a bearer token.
+    PersonalAccessToken(String),
+    /// Jira Cloud through Atlassian's API gateway: an OAuth 2.0 (3LO) access
+    /// token, sent as a bearer token.
+    OAuth(String),
+}
+
+impl JiraAuth {
+    fn api_version(&self) -> u8 {
+        match self {
+            JiraAuth::PersonalAccessToken(_) => 2,
+            JiraAuth::Basic { .. } | JiraAuth::OAuth(_) => 3,
+        }
+    }
+}
+
+/// The account a set of credentials belongs to.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JiraUser {
+    /// Cloud account ID; Server and Data Center identify users by `name`.
+    pub account_id: Option<String>,
+    pub name: Option<String>,
+    pub display_name: String,
+    pub email_address: Option<String>,
+}
+
 pub struct JiraClient {
-    http: Client,
+    http: HttpClient,
     server_url: String,
-    token: String,
+    site_url: String,
+    auth: JiraAuth,
     default_project: Option<String>,
+    issue_type: String,
 }
 
+/// The issue type new issues get unless the client is told otherwise.
+pub const DEFAULT_ISSUE_TYPE: &str = "Task";
+
 impl JiraClient {
-    pub fn new(token: String, server_url: String, default_project: Option<String>) -> Self {
+    pub fn new(auth: JiraAuth, server_url: String, default_project: Option<String>) -> Self {
+        let server_url = server_url.trim_end_matches('/').to_string();
         Self {
-            http: Client::new(),
+            http: HttpClient::shared(),
+            site_url: server_url.clone(),
             server_url,
-            token,
+            auth,
             default_project,
+            issue_type: DEFAULT_ISSUE_TYPE.to_string(),
         }
     }
 
-    pub fn with_project(token: String, server_url: String, project: String) -> Self {
-        Self {
-            http: Client::new(),
-            server_url,
-            token,
-            default_project: Some(project),
-        }
+    pub fn with_project(auth: JiraAuth, server_url: String, project: String) -> Self {
+        Self::new(auth, server_url, Some(project))
+    }
+
+    /// Sends requests through `http` instead of the shared client, e.g. one
+    /// configured with [`HttpClient::with_options`].
+    pub fn with_http(mut self, http: HttpClient) -> Self {
+        self.http = http;
+        self
+    }
+
+    /// Creates issues of type `issue_type`, e.g. `Story` in projects that
+    /// have no `Task`.
+    pub fn with_issue_type(mut self, issue_type: impl Into<String>) -> Self {
+        self.issue_type = issue_type.into();
+        self
+    }
+
+    /// Links issues to `site_url` rather than the server the API is reached
+    /// through, which for OAuth is Atlassian's gateway.
+    pub fn with_site_url(mut self, site_url: &str) -> Self {
+        self.site_url = site_url.trim_end_matches('/').to_string();
+        self
     }
 
     pub fn config(&self) -> Option<&String> {
         self.default_project.as_ref()
     }
 
-    fn auth_header(&self) -> String {
-        format!("Basic {}", base64::engine::general_purpose::STANDARD.encode(format!("{}:{}", "email", self.token)))
+    fn request(&self, method: Method, url: &str) -> RequestBuilder {
+        let request = self.http.request(method, url);
+        match &self.auth {
+            JiraAuth::Basic { email, token } => request.basic_auth(email, Some(token)),
+            JiraAuth::PersonalAccessToken(token) | JiraAuth::OAuth(token) => {
+                request.bearer_auth(token)
+            }
+        }
+    }
+
+    fn api_url(&self, path: &str) -> String {
+        format!("{}/rest/api/{}/{}", self.server_url, self.auth.api_version(), path)
+    }
+
+    /// A description as the API version expects it: a document in v3, plain
+    /// text in v2.
+    fn description(&self, text: &str) -> Value {
+        match self.auth.api_version() {
+            2 => Value::String(text.to_string()),
+            _ => adf_document(text),
+        }
+    }
+
+    /// Sends the request and turns error statuses into typed errors.
+    async fn send(&self, request: RequestBuilder) -> Result<Response, JiraError> {
+        check(request.send_with(&self.http).await?).await
+    }
+
+    fn issue_url(&self, key: &str) -> String {
+        self.api_url(&format!("issue/{}", key))
     }
 
     fn resolve_project(&self, override_project: Option<&String>) -> Result<String, JiraError> {
@@ -137,87 +372,245 @@ impl JiraClient {
 
     pub async fn list_issues(&self, project: Option<String>) -> Result<Vec<JiraIssue>, JiraError> {
         let project_key = self.resolve_project(project.as_ref())?;
-        
-        let jql = format!("project = {} AND status != Done ORDER BY created DESC", project_key);
-        let url = format!("{}/rest/api/3/search?jql={}&maxResults=50", 
-            self.server_url,
-            urlencoding::encode(&jql)
-        );
+        let jql = format!("project = {} AND statusCategory != Done ORDER BY created DESC", project_key);
+        let page = self.search_page(&jql, 50, None).await?;
+        Ok(page.issues)
+    }
+
+    /// Runs a JQL search, `max_results` at a time. Cloud searches through
+    /// `search/jql`, which pages with opaque tokens; Server and Data Center
+    /// only have `search`, which pages by offset.
+    pub async fn search_page(
+        &self,
+        jql: &str,
+        max_results: usize,
+        cursor: Option<String>,
+    ) -> Result<JiraIssuePage, JiraError> {
+        let max_results = max_results.to_string();
+        let mut params = vec![
+            ("jql", jql),
+            ("maxResults", max_results.as_str()),
+            ("fields", ISSUE_FIELDS),
+        ];
+        let url = match self.auth.api_version() {
+            2 => {
+                params.push(("startAt", cursor.as_deref().unwrap_or("0")));
+                self.api_url("search")
+            }
+            _ => {
+                if let Some(token) = cursor.as_deref() {
+                    params.push(("nextPageToken", token));
+                }
+                self.api_url("search/jql")
+            }
+        };
 
         let response = self
-            .http
-            .get(&url)
-            .header("Authorization", self.auth_header())
-            .header("Content-Type", "application/json")
-            .send()
+            .send(self.request(Method::GET, &url).query(&params))
             .await?;
 
-        let body: serde_json::Value = response.json().await?;
+        let body: Value = response.json().await?;
         let issues = body
             .get("issues")
-            .and_then(|v| v.as_array())
-            .map(|arr| arr.iter().map(JiraIssue::from_json).collect())
-            .unwrap_or_default();
+            .and_then(Value::as_array)
+            .ok_or_else(|| JiraError::UnexpectedResponse(body.to_string()))?;
 
-        Ok(issues)
+        let next = match self.auth.api_version() {
+            2 => {
+                let start_at = body.get("startAt").and_then(Value::as_u64).unwrap_or(0);
+                let total = body.get("total").and_then(Value::as_u64).unwrap_or(0);
+                let end = start_at + issues.len() as u64;
+                (!issues.is_empty() && end < total).then(|| end.to_string())
+            }
+            _ => body
+                .get("nextPageToken")
+                .and_then(Value::as_str)
+                .filter(|_| body.get("isLast").and_then(Value::as_bool) != Some(true))
+                .map(String::from),
+        };
+
+        Ok(JiraIssuePage {
+            issues: issues
+                .iter()
+                .map(|issue| JiraIssue::from_json(issue, &self.site_url))
+                .collect(),
+            next,
+        })
+    }
+
+    pub async fn get_issue(&self, key: &str) -> Result<JiraIssue, JiraError> {
+        let response = self
+            .send(
+                self.request(Method::GET, &self.issue_url(key))
+                    .query(&[("fields", ISSUE_FIELDS)]),
+            )
+            .await?;
+
+        let issue: Value = response.json().await?;
+        if issue.get("key").is_none() {
+            return Err(JiraError::UnexpectedResponse(issue.to_string()));
+        }
+        Ok(JiraIssue::from_json(&issue, &self.site_url))
     }
 
     pub async fn create_issue(&self, project: Option<String>, summary: &str, description: Option<&str>) -> Result<JiraIssue, JiraError> {
+        let create = JiraIssueCreate {
+            summary: summary.to_string(),
+            description: description.map(String::from),
+            ..Default::default()
+        };
+        self.create_issue_with(project, &create).await
+    }
+
+    pub async fn create_issue_with(&self, project: Option<String>, create: &JiraIssueCreate) -> Result<JiraIssue, JiraError> {
         let project_key = self.resolve_project(project.as_ref())?;
-        
-        let url = format!("{}/rest/api/3/issue", self.server_url);
 
-        let mut fields = serde_json::json!({
+        let url = self.api_url("issue");
+
+        let mut fields = json!({
             "project": {
                 "key": project_key
             },
-            "summary": summary,
+            "summary": create.summary,
             "issuetype": {
-                "name": "Task"
+                "name": self.issue_type
             }
         });
 
-        if let Some(desc) = description {
-            fields["description"] = serde_json::json!({
-                "type": "doc",
-                "version": 1,
-                "content": [{
-                    "type": "paragraph",
-                    "content": [{
-                        "type": "text",
-                        "text": desc
-                    }]
-                }]
-            });
+        if let Some(desc) = &create.description {
+            fields["description"] = self.description(desc);
+        }
+        if let Some(priority) = &create.priority {
+            fields["priority"] = json!({ "name": priority });
+        }
+        if let Some(due_date) = create.due_date {
+            fields["duedate"] = json!(due_date.format("%Y-%m-%d").to_string());
         }
 
-        let payload = serde_json::json!({ "fields": fields });
+        let payload = json!({ "fields": fields });
 
         let response = self
-            .http
-            .post(&url)
-            .header("Authorization", self.auth_header())
-            .header("Content-Type", "application/json")
-            .json(&payload)
-            .send()
+            .send(self.request(Method::POST, &url).json(&payload))
             .await?;
 
-        let issue: serde_json::Value = response.json().await?;
-        Ok(JiraIssue::from_json(&issue))
+        // The create endpoint only echoes `id`/`key`/`self`, so read the issue back.
+        let created: Value = response.json().await?;
+        let key = created
+            .get("key")
+            .and_then(Value::as_str)
+            .ok_or_else(|| JiraError::UnexpectedResponse(created.to_string()))?;
+        self.get_issue(key).await
+    }
+
+    pub async fn update_issue(&self, key: &str, update: &JiraIssueUpdate) -> Result<JiraIssue, JiraError> {
+        let mut fields = Map::new();
+        if let Some(summary) = &update.summary {
+            fields.insert("summary".into(), Value::String(summary.clone()));
+        }
+        if let Some(description) = &update.description {
+            fields.insert("description".into(), self.description(description));
+        }
+
+        self.send(
+            self.request(Method::PUT, &self.issue_url(key))
+                .json(&json!({ "fields": fields })),
+        )
+        .await?;
+        self.get_issue(key).await
+    }
+
+    /// Runs the first available transition whose target status falls in
+    /// `category` (`new`, `indeterminate` or `done`).
+    pub async fn transition_to(&self, key: &str, category: &str) -> Result<JiraIssue, JiraError> {
+        let url = format!("{}/transitions", self.issue_url(key));
+        let response = self.send(self.request(Method::GET, &url)).await?;
+        let body: Value = response.json().await?;
+
+        let transition_id = body
+            .get("transitions")
+            .and_then(Value::as_array)
+            .and_then(|transitions| {
+                transitions.iter().find(|transition| {
+                    transition
+                        .pointer("/to/statusCategory/key")
+                        .and_then(Value::as_str)
+                        == Some(category)
+                })
+            })
+            .and_then(|transition| transition.get("id"))
+            .and_then(Value::as_str)
+            .ok_or_else(|| {
+                JiraError::Configuration(format!(
+                    "no transition to a '{}' status is available for {}",
+                    category, key
+                ))
+            })?;
+
+        self.send(
+            self.request(Method::POST, &url)
+                .json(&json!({ "transition": { "id": transition_id } })),
+        )
+        .await?;
+        self.get_issue(key).await
+    }
+
+    pub async fn delete_issue(&self, key: &str) -> Result<(), JiraError> {
+        self.send(self.request(Method::DELETE, &self.issue_url(key))).await?;
+        Ok(())
     }
 
     pub async fn list_projects(&self) -> Result<Vec<JiraProject>, JiraError> {
-        let url = format!("{}/rest/api/3/project", self.server_url);
+        let url = self.api_url("project");
 
-        let response = self
-            .http
-            .get(&url)
-            .header("Authorization", self.auth_header())
-            .header("Content-Type", "application/json")
-            .send()
-            .await?;
+        let response = self.send(self.request(Method::GET, &url)).await?;
 
         let projects: Vec<JiraProject> = response.json().await?;
         Ok(projects)
     }
+
+    /// The account the credentials belong to; fails if they are not accepted.
+    pub async fn myself(&self) -> Result<JiraUser, JiraError> {
+        let response = self
+            .send(self.request(Method::GET, &self.api_url("myself")))
+            .await?;
+        Ok(response.json().await?)
+    }
+}
+
+/// Passes successful responses through and reads the error body of the rest.
+/// Jira reports `{"errorMessages": [...], "errors": {"field": "message"}}`.
+async fn check(response: Response) -> Result<Response, JiraError> {
+    let Some(failure) = Failure::of(&response) else {
+        return Ok(response);
+    };
+    let status = response.status();
+    let body: Value = response.json().await.unwrap_or_default();
+
+    let field_error = body
+        .get("errors")
+        .and_then(Value::as_object)
+        .and_then(|errors| errors.iter().next())
+        .map(|(field, message)| (field.clone(), message.as_str().unwrap_or_default().to_string()));
+    let messages: Vec<&str> = body
+        .get("errorMessages")
+        .and_then(Value::as_array)
+        .map(|messages| messages.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default();
+    let message = match (messages.is_empty(), &field_error) {
+        (false, _) => messages.join("; "),
+        (true, Some((_, message))) => message.clone(),
+        (true, None) => status.canonical_reason().unwrap_or_default().to_string(),
+    };
+
+    Err(match failure {
+        Failure::Unauthorized => JiraError::Unauthorized(message),
+        Failure::Forbidden => JiraError::Forbidden(message),
+        Failure::NotFound => JiraError::NotFound(message),
+        Failure::RateLimited { reset } => JiraError::RateLimited { reset, message },
+        Failure::Validation => JiraError::Validation {
+            field: field_error.map(|(field, _)| field),
+            message,
+        },
+        Failure::Other(status) => JiraError::UnexpectedResponse(format!("{}: {}", status, message)),
+    })
 }
@@ -1,10 +1,11 @@
 use anyhow::Result;
-use reqwest::Client;
+use chrono::{DateTime, NaiveDate, Utc};
+use kirei_http::{rate_limit_reset, Failure, HttpClient, RequestBuilderExt};
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use serde_json::{json, Value};
 use thiserror::Error;
 
-const LINEAR_GRAPHQL: &str = "https://api.linear.app/graphql";
+const LINEAR_API: &str = "https://api.linear.app";
 
 #[derive(Error, Debug)]
 pub enum LinearError {
@@ -14,6 +15,24 @@ pub enum LinearError {
     WorkspaceRequired,
     #[error("configuration error: {0}")]
     Configuration(String),
+    #[error("unauthorized: {0}")]
+    Unauthorized(String),
+    #[error("forbidden: {0}")]
+    Forbidden(String),
+    #[error("not found: {0}")]
+    NotFound(String),
+    #[error("rate limited: {message}")]
+    RateLimited {
+        reset: Option<DateTime<Utc>>,
+        message: String,
+    },
+    #[error("invalid {}: {message}", .field.as_deref().unwrap_or("request"))]
+    Validation {
+        field: Option<String>,
+        message: String,
+    },
+    #[error("unexpected response: {0}")]
+    UnexpectedResponse(String),
     #[error(transparent)]
     Http(#[from] reqwest::Error),
 }
@@ -23,13 +42,58 @@ pub struct LinearConfig {
     pub default_workspace: Option<String>,
 }
 
+/// Selection set shared by every query that returns an issue.
+const ISSUE_FIELDS: &str = r#"
+    id
+    identifier
+    title
+    description
+    url
+    priority
+    createdAt
+    updatedAt
+    dueDate
+    state {
+        name
+        type
+    }
+    assignee {
+        displayName
+    }
+    labels {
+        nodes {
+            name
+        }
+    }
+    comments(first: 100) {
+        nodes {
+            id
+        }
+    }
+"#;
+
 #[derive(Clone, Debug)]
 pub struct LinearIssue {
     pub id: String,
+    /// Team-scoped reference, e.g. `ENG-123`.
+    pub identifier: String,
     pub title: String,
     pub description: Option<String>,
     pub state: String,
+    /// Workflow state type: `triage`, `backlog`, `unstarted`, `started`,
+    /// `completed` or `canceled`.
+    pub state_type: Option<String>,
     pub url: Option<String>,
+    pub assignees: Vec<String>,
+    pub labels: Vec<String>,
+    /// Linear priority: 1 (urgent) to 4 (low); `None` when unset.
+    pub priority: Option<u8>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+    pub due_date: Option<NaiveDate>,
+    pub comment_count: u32,
+    /// The issue as returned by the API.
+    pub raw: Value,
 }
 
 impl LinearIssue {
@@ -58,18 +122,115 @@ impl LinearIssue {
             .unwrap_or("unknown")
             .to_string();
 
+        let state_type = value
+            .pointer("/state/type")
+            .and_then(Value::as_str)
+            .map(String::from);
+
         let url = value.get("url").and_then(Value::as_str).map(String::from);
 
+        let identifier = value
+            .get("identifier")
+            .and_then(Value::as_str)
+            .map(String::from)
+            .unwrap_or_else(|| id.clone());
+
+        let assignees = value
+            .pointer("/assignee/displayName")
+            .and_then(Value::as_str)
+            .map(|name| vec![name.to_string()])
+            .unwrap_or_default();
+
+        let labels = value
+            .pointer("/labels/nodes")
+            .and_then(Value::as_array)
+            .map(|nodes| {
+                nodes
+                    .iter()
+                    .filter_map(|label| label.get("name").and_then(Value::as_str))
+                    .map(String::from)
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let priority = value
+            .get("priority")
+            .and_then(Value::as_f64)
+            .map(|priority| priority as u8)
+            .filter(|priority| *priority != 0);
+
+        let timestamp = |key: &str| {
+            value
+                .get(key)
+                .and_then(Value::as_str)
+                .and_then(|s| s.parse::<DateTime<Utc>>().ok())
+        };
+        let created_at = timestamp("createdAt");
+        let updated_at = timestamp("updatedAt");
+
+        let due_date = value
+            .get("dueDate")
+            .and_then(Value::as_str)
+            .and_then(|s| NaiveDate::parse_from_str(s, "%Y-%m-%d").ok());
+
+        let comment_count = value
+            .pointer("/comments/nodes")
+            .and_then(Value::as_array)
+            .map_or(0, |nodes| nodes.len() as u32);
+
         Self {
             id,
+            identifier,
             title,
             description,
             state,
+            state_type,
             url,
+            assignees,
+            labels,
+            priority,
+            created_at,
+            updated_at,
+            due_date,
+            comment_count,
+            raw: value.clone(),
         }
     }
 }
 
+/// One page of issues and the cursor of the next one.
+#[derive(Clone, Debug, Default)]
+pub struct LinearIssuePage {
+    pub issues: Vec<LinearIssue>,
+    pub next: Option<String>,
+}
+
+/// An `IssueCreateInput` without the team, which comes from the workspace.
+#[derive(Clone, Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LinearIssueCreate {
+    pub title: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// 1 (urgent) to 4 (low).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub priority: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub due_date: Option<NaiveDate>,
+}
+
+/// An `IssueUpdateInput`; `None` leaves a field as is.
+#[derive(Clone, Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LinearIssueUpdate {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub state_id: Option<String>,
+}
+
 #[derive(Clone, Debug, Deserialize)]
 pub struct LinearWorkspace {
     pub id: String,
@@ -85,15 +246,21 @@ pub struct LinearTeam {
 }
 
 pub struct LinearClient {
-    http: Client,
+    http: HttpClient,
+    /// The GraphQL endpoint.
+    graphql_url: String,
     token: String,
     default_workspace: Option<String>,
 }
 
 impl LinearClient {
-    pub fn new(token: String, default_workspace: Option<String>) -> Self {
+    /// `base_url` replaces `https://api.linear.app`, e.g. to talk to a stub;
+    /// the GraphQL endpoint is `{base_url}/graphql`.
+    pub fn new(token: String, default_workspace: Option<String>, base_url: Option<&str>) -> Self {
+        let base_url = base_url.unwrap_or(LINEAR_API).trim_end_matches('/');
         Self {
-            http: Client::new(),
+            http: HttpClient::shared(),
+            graphql_url: format!("{}/graphql", base_url),
             token,
             default_workspace,
         }
@@ -101,12 +268,20 @@ impl LinearClient {
 
     pub fn with_workspace(token: String, workspace: String) -> Self {
         Self {
-            http: Client::new(),
+            http: HttpClient::shared(),
+            graphql_url: format!("{}/graphql", LINEAR_API),
             token,
             default_workspace: Some(workspace),
         }
     }
 
+    /// Sends requests through `http` instead of the shared client, e.g. one
+    /// configured with [`HttpClient::with_options`].
+    pub fn with_http(mut self, http: HttpClient) -> Self {
+        self.http = http;
+        self
+    }
+
     pub fn config(&self) -> Option<&String> {
         self.default_workspace.as_ref()
     }
@@ -117,129 +292,265 @@ impl LinearClient {
             .or_else(|| self.default_workspace.clone())
     }
 
-    pub async fn list_issues(&self, workspace: Option<String>) -> Result<Vec<LinearIssue>, LinearError> {
-        let workspace_id = self.workspace_variable(workspace.as_ref());
-        
-        let payload = serde_json::json!({
-            "query": r#"
-                query($workspaceId: String) {
-                    issues(first: 50, filter: { state: { type: { neq: "completed" } } }) {
-                        nodes {
-                            id
-                            title
-                            description
-                            url
-                            state {
-                                name
-                            }
-                        }
-                    }
-                }
-            "#,
-            "variables": {
-                "workspaceId": workspace_id
-            }
+    /// Runs a GraphQL operation and returns its `data` object. Failures come
+    /// back as an `errors` array, with a `200` or a `400` alike.
+    async fn graphql(&self, query: &str, variables: Value) -> Result<Value, LinearError> {
+        let payload = json!({
+            "query": query,
+            "variables": variables,
         });
 
         let response = self
             .http
-            .post(LINEAR_GRAPHQL)
+            .post(&self.graphql_url)
             .bearer_auth(&self.token)
             .json(&payload)
-            .send()
+            .send_with(&self.http)
+            .await?;
+
+        let failure = Failure::of(&response);
+        let reset = rate_limit_reset(response.headers()).map(DateTime::from);
+        let status = response.status();
+        let mut body: Value = match failure {
+            None => response.json().await?,
+            Some(_) => response.json().await.unwrap_or_default(),
+        };
+
+        if let Some(error) = body.pointer("/errors/0") {
+            return Err(graphql_error(error, failure, reset));
+        }
+        if let Some(failure) = failure {
+            let message = status.canonical_reason().unwrap_or_default().to_string();
+            return Err(failure_error(failure, message));
+        }
+        match body.get_mut("data").map(Value::take) {
+            Some(data) if !data.is_null() => Ok(data),
+            _ => Err(LinearError::UnexpectedResponse(body.to_string())),
+        }
+    }
+
+    pub async fn list_issues(&self, workspace: Option<String>) -> Result<Vec<LinearIssue>, LinearError> {
+        let filter = json!({ "state": { "type": { "neq": "completed" } } });
+        let page = self.list_issues_page(workspace, filter, 50, None).await?;
+        Ok(page.issues)
+    }
+
+    /// Lists issues matching an `IssueFilter`, `first` at a time, starting
+    /// after the `endCursor` of the previous page.
+    pub async fn list_issues_page(
+        &self,
+        workspace: Option<String>,
+        filter: Value,
+        first: usize,
+        after: Option<String>,
+    ) -> Result<LinearIssuePage, LinearError> {
+        let workspace_id = self.workspace_variable(workspace.as_ref());
+
+        let query = format!(
+            r#"
+                query($workspaceId: String, $filter: IssueFilter, $first: Int, $after: String) {{
+                    issues(first: $first, after: $after, filter: $filter) {{
+                        nodes {{ {} }}
+                        pageInfo {{
+                            hasNextPage
+                            endCursor
+                        }}
+                    }}
+                }}
+            "#,
+            ISSUE_FIELDS
+        );
+
+        let data = self
+            .graphql(
+                &query,
+                json!({
+                    "workspaceId": workspace_id,
+                    "filter": filter,
+                    "first": first,
+                    "after": after,
+                }),
+            )
             .await?;
 
-        let body: Value = response.json().await?;
-        let nodes = body
-            .get("data")
-            .and_then(|data| data.get("issues"))
-            .and_then(|issues| issues.get("nodes"))
+        let nodes = data
+            .pointer("/issues/nodes")
             .and_then(Value::as_array)
-            .map(|nodes| nodes.clone())
-            .ok_or_else(|| LinearError::Configuration("Failed to parse response".to_string()))?;
+            .ok_or_else(|| LinearError::UnexpectedResponse(data.to_string()))?;
+        let next = data
+            .pointer("/issues/pageInfo")
+            .filter(|info| info.get("hasNextPage").and_then(Value::as_bool) == Some(true))
+            .and_then(|info| info.get("endCursor"))
+            .and_then(Value::as_str)
+            .map(String::from);
 
-        Ok(nodes.iter().map(|node| LinearIssue::from_json(node)).collect())
+        Ok(LinearIssuePage {
+            issues: nodes.iter().map(LinearIssue::from_json).collect(),
+            next,
+        })
+    }
+
+    /// Fetches an issue by id or identifier (`ENG-123`).
+    pub async fn get_issue(&self, id: &str) -> Result<LinearIssue, LinearError> {
+        let query = format!("query($id: String!) {{ issue(id: $id) {{ {} }} }}", ISSUE_FIELDS);
+        let data = self.graphql(&query, json!({ "id": id })).await?;
+        let issue = data
+            .get("issue")
+            .filter(|issue| !issue.is_null())
+            .ok_or_else(|| LinearError::NotFound(format!("issue {}", id)))?;
+
+        Ok(LinearIssue::from_json(issue))
     }
 
     pub async fn create_issue(&self, workspace: Option<String>, title: &str, body: Option<&str>) -> Result<LinearIssue, LinearError> {
+        let create = LinearIssueCreate {
+            title: title.to_string(),
+            description: body.map(String::from),
+            ..Default::default()
+        };
+        self.create_issue_with(workspace, &create).await
+    }
+
+    pub async fn create_issue_with(&self, workspace: Option<String>, create: &LinearIssueCreate) -> Result<LinearIssue, LinearError> {
         let workspace_id = self.workspace_variable(workspace.as_ref());
-        
-        let input = serde_json::json!({
-            "title": title,
-            "description": body,
-            "teamId": workspace_id,
-        });
-        
-        let payload = serde_json::json!({
-            "query": r#"
-                mutation IssueCreate($input: IssueCreateInput!) {
-                    issueCreate(input: $input) {
+
+        let mut input = json!(create);
+        input["teamId"] = json!(workspace_id);
+
+        let query = format!(
+            r#"
+                mutation IssueCreate($input: IssueCreateInput!) {{
+                    issueCreate(input: $input) {{
                         success
-                        issue {
-                            id
-                            title
-                            description
-                            url
-                            state {
-                                name
+                        issue {{ {} }}
+                    }}
+                }}
+            "#,
+            ISSUE_FIELDS
+        );
+
+        let data = self.graphql(&query, json!({ "input": input })).await?;
+        let issue = data
+            .pointer("/issueCreate/issue")
+            .filter(|issue| !issue.is_null())
+            .ok_or_else(|| LinearError::Configuration("Failed to create issue".to_string()))?;
+
+        Ok(LinearIssue::from_json(issue))
+    }
+
+    pub async fn update_issue(&self, id: &str, update: &LinearIssueUpdate) -> Result<LinearIssue, LinearError> {
+        let query = format!(
+            r#"
+                mutation IssueUpdate($id: String!, $input: IssueUpdateInput!) {{
+                    issueUpdate(id: $id, input: $input) {{
+                        success
+                        issue {{ {} }}
+                    }}
+                }}
+            "#,
+            ISSUE_FIELDS
+        );
+
+        let data = self
+            .graphql(&query, json!({ "id": id, "input": update }))
+            .await?;
+        let issue = data
+            .pointer("/issueUpdate/issue")
+            .filter(|issue| !issue.is_null())
+            .ok_or_else(|| LinearError::UnexpectedResponse(data.to_string()))?;
+
+        Ok(LinearIssue::from_json(issue))
+    }
+
+    /// Finds the first workflow state of the issue's team whose type is one of
+    /// `types`, in the order given, e.g. to close or reopen the issue.
+    pub async fn workflow_state_id(&self, issue_id: &str, types: &[&str]) -> Result<String, LinearError> {
+        let data = self
+            .graphql(
+                r#"
+                    query($id: String!) {
+                        issue(id: $id) {
+                            team {
+                                states {
+                                    nodes {
+                                        id
+                                        type
+                                        position
+                                    }
+                                }
                             }
                         }
                     }
-                }
-            "#,
-            "variables": {
-                "input": input
-            }
-        });
-
-        let response = self
-            .http
-            .post(LINEAR_GRAPHQL)
-            .bearer_auth(&self.token)
-            .json(&payload)
-            .send()
+                "#,
+                json!({ "id": issue_id }),
+            )
             .await?;
+        let states = data
+            .pointer("/issue/team/states/nodes")
+            .and_then(Value::as_array)
+            .ok_or_else(|| LinearError::UnexpectedResponse(data.to_string()))?;
+
+        types
+            .iter()
+            .find_map(|wanted| {
+                states
+                    .iter()
+                    .filter(|state| state.get("type").and_then(Value::as_str) == Some(wanted))
+                    .min_by(|a, b| {
+                        let position = |s: &Value| s.get("position").and_then(Value::as_f64);
+                        position(a)
+                            .partial_cmp(&position(b))
+                            .unwrap_or(std::cmp::Ordering::Equal)
+                    })
+                    .and_then(|state| state.get("id").and_then(Value::as_str))
+            })
+            .map(String::from)
+            .ok_or_else(|| {
+                LinearError::Configuration(format!(
+                    "team has no workflow state of type {}",
+                    types.join(" or ")
+                ))
+            })
+    }
 
-        let body: Value = response.json().await?;
-        let issue = body
-            .get("data")
-            .and_then(|data| data.get("issueCreate"))
-            .and_then(|create| create.get("issue"))
-            .ok_or_else(|| LinearError::Configuration("Failed to create issue".to_string()))?;
+    /// Moves the issue to the trash, where it can still be restored for a while.
+    pub async fn delete_issue(&self, id: &str) -> Result<(), LinearError> {
+        let data = self
+            .graphql(
+                "mutation($id: String!) { issueDelete(id: $id) { success } }",
+                json!({ "id": id }),
+            )
+            .await?;
 
-        Ok(LinearIssue::from_json(issue))
+        match data.pointer("/issueDelete/success").and_then(Value::as_bool) {
+            Some(true) => Ok(()),
+            _ => Err(LinearError::UnexpectedResponse(data.to_string())),
+        }
     }
 
     pub async fn list_workspaces(&self) -> Result<Vec<LinearWorkspace>, LinearError> {
-        let payload = serde_json::json!({
-            "query": r#"
-                query {
-                    organizations {
-                        nodes {
-                            id
-                            name
-                            slug
+        let data = self
+            .graphql(
+                r#"
+                    query {
+                        organizations {
+                            nodes {
+                                id
+                                name
+                                slug
+                            }
                         }
                     }
-                }
-            "#
-        });
-
-        let response = self
-            .http
-            .post(LINEAR_GRAPHQL)
-            .bearer_auth(&self.token)
-            .json(&payload)
-            .send()
+                "#,
+                Value::Null,
+            )
             .await?;
 
-        let body: Value = response.json().await?;
-        let nodes = body
-            .get("data")
-            .and_then(|data| data.get("organizations"))
+        let nodes = data
+            .get("organizations")
             .and_then(|orgs| orgs.get("nodes"))
             .and_then(Value::as_array)
-            .map(|nodes| nodes.clone())
+            .cloned()
             .ok_or_else(|| LinearError::Configuration("Failed to parse workspaces".to_string()))?;
 
         Ok(nodes.iter().filter_map(|node| {
@@ -254,38 +565,28 @@ impl LinearClient {
     pub async fn list_teams(&self, workspace: Option<String>) -> Result<Vec<LinearTeam>, LinearError> {
         let workspace_id = self.workspace_variable(workspace.as_ref());
         
-        let payload = serde_json::json!({
-            "query": r#"
-                query($workspaceId: String!) {
-                    teams(first: 50, filter: { organization: { id: { eq: $workspaceId } } }) {
-                        nodes {
-                            id
-                            name
-                            key
+        let data = self
+            .graphql(
+                r#"
+                    query($workspaceId: String!) {
+                        teams(first: 50, filter: { organization: { id: { eq: $workspaceId } } }) {
+                            nodes {
+                                id
+                                name
+                                key
+                            }
                         }
                     }
-                }
-            "#,
-            "variables": {
-                "workspaceId": workspace_id
-            }
-        });
-
-        let response = self
-            .http
-            .post(LINEAR_GRAPHQL)
-            .bearer_auth(&self.token)
-            .json(&payload)
-            .send()
+                "#,
+                json!({ "workspaceId": workspace_id }),
+            )
             .await?;
 
-        let body: Value = response.json().await?;
-        let nodes = body
-            .get("data")
-            .and_then(|data| data.get("teams"))
+        let nodes = data
+            .get("teams")
             .and_then(|teams| teams.get("nodes"))
             .and_then(Value::as_array)
-            .map(|nodes| nodes.clone())
+            .cloned()
             .ok_or_else(|| LinearError::Configuration("Failed to parse teams".to_string()))?;
 
         Ok(nodes.iter().filter_map(|node| {
@@ -297,3 +598,47 @@ impl LinearClient {
         }).collect())
     }
 }
+
+/// Maps an entry of a GraphQL `errors` array by its `extensions.code`, falling
+/// back to the HTTP status when the code is missing or unfamiliar.
+fn graphql_error(error: &Value, failure: Option<Failure>, reset: Option<DateTime<Utc>>) -> LinearError {
+    let raw = error
+        .get("message")
+        .and_then(Value::as_str)
+        .unwrap_or_default();
+    let message = error
+        .pointer("/extensions/userPresentableMessage")
+        .and_then(Value::as_str)
+        .unwrap_or(raw)
+        .to_string();
+
+    match error.pointer("/extensions/code").and_then(Value::as_str) {
+        Some("AUTHENTICATION_ERROR") => LinearError::Unauthorized(message),
+        Some("FORBIDDEN") => LinearError::Forbidden(message),
+        Some("RATELIMITED") => LinearError::RateLimited { reset, message },
+        // Unknown ids are reported as invalid input.
+        _ if raw.starts_with("Entity not found") => LinearError::NotFound(message),
+        Some("INVALID_INPUT") | Some("GRAPHQL_VALIDATION_FAILED") => LinearError::Validation {
+            field: None,
+            message,
+        },
+        _ => match failure {
+            Some(failure) => failure_error(failure, message),
+            None => LinearError::UnexpectedResponse(message),
+        },
+    }
+}
+
+fn failure_error(failure: Failure, message: String) -> LinearError {
+    match failure {
+        Failure::Unauthorized => LinearError::Unauthorized(message),
+        Failure::Forbidden => LinearError::Forbidden(message),
+        Failure::NotFound => LinearError::NotFound(message),
+        Failure::RateLimited { reset } => LinearError::RateLimited { reset, message },
+        Failure::Validation => LinearError::Validation {
+            field: None,
+            message,
+        },
+        Failure::Other(status) => LinearError::UnexpectedResponse(format!("{}: {}", status, message)),
+    }
+}
@@ -1,8 +1,13 @@
 use anyhow::Result;
-use reqwest::Client;
+use chrono::{DateTime, NaiveDate, Utc};
+use kirei_http::{Failure, HttpClient, RequestBuilderExt};
+use reqwest::{Method, RequestBuilder, Response};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use thiserror::Error;
 
+const TRELLO_API: &str = "https://api.trello.com/1";
+
 #[derive(Error, Debug)]
 pub enum TrelloError {
     #[error("missing credentials")]
@@ -13,6 +18,24 @@ pub enum TrelloError {
     BoardRequired,
     #[error("configuration error: {0}")]
     Configuration(String),
+    #[error("unauthorized: {0}")]
+    Unauthorized(String),
+    #[error("forbidden: {0}")]
+    Forbidden(String),
+    #[error("not found: {0}")]
+    NotFound(String),
+    #[error("rate limited: {message}")]
+    RateLimited {
+        reset: Option<DateTime<Utc>>,
+        message: String,
+    },
+    #[error("invalid {}: {message}", .field.as_deref().unwrap_or("request"))]
+    Validation {
+        field: Option<String>,
+        message: String,
+    },
+    #[error("unexpected response: {0}")]
+    UnexpectedResponse(String),
     #[error(transparent)]
     Http(#[from] reqwest::Error),
 }
@@ -23,13 +46,28 @@ pub struct TrelloConfig {
     pub api_key: Option<String>,
 }
 
+/// Embeds member usernames in card responses so assignees need no extra request.
+const CARD_MEMBERS: [(&str, &str); 2] = [("members", "true"), ("member_fields", "username")];
+
 #[derive(Clone, Debug)]
 pub struct TrelloCard {
     pub id: String,
+    /// Short id used in card URLs, e.g. `AbCd1234`.
+    pub short_link: String,
     pub name: String,
     pub description: Option<String>,
     pub list_name: String,
     pub url: Option<String>,
+    pub closed: bool,
+    /// Usernames of the card members.
+    pub members: Vec<String>,
+    pub labels: Vec<String>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub last_activity: Option<DateTime<Utc>>,
+    pub due: Option<DateTime<Utc>>,
+    pub comment_count: u32,
+    /// The card as returned by the API.
+    pub raw: Value,
 }
 
 impl TrelloCard {
@@ -54,16 +92,95 @@ impl TrelloCard {
 
         let url = value.get("url").and_then(|v| v.as_str()).map(String::from);
 
+        let short_link = value
+            .get("shortLink")
+            .and_then(|v| v.as_str())
+            .unwrap_or_default()
+            .to_string();
+
+        let closed = value
+            .get("closed")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false);
+
+        let members = value
+            .get("members")
+            .and_then(|v| v.as_array())
+            .map(|members| {
+                members
+                    .iter()
+                    .filter_map(|m| m.get("username").and_then(|v| v.as_str()))
+                    .map(String::from)
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        // Unnamed labels are only identified by their colour.
+        let labels = value
+            .get("labels")
+            .and_then(|v| v.as_array())
+            .map(|labels| {
+                labels
+                    .iter()
+                    .filter_map(|label| {
+                        label
+                            .get("name")
+                            .and_then(|v| v.as_str())
+                            .filter(|name| !name.is_empty())
+                            .or_else(|| label.get("color").and_then(|v| v.as_str()))
+                    })
+                    .map(String::from)
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        // Card ids are Mongo object ids, whose first four bytes are the creation time.
+        let created_at = id
+            .get(..8)
+            .and_then(|hex| i64::from_str_radix(hex, 16).ok())
+            .and_then(|seconds| DateTime::from_timestamp(seconds, 0));
+
+        let timestamp = |key: &str| {
+            value
+                .get(key)
+                .and_then(|v| v.as_str())
+                .and_then(|s| s.parse::<DateTime<Utc>>().ok())
+        };
+        let last_activity = timestamp("dateLastActivity");
+        let due = timestamp("due");
+
+        let comment_count = value
+            .get("badges")
+            .and_then(|b| b.get("comments"))
+            .and_then(|v| v.as_u64())
+            .unwrap_or(0) as u32;
+
         Self {
             id,
+            short_link,
             name,
             description,
             list_name: list_name.to_string(),
             url,
+            closed,
+            members,
+            labels,
+            created_at,
+            last_activity,
+            due,
+            comment_count,
+            raw: value.clone(),
         }
     }
 }
 
+/// One page of cards and the cursor of the next one.
+#[derive(Clone, Debug, Default)]
+pub struct TrelloCardPage {
+    pub cards: Vec<TrelloCard>,
+    pub next: Option<String>,
+}
+
 #[derive(Clone, Debug, Deserialize)]
 pub struct TrelloBoard {
     pub id: String,
@@ -78,16 +195,19 @@ pub struct TrelloList {
 }
 
 pub struct TrelloClient {
-    http: Client,
+    http: HttpClient,
+    base_url: String,
     token: String,
     api_key: String,
     default_board: Option<String>,
 }
 
 impl TrelloClient {
-    pub fn new(token: String, api_key: String, default_board: Option<String>) -> Self {
+    /// `base_url` replaces `https://api.trello.com/1`, e.g. to talk to a stub.
+    pub fn new(token: String, api_key: String, default_board: Option<String>, base_url: Option<&str>) -> Self {
         Self {
-            http: Client::new(),
+            http: HttpClient::shared(),
+            base_url: base_url.unwrap_or(TRELLO_API).trim_end_matches('/').to_string(),
             token,
             api_key,
             default_board,
@@ -96,13 +216,21 @@ impl TrelloClient {
 
     pub fn with_board(token: String, api_key: String, board: String) -> Self {
         Self {
-            http: Client::new(),
+            http: HttpClient::shared(),
+            base_url: TRELLO_API.to_string(),
             token,
             api_key,
             default_board: Some(board),
         }
     }
 
+    /// Sends requests through `http` instead of the shared client, e.g. one
+    /// configured with [`HttpClient::with_options`].
+    pub fn with_http(mut self, http: HttpClient) -> Self {
+        self.http = http;
+        self
+    }
+
     pub fn config(&self) -> Option<&String> {
         self.default_board.as_ref()
     }
@@ -114,6 +242,17 @@ impl TrelloClient {
         ]
     }
 
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        self.http
+            .request(method, format!("{}{}", self.base_url, path))
+            .query(&self.auth_params())
+    }
+
+    /// Sends the request and turns error statuses into typed errors.
+    async fn send(&self, request: RequestBuilder) -> Result<Response, TrelloError> {
+        check(request.send_with(&self.http).await?).await
+    }
+
     fn resolve_board(&self, override_board: Option<&String>) -> Result<String, TrelloError> {
         override_board
             .cloned()
@@ -122,76 +261,162 @@ impl TrelloClient {
     }
 
     pub async fn list_cards(&self, board: Option<String>) -> Result<Vec<TrelloCard>, TrelloError> {
+        let page = self.list_cards_page(board, 1000, None).await?;
+        Ok(page.cards)
+    }
+
+    /// Lists the open cards of a board, newest first, `limit` at a time.
+    /// `before` is the id of the oldest card of the previous page.
+    pub async fn list_cards_page(
+        &self,
+        board: Option<String>,
+        limit: usize,
+        before: Option<String>,
+    ) -> Result<TrelloCardPage, TrelloError> {
         let board_id = self.resolve_board(board.as_ref())?;
-        
-        let url = format!(
-            "https://api.trello.com/1/boards/{}/cards",
-            board_id
-        );
 
-        let response = self
-            .http
-            .get(&url)
-            .query(&self.auth_params())
-            .send()
-            .await?;
+        let mut request = self
+            .request(Method::GET, &format!("/boards/{}/cards", board_id))
+            .query(&CARD_MEMBERS)
+            .query(&[("limit", limit.to_string())]);
+        if let Some(before) = &before {
+            request = request.query(&[("before", before)]);
+        }
+        let response = self.send(request).await?;
+
+        let cards: Vec<Value> = response.json().await?;
 
-        let cards: Vec<serde_json::Value> = response.json().await?;
-        
         // Get lists for board to map card to list name
-        let lists = self.list_lists(Some(board_id.clone())).await.unwrap_or_default();
+        let lists = self.list_lists(Some(board_id)).await?;
         let list_map: std::collections::HashMap<String, String> = lists
             .into_iter()
             .map(|l| (l.id, l.name))
             .collect();
 
-        Ok(cards.into_iter().map(|card| {
+        let next = cards
+            .last()
+            .filter(|_| cards.len() == limit)
+            .and_then(|card| card.get("id"))
+            .and_then(Value::as_str)
+            .map(String::from);
+
+        let cards = cards.iter().map(|card| {
             let list_name = card.get("idList")
                 .and_then(|v| v.as_str())
                 .and_then(|id| list_map.get(id))
-                .map(|s| s.to_string())
-                .unwrap_or_else(|| "Unknown".to_string());
-            TrelloCard::from_json(&card, &list_name)
-        }).collect())
+                .map(|s| s.as_str())
+                .unwrap_or("Unknown");
+            TrelloCard::from_json(card, list_name)
+        }).collect();
+
+        Ok(TrelloCardPage { cards, next })
+    }
+
+    /// Runs a card search on the board using Trello's search operators, e.g.
+    /// `is:open label:"bug" @me`. The cursor is the page number.
+    pub async fn search_cards_page(
+        &self,
+        board: Option<String>,
+        query: &str,
+        limit: usize,
+        page: Option<String>,
+    ) -> Result<TrelloCardPage, TrelloError> {
+        let board_id = self.resolve_board(board.as_ref())?;
+        let page: usize = page.and_then(|page| page.parse().ok()).unwrap_or(0);
+
+        let response = self
+            .send(self.request(Method::GET, "/search").query(&[
+                ("query", query),
+                ("idBoards", board_id.as_str()),
+                ("modelTypes", "cards"),
+                ("cards_limit", &limit.to_string()),
+                ("cards_page", &page.to_string()),
+                ("card_list", "true"),
+                ("card_members", "true"),
+            ]))
+            .await?;
+
+        let body: Value = response.json().await?;
+        let cards = body
+            .get("cards")
+            .and_then(Value::as_array)
+            .ok_or_else(|| TrelloError::UnexpectedResponse(body.to_string()))?;
+
+        Ok(TrelloCardPage {
+            cards: cards.iter().map(card_with_list).collect(),
+            next: (cards.len() == limit).then(|| (page + 1).to_string()),
+        })
+    }
+
+    /// Fetches a card by id or short link.
+    pub async fn get_card(&self, id: &str) -> Result<TrelloCard, TrelloError> {
+        let response = self
+            .send(
+                self.request(Method::GET, &format!("/cards/{}", id))
+                    .query(&CARD_MEMBERS)
+                    .query(&[("list", "true")]),
+            )
+            .await?;
+
+        let card: Value = response.json().await?;
+        Ok(card_with_list(&card))
     }
 
     pub async fn create_card(&self, board: Option<String>, name: &str, description: Option<&str>) -> Result<TrelloCard, TrelloError> {
+        self.create_card_with(board, name, description, None).await
+    }
+
+    /// Creates a card in the first list of the board. A `due` date is sent as
+    /// noon UTC so it falls on the same day in every time zone.
+    pub async fn create_card_with(
+        &self,
+        board: Option<String>,
+        name: &str,
+        description: Option<&str>,
+        due: Option<NaiveDate>,
+    ) -> Result<TrelloCard, TrelloError> {
         let board_id = self.resolve_board(board.as_ref())?;
-        
+
         // Get the first list on the board
         let lists = self.list_lists(Some(board_id.clone())).await?;
-        let list_id = lists.first()
-            .map(|l| l.id.clone())
+        let list = lists.first()
             .ok_or_else(|| TrelloError::Configuration("No lists found on board".to_string()))?;
 
-        let url = format!("https://api.trello.com/1/cards");
-
-        let mut params = self.auth_params();
-        params.push(("name", name));
-        params.push(("idList", &list_id));
+        let mut params = vec![("name", name), ("idList", list.id.as_str())];
         if let Some(desc) = description {
             params.push(("desc", desc));
         }
+        let due = due
+            .and_then(|date| date.and_hms_opt(12, 0, 0))
+            .map(|time| time.and_utc().to_rfc3339());
+        if let Some(due) = &due {
+            params.push(("due", due));
+        }
 
         let response = self
-            .http
-            .post(&url)
-            .query(&params)
-            .send()
+            .send(self.request(Method::POST, "/cards").query(&params))
             .await?;
 
-        let card: serde_json::Value = response.json().await?;
-        Ok(TrelloCard::from_json(&card, ""))
+        let card: Value = response.json().await?;
+        Ok(TrelloCard::from_json(&card, &list.name))
     }
 
-    pub async fn list_boards(&self) -> Result<Vec<TrelloBoard>, TrelloError> {
-        let url = "https://api.trello.com/1/members/me/boards";
+    /// Updates card attributes (`name`, `desc`, `closed`, ...) and returns the card.
+    pub async fn update_card(&self, id: &str, params: &[(&str, &str)]) -> Result<TrelloCard, TrelloError> {
+        self.send(self.request(Method::PUT, &format!("/cards/{}", id)).query(params))
+            .await?;
+        self.get_card(id).await
+    }
+
+    pub async fn delete_card(&self, id: &str) -> Result<(), TrelloError> {
+        self.send(self.request(Method::DELETE, &format!("/cards/{}", id)))
+            .await?;
+        Ok(())
+    }
 
+    pub async fn list_boards(&self) -> Result<Vec<TrelloBoard>, TrelloError> {
         let response = self
-            .http
-            .get(url)
-            .query(&self.auth_params())
-            .send()
+            .send(self.request(Method::GET, "/members/me/boards"))
             .await?;
 
         let boards: Vec<TrelloBoard> = response.json().await?;
@@ -200,20 +425,49 @@ impl TrelloClient {
 
     pub async fn list_lists(&self, board: Option<String>) -> Result<Vec<TrelloList>, TrelloError> {
         let board_id = self.resolve_board(board.as_ref())?;
-        
-        let url = format!(
-            "https://api.trello.com/1/boards/{}/lists",
-            board_id
-        );
 
         let response = self
-            .http
-            .get(&url)
-            .query(&self.auth_params())
-            .send()
+            .send(self.request(Method::GET, &format!("/boards/{}/lists", board_id)))
             .await?;
 
         let lists: Vec<TrelloList> = response.json().await?;
         Ok(lists)
     }
 }
+
+/// Passes successful responses through and reads the error body of the rest.
+/// Trello mostly answers in plain text (`invalid token`), and rate limits in
+/// JSON with a `message`.
+async fn check(response: Response) -> Result<Response, TrelloError> {
+    let Some(failure) = Failure::of(&response) else {
+        return Ok(response);
+    };
+    let status = response.status();
+    let text = response.text().await.unwrap_or_default();
+    let message = serde_json::from_str::<Value>(&text)
+        .ok()
+        .and_then(|body| body.get("message").and_then(Value::as_str).map(String::from))
+        .or_else(|| Some(text.trim().to_string()).filter(|text| !text.is_empty()))
+        .unwrap_or_else(|| status.canonical_reason().unwrap_or_default().to_string());
+
+    Err(match failure {
+        Failure::Unauthorized => TrelloError::Unauthorized(message),
+        Failure::Forbidden => TrelloError::Forbidden(message),
+        Failure::NotFound => TrelloError::NotFound(message),
+        Failure::RateLimited { reset } => TrelloError::RateLimited { reset, message },
+        Failure::Validation => TrelloError::Validation {
+            field: None,
+            message,
+        },
+        Failure::Other(status) => TrelloError::UnexpectedResponse(format!("{}: {}", status, message)),
+    })
+}
+
+/// Parses a card fetched with its list embedded (`list=true`/`card_list=true`).
+fn card_with_list(card: &Value) -> TrelloCard {
+    let list_name = card
+        .pointer("/list/name")
+        .and_then(Value::as_str)
+        .unwrap_or("Unknown");
+    TrelloCard::from_json(card, list_name)
+}